@@ -1,7 +1,8 @@
 use super::Result;
-use crate::transform::Company;
+use crate::transform::{Cnpj, CnpjError, Company, Lookups, SuppressionList};
 use actix_web::{HttpResponse, Responder, web};
-use postgres::{Client, NoTls};
+use postgres::types::FromSql;
+use postgres::{Client, NoTls, Row};
 use serde_json::json;
 
 /// Prometheus exposition format version
@@ -11,29 +12,54 @@ const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
 pub async fn get_company_handler(
     cnpj: web::Path<String>,
     db_url: web::Data<String>,
+    suppression: web::Data<SuppressionList>,
+    lookups: web::Data<Lookups>,
 ) -> impl Responder {
-    match get_company(&cnpj, &db_url).await {
+    match get_company(&cnpj, &db_url, &suppression, &lookups).await {
         Ok(Some(company)) => HttpResponse::Ok().json(company),
         Ok(None) => HttpResponse::NotFound().json(json!({
             "error": "Company not found",
             "cnpj": cnpj.as_str()
         })),
+        Err(super::ApiError::InvalidCnpj(e)) => HttpResponse::BadRequest().json(json!({
+            "error": format!("Invalid CNPJ: {}", e),
+            "cnpj": cnpj.as_str()
+        })),
         Err(e) => HttpResponse::InternalServerError().json(json!({
             "error": format!("Database error: {}", e)
         })),
     }
 }
 
-/// Handler for getting company by CNPJ
-pub async fn get_company(cnpj: &str, db_url: &str) -> Result<Option<Company>> {
-    // Validate and clean CNPJ (remove non-digits)
-    let clean_cnpj: String = cnpj.chars().filter(|c| c.is_ascii_digit()).collect();
+/// A column of a company row, or a database error naming it
+///
+/// A coded column holding a code the layout does not define is an error too,
+/// rather than a field silently left out.
+fn column<'a, T: FromSql<'a>>(row: &'a Row, index: usize) -> Result<T> {
+    row.try_get(index).map_err(|e| {
+        super::ApiError::DatabaseError(format!("column {}: {}", row.columns()[index].name(), e))
+    })
+}
 
-    if clean_cnpj.len() != 14 {
-        return Err(super::ApiError::InvalidCnpj(cnpj.to_string()));
+/// Handler for getting company by CNPJ
+///
+/// Companies of the suppression list are not found. Descriptions missing from
+/// the database row are taken from `lookups`.
+pub async fn get_company(
+    cnpj: &str,
+    db_url: &str,
+    suppression: &SuppressionList,
+    lookups: &Lookups,
+) -> Result<Option<Company>> {
+    // Validate the CNPJ, numeric or alphanumeric, with or without punctuation
+    let cnpj: Cnpj = cnpj
+        .parse()
+        .map_err(|e: CnpjError| super::ApiError::InvalidCnpj(e.to_string()))?;
+    if suppression.suppresses_cnpj(&cnpj) {
+        return Ok(None);
     }
 
-    tracing::info!("Getting company with CNPJ: {}", clean_cnpj);
+    tracing::info!("Getting company with CNPJ: {}", cnpj);
 
     // Connect to database
     let mut client = Client::connect(db_url, NoTls)
@@ -46,35 +72,54 @@ pub async fn get_company(cnpj: &str, db_url: &str) -> Result<Option<Company>> {
                 descricao_situacao_cadastral, data_situacao_cadastral,
                 uf, municipio, cnae_fiscal, cnae_fiscal_descricao,
                 logradouro, numero, complemento, bairro, cep,
-                email, codigo_natureza_juridica, capital_social
+                email, codigo_natureza_juridica, capital_social::TEXT,
+                cnae_secao, cnae_secao_descricao, cnae_divisao, cnae_divisao_descricao,
+                cnae_grupo, cnae_classe, identificador_matriz_filial,
+                descricao_identificador_matriz_filial, codigo_porte, porte,
+                cnae_grupo_descricao, cnae_classe_descricao, situacao_especial,
+                data_situacao_especial
          FROM companies WHERE cnpj = $1",
-            &[&clean_cnpj],
+            &[&cnpj],
         )
         .map_err(|e| super::ApiError::DatabaseError(e.to_string()))?;
 
     if let Some(row) = row {
-        let company = Company {
-            cnpj: row.get(0),
-            razao_social: row.get(1),
-            nome_fantasia: row.get(2),
-            situacao_cadastral: row.get(3),
-            descricao_situacao_cadastral: row.get(4),
-            data_situacao_cadastral: row.get::<_, Option<String>>(5),
-            uf: row.get(6),
-            municipio: row.get(7),
-            cnae_fiscal: row.get(8),
-            cnae_fiscal_descricao: row.get(9),
-            logradouro: row.get(10),
-            numero: row.get(11),
-            complemento: row.get(12),
-            bairro: row.get(13),
-            cep: row.get(14),
-            email: row.get(15),
-            codigo_natureza_juridica: row.get(16),
-            capital_social: row.get(17),
+        let mut company = Company {
+            cnpj: column(&row, 0)?,
+            razao_social: column(&row, 1)?,
+            nome_fantasia: column(&row, 2)?,
+            situacao_cadastral: column(&row, 3)?,
+            descricao_situacao_cadastral: column(&row, 4)?,
+            data_situacao_cadastral: column(&row, 5)?,
+            uf: column(&row, 6)?,
+            municipio: column(&row, 7)?,
+            cnae_fiscal: column(&row, 8)?,
+            cnae_fiscal_descricao: column(&row, 9)?,
+            logradouro: column(&row, 10)?,
+            numero: column(&row, 11)?,
+            complemento: column(&row, 12)?,
+            bairro: column(&row, 13)?,
+            cep: column(&row, 14)?,
+            email: column(&row, 15)?,
+            codigo_natureza_juridica: column(&row, 16)?,
+            // NUMERIC is read as text and parsed, keeping the exact cents
+            capital_social: column::<Option<String>>(&row, 17)?
+                .and_then(|value| value.parse().ok()),
+            cnae_secao: column(&row, 18)?,
+            cnae_secao_descricao: column(&row, 19)?,
+            cnae_divisao: column(&row, 20)?,
+            cnae_divisao_descricao: column(&row, 21)?,
+            cnae_grupo: column(&row, 22)?,
+            cnae_classe: column(&row, 23)?,
+            cnae_grupo_descricao: column(&row, 28)?,
+            cnae_classe_descricao: column(&row, 29)?,
+            identificador_matriz_filial: column(&row, 24)?,
+            descricao_identificador_matriz_filial: column(&row, 25)?,
+            codigo_porte: column(&row, 26)?,
+            porte: column(&row, 27)?,
+            situacao_especial: column(&row, 30)?,
+            data_situacao_especial: column(&row, 31)?,
             // Fill remaining fields with defaults for now
-            identificador_matriz_filial: None,
-            descricao_identificador_matriz_filial: None,
             motivo_situacao_cadastral: None,
             descricao_motivo_situacao_cadastral: None,
             nome_cidade_no_exterior: String::new(),
@@ -85,8 +130,6 @@ pub async fn get_company(cnpj: &str, db_url: &str) -> Result<Option<Company>> {
             ddd_telefone_1: String::new(),
             ddd_telefone_2: String::new(),
             ddd_fax: String::new(),
-            situacao_especial: String::new(),
-            data_situacao_especial: None,
             opcao_pelo_simples: None,
             data_opcao_pelo_simples: None,
             data_exclusao_do_simples: None,
@@ -95,13 +138,26 @@ pub async fn get_company(cnpj: &str, db_url: &str) -> Result<Option<Company>> {
             data_exclusao_do_mei: None,
             natureza_juridica: None,
             qualificacao_do_responsavel: None,
-            codigo_porte: None,
-            porte: None,
             ente_federativo_responsavel: String::new(),
             codigo_municipio: None,
             codigo_municipio_ibge: None,
+            regiao: None,
+            cnaes_secundarios: Vec::new(),
+            qsa: Vec::new(),
         };
 
+        if suppression.suppresses_company(&company.cnpj, &company.razao_social) {
+            return Ok(None);
+        }
+
+        if company.cnae_fiscal_descricao.is_none() {
+            company.cnae_fiscal_descricao = company
+                .cnae_fiscal
+                .and_then(|code| lookups.get_cnae(code).cloned());
+        }
+        company.natureza_juridica = company
+            .codigo_natureza_juridica
+            .and_then(|code| lookups.get_legal_nature(code).cloned());
         Ok(Some(company))
     } else {
         Ok(None)
@@ -145,4 +201,35 @@ mod tests {
         // Response is an impl Responder, can't directly test here
         // In integration tests, we would test the actual HTTP response
     }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_get_company_with_unknown_codes() {
+        use crate::db::{Database, PostgresDatabase};
+
+        let db = PostgresDatabase::from_env().unwrap();
+        db.create().unwrap();
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let mut client = Client::connect(&url, NoTls).unwrap();
+        client
+            .batch_execute(
+                "DELETE FROM companies WHERE cnpj = '11222333000181';
+                INSERT INTO companies (cnpj, situacao_cadastral, codigo_porte, situacao_especial, razao_social)
+                VALUES ('11222333000181', 7, 5, 'EM OBRAS', 'EXEMPLO LTDA')",
+            )
+            .unwrap();
+
+        let result = get_company(
+            "11222333000181",
+            &url,
+            &SuppressionList::default(),
+            &Lookups::new(),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(super::super::ApiError::DatabaseError(message))
+                if message.starts_with("column situacao_cadastral:")
+        ));
+    }
 }
@@ -1,14 +1,33 @@
 use super::{ApiConfig, Result, handlers};
+use crate::transform::{Lookups, SuppressionList};
 use actix_web::{App, HttpServer, middleware, web};
 
 /// API server
 pub struct ApiServer {
     config: ApiConfig,
+    suppression: SuppressionList,
+    lookups: Lookups,
 }
 
 impl ApiServer {
     pub fn new(config: ApiConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            suppression: SuppressionList::default(),
+            lookups: Lookups::default(),
+        }
+    }
+
+    /// Answer as if the companies of `suppression` did not exist
+    pub fn with_suppression(mut self, suppression: SuppressionList) -> Self {
+        self.suppression = suppression;
+        self
+    }
+
+    /// Describe codes the database does not, e.g. from `Lookups::from_cache`
+    pub fn with_lookups(mut self, lookups: Lookups) -> Self {
+        self.lookups = lookups;
+        self
     }
 
     /// Start the API server
@@ -21,10 +40,14 @@ impl ApiServer {
 
         let bind_addr = format!("{}:{}", self.config.host, self.config.port);
         let db_url = self.config.database_url.clone();
+        let suppression = web::Data::new(self.suppression.clone());
+        let lookups = web::Data::new(self.lookups.clone());
 
         HttpServer::new(move || {
             App::new()
                 .app_data(web::Data::new(db_url.clone()))
+                .app_data(suppression.clone())
+                .app_data(lookups.clone())
                 .wrap(middleware::Logger::default())
                 .wrap(middleware::Compress::default())
                 .service(
@@ -4,6 +4,8 @@ mod schema;
 pub use postgres::PostgresDatabase;
 pub use schema::create_schema;
 
+use crate::transform::SuppressionList;
+use std::collections::BTreeMap;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -31,6 +33,14 @@ pub trait Database {
     /// Drop all tables
     fn drop(&self) -> Result<()>;
 
+    /// Rebuild the company groups (one row per `cnpj_basico`) and their
+    /// branch counts from the companies table, returning the rows per table
+    fn aggregate(&self) -> Result<BTreeMap<String, u64>>;
+
+    /// Delete the companies and partners of a suppression list and redact its
+    /// legal representatives, returning the rows changed per table
+    fn suppress(&self, list: &SuppressionList) -> Result<BTreeMap<String, u64>>;
+
     /// Close database connection
     fn close(&self) -> Result<()>;
 }
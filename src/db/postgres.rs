@@ -1,5 +1,90 @@
 use super::{Database, DatabaseError, Result};
-use postgres::{Client, NoTls};
+use crate::transform::SuppressionList;
+use postgres::{Client, NoTls, Transaction};
+use std::collections::BTreeMap;
+
+/// SQL for a name column in upper case with single spaces, as suppression
+/// list names are
+fn normalized_name(column: &str) -> String {
+    format!(r"upper(regexp_replace(trim({}), '\s+', ' ', 'g'))", column)
+}
+
+/// Head office columns of `company_groups`, as in `companies`
+const MATRIZ_COLUMNS: &str = "razao_social, nome_fantasia, situacao_cadastral,
+    descricao_situacao_cadastral, codigo_natureza_juridica, natureza_juridica,
+    capital_social, codigo_porte, porte, cnae_fiscal, cnae_fiscal_descricao, uf,
+    codigo_municipio, municipio, regiao, opcao_pelo_simples, opcao_pelo_mei";
+
+/// Rebuild `company_groups` and `company_group_branches` from `companies`,
+/// returning the rows written per table
+fn rebuild_groups(transaction: &mut Transaction) -> Result<BTreeMap<String, u64>> {
+    transaction
+        .batch_execute("DELETE FROM company_group_branches; DELETE FROM company_groups;")
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+    let matriz: Vec<String> = MATRIZ_COLUMNS
+        .split(',')
+        .map(|column| format!("m.{}", column.trim()))
+        .collect();
+    let groups = transaction
+        .execute(
+            &format!(
+                "INSERT INTO company_groups (cnpj_basico, cnpj_matriz, {},
+                    estabelecimentos, filiais, data_inicio_atividade, cnpjs_filiais)
+                SELECT g.cnpj_basico, m.cnpj, {},
+                    g.estabelecimentos, g.filiais, g.data_inicio_atividade, g.cnpjs_filiais
+                FROM (
+                    SELECT left(cnpj, 8) AS cnpj_basico,
+                        count(*) AS estabelecimentos,
+                        count(*) FILTER (WHERE identificador_matriz_filial = 2) AS filiais,
+                        min(data_inicio_atividade) AS data_inicio_atividade,
+                        coalesce(
+                            array_agg(cnpj ORDER BY cnpj)
+                                FILTER (WHERE identificador_matriz_filial = 2),
+                            '{{}}'
+                        ) AS cnpjs_filiais
+                    FROM companies
+                    GROUP BY left(cnpj, 8)
+                ) g
+                LEFT JOIN companies m
+                    ON left(m.cnpj, 8) = g.cnpj_basico AND m.identificador_matriz_filial = 1",
+                MATRIZ_COLUMNS,
+                matriz.join(", ")
+            ),
+            &[],
+        )
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+    let branches = transaction
+        .execute(
+            "INSERT INTO company_group_branches (cnpj_basico, uf, regiao, situacao_cadastral, filiais)
+            SELECT left(cnpj, 8), uf, regiao, situacao_cadastral, count(*)
+            FROM companies
+            WHERE identificador_matriz_filial = 2
+            GROUP BY left(cnpj, 8), uf, regiao, situacao_cadastral",
+            &[],
+        )
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+    Ok(BTreeMap::from([
+        ("company_groups".to_string(), groups),
+        ("company_group_branches".to_string(), branches),
+    ]))
+}
+
+/// Columns added to `companies` since the first release, with their types;
+/// `create` adds them to tables created before them
+const ADDED_COMPANY_COLUMNS: [(&str, &str); 9] = [
+    ("cnae_secao", "VARCHAR(1)"),
+    ("cnae_secao_descricao", "TEXT"),
+    ("cnae_divisao", "INTEGER"),
+    ("cnae_divisao_descricao", "TEXT"),
+    ("cnae_grupo", "INTEGER"),
+    ("cnae_classe", "INTEGER"),
+    ("regiao", "VARCHAR(20)"),
+    ("cnae_grupo_descricao", "TEXT"),
+    ("cnae_classe_descricao", "TEXT"),
+];
 
 /// PostgreSQL database implementation
 pub struct PostgresDatabase {
@@ -59,6 +144,14 @@ impl Database for PostgresDatabase {
                 data_inicio_atividade DATE,
                 cnae_fiscal INTEGER,
                 cnae_fiscal_descricao TEXT,
+                cnae_secao VARCHAR(1),
+                cnae_secao_descricao TEXT,
+                cnae_divisao INTEGER,
+                cnae_divisao_descricao TEXT,
+                cnae_grupo INTEGER,
+                cnae_grupo_descricao TEXT,
+                cnae_classe INTEGER,
+                cnae_classe_descricao TEXT,
                 descricao_tipo_de_logradouro VARCHAR(100),
                 logradouro TEXT,
                 numero VARCHAR(20),
@@ -69,6 +162,7 @@ impl Database for PostgresDatabase {
                 codigo_municipio INTEGER,
                 codigo_municipio_ibge INTEGER,
                 municipio VARCHAR(100),
+                regiao VARCHAR(20),
                 ddd_telefone_1 VARCHAR(20),
                 ddd_telefone_2 VARCHAR(20),
                 ddd_fax VARCHAR(20),
@@ -94,6 +188,18 @@ impl Database for PostgresDatabase {
             )
             .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
 
+        for (column, kind) in ADDED_COMPANY_COLUMNS {
+            client
+                .execute(
+                    &format!(
+                        "ALTER TABLE companies ADD COLUMN IF NOT EXISTS {} {}",
+                        column, kind
+                    ),
+                    &[],
+                )
+                .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        }
+
         // Create partners table
         client
             .execute(
@@ -119,6 +225,46 @@ impl Database for PostgresDatabase {
             )
             .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
 
+        // Create company group tables
+        for table_sql in [
+            "CREATE TABLE IF NOT EXISTS company_groups (
+                cnpj_basico VARCHAR(8) PRIMARY KEY,
+                cnpj_matriz VARCHAR(14),
+                razao_social TEXT,
+                nome_fantasia TEXT,
+                situacao_cadastral INTEGER,
+                descricao_situacao_cadastral VARCHAR(50),
+                codigo_natureza_juridica INTEGER,
+                natureza_juridica TEXT,
+                capital_social NUMERIC(20, 2),
+                codigo_porte INTEGER,
+                porte VARCHAR(50),
+                cnae_fiscal INTEGER,
+                cnae_fiscal_descricao TEXT,
+                uf VARCHAR(2),
+                codigo_municipio INTEGER,
+                municipio VARCHAR(100),
+                regiao VARCHAR(20),
+                opcao_pelo_simples BOOLEAN,
+                opcao_pelo_mei BOOLEAN,
+                estabelecimentos INTEGER NOT NULL,
+                filiais INTEGER NOT NULL,
+                data_inicio_atividade DATE,
+                cnpjs_filiais VARCHAR(14)[] NOT NULL
+            )",
+            "CREATE TABLE IF NOT EXISTS company_group_branches (
+                cnpj_basico VARCHAR(8) NOT NULL,
+                uf VARCHAR(2),
+                regiao VARCHAR(20),
+                situacao_cadastral INTEGER,
+                filiais INTEGER NOT NULL
+            )",
+        ] {
+            client
+                .execute(table_sql, &[])
+                .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        }
+
         // Create indexes
         let indexes = vec![
             "CREATE INDEX IF NOT EXISTS idx_companies_cnpj ON companies(cnpj)",
@@ -127,8 +273,13 @@ impl Database for PostgresDatabase {
             "CREATE INDEX IF NOT EXISTS idx_companies_uf ON companies(uf)",
             "CREATE INDEX IF NOT EXISTS idx_companies_municipio ON companies(codigo_municipio)",
             "CREATE INDEX IF NOT EXISTS idx_companies_cnae ON companies(cnae_fiscal)",
+            "CREATE INDEX IF NOT EXISTS idx_companies_cnae_secao ON companies(cnae_secao)",
+            "CREATE INDEX IF NOT EXISTS idx_companies_cnae_divisao ON companies(cnae_divisao)",
             "CREATE INDEX IF NOT EXISTS idx_partners_cnpj ON partners(cnpj)",
             "CREATE INDEX IF NOT EXISTS idx_partners_nome ON partners(nome_socio)",
+            "CREATE INDEX IF NOT EXISTS idx_company_groups_razao_social ON company_groups(razao_social)",
+            "CREATE INDEX IF NOT EXISTS idx_company_group_branches_cnpj_basico ON company_group_branches(cnpj_basico)",
+            "CREATE INDEX IF NOT EXISTS idx_company_group_branches_regiao ON company_group_branches(regiao)",
         ];
 
         for idx_sql in indexes {
@@ -152,6 +303,12 @@ impl Database for PostgresDatabase {
             .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
 
         // Drop tables
+        for table in ["company_group_branches", "company_groups"] {
+            client
+                .execute(&format!("DROP TABLE IF EXISTS {} CASCADE", table), &[])
+                .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        }
+
         client
             .execute("DROP TABLE IF EXISTS partners CASCADE", &[])
             .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
@@ -164,6 +321,124 @@ impl Database for PostgresDatabase {
         Ok(())
     }
 
+    fn aggregate(&self) -> Result<BTreeMap<String, u64>> {
+        tracing::info!("Aggregating company groups in schema: {}", self.schema);
+
+        let mut client = self.get_client()?;
+        let mut transaction = client
+            .transaction()
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        transaction
+            .execute(&format!("SET LOCAL search_path TO {}", self.schema), &[])
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        let rows = rebuild_groups(&mut transaction)?;
+        transaction
+            .commit()
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        tracing::info!(
+            "Wrote {} company groups",
+            rows.get("company_groups").copied().unwrap_or_default()
+        );
+        Ok(rows)
+    }
+
+    fn suppress(&self, list: &SuppressionList) -> Result<BTreeMap<String, u64>> {
+        tracing::info!(
+            "Suppressing {} entries in schema: {}",
+            list.len(),
+            self.schema
+        );
+
+        let cnpjs: Vec<&str> = list.cnpjs().collect();
+        let basicos: Vec<&str> = list.basicos().collect();
+        let names: Vec<&str> = list.names().collect();
+        // MEI names end with the full CPF; partner CPFs keep the middle digits
+        let cpf_patterns: Vec<String> = list
+            .cpfs()
+            .map(|c| {
+                format!(
+                    r"(^|\D){}\.?{}\.?{}-?{}(\D|$)",
+                    &c[..3],
+                    &c[3..6],
+                    &c[6..9],
+                    &c[9..]
+                )
+            })
+            .collect();
+        let cpf_digits: Vec<&str> = list.cpfs().map(|c| &c[3..9]).collect();
+
+        let mut client = self.get_client()?;
+        let mut transaction = client
+            .transaction()
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        transaction
+            .execute(&format!("SET LOCAL search_path TO {}", self.schema), &[])
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        let companies = transaction
+            .execute(
+                "DELETE FROM companies
+                WHERE cnpj = ANY($1) OR left(cnpj, 8) = ANY($2) OR razao_social ~ ANY($3)",
+                &[&cnpjs, &basicos, &cpf_patterns],
+            )
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        let partners = transaction
+            .execute(
+                &format!(
+                    "DELETE FROM partners
+                    WHERE left(cnpj, 8) = ANY($2)
+                        OR cnpj_cpf_socio = ANY($1)
+                        OR (length(cnpj_cpf_socio) = 14 AND left(cnpj_cpf_socio, 8) = ANY($2))
+                        OR (length(cnpj_cpf_socio) = 11 AND substr(cnpj_cpf_socio, 4, 6) = ANY($3))
+                        OR {} = ANY($4)",
+                    normalized_name("nome_socio")
+                ),
+                &[&cnpjs, &basicos, &cpf_digits, &names],
+            )
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        let representatives = transaction
+            .execute(
+                &format!(
+                    "UPDATE partners
+                    SET cpf_representante_legal = NULL, nome_representante_legal = NULL
+                    WHERE (length(cpf_representante_legal) = 11
+                            AND substr(cpf_representante_legal, 4, 6) = ANY($1))
+                        OR {} = ANY($2)",
+                    normalized_name("nome_representante_legal")
+                ),
+                &[&cpf_digits, &names],
+            )
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        // The groups copy company data, so they are rebuilt without the
+        // suppressed rows; schemas created before the groups have none
+        let groups = transaction
+            .query_one("SELECT to_regclass('company_groups') IS NOT NULL", &[])
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        if groups.get::<_, bool>(0) {
+            rebuild_groups(&mut transaction)?;
+        }
+
+        transaction
+            .commit()
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        tracing::info!(
+            "Deleted {} companies and {} partners, redacted {} legal representatives",
+            companies,
+            partners,
+            representatives
+        );
+        Ok(BTreeMap::from([
+            ("companies".to_string(), companies),
+            ("partners".to_string(), partners),
+            ("representatives".to_string(), representatives),
+        ]))
+    }
+
     fn close(&self) -> Result<()> {
         // Connection cleanup happens automatically
         Ok(())
@@ -182,4 +457,16 @@ mod tests {
         );
         assert_eq!(db.schema, "public");
     }
+
+    #[test]
+    fn test_added_columns_match_schema() {
+        let schema = crate::db::create_schema();
+        for (column, kind) in ADDED_COMPANY_COLUMNS {
+            assert!(schema.contains(&format!("        {} {},", column, kind)));
+            assert!(schema.contains(&format!(
+                "ALTER TABLE companies ADD COLUMN IF NOT EXISTS {} {};",
+                column, kind
+            )));
+        }
+    }
 }
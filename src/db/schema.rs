@@ -18,6 +18,14 @@ pub fn create_schema() -> &'static str {
         data_inicio_atividade DATE,
         cnae_fiscal INTEGER,
         cnae_fiscal_descricao TEXT,
+        cnae_secao VARCHAR(1),
+        cnae_secao_descricao TEXT,
+        cnae_divisao INTEGER,
+        cnae_divisao_descricao TEXT,
+        cnae_grupo INTEGER,
+        cnae_grupo_descricao TEXT,
+        cnae_classe INTEGER,
+        cnae_classe_descricao TEXT,
         descricao_tipo_de_logradouro VARCHAR(100),
         logradouro TEXT,
         numero VARCHAR(20),
@@ -28,6 +36,7 @@ pub fn create_schema() -> &'static str {
         codigo_municipio INTEGER,
         codigo_municipio_ibge INTEGER,
         municipio VARCHAR(100),
+        regiao VARCHAR(20),
         ddd_telefone_1 VARCHAR(20),
         ddd_telefone_2 VARCHAR(20),
         ddd_fax VARCHAR(20),
@@ -50,6 +59,17 @@ pub fn create_schema() -> &'static str {
         ente_federativo_responsavel TEXT
     );
 
+    -- Columns added since the first release, for tables created before them
+    ALTER TABLE companies ADD COLUMN IF NOT EXISTS cnae_secao VARCHAR(1);
+    ALTER TABLE companies ADD COLUMN IF NOT EXISTS cnae_secao_descricao TEXT;
+    ALTER TABLE companies ADD COLUMN IF NOT EXISTS cnae_divisao INTEGER;
+    ALTER TABLE companies ADD COLUMN IF NOT EXISTS cnae_divisao_descricao TEXT;
+    ALTER TABLE companies ADD COLUMN IF NOT EXISTS cnae_grupo INTEGER;
+    ALTER TABLE companies ADD COLUMN IF NOT EXISTS cnae_classe INTEGER;
+    ALTER TABLE companies ADD COLUMN IF NOT EXISTS regiao VARCHAR(20);
+    ALTER TABLE companies ADD COLUMN IF NOT EXISTS cnae_grupo_descricao TEXT;
+    ALTER TABLE companies ADD COLUMN IF NOT EXISTS cnae_classe_descricao TEXT;
+
     -- Partners table (Sócios)
     CREATE TABLE IF NOT EXISTS partners (
         id SERIAL PRIMARY KEY,
@@ -71,6 +91,43 @@ pub fn create_schema() -> &'static str {
         FOREIGN KEY (cnpj) REFERENCES companies(cnpj)
     );
 
+    -- Company groups: one row per cnpj_basico with its head office
+    -- (rebuilt from companies by `rfb db aggregate`)
+    CREATE TABLE IF NOT EXISTS company_groups (
+        cnpj_basico VARCHAR(8) PRIMARY KEY,
+        cnpj_matriz VARCHAR(14),
+        razao_social TEXT,
+        nome_fantasia TEXT,
+        situacao_cadastral INTEGER,
+        descricao_situacao_cadastral VARCHAR(50),
+        codigo_natureza_juridica INTEGER,
+        natureza_juridica TEXT,
+        capital_social NUMERIC(20, 2),
+        codigo_porte INTEGER,
+        porte VARCHAR(50),
+        cnae_fiscal INTEGER,
+        cnae_fiscal_descricao TEXT,
+        uf VARCHAR(2),
+        codigo_municipio INTEGER,
+        municipio VARCHAR(100),
+        regiao VARCHAR(20),
+        opcao_pelo_simples BOOLEAN,
+        opcao_pelo_mei BOOLEAN,
+        estabelecimentos INTEGER NOT NULL,
+        filiais INTEGER NOT NULL,
+        data_inicio_atividade DATE,
+        cnpjs_filiais VARCHAR(14)[] NOT NULL
+    );
+
+    -- Branches of each company group by UF, region and situação
+    CREATE TABLE IF NOT EXISTS company_group_branches (
+        cnpj_basico VARCHAR(8) NOT NULL,
+        uf VARCHAR(2),
+        regiao VARCHAR(20),
+        situacao_cadastral INTEGER,
+        filiais INTEGER NOT NULL
+    );
+
     -- Create indexes
     CREATE INDEX IF NOT EXISTS idx_companies_cnpj ON companies(cnpj);
     CREATE INDEX IF NOT EXISTS idx_companies_razao_social ON companies(razao_social);
@@ -78,7 +135,12 @@ pub fn create_schema() -> &'static str {
     CREATE INDEX IF NOT EXISTS idx_companies_uf ON companies(uf);
     CREATE INDEX IF NOT EXISTS idx_companies_municipio ON companies(codigo_municipio);
     CREATE INDEX IF NOT EXISTS idx_companies_cnae ON companies(cnae_fiscal);
+    CREATE INDEX IF NOT EXISTS idx_companies_cnae_secao ON companies(cnae_secao);
+    CREATE INDEX IF NOT EXISTS idx_companies_cnae_divisao ON companies(cnae_divisao);
     CREATE INDEX IF NOT EXISTS idx_partners_cnpj ON partners(cnpj);
     CREATE INDEX IF NOT EXISTS idx_partners_nome ON partners(nome_socio);
+    CREATE INDEX IF NOT EXISTS idx_company_groups_razao_social ON company_groups(razao_social);
+    CREATE INDEX IF NOT EXISTS idx_company_group_branches_cnpj_basico ON company_group_branches(cnpj_basico);
+    CREATE INDEX IF NOT EXISTS idx_company_group_branches_regiao ON company_group_branches(regiao);
     "#
 }
@@ -28,8 +28,17 @@ impl Downloader {
         Self { config, client }
     }
 
+    /// URLs of every file, in the configured directory
+    fn file_urls(&self) -> Vec<String> {
+        match &self.config.base_url {
+            Some(base_url) => FederalRevenue::file_urls_at(base_url),
+            None => FederalRevenue::file_urls(),
+        }
+    }
+
     /// Download all required files from Federal Revenue
     pub async fn download(&self) -> Result<()> {
+        self.config.validate()?;
         tracing::info!("Starting download process");
         tracing::info!("Data directory: {}", self.config.data_dir);
 
@@ -37,7 +46,7 @@ impl Downloader {
         create_dir_all(&self.config.data_dir)?;
 
         // Get list of files to download
-        let urls = FederalRevenue::file_urls();
+        let urls = self.file_urls();
         let mut to_download = Vec::new();
 
         for url in urls {
@@ -91,7 +100,7 @@ impl Downloader {
 
     /// List URLs of all files that need to be downloaded
     pub async fn list_urls(&self) -> Result<Vec<String>> {
-        let mut urls = FederalRevenue::file_urls();
+        let mut urls = self.file_urls();
 
         if self.config.skip_existing {
             urls.retain(|url| {
@@ -20,7 +20,15 @@ impl FederalRevenue {
 
     /// Get list of all file URLs to download
     pub fn file_urls() -> Vec<String> {
-        let base = Self::get_base_url_with_date();
+        Self::file_urls_at(&Self::get_base_url_with_date())
+    }
+
+    /// Get list of all file URLs in another directory, e.g. a mirror
+    pub fn file_urls_at(base_url: &str) -> Vec<String> {
+        let base = match base_url.ends_with('/') {
+            true => base_url.to_string(),
+            false => format!("{}/", base_url),
+        };
         let mut urls = Vec::new();
 
         // Companies files (Estabelecimentos)
@@ -87,6 +95,16 @@ mod tests {
         assert_eq!(year_month.chars().nth(4), Some('-'));
     }
 
+    #[test]
+    fn test_file_urls_at() {
+        let urls = FederalRevenue::file_urls_at("http://127.0.0.1:8000/2025-10");
+        assert_eq!(urls.len(), 37);
+        assert_eq!(
+            urls[0],
+            "http://127.0.0.1:8000/2025-10/Estabelecimentos0.zip"
+        );
+    }
+
     #[test]
     fn test_filename_from_url() {
         let url = "https://arquivos.receitafederal.gov.br/dados/cnpj/dados_abertos_cnpj/2025-11/Estabelecimentos0.zip";
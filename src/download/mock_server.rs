@@ -0,0 +1,511 @@
+use super::{DownloadError, Result};
+use actix_web::body::SizedStream;
+use actix_web::dev::ServerHandle;
+use actix_web::http::{Method, StatusCode, header};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream, StreamExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::task::JoinHandle;
+
+/// Bytes read from disk and sent at a time
+const SEND_CHUNK: u64 = 64 * 1024;
+
+/// Faults injected into the GET requests of files
+///
+/// Each rate is the probability that a request gets the fault; they are
+/// drawn in order (error, drop, truncate) from a seeded generator, and a
+/// request gets at most one. Directory listings and HEAD requests are always
+/// answered, so a client can learn the file sizes before it downloads.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Faults {
+    /// Requests answered with `503 Service Unavailable`
+    pub error_rate: f64,
+    /// Requests whose connection is dropped halfway through the body
+    pub drop_rate: f64,
+    /// Requests answered in full, but with only the first half of the bytes
+    /// asked for (and a `Content-Length` to match)
+    pub truncate_rate: f64,
+    /// Bytes per second each response is sent at; `None` is unlimited
+    pub bandwidth: Option<u64>,
+    /// Faults per file, after which its requests are answered normally, so
+    /// that a client retrying enough always succeeds; `None` is unlimited
+    pub max_per_file: Option<u32>,
+    pub seed: u64,
+}
+
+impl Faults {
+    fn validate(&self) -> Result<()> {
+        let rates = [
+            ("error_rate", self.error_rate),
+            ("drop_rate", self.drop_rate),
+            ("truncate_rate", self.truncate_rate),
+        ];
+        for (name, rate) in rates {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(DownloadError::InvalidConfig(format!(
+                    "{} must be between 0 and 1, got {}",
+                    name, rate
+                )));
+            }
+        }
+        if self.bandwidth == Some(0) {
+            return Err(DownloadError::InvalidConfig(
+                "bandwidth must be at least 1 byte per second".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MockServerConfig {
+    /// Directory served, e.g. the output of `rfb fixtures generate`
+    pub root: String,
+    pub host: String,
+    /// Port to bind to; 0 picks a free one
+    pub port: u16,
+    pub faults: Faults,
+}
+
+impl MockServerConfig {
+    /// Validate the configuration
+    pub fn validate(&self) -> Result<()> {
+        if !Path::new(&self.root).is_dir() {
+            return Err(DownloadError::InvalidConfig(format!(
+                "{} is not a directory",
+                self.root
+            )));
+        }
+        self.faults.validate()
+    }
+}
+
+impl Default for MockServerConfig {
+    fn default() -> Self {
+        Self {
+            root: "fixtures".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            faults: Faults::default(),
+        }
+    }
+}
+
+/// Requests a `MockServer` answered, and the faults it injected
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MockStats {
+    pub requests: u64,
+    pub errors: u64,
+    pub drops: u64,
+    pub truncations: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    Error,
+    Drop,
+    Truncate,
+}
+
+struct State {
+    root: PathBuf,
+    faults: Faults,
+    rng: Mutex<StdRng>,
+    /// Faults injected per file, for `Faults::max_per_file`
+    injected: Mutex<HashMap<PathBuf, u32>>,
+    stats: Arc<Mutex<MockStats>>,
+}
+
+impl State {
+    /// The fault of a GET request of `path`, if it gets one
+    fn fault(&self, path: &Path) -> Option<Fault> {
+        let faults = &self.faults;
+        let mut injected = self.injected.lock().unwrap();
+        let count = injected.entry(path.to_path_buf()).or_default();
+        if faults.max_per_file.is_some_and(|max| *count >= max) {
+            return None;
+        }
+
+        let mut rng = self.rng.lock().unwrap();
+        let fault = [
+            (Fault::Error, faults.error_rate),
+            (Fault::Drop, faults.drop_rate),
+            (Fault::Truncate, faults.truncate_rate),
+        ]
+        .into_iter()
+        .find(|(_, rate)| rng.random_bool(*rate))
+        .map(|(fault, _)| fault)?;
+
+        *count += 1;
+        let mut stats = self.stats.lock().unwrap();
+        match fault {
+            Fault::Error => stats.errors += 1,
+            Fault::Drop => stats.drops += 1,
+            Fault::Truncate => stats.truncations += 1,
+        }
+        Some(fault)
+    }
+}
+
+/// A local stand-in for the Receita Federal file server
+///
+/// Serves a directory over HTTP the way `arquivos.receitafederal.gov.br`
+/// does: HTML directory listings, `Range` requests, `ETag` and
+/// `Last-Modified` validators, plus the faults of `Faults`. Point
+/// `DownloadConfig::base_url` at `url()` to test downloads offline.
+pub struct MockServer {
+    url: String,
+    handle: ServerHandle,
+    task: JoinHandle<std::io::Result<()>>,
+    stats: Arc<Mutex<MockStats>>,
+}
+
+impl MockServer {
+    /// Bind and start serving in the background; must be called from
+    /// within a Tokio runtime
+    pub fn start(config: MockServerConfig) -> Result<Self> {
+        config.validate()?;
+        let stats = Arc::new(Mutex::new(MockStats::default()));
+        let state = web::Data::new(State {
+            root: PathBuf::from(&config.root),
+            rng: Mutex::new(StdRng::seed_from_u64(config.faults.seed)),
+            faults: config.faults,
+            injected: Mutex::new(HashMap::new()),
+            stats: stats.clone(),
+        });
+
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(state.clone())
+                .default_service(web::route().to(serve))
+        })
+        .workers(2)
+        .disable_signals()
+        .bind((config.host.as_str(), config.port))?;
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        let task = tokio::spawn(server);
+
+        let url = format!("http://{}/", addr);
+        tracing::info!("Mock server serving {} at {}", config.root, url);
+        Ok(Self {
+            url,
+            handle,
+            task,
+            stats,
+        })
+    }
+
+    /// Base URL of the served directory, with a trailing slash
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn stats(&self) -> MockStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Stop serving, dropping the open connections
+    pub async fn stop(self) -> Result<()> {
+        self.handle.stop(false).await;
+        self.wait().await
+    }
+
+    /// Serve until the server is stopped
+    pub async fn wait(self) -> Result<()> {
+        self.task
+            .await
+            .map_err(|e| DownloadError::IoError(std::io::Error::other(e)))??;
+        Ok(())
+    }
+}
+
+async fn serve(req: HttpRequest, state: web::Data<State>) -> HttpResponse {
+    state.stats.lock().unwrap().requests += 1;
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return HttpResponse::MethodNotAllowed()
+            .insert_header((header::ALLOW, "GET, HEAD"))
+            .finish();
+    }
+    let Some(path) = resolve(&state.root, req.path()) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    if path.is_dir() {
+        if !req.path().ends_with('/') {
+            return HttpResponse::MovedPermanently()
+                .insert_header((header::LOCATION, format!("{}/", req.path())))
+                .finish();
+        }
+        return match listing(&path, req.path()) {
+            Ok(html) => HttpResponse::Ok()
+                .content_type("text/html;charset=UTF-8")
+                .body(html),
+            Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+        };
+    }
+    match file(&req, &state, &path).await {
+        Ok(response) => response,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// The file or directory of a request path inside `root`, refusing paths
+/// that would leave it
+fn resolve(root: &Path, request_path: &str) -> Option<PathBuf> {
+    let decoded = percent_encoding::percent_decode_str(request_path)
+        .decode_utf8()
+        .ok()?;
+    let mut path = root.to_path_buf();
+    for segment in decoded.split('/').filter(|segment| !segment.is_empty()) {
+        if segment == "." || segment == ".." || segment.contains('\\') {
+            return None;
+        }
+        path.push(segment);
+    }
+    path.exists().then_some(path)
+}
+
+/// An Apache-style index of a directory, subdirectories first
+fn listing(dir: &Path, request_path: &str) -> std::io::Result<String> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        entries.push((!metadata.is_dir(), name, metadata));
+    }
+    entries.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+    let mut html = format!(
+        "<!DOCTYPE HTML PUBLIC \"-//W3C//DTD HTML 3.2 Final//EN\">\n<html>\n<head>\n<title>Index of {0}</title>\n</head>\n<body>\n<h1>Index of {0}</h1>\n<table>\n<tr><th>Name</th><th>Last modified</th><th>Size</th></tr>\n",
+        request_path
+    );
+    if request_path != "/" {
+        html.push_str("<tr><td><a href=\"../\">Parent Directory</a></td><td>&nbsp;</td><td align=\"right\">-</td></tr>\n");
+    }
+    for (is_file, name, metadata) in entries {
+        let modified = metadata
+            .modified()
+            .map(|time| {
+                DateTime::<Utc>::from(time)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        let (href, size) = match is_file {
+            true => (name.clone(), metadata.len().to_string()),
+            false => (format!("{}/", name), "-".to_string()),
+        };
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}\">{}</a></td><td align=\"right\">{}</td><td align=\"right\">{}</td></tr>\n",
+            href, href, modified, size
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    Ok(html)
+}
+
+/// Bytes of a file a request asks for
+#[derive(Debug, PartialEq, Eq)]
+enum Requested {
+    Full,
+    /// `start..=end`
+    Range(u64, u64),
+    Unsatisfiable,
+}
+
+/// The bytes a `Range` header asks for, out of `len`
+///
+/// Only single `bytes` ranges are supported; anything else is answered
+/// with the full file, as the HTTP specification allows.
+fn requested(range: Option<&str>, len: u64) -> Requested {
+    let Some(spec) = range.and_then(|range| range.trim().strip_prefix("bytes=")) else {
+        return Requested::Full;
+    };
+    let Some((start, end)) = spec.split_once('-').filter(|_| !spec.contains(',')) else {
+        return Requested::Full;
+    };
+    let parse = |value: &str| value.trim().parse::<u64>().ok();
+    let last = len.saturating_sub(1);
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => match parse(suffix) {
+            Some(0) => return Requested::Unsatisfiable,
+            Some(suffix) => (len.saturating_sub(suffix), last),
+            None => return Requested::Full,
+        },
+        (start, "") => match parse(start) {
+            Some(start) => (start, last),
+            None => return Requested::Full,
+        },
+        (start, end) => match (parse(start), parse(end)) {
+            (Some(start), Some(end)) if start <= end => (start, end.min(last)),
+            _ => return Requested::Full,
+        },
+    };
+    if len == 0 || start >= len {
+        return Requested::Unsatisfiable;
+    }
+    Requested::Range(start, end)
+}
+
+async fn file(req: &HttpRequest, state: &State, path: &Path) -> std::io::Result<HttpResponse> {
+    let metadata = tokio::fs::metadata(path).await?;
+    let len = metadata.len();
+    let modified = metadata.modified()?;
+    let seconds = modified
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let etag = format!("\"{:x}-{:x}\"", len, seconds);
+    let last_modified = DateTime::<Utc>::from(modified)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    let header = |name: header::HeaderName| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+
+    let mut response = HttpResponse::build(StatusCode::OK);
+    response
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::ETAG, etag.as_str()))
+        .insert_header((header::LAST_MODIFIED, last_modified))
+        .content_type("application/zip");
+    if header(header::IF_NONE_MATCH)
+        .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag))
+    {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).finish());
+    }
+
+    // A range of an older version of the file is not what the client wants
+    let range = match header(header::IF_RANGE) {
+        Some(tag) if tag.trim() != etag => None,
+        _ => header(header::RANGE),
+    };
+    let (start, end, partial) = match requested(range, len) {
+        Requested::Full => (0, len, false),
+        Requested::Range(start, end) => (start, end + 1, true),
+        Requested::Unsatisfiable => {
+            return Ok(response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .insert_header((header::CONTENT_RANGE, format!("bytes */{}", len)))
+                .finish());
+        }
+    };
+    let content_range = |end: u64| format!("bytes {}-{}/{}", start, end - 1, len);
+    if partial {
+        response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .insert_header((header::CONTENT_RANGE, content_range(end)));
+    }
+
+    let size = end - start;
+    if req.method() == Method::HEAD {
+        return Ok(response.body(SizedStream::new(
+            size,
+            stream::empty::<std::io::Result<Bytes>>(),
+        )));
+    }
+    let bandwidth = state.faults.bandwidth;
+    let body = match state.fault(path) {
+        None => body(path, start, size, bandwidth).await?.boxed_local(),
+        Some(Fault::Error) => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .insert_header((header::RETRY_AFTER, "1"))
+                .body("fault injected by the mock server"));
+        }
+        Some(Fault::Drop) => {
+            let dropped = stream::once(async {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionAborted,
+                    "connection dropped by the mock server",
+                ))
+            });
+            let sent = body(path, start, size / 2, bandwidth).await?;
+            return Ok(response.body(SizedStream::new(size, sent.chain(dropped).boxed_local())));
+        }
+        Some(Fault::Truncate) => {
+            let size = size / 2;
+            if partial && size > 0 {
+                response.insert_header((header::CONTENT_RANGE, content_range(start + size)));
+            }
+            return Ok(response.body(SizedStream::new(
+                size,
+                body(path, start, size, bandwidth).await?.boxed_local(),
+            )));
+        }
+    };
+    Ok(response.body(SizedStream::new(size, body)))
+}
+
+/// `size` bytes of a file from `start`, at most `bandwidth` bytes per second
+async fn body(
+    path: &Path,
+    start: u64,
+    size: u64,
+    bandwidth: Option<u64>,
+) -> std::io::Result<impl Stream<Item = std::io::Result<Bytes>> + use<>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    let chunk = bandwidth.map_or(SEND_CHUNK, |bandwidth| bandwidth.clamp(1, SEND_CHUNK));
+
+    Ok(stream::try_unfold(
+        (file, size),
+        move |(mut file, remaining)| async move {
+            if remaining == 0 {
+                return Ok(None);
+            }
+            let mut buf = vec![0; remaining.min(chunk) as usize];
+            file.read_exact(&mut buf).await?;
+            if let Some(bandwidth) = bandwidth {
+                tokio::time::sleep(Duration::from_secs_f64(buf.len() as f64 / bandwidth as f64))
+                    .await;
+            }
+            let remaining = remaining - buf.len() as u64;
+            Ok(Some((Bytes::from(buf), (file, remaining))))
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requested_range() {
+        let range = |header: &str| requested(Some(header), 100);
+        assert_eq!(requested(None, 100), Requested::Full);
+        assert_eq!(range("bytes=0-9"), Requested::Range(0, 9));
+        assert_eq!(range("bytes=90-"), Requested::Range(90, 99));
+        assert_eq!(range("bytes=-10"), Requested::Range(90, 99));
+        assert_eq!(range("bytes=50-500"), Requested::Range(50, 99));
+        assert_eq!(range("bytes=100-"), Requested::Unsatisfiable);
+        assert_eq!(range("bytes=0-1,5-6"), Requested::Full);
+        assert_eq!(range("items=0-1"), Requested::Full);
+        assert_eq!(requested(Some("bytes=0-"), 0), Requested::Unsatisfiable);
+    }
+
+    #[test]
+    fn test_resolve_stays_in_root() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Cnaes.zip"), b"zip").unwrap();
+        assert!(resolve(dir.path(), "/Cnaes.zip").is_some());
+        assert!(resolve(dir.path(), "/%43naes.zip").is_some());
+        assert!(resolve(dir.path(), "/../Cnaes.zip").is_none());
+        assert!(resolve(dir.path(), "/%2e%2e/etc/passwd").is_none());
+        assert!(resolve(dir.path(), "/Missing.zip").is_none());
+    }
+}
@@ -1,10 +1,12 @@
 mod check;
 mod downloader;
 mod federal_revenue;
+mod mock_server;
 
 pub use check::check_zip_integrity;
 pub use downloader::Downloader;
 pub use federal_revenue::FederalRevenue;
+pub use mock_server::{Faults, MockServer, MockServerConfig, MockStats};
 
 use thiserror::Error;
 
@@ -49,6 +51,9 @@ pub struct DownloadConfig {
     pub chunk_size: i64,
     pub skip_existing: bool,
     pub restart: bool,
+    /// Directory the ZIP files are published in, e.g. a mirror or a
+    /// `MockServer`; `None` uses the Receita Federal one of the current month
+    pub base_url: Option<String>,
 }
 
 impl DownloadConfig {
@@ -64,6 +69,14 @@ impl DownloadConfig {
                 "max_retries must be at least 1".to_string(),
             ));
         }
+        if let Some(base_url) = &self.base_url {
+            let scheme = url::Url::parse(base_url)
+                .map(|url| url.scheme().to_string())
+                .map_err(|_| DownloadError::InvalidUrl(base_url.clone()))?;
+            if scheme != "http" && scheme != "https" {
+                return Err(DownloadError::InvalidUrl(base_url.clone()));
+            }
+        }
         Ok(())
     }
 }
@@ -78,6 +91,7 @@ impl Default for DownloadConfig {
             chunk_size: DEFAULT_CHUNK_SIZE,
             skip_existing: false,
             restart: false,
+            base_url: None,
         }
     }
 }
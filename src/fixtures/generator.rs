@@ -0,0 +1,721 @@
+use super::pools::*;
+use super::{FixtureConfig, FixtureError, FixtureReport, Result};
+use crate::transform::{Cnpj, Encoding, LookupKind};
+use chrono::{Datelike, Duration, NaiveDate};
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+use std::fs::{File, create_dir_all};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// A date that does not exist, written where dirty data calls for one
+const INVALID_DATE: &str = "20230231";
+
+/// Missing dates as the Simples file writes them
+const NO_DATE: &str = "00000000";
+
+/// Representative of the partners that have none
+const NO_REPRESENTATIVE: &str = "***000000**";
+
+/// Prime spreading the company indices over the `cnpj_basico` space; it is
+/// coprime with `BASICOS`, so no two indices share a `cnpj_basico`
+const BASICO_STEP: u64 = 48_271;
+const BASICOS: u64 = 100_000_000;
+
+/// One CSV file inside its own ZIP archive, written as rows are generated
+struct Sink {
+    entry: String,
+    zip: ZipWriter<BufWriter<File>>,
+    rows: u64,
+}
+
+impl Sink {
+    fn create(
+        dir: &Path,
+        archive: &str,
+        entry: String,
+        options: SimpleFileOptions,
+    ) -> Result<Self> {
+        let mut zip = ZipWriter::new(BufWriter::new(File::create(dir.join(archive))?));
+        zip.start_file(entry.as_str(), options)?;
+        Ok(Self {
+            entry,
+            zip,
+            rows: 0,
+        })
+    }
+
+    /// Write a record as the Receita Federal does: every field quoted,
+    /// `;`-separated, in Latin-1
+    fn write(&mut self, fields: &[String]) -> Result<()> {
+        let record = fields
+            .iter()
+            .map(|field| format!("\"{}\"", field))
+            .collect::<Vec<_>>()
+            .join(";");
+        self.zip.write_all(&Encoding::Latin1.encode(&record))?;
+        self.zip.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(self, report: &mut FixtureReport) -> Result<()> {
+        report.rows.insert(self.entry, self.rows);
+        self.zip.finish()?.flush()?;
+        Ok(())
+    }
+}
+
+/// The data files being written, one per shard
+struct Sinks {
+    estabelecimentos: Vec<Sink>,
+    empresas: Vec<Sink>,
+    socios: Vec<Sink>,
+    simples: Sink,
+}
+
+impl Sinks {
+    fn finish(self, report: &mut FixtureReport) -> Result<()> {
+        let shards = self
+            .estabelecimentos
+            .into_iter()
+            .chain(self.empresas)
+            .chain(self.socios);
+        for sink in shards.chain([self.simples]) {
+            sink.finish(report)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a synthetic release in the layout of the Receita Federal dump
+///
+/// Every company is drawn from its own random generator, seeded from the
+/// configured seed and its index, so a seed always writes the same files
+/// whatever the number of shards. Dirty data comes from a second generator
+/// per company: the clean rows do not change with the dirty rates.
+pub struct Generator {
+    config: FixtureConfig,
+}
+
+impl Generator {
+    pub fn new(config: FixtureConfig) -> Self {
+        Self { config }
+    }
+
+    /// Write the Estabelecimentos, Empresas, Socios, Simples and lookup ZIPs
+    /// into the output directory
+    pub fn generate(&self) -> Result<FixtureReport> {
+        self.config.validate()?;
+        let dir = Path::new(&self.config.output_dir);
+        create_dir_all(dir)?;
+        let options = self.zip_options()?;
+        let date = self.date_token();
+
+        let shards = |archive: &str, token: &str| {
+            (0..self.config.shards)
+                .map(|i| {
+                    let entry = format!("K3241.K03200Y{}.{}.{}", i, date, token);
+                    Sink::create(dir, &format!("{}{}.zip", archive, i), entry, options)
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        let mut sinks = Sinks {
+            estabelecimentos: shards("Estabelecimentos", "ESTABELE")?,
+            empresas: shards("Empresas", "EMPRECSV")?,
+            socios: shards("Socios", "SOCIOCSV")?,
+            simples: Sink::create(
+                dir,
+                "Simples.zip",
+                format!("F.K03200$W.SIMPLES.CSV.{}", date),
+                options,
+            )?,
+        };
+
+        let mut report = FixtureReport::default();
+        for column in ["data_inicio_atividade", "data_entrada_sociedade"] {
+            report.invalid_dates.insert(column.to_string(), 0);
+        }
+        report.unknown_codes.insert("municipio".to_string(), 0);
+        for table in ["socios", "simples"] {
+            report.orphans.insert(table.to_string(), 0);
+        }
+
+        for index in 0..self.config.companies {
+            CompanyWriter::new(self, index).write(&mut sinks, &mut report)?;
+        }
+        sinks.finish(&mut report)?;
+
+        for kind in LookupKind::ALL {
+            let entry = format!("F.K03200$Z.{}.{}", date, kind.token());
+            let mut sink = Sink::create(dir, kind.archive(), entry, options)?;
+            for row in lookup_rows(kind) {
+                sink.write(&row)?;
+                sink.rows += 1;
+            }
+            sink.finish(&mut report)?;
+        }
+
+        tracing::info!(
+            "Generated {} companies in {} shards into {:?}",
+            self.config.companies,
+            self.config.shards,
+            dir
+        );
+        Ok(report)
+    }
+
+    /// Date segment of the file names, e.g. `D51011` for 2025-10-11
+    fn date_token(&self) -> String {
+        let release = self.config.release;
+        format!(
+            "D{}{:02}{:02}",
+            release.year().rem_euclid(10),
+            release.month(),
+            release.day()
+        )
+    }
+
+    /// Deflated entries dated on the release, so the archives do not depend
+    /// on when they were written
+    fn zip_options(&self) -> Result<SimpleFileOptions> {
+        let release = self.config.release;
+        let time = u16::try_from(release.year())
+            .ok()
+            .and_then(|year| {
+                zip::DateTime::from_date_and_time(
+                    year,
+                    release.month() as u8,
+                    release.day() as u8,
+                    0,
+                    0,
+                    0,
+                )
+                .ok()
+            })
+            .ok_or_else(|| {
+                FixtureError::InvalidConfig(format!(
+                    "release {} cannot be stored in a ZIP file",
+                    release
+                ))
+            })?;
+        Ok(SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .last_modified_time(time))
+    }
+
+    /// `cnpj_basico` of a company index; indices past `companies` are the
+    /// orphans, which have no establishment
+    fn basico(&self, index: usize) -> String {
+        let offset = self.config.seed % BASICOS;
+        format!("{:08}", (index as u64 * BASICO_STEP + offset) % BASICOS)
+    }
+
+    /// Random generator of one company, `stream` 0 for its rows and 1 for
+    /// their dirty data
+    fn rng(&self, index: usize, stream: u8) -> StdRng {
+        let mut seed = [0; 32];
+        seed[..8].copy_from_slice(&self.config.seed.to_le_bytes());
+        seed[8..16].copy_from_slice(&(index as u64).to_le_bytes());
+        seed[16] = stream;
+        StdRng::from_seed(seed)
+    }
+}
+
+/// Writes the rows of one company: its Empresas row, establishments,
+/// partners and Simples option, and the orphan rows drawn with it
+struct CompanyWriter<'a> {
+    config: &'a FixtureConfig,
+    shard: usize,
+    basico: String,
+    orphan: String,
+    rng: StdRng,
+    dirt: StdRng,
+}
+
+impl<'a> CompanyWriter<'a> {
+    fn new(generator: &'a Generator, index: usize) -> Self {
+        let config = &generator.config;
+        Self {
+            config,
+            shard: index % config.shards,
+            basico: generator.basico(index),
+            orphan: generator.basico(config.companies + index),
+            rng: generator.rng(index, 0),
+            dirt: generator.rng(index, 1),
+        }
+    }
+
+    fn write(mut self, sinks: &mut Sinks, report: &mut FixtureReport) -> Result<()> {
+        let release = self.config.release;
+        let first = NaiveDate::from_ymd_opt(1990, 1, 1).expect("valid date");
+        let start = date_between(&mut self.rng, first.min(release), release);
+
+        let &(natureza, _, _, individual) = NATUREZAS
+            .choose_weighted(&mut self.rng, |natureza| natureza.2)
+            .expect("positive weights");
+        let porte = match individual {
+            true => 1,
+            false => choose(&mut self.rng, &PORTES),
+        };
+
+        let empresa = self.empresa(natureza, individual, porte);
+        self.emit(&mut sinks.empresas[self.shard], &empresa, report)?;
+
+        let cnae = CNAES.choose(&mut self.rng).expect("not empty").0;
+        let municipality = *MUNICIPALITIES.choose(&mut self.rng).expect("not empty");
+        let establishments = self.rng.random_range(1..=self.config.max_establishments);
+        for ordem in 1..=establishments {
+            let mut fields = self.estabelecimento(ordem, start, cnae, municipality);
+            let rates = self.config.dirty;
+            if self.dirt.random_bool(rates.invalid_cnpjs) {
+                let dv: u32 = fields[2].parse().expect("numeric check digits");
+                fields[2] = format!("{:02}", (dv + self.dirt.random_range(1..100)) % 100);
+                report.invalid_cnpjs += 1;
+            } else if self.dirt.random_bool(rates.invalid_dates) {
+                fields[10] = INVALID_DATE.to_string();
+                increment(&mut report.invalid_dates, "data_inicio_atividade");
+            } else if self.dirt.random_bool(rates.unknown_codes) {
+                fields[20] = format!("{:04}", UNKNOWN_MUNICIPALITY);
+                increment(&mut report.unknown_codes, "municipio");
+            }
+            self.emit(&mut sinks.estabelecimentos[self.shard], &fields, report)?;
+        }
+
+        let partners = match individual || self.config.max_partners == 0 {
+            true => 0,
+            false => self.rng.random_range(1..=self.config.max_partners),
+        };
+        for _ in 0..partners {
+            let mut fields = self.socio(&self.basico.clone(), start);
+            if self.dirt.random_bool(self.config.dirty.invalid_dates) {
+                fields[5] = INVALID_DATE.to_string();
+                increment(&mut report.invalid_dates, "data_entrada_sociedade");
+            }
+            self.emit(&mut sinks.socios[self.shard], &fields, report)?;
+        }
+
+        if let Some(fields) = self.simples(&self.basico.clone(), start, porte, individual) {
+            self.emit(&mut sinks.simples, &fields, report)?;
+        }
+
+        if self.dirt.random_bool(self.config.dirty.orphans) {
+            let fields = self.socio(&self.orphan.clone(), start);
+            self.emit(&mut sinks.socios[self.shard], &fields, report)?;
+            increment(&mut report.orphans, "socios");
+        }
+        if self.dirt.random_bool(self.config.dirty.orphans) {
+            let fields = self.simples(&self.orphan.clone(), start, 1, false);
+            if let Some(fields) = fields {
+                self.emit(&mut sinks.simples, &fields, report)?;
+                increment(&mut report.orphans, "simples");
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a row, followed by a copy missing its last field at the
+    /// malformed lines rate
+    fn emit(
+        &mut self,
+        sink: &mut Sink,
+        fields: &[String],
+        report: &mut FixtureReport,
+    ) -> Result<()> {
+        sink.write(fields)?;
+        sink.rows += 1;
+        if self.dirt.random_bool(self.config.dirty.malformed_lines) {
+            sink.write(&fields[..fields.len() - 1])?;
+            report.malformed_lines += 1;
+        }
+        Ok(())
+    }
+
+    fn empresa(&mut self, natureza: u32, individual: bool, porte: u32) -> Vec<String> {
+        let rng = &mut self.rng;
+        let (razao_social, qualificacao, capital) = match (individual, natureza) {
+            // The owner's CPF follows their name, as in the published files
+            (true, _) => (
+                format!("{} {}", person_name(rng), digits(rng, 11)),
+                50,
+                rng.random_range(1..=50) * 1_000,
+            ),
+            (false, 3999) => (company_name(rng, natureza), 16, 0),
+            (false, _) => (
+                company_name(rng, natureza),
+                49,
+                rng.random_range(1..=5_000) * 1_000,
+            ),
+        };
+        vec![
+            self.basico.clone(),
+            razao_social,
+            format!("{:04}", natureza),
+            format!("{:02}", qualificacao),
+            format!("{},00", capital),
+            format!("{:02}", porte),
+            String::new(),
+        ]
+    }
+
+    fn estabelecimento(
+        &mut self,
+        ordem: usize,
+        start: NaiveDate,
+        cnae: u32,
+        matriz: (u32, &str, &str, &str, &str),
+    ) -> Vec<String> {
+        let release = self.config.release;
+        let rng = &mut self.rng;
+        let (inicio, cnae, (tom, _, uf, ddd, cep)) = match ordem {
+            1 => (start, cnae, matriz),
+            _ => (
+                date_between(rng, start, release),
+                match rng.random_bool(0.8) {
+                    true => cnae,
+                    false => CNAES.choose(rng).expect("not empty").0,
+                },
+                *MUNICIPALITIES.choose(rng).expect("not empty"),
+            ),
+        };
+        let ordem = format!("{:04}", ordem);
+        let cnpj =
+            Cnpj::from_parts(&self.basico, &ordem).expect("8-digit basico and 4-digit ordem");
+        let &(situacao, _, motivo) = SITUACOES
+            .choose_weighted(rng, |situacao| situacao.1)
+            .expect("positive weights");
+
+        let nome_fantasia = match rng.random_bool(0.6) {
+            true => format!(
+                "{} {}",
+                BUSINESS_NAMES.choose(rng).expect("not empty"),
+                BUSINESS_WORDS.choose(rng).expect("not empty")
+            ),
+            false => String::new(),
+        };
+        let count = rng.random_range(0..=3);
+        let secundarias: Vec<String> = CNAES
+            .choose_multiple(rng, count)
+            .filter(|secundaria| secundaria.0 != cnae)
+            .map(|secundaria| format!("{:07}", secundaria.0))
+            .collect();
+        let numero = match rng.random_bool(0.1) {
+            true => "S/N".to_string(),
+            false => rng.random_range(1..=3_000).to_string(),
+        };
+        let complemento = match rng.random_bool(0.3) {
+            true => COMPLEMENTS.choose(rng).expect("not empty").to_string(),
+            false => String::new(),
+        };
+        let email = match rng.random_bool(0.4) {
+            true => format!(
+                "contato{}@{}",
+                &self.basico[..4],
+                EMAIL_DOMAINS.choose(rng).expect("not empty")
+            ),
+            false => String::new(),
+        };
+        let (especial, data_especial) = match situacao == 2 && rng.random_bool(0.01) {
+            true => (
+                "RECUPERACAO JUDICIAL".to_string(),
+                format_date(date_between(rng, inicio, release)),
+            ),
+            false => (String::new(), String::new()),
+        };
+
+        vec![
+            self.basico.clone(),
+            ordem,
+            cnpj.dv().to_string(),
+            if cnpj.ordem() == "0001" { "1" } else { "2" }.to_string(),
+            nome_fantasia,
+            format!("{:02}", situacao),
+            format_date(date_between(rng, inicio, release)),
+            format!("{:02}", motivo),
+            String::new(),
+            String::new(),
+            format_date(inicio),
+            format!("{:07}", cnae),
+            secundarias.join(","),
+            STREET_TYPES.choose(rng).expect("not empty").to_string(),
+            STREETS.choose(rng).expect("not empty").to_string(),
+            numero,
+            complemento,
+            NEIGHBORHOODS.choose(rng).expect("not empty").to_string(),
+            format!("{}{}", cep, digits(rng, 6)),
+            uf.to_string(),
+            format!("{:04}", tom),
+            ddd.to_string(),
+            format!("3{}", digits(rng, 7)),
+            String::new(),
+            String::new(),
+            String::new(),
+            String::new(),
+            email,
+            especial,
+            data_especial,
+        ]
+    }
+
+    fn socio(&mut self, basico: &str, start: NaiveDate) -> Vec<String> {
+        let release = self.config.release;
+        let rng = &mut self.rng;
+        let entrada = format_date(date_between(rng, start, release));
+        let representative = || {
+            (
+                NO_REPRESENTATIVE.to_string(),
+                String::new(),
+                "00".to_string(),
+            )
+        };
+        let (identificador, nome, documento, qualificacao, pais, representante, faixa) =
+            match rng.random_range(0..100) {
+                0..85 => (
+                    2,
+                    person_name(rng),
+                    masked_cpf(rng),
+                    *[49, 22, 5].choose(rng).expect("not empty"),
+                    String::new(),
+                    representative(),
+                    rng.random_range(2..=9),
+                ),
+                85..95 => (
+                    1,
+                    company_name(rng, 2062),
+                    Cnpj::matriz(&digits(rng, 8))
+                        .expect("8-digit basico")
+                        .as_str()
+                        .to_string(),
+                    22,
+                    String::new(),
+                    representative(),
+                    0,
+                ),
+                _ => (
+                    3,
+                    FOREIGN_NAMES.choose(rng).expect("not empty").to_string(),
+                    "***999999**".to_string(),
+                    22,
+                    format!("{:03}", PAISES[rng.random_range(1..PAISES.len())].0),
+                    (masked_cpf(rng), person_name(rng), "17".to_string()),
+                    rng.random_range(3..=8),
+                ),
+            };
+        vec![
+            basico.to_string(),
+            identificador.to_string(),
+            nome,
+            documento,
+            format!("{:02}", qualificacao),
+            entrada,
+            pais,
+            representante.0,
+            representante.1,
+            representante.2,
+            faixa.to_string(),
+        ]
+    }
+
+    /// The Simples row of a company, if it ever opted for the Simples
+    /// Nacional or the MEI
+    fn simples(
+        &mut self,
+        basico: &str,
+        start: NaiveDate,
+        porte: u32,
+        individual: bool,
+    ) -> Option<Vec<String>> {
+        let release = self.config.release;
+        let rng = &mut self.rng;
+        let opted = date_between(rng, start, release);
+        let [simples, opcao, exclusao, mei, opcao_mei, exclusao_mei] = match (individual, porte) {
+            (true, _) if rng.random_bool(0.6) => {
+                let opted = format_date(opted);
+                ["S", &opted, NO_DATE, "S", &opted, NO_DATE].map(String::from)
+            }
+            (_, 1 | 3) if rng.random_bool(0.7) => {
+                ["S", &format_date(opted), NO_DATE, "N", NO_DATE, NO_DATE].map(String::from)
+            }
+            (_, 1 | 3) => {
+                let excluded = format_date(date_between(rng, opted, release));
+                ["N", &format_date(opted), &excluded, "N", NO_DATE, NO_DATE].map(String::from)
+            }
+            _ => return None,
+        };
+        Some(vec![
+            basico.to_string(),
+            simples,
+            opcao,
+            exclusao,
+            mei,
+            opcao_mei,
+            exclusao_mei,
+        ])
+    }
+}
+
+/// Rows of a lookup table, with codes as wide as the published ones
+fn lookup_rows(kind: LookupKind) -> Vec<Vec<String>> {
+    let row = |code: String, name: &str| vec![code, name.to_string()];
+    match kind {
+        LookupKind::Paises => PAISES
+            .iter()
+            .map(|(code, name)| row(format!("{:03}", code), name))
+            .collect(),
+        LookupKind::Municipios => MUNICIPALITIES
+            .iter()
+            .map(|(code, name, ..)| row(format!("{:04}", code), name))
+            .collect(),
+        LookupKind::Naturezas => NATUREZAS
+            .iter()
+            .map(|(code, name, ..)| row(format!("{:04}", code), name))
+            .collect(),
+        LookupKind::Qualificacoes => QUALIFICACOES
+            .iter()
+            .map(|(code, name)| row(format!("{:02}", code), name))
+            .collect(),
+        LookupKind::Cnaes => CNAES
+            .iter()
+            .map(|(code, name)| row(format!("{:07}", code), name))
+            .collect(),
+        LookupKind::Motivos => MOTIVOS
+            .iter()
+            .map(|(code, name)| row(format!("{:02}", code), name))
+            .collect(),
+    }
+}
+
+fn increment(counts: &mut std::collections::BTreeMap<String, u64>, key: &str) {
+    *counts.entry(key.to_string()).or_default() += 1;
+}
+
+/// A code drawn from `(code, weight)` pairs
+fn choose(rng: &mut StdRng, weighted: &[(u32, u32)]) -> u32 {
+    weighted
+        .choose_weighted(rng, |(_, weight)| *weight)
+        .expect("positive weights")
+        .0
+}
+
+/// A day between `from` and `to`, both included
+fn date_between(rng: &mut StdRng, from: NaiveDate, to: NaiveDate) -> NaiveDate {
+    let days = (to - from).num_days().max(0);
+    from + Duration::days(rng.random_range(0..=days))
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+fn digits(rng: &mut StdRng, count: usize) -> String {
+    (0..count)
+        .map(|_| char::from(b'0' + rng.random_range(0..10)))
+        .collect()
+}
+
+/// A CPF as the Receita Federal publishes it, with only the middle digits
+fn masked_cpf(rng: &mut StdRng) -> String {
+    format!("***{}**", digits(rng, 6))
+}
+
+fn person_name(rng: &mut StdRng) -> String {
+    format!(
+        "{} {} {}",
+        FIRST_NAMES.choose(rng).expect("not empty"),
+        SURNAMES.choose(rng).expect("not empty"),
+        SURNAMES.choose(rng).expect("not empty")
+    )
+}
+
+fn company_name(rng: &mut StdRng, natureza: u32) -> String {
+    format!(
+        "{} {}{}",
+        BUSINESS_NAMES.choose(rng).expect("not empty"),
+        BUSINESS_WORDS.choose(rng).expect("not empty"),
+        company_suffix(natureza)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::DirtyRates;
+    use std::io::Read;
+    use tempfile::tempdir;
+
+    fn read_entry(path: &Path) -> Vec<u8> {
+        let mut archive = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+        let mut bytes = Vec::new();
+        archive
+            .by_index(0)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_generate_is_deterministic() {
+        let first = tempdir().unwrap();
+        let second = tempdir().unwrap();
+        let config = |dir: &Path| FixtureConfig {
+            output_dir: dir.to_string_lossy().to_string(),
+            companies: 50,
+            shards: 2,
+            dirty: DirtyRates::uniform(0.1),
+            ..Default::default()
+        };
+
+        let report = Generator::new(config(first.path())).generate().unwrap();
+        let again = Generator::new(config(second.path())).generate().unwrap();
+        assert_eq!(report, again);
+        assert_eq!(report.rows["K3241.K03200Y0.D51011.EMPRECSV"], 25);
+        for archive in ["Estabelecimentos1.zip", "Socios0.zip", "Simples.zip"] {
+            assert_eq!(
+                std::fs::read(first.path().join(archive)).unwrap(),
+                std::fs::read(second.path().join(archive)).unwrap()
+            );
+        }
+
+        let other = tempdir().unwrap();
+        let config = FixtureConfig {
+            seed: 7,
+            ..config(other.path())
+        };
+        Generator::new(config).generate().unwrap();
+        assert_ne!(
+            read_entry(&first.path().join("Empresas0.zip")),
+            read_entry(&other.path().join("Empresas0.zip"))
+        );
+    }
+
+    #[test]
+    fn test_generate_writes_valid_latin1_rows() {
+        let dir = tempdir().unwrap();
+        let config = FixtureConfig {
+            output_dir: dir.path().to_string_lossy().to_string(),
+            companies: 20,
+            shards: 1,
+            ..Default::default()
+        };
+        let report = Generator::new(config).generate().unwrap();
+        assert_eq!(report.malformed_lines, 0);
+        assert_eq!(report.invalid_cnpjs, 0);
+
+        let bytes = read_entry(&dir.path().join("Estabelecimentos0.zip"));
+        assert!(std::str::from_utf8(&bytes).is_err(), "written in Latin-1");
+        let text = Encoding::Latin1.decode(&bytes);
+        let mut rows = 0;
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split(';').map(|f| f.trim_matches('"')).collect();
+            assert_eq!(fields.len(), 30);
+            let cnpj = format!("{}{}{}", fields[0], fields[1], fields[2]);
+            assert!(cnpj.parse::<Cnpj>().is_ok(), "{}", cnpj);
+            rows += 1;
+        }
+        assert_eq!(report.rows["K3241.K03200Y0.D51011.ESTABELE"], rows);
+        assert_eq!(report.rows["F.K03200$Z.D51011.CNAECSV"], CNAES.len() as u64);
+    }
+}
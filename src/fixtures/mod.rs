@@ -0,0 +1,174 @@
+mod generator;
+mod pools;
+
+pub use generator::Generator;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FixtureError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("ZIP error: {0}")]
+    ZipError(#[from] zip::result::ZipError),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+}
+
+pub type Result<T> = std::result::Result<T, FixtureError>;
+
+/// Share of the generated rows that carry each kind of dirty data
+///
+/// Each rate is a probability between 0 and 1; a row carries at most one
+/// kind of dirt, so the counts in `FixtureReport` match the quality report
+/// of a transform of the dataset.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DirtyRates {
+    /// Extra lines with a missing field, per line of every input file
+    pub malformed_lines: f64,
+    /// Establishments with wrong check digits
+    pub invalid_cnpjs: f64,
+    /// Establishment and partner dates that do not exist, e.g. `20230231`
+    pub invalid_dates: f64,
+    /// Establishments in a municipality missing from the Municipios lookup
+    pub unknown_codes: f64,
+    /// Extra Socios and Simples rows of companies without establishments
+    pub orphans: f64,
+}
+
+impl DirtyRates {
+    /// The same rate for every kind of dirty data
+    pub fn uniform(rate: f64) -> Self {
+        Self {
+            malformed_lines: rate,
+            invalid_cnpjs: rate,
+            invalid_dates: rate,
+            unknown_codes: rate,
+            orphans: rate,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let rates = [
+            ("malformed_lines", self.malformed_lines),
+            ("invalid_cnpjs", self.invalid_cnpjs),
+            ("invalid_dates", self.invalid_dates),
+            ("unknown_codes", self.unknown_codes),
+            ("orphans", self.orphans),
+        ];
+        for (name, rate) in rates {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(FixtureError::InvalidConfig(format!(
+                    "{} rate must be between 0 and 1, got {}",
+                    name, rate
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Most companies a dataset can have; the orphans take as many `cnpj_basico`
+/// again, out of the 10^8 there are
+pub const MAX_COMPANIES: usize = 50_000_000;
+
+#[derive(Debug, Clone)]
+pub struct FixtureConfig {
+    pub output_dir: String,
+    /// Seed of the generator; the same seed writes the same files
+    pub seed: u64,
+    /// Number of companies (Empresas rows)
+    pub companies: usize,
+    /// Establishments per company, at least the head office
+    pub max_establishments: usize,
+    /// Partners per company, from none
+    pub max_partners: usize,
+    /// Number of Estabelecimentos, Empresas and Socios shards (at most 10)
+    pub shards: usize,
+    /// Release date, used in the file names (`D51011`) and as the latest date
+    pub release: NaiveDate,
+    pub dirty: DirtyRates,
+}
+
+impl FixtureConfig {
+    /// Validate the configuration
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=MAX_COMPANIES).contains(&self.companies) {
+            return Err(FixtureError::InvalidConfig(format!(
+                "companies must be between 1 and {}",
+                MAX_COMPANIES
+            )));
+        }
+        if self.max_establishments == 0 {
+            return Err(FixtureError::InvalidConfig(
+                "max_establishments must be at least 1".to_string(),
+            ));
+        }
+        if !(1..=10).contains(&self.shards) {
+            return Err(FixtureError::InvalidConfig(
+                "shards must be between 1 and 10".to_string(),
+            ));
+        }
+        self.dirty.validate()
+    }
+}
+
+impl Default for FixtureConfig {
+    fn default() -> Self {
+        Self {
+            output_dir: "fixtures".to_string(),
+            seed: 42,
+            companies: 1000,
+            max_establishments: 3,
+            max_partners: 4,
+            shards: 10,
+            release: NaiveDate::from_ymd_opt(2025, 10, 11).expect("valid date"),
+            dirty: DirtyRates::default(),
+        }
+    }
+}
+
+/// Rows and dirty data written by `Generator::generate`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FixtureReport {
+    /// Lines written per input file, by name inside its ZIP
+    pub rows: BTreeMap<String, u64>,
+    pub malformed_lines: u64,
+    pub invalid_cnpjs: u64,
+    /// Invalid dates per column
+    pub invalid_dates: BTreeMap<String, u64>,
+    /// Unknown codes per lookup (`municipio`)
+    pub unknown_codes: BTreeMap<String, u64>,
+    /// Orphan rows per table (`socios`, `simples`)
+    pub orphans: BTreeMap<String, u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_validation() {
+        assert!(FixtureConfig::default().validate().is_ok());
+
+        let config = FixtureConfig {
+            shards: 11,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = FixtureConfig {
+            dirty: DirtyRates {
+                orphans: 1.5,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! Values the synthetic dataset is drawn from, modelled on the published files
+
+/// Municipalities: TOM code, name, UF, DDD and the first digits of their CEPs
+///
+/// The same TOM codes as the embedded IBGE crosswalk, so every generated
+/// establishment is enriched with its IBGE code.
+pub const MUNICIPALITIES: [(u32, &str, &str, &str, &str); 4] = [
+    (4123, "BELO HORIZONTE", "MG", "31", "30"),
+    (6001, "RIO DE JANEIRO", "RJ", "21", "20"),
+    (7107, "SAO PAULO", "SP", "11", "01"),
+    (9701, "BRASILIA", "DF", "61", "70"),
+];
+
+/// A TOM code missing from the Municipios lookup
+pub const UNKNOWN_MUNICIPALITY: u32 = 9999;
+
+/// CNAE subclasses of the Cnaes lookup
+pub const CNAES: [(u32, &str); 12] = [
+    (
+        1091102,
+        "Fabricação de produtos de padaria e confeitaria com predominância de produção própria",
+    ),
+    (4120400, "Construção de edifícios"),
+    (
+        4711301,
+        "Comércio varejista de mercadorias em geral, com predominância de produtos alimentícios - hipermercados",
+    ),
+    (
+        4751201,
+        "Comércio varejista especializado de equipamentos e suprimentos de informática",
+    ),
+    (
+        4781400,
+        "Comércio varejista de artigos do vestuário e acessórios",
+    ),
+    (
+        4930202,
+        "Transporte rodoviário de carga, exceto produtos perigosos e mudanças, intermunicipal, interestadual e internacional",
+    ),
+    (5611201, "Restaurantes e similares"),
+    (
+        6201501,
+        "Desenvolvimento de programas de computador sob encomenda",
+    ),
+    (6204000, "Consultoria em tecnologia da informação"),
+    (6920601, "Atividades de contabilidade"),
+    (
+        8630503,
+        "Atividade médica ambulatorial restrita a consultas",
+    ),
+    (9602501, "Cabeleireiros, manicure e pedicure"),
+];
+
+/// Legal natures: code, description, weight and whether the company is a
+/// single person (no partners, the CPF in the name)
+pub const NATUREZAS: [(u32, &str, u32, bool); 5] = [
+    (2062, "Sociedade Empresária Limitada", 50, false),
+    (2135, "Empresário (Individual)", 35, true),
+    (2054, "Sociedade Anônima Fechada", 5, false),
+    (2240, "Sociedade Simples Limitada", 5, false),
+    (3999, "Associação Privada", 5, false),
+];
+
+/// Partner qualifications
+pub const QUALIFICACOES: [(u32, &str); 8] = [
+    (0, "Não informada"),
+    (5, "Administrador"),
+    (10, "Diretor"),
+    (16, "Presidente"),
+    (17, "Procurador"),
+    (22, "Sócio"),
+    (49, "Sócio-Administrador"),
+    (50, "Empresário"),
+];
+
+/// Countries; Brazil is left blank in the Estabelecimentos and Socios files
+pub const PAISES: [(u32, &str); 4] = [
+    (105, "BRASIL"),
+    (63, "ARGENTINA"),
+    (249, "ESTADOS UNIDOS"),
+    (607, "PORTUGAL"),
+];
+
+/// Reasons of the registration status
+pub const MOTIVOS: [(u32, &str); 3] = [
+    (0, "SEM MOTIVO"),
+    (1, "EXTINÇÃO POR ENCERRAMENTO LIQUIDAÇÃO VOLUNTÁRIA"),
+    (63, "OMISSÃO DE DECLARAÇÕES"),
+];
+
+/// Registration status codes with their weight and `motivo`
+pub const SITUACOES: [(u32, u32, u32); 5] =
+    [(2, 60, 0), (8, 30, 1), (4, 7, 63), (3, 2, 0), (1, 1, 0)];
+
+/// Porte codes with their weight
+pub const PORTES: [(u32, u32); 4] = [(1, 55), (3, 20), (5, 24), (0, 1)];
+
+pub const FIRST_NAMES: [&str; 16] = [
+    "JOSÉ",
+    "MARIA",
+    "JOÃO",
+    "ANA",
+    "ANTÔNIO",
+    "FRANCISCA",
+    "CARLOS",
+    "LÚCIA",
+    "PAULO",
+    "MÁRCIA",
+    "LUÍS",
+    "CONCEIÇÃO",
+    "ANDRÉ",
+    "PATRÍCIA",
+    "FÁBIO",
+    "JÉSSICA",
+];
+
+pub const SURNAMES: [&str; 16] = [
+    "SILVA",
+    "SANTOS",
+    "OLIVEIRA",
+    "SOUZA",
+    "PEREIRA",
+    "LIMA",
+    "CARVALHO",
+    "GONÇALVES",
+    "RIBEIRO",
+    "ARAÚJO",
+    "FERREIRA",
+    "RODRIGUES",
+    "ALMEIDA",
+    "NASCIMENTO",
+    "BRANDÃO",
+    "MONTEIRO",
+];
+
+pub const FOREIGN_NAMES: [&str; 4] = [
+    "JOHN SMITH",
+    "MARTÍN GARCÍA",
+    "ANNA MÜLLER",
+    "PIERRE DUBOIS",
+];
+
+pub const BUSINESS_WORDS: [&str; 12] = [
+    "COMÉRCIO",
+    "SERVIÇOS",
+    "TECNOLOGIA",
+    "ALIMENTOS",
+    "CONSTRUÇÕES",
+    "TRANSPORTES",
+    "CONSULTORIA",
+    "PADARIA",
+    "DISTRIBUIDORA",
+    "SOLUÇÕES",
+    "MODAS",
+    "SAÚDE",
+];
+
+pub const BUSINESS_NAMES: [&str; 10] = [
+    "ESTRELA",
+    "AURORA",
+    "HORIZONTE",
+    "PIONEIRA",
+    "BOA VISTA",
+    "SÃO JORGE",
+    "IPÊ",
+    "PRIMAVERA",
+    "ATLÂNTICO",
+    "CENTRAL",
+];
+
+/// Suffix of the company name by legal nature
+pub fn company_suffix(natureza: u32) -> &'static str {
+    match natureza {
+        2054 => " S.A.",
+        3999 => "",
+        _ => " LTDA",
+    }
+}
+
+pub const STREET_TYPES: [&str; 5] = ["RUA", "AVENIDA", "PRACA", "TRAVESSA", "QUADRA"];
+
+pub const STREETS: [&str; 10] = [
+    "DAS FLORES",
+    "SÃO JOÃO",
+    "BRASIL",
+    "GETÚLIO VARGAS",
+    "SETE DE SETEMBRO",
+    "DA CONCEIÇÃO",
+    "TIRADENTES",
+    "DOM PEDRO II",
+    "DAS ACÁCIAS",
+    "MARECHAL DEODORO",
+];
+
+pub const NEIGHBORHOODS: [&str; 8] = [
+    "CENTRO",
+    "JARDIM AMÉRICA",
+    "VILA NOVA",
+    "SÃO JOSÉ",
+    "BELA VISTA",
+    "ASA NORTE",
+    "COPACABANA",
+    "SAVASSI",
+];
+
+pub const COMPLEMENTS: [&str; 4] = ["SALA 101", "LOJA 2", "ANDAR 3", "BLOCO B"];
+
+pub const EMAIL_DOMAINS: [&str; 4] = ["gmail.com", "hotmail.com", "uol.com.br", "empresa.com.br"];
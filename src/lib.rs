@@ -2,6 +2,7 @@
 pub mod api;
 pub mod db;
 pub mod download;
+pub mod fixtures;
 pub mod transform;
 
 // Re-export main types and functions
@@ -28,6 +28,11 @@ enum Commands {
         /// Restart downloads from beginning
         #[arg(short, long)]
         restart: bool,
+
+        /// Directory the files are published in, e.g. a mirror or `rfb mock-server`
+        /// (default: the Receita Federal one of the current month)
+        #[arg(long)]
+        base_url: Option<String>,
     },
 
     /// Transform downloaded data
@@ -43,6 +48,115 @@ enum Commands {
         /// Enable privacy mode (mask sensitive data)
         #[arg(short, long)]
         privacy: bool,
+
+        /// Privacy mode policy per field (keep, mask, drop), e.g. email=mask
+        #[arg(long, value_delimiter = ',')]
+        privacy_policy: Vec<rfb_rs::transform::FieldPolicy>,
+
+        /// Replace partner CPFs and names with tokens keyed by RFB_PSEUDONYM_KEY
+        /// (requires --privacy)
+        #[arg(long)]
+        pseudonymize: bool,
+
+        /// File of CNPJs, CPFs and partner names to leave out of every output
+        #[arg(long)]
+        suppression_list: Option<String>,
+
+        /// Only establishments in these UFs, e.g. SP,RJ
+        #[arg(long, value_delimiter = ',')]
+        uf: Vec<String>,
+
+        /// Only establishments with this situação cadastral, by name or code,
+        /// e.g. ATIVA
+        #[arg(long, value_delimiter = ',')]
+        situacao: Vec<rfb_rs::transform::SituacaoCadastral>,
+
+        /// Only establishments whose main CNAE matches, e.g. 62* or 4751201
+        #[arg(long, value_delimiter = ',')]
+        cnae: Vec<rfb_rs::transform::CnaePattern>,
+
+        /// Only companies of this porte, by name or code, e.g. "MICRO EMPRESA" or 01
+        #[arg(long, value_delimiter = ',')]
+        porte: Vec<rfb_rs::transform::Porte>,
+
+        /// Output format (parquet, jsonl)
+        #[arg(short, long, default_value = "parquet")]
+        format: rfb_rs::transform::OutputFormat,
+
+        /// Compression codec (none, snappy, gzip, lz4, zstd, brotli)
+        #[arg(short, long, default_value = "zstd")]
+        compression: rfb_rs::transform::Compression,
+
+        /// Rows per Parquet row group
+        #[arg(long)]
+        row_group_size: Option<usize>,
+
+        /// Memory budget for joins, e.g. 16G or 512M (spills to disk when exceeded)
+        #[arg(long)]
+        memory_limit: Option<rfb_rs::transform::MemoryLimit>,
+
+        /// Encoding of the input files (auto, latin1, utf8)
+        #[arg(long, default_value = "auto")]
+        encoding: rfb_rs::transform::Encoding,
+
+        /// Worker threads for shards and tables (default: one per core)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        /// Rebuild every output, even for unchanged inputs
+        #[arg(long)]
+        force: bool,
+
+        /// Fail when a quality metric exceeds a limit, e.g. invalid_cnpjs=0 or
+        /// null_rate.empresas.razao_social=0.01 (repeatable)
+        #[arg(long, value_delimiter = ',')]
+        fail_on: Vec<rfb_rs::transform::Threshold>,
+
+        /// Stop when more lines than this are malformed (default: no limit)
+        #[arg(long)]
+        max_rejects: Option<u64>,
+
+        /// Also write the ownership graph of companies and partners to graph/
+        /// (edge list Parquet, GraphML and Neo4j import files)
+        #[arg(long)]
+        graph: bool,
+
+        /// Also write one record per cnpj_basico with its head office and
+        /// branches to company_groups/ (Parquet only)
+        #[arg(long)]
+        groups: bool,
+
+        /// Estabelecimentos, Empresas and Socios shards to expect; a release
+        /// missing any of them fails
+        #[arg(long, default_value_t = rfb_rs::transform::RFB_SHARDS)]
+        shards: usize,
+    },
+
+    /// Compare the transformed outputs of two releases
+    Diff {
+        /// Earlier release, as YYYY-MM inside --root or a transform output directory
+        #[arg(long)]
+        from: String,
+
+        /// Later release, as YYYY-MM inside --root or a transform output directory
+        #[arg(long)]
+        to: String,
+
+        /// Directory holding one transform output per release (YYYY-MM)
+        #[arg(long, default_value = "output")]
+        root: String,
+
+        /// Output directory of the change events
+        #[arg(short, long, default_value = "diff")]
+        output: String,
+
+        /// Output format (parquet, jsonl)
+        #[arg(short, long, default_value = "parquet")]
+        format: rfb_rs::transform::OutputFormat,
+
+        /// Compression codec (none, snappy, gzip, lz4, zstd, brotli)
+        #[arg(short, long, default_value = "zstd")]
+        compression: rfb_rs::transform::Compression,
     },
 
     /// Database commands
@@ -64,6 +178,14 @@ enum Commands {
         /// Database URL
         #[arg(short, long)]
         database_url: Option<String>,
+
+        /// File of CNPJs and CPFs whose companies are answered as not found
+        #[arg(long)]
+        suppression_list: Option<String>,
+
+        /// Lookup cache written by `rfb transform` (`<output>/lookups.cache`)
+        #[arg(long)]
+        lookups: Option<String>,
     },
 
     /// Check integrity of downloaded ZIP files
@@ -76,6 +198,51 @@ enum Commands {
         #[arg(short = 'x', long)]
         delete: bool,
     },
+
+    /// Serve a directory of ZIP files like the Receita Federal file server
+    MockServer {
+        /// Directory to serve, e.g. the output of `rfb fixtures generate`
+        #[arg(short, long, default_value = "fixtures")]
+        directory: String,
+
+        /// Host to bind to
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+
+        /// Port to bind to
+        #[arg(short, long, default_value_t = 8000)]
+        port: u16,
+
+        /// Share of GET requests answered with 503 Service Unavailable
+        #[arg(long, default_value_t = 0.0)]
+        error_rate: f64,
+
+        /// Share of GET requests whose connection is dropped halfway through
+        #[arg(long, default_value_t = 0.0)]
+        drop_rate: f64,
+
+        /// Share of GET requests answered with half of the bytes asked for
+        #[arg(long, default_value_t = 0.0)]
+        truncate_rate: f64,
+
+        /// Bytes per second each response is sent at (default: unlimited)
+        #[arg(long)]
+        bandwidth: Option<u64>,
+
+        /// Faults per file, after which its requests succeed (default: unlimited)
+        #[arg(long)]
+        max_faults_per_file: Option<u32>,
+
+        /// Seed of the fault injection
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+    },
+
+    /// Synthetic datasets for offline testing
+    Fixtures {
+        #[command(subcommand)]
+        command: FixtureCommands,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -101,6 +268,78 @@ enum DbCommands {
         #[arg(short, long, default_value = "public")]
         schema: String,
     },
+
+    /// Rebuild the company groups from the companies table
+    Aggregate {
+        /// Database URL
+        #[arg(short, long)]
+        database_url: Option<String>,
+
+        /// PostgreSQL schema
+        #[arg(short, long, default_value = "public")]
+        schema: String,
+    },
+
+    /// Delete the companies and partners of a suppression list
+    Suppress {
+        /// File of CNPJs, CPFs and partner names
+        suppression_list: String,
+
+        /// Database URL
+        #[arg(short, long)]
+        database_url: Option<String>,
+
+        /// PostgreSQL schema
+        #[arg(short, long, default_value = "public")]
+        schema: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum FixtureCommands {
+    /// Write a seeded synthetic release in the Receita Federal layout
+    Generate {
+        /// Output directory of the ZIP files
+        #[arg(short, long, default_value = "fixtures")]
+        directory: String,
+
+        /// Number of companies
+        #[arg(long, default_value_t = 1000)]
+        companies: usize,
+
+        /// Number of Estabelecimentos, Empresas and Socios shards (1 to 10)
+        #[arg(long, default_value_t = 10)]
+        shards: usize,
+
+        /// Seed of the generator; the same seed writes the same files
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+
+        /// Establishments per company, at most
+        #[arg(long, default_value_t = 3)]
+        max_establishments: usize,
+
+        /// Partners per company, at most
+        #[arg(long, default_value_t = 4)]
+        max_partners: usize,
+
+        /// Release date used in the file names (YYYY-MM-DD)
+        #[arg(long, default_value = "2025-10-11")]
+        release: chrono::NaiveDate,
+
+        /// Share of rows with each kind of dirty data (malformed lines,
+        /// invalid CNPJs and dates, unknown municipalities, orphans)
+        #[arg(long, default_value_t = 0.0)]
+        dirty_rate: f64,
+    },
+}
+
+/// Load a suppression list, or an empty one without a file
+fn suppression_list(path: Option<String>) -> anyhow::Result<rfb_rs::transform::SuppressionList> {
+    Ok(match path {
+        Some(path) => rfb_rs::transform::SuppressionList::load(std::path::Path::new(&path))?,
+        None => Default::default(),
+    })
 }
 
 #[tokio::main]
@@ -116,6 +355,7 @@ async fn main() -> anyhow::Result<()> {
             skip_existing,
             parallel,
             restart,
+            base_url,
         } => {
             // Validate parallel parameter
             if parallel == 0 {
@@ -128,6 +368,7 @@ async fn main() -> anyhow::Result<()> {
                 skip_existing,
                 max_parallel: parallel,
                 restart,
+                base_url,
                 ..Default::default()
             };
             let downloader = rfb_rs::Downloader::new(config);
@@ -138,17 +379,89 @@ async fn main() -> anyhow::Result<()> {
             directory,
             output,
             privacy,
+            privacy_policy,
+            pseudonymize,
+            suppression_list: suppression_path,
+            uf,
+            situacao,
+            cnae,
+            porte,
+            format,
+            compression,
+            row_group_size,
+            memory_limit,
+            encoding,
+            jobs,
+            force,
+            fail_on,
+            max_rejects,
+            graph,
+            groups,
+            shards,
         } => {
+            use rfb_rs::transform::PseudonymKey;
+            let pseudonym_key = if pseudonymize {
+                let secret = std::env::var(PseudonymKey::ENV).map_err(|_| {
+                    anyhow::anyhow!("{} must be set to use --pseudonymize", PseudonymKey::ENV)
+                })?;
+                Some(PseudonymKey::new(&secret)?)
+            } else {
+                None
+            };
             let config = rfb_rs::transform::TransformConfig {
                 data_dir: directory,
                 output_dir: output,
                 privacy_mode: privacy,
+                privacy_policies: privacy_policy,
+                pseudonym_key,
+                suppression: suppression_list(suppression_path)?,
+                filters: rfb_rs::transform::Filters {
+                    ufs: uf.iter().map(|uf| uf.trim().to_uppercase()).collect(),
+                    situacoes: situacao,
+                    cnaes: cnae,
+                    portes: porte,
+                },
+                format,
+                compression,
+                row_group_size,
+                memory_limit,
+                encoding,
+                jobs,
+                force,
+                fail_on,
+                max_rejects,
+                graph,
+                groups,
+                shards: Some(shards),
             };
             let mut transformer = rfb_rs::Transformer::new(config);
             transformer.load_lookups()?;
             transformer.transform().await?;
         }
 
+        Commands::Diff {
+            from,
+            to,
+            root,
+            output,
+            format,
+            compression,
+        } => {
+            use rfb_rs::transform::{DiffConfig, Differ, release_dir};
+            let config = DiffConfig {
+                from: release_dir(&root, &from).to_string_lossy().to_string(),
+                to: release_dir(&root, &to).to_string_lossy().to_string(),
+                output_dir: output,
+                format,
+                compression,
+            };
+            let summary = Differ::new(config).diff().await?;
+            for (kind, count) in &summary.changes {
+                println!("{}: {}", kind, count);
+            }
+            println!("{} changes", summary.total());
+        }
+
         Commands::Db { command } => match command {
             DbCommands::Create {
                 database_url,
@@ -177,12 +490,46 @@ async fn main() -> anyhow::Result<()> {
                 db.drop()?;
                 println!("Database tables dropped successfully");
             }
+
+            DbCommands::Aggregate {
+                database_url,
+                schema,
+            } => {
+                use rfb_rs::db::{Database, PostgresDatabase};
+                let db_url = database_url.unwrap_or_else(|| {
+                    std::env::var("DATABASE_URL")
+                        .expect("DATABASE_URL must be set or provided via --database-url")
+                });
+                let db = PostgresDatabase::new(db_url, schema);
+                for (table, rows) in db.aggregate()? {
+                    println!("{}: {} rows", table, rows);
+                }
+            }
+
+            DbCommands::Suppress {
+                suppression_list: path,
+                database_url,
+                schema,
+            } => {
+                use rfb_rs::db::{Database, PostgresDatabase};
+                let list = suppression_list(Some(path))?;
+                let db_url = database_url.unwrap_or_else(|| {
+                    std::env::var("DATABASE_URL")
+                        .expect("DATABASE_URL must be set or provided via --database-url")
+                });
+                let db = PostgresDatabase::new(db_url, schema);
+                for (table, rows) in db.suppress(&list)? {
+                    println!("{}: {} rows suppressed", table, rows);
+                }
+            }
         },
 
         Commands::Api {
             host,
             port,
             database_url,
+            suppression_list: suppression_path,
+            lookups,
         } => {
             let config = rfb_rs::api::ApiConfig {
                 host,
@@ -192,7 +539,13 @@ async fn main() -> anyhow::Result<()> {
                         .unwrap_or_else(|_| "postgres://localhost/rfb".to_string())
                 }),
             };
-            let server = rfb_rs::ApiServer::new(config);
+            let mut server = rfb_rs::ApiServer::new(config)
+                .with_suppression(suppression_list(suppression_path)?);
+            if let Some(path) = lookups {
+                server = server.with_lookups(rfb_rs::transform::Lookups::from_cache(
+                    std::path::Path::new(&path),
+                )?);
+            }
             server.start().await?;
         }
 
@@ -237,6 +590,83 @@ async fn main() -> anyhow::Result<()> {
 
             println!("\nChecked {} files, {} errors", checked, errors);
         }
+
+        Commands::MockServer {
+            directory,
+            host,
+            port,
+            error_rate,
+            drop_rate,
+            truncate_rate,
+            bandwidth,
+            max_faults_per_file,
+            seed,
+        } => {
+            use rfb_rs::download::{Faults, MockServer, MockServerConfig};
+            let config = MockServerConfig {
+                root: directory,
+                host,
+                port,
+                faults: Faults {
+                    error_rate,
+                    drop_rate,
+                    truncate_rate,
+                    bandwidth,
+                    max_per_file: max_faults_per_file,
+                    seed,
+                },
+            };
+            let server = MockServer::start(config)?;
+            println!("Serving at {} (Ctrl-C to stop)", server.url());
+            tokio::signal::ctrl_c().await?;
+            let stats = server.stats();
+            server.stop().await?;
+            println!(
+                "{} requests, {} errors, {} drops, {} truncations",
+                stats.requests, stats.errors, stats.drops, stats.truncations
+            );
+        }
+
+        Commands::Fixtures { command } => match command {
+            FixtureCommands::Generate {
+                directory,
+                companies,
+                shards,
+                seed,
+                max_establishments,
+                max_partners,
+                release,
+                dirty_rate,
+            } => {
+                use rfb_rs::fixtures::{DirtyRates, FixtureConfig, Generator};
+                let config = FixtureConfig {
+                    output_dir: directory.clone(),
+                    seed,
+                    companies,
+                    max_establishments,
+                    max_partners,
+                    shards,
+                    release,
+                    dirty: DirtyRates::uniform(dirty_rate),
+                };
+                let report = Generator::new(config).generate()?;
+                for (file, rows) in &report.rows {
+                    println!("{}: {} rows", file, rows);
+                }
+                println!("malformed lines: {}", report.malformed_lines);
+                println!("invalid CNPJs: {}", report.invalid_cnpjs);
+                for (name, counts) in [
+                    ("invalid dates", &report.invalid_dates),
+                    ("unknown codes", &report.unknown_codes),
+                    ("orphans", &report.orphans),
+                ] {
+                    for (key, count) in counts {
+                        println!("{} ({}): {}", name, key, count);
+                    }
+                }
+                println!("Fixtures written to {}", directory);
+            }
+        },
     }
 
     Ok(())
@@ -0,0 +1,55 @@
+use bigdecimal::{BigDecimal, RoundingMode};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Decimal places kept for amounts, matching `NUMERIC(20, 2)`
+pub const AMOUNT_SCALE: i64 = 2;
+
+/// An amount that is not a number in the Brazilian format
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid amount: {0}")]
+pub struct InvalidAmount(pub String);
+
+/// Parse an amount written in the Brazilian format (e.g. `1.234.567.890,12`)
+///
+/// `.` groups thousands and `,` separates the cents. Blank values give
+/// `Ok(None)`; amounts are rounded half-up to cents, as PostgreSQL does.
+pub fn parse_amount(raw: &str) -> Result<Option<BigDecimal>, InvalidAmount> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Ok(None);
+    }
+
+    let normalized = raw.replace('.', "").replace(',', ".");
+    if !normalized
+        .chars()
+        .all(|c| c.is_ascii_digit() || c == '.' || c == '-')
+    {
+        return Err(InvalidAmount(raw.to_string()));
+    }
+
+    BigDecimal::from_str(&normalized)
+        .map(|value| Some(value.with_scale_round(AMOUNT_SCALE, RoundingMode::HalfUp)))
+        .map_err(|_| InvalidAmount(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_amount() {
+        let amount = parse_amount("1234567890,12").unwrap().unwrap();
+        assert_eq!(amount.to_string(), "1234567890.12");
+
+        let amount = parse_amount("1.061.004.800,00").unwrap().unwrap();
+        assert_eq!(amount.to_string(), "1061004800.00");
+
+        assert_eq!(
+            parse_amount("15000,5").unwrap().unwrap().to_string(),
+            "15000.50"
+        );
+        assert_eq!(parse_amount(""), Ok(None));
+        assert!(parse_amount("R$ 10,00").is_err());
+    }
+}
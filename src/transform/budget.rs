@@ -0,0 +1,97 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Approximate in-memory size of a frame relative to its CSV input
+///
+/// Strings are stored with offsets and validity, and a join keeps both
+/// sides plus the output alive, so a few times the file size is needed.
+const EXPANSION_FACTOR: u64 = 4;
+
+/// Upper bound on the number of join partitions spilled to disk
+const MAX_PARTITIONS: usize = 256;
+
+/// Memory budget of the transform stage (e.g. `16G`, `512M`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLimit {
+    bytes: u64,
+}
+
+impl MemoryLimit {
+    pub fn new(bytes: u64) -> Self {
+        Self { bytes }
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Number of `cnpj_basico` partitions needed to join `input_bytes` of CSV
+    /// within the budget; `1` means the join runs without spilling
+    pub fn partitions(&self, input_bytes: u64) -> usize {
+        let needed = input_bytes.saturating_mul(EXPANSION_FACTOR);
+        let partitions = needed.div_ceil(self.bytes.max(1));
+        (partitions as usize).clamp(1, MAX_PARTITIONS)
+    }
+}
+
+impl FromStr for MemoryLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim().to_uppercase();
+        let digits = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let unit = match s[digits.len()..].trim_end_matches('B') {
+            "" => 1,
+            "K" => 1 << 10,
+            "M" => 1 << 20,
+            "G" => 1 << 30,
+            "T" => 1 << 40,
+            _ => return Err(format!("invalid memory limit: {}", s)),
+        };
+
+        match digits.trim().parse::<u64>() {
+            Ok(value) if value > 0 => Ok(Self::new(value.saturating_mul(unit))),
+            _ => Err(format!("invalid memory limit: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for MemoryLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (unit, size) in [
+            ("T", 1u64 << 40),
+            ("G", 1 << 30),
+            ("M", 1 << 20),
+            ("K", 1 << 10),
+        ] {
+            if self.bytes.is_multiple_of(size) {
+                return write!(f, "{}{}", self.bytes / size, unit);
+            }
+        }
+        write!(f, "{}", self.bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memory_limit() {
+        assert_eq!("16G".parse(), Ok(MemoryLimit::new(16 << 30)));
+        assert_eq!("512mb".parse(), Ok(MemoryLimit::new(512 << 20)));
+        assert_eq!("1000".parse(), Ok(MemoryLimit::new(1000)));
+        assert!("0".parse::<MemoryLimit>().is_err());
+        assert!("lots".parse::<MemoryLimit>().is_err());
+        assert_eq!(MemoryLimit::new(16 << 30).to_string(), "16G");
+    }
+
+    #[test]
+    fn test_partitions() {
+        let limit = MemoryLimit::new(1 << 30);
+        assert_eq!(limit.partitions(0), 1);
+        assert_eq!(limit.partitions(100 << 20), 1);
+        assert_eq!(limit.partitions(1 << 30), 4);
+        assert_eq!(MemoryLimit::new(1).partitions(u64::MAX), MAX_PARTITIONS);
+    }
+}
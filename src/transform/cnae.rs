@@ -1,4 +1,37 @@
+use super::{Result, TransformError};
+use polars::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// IBGE CNAE 2.3 sections and divisions, embedded in the binary
+const STRUCTURE: &str = include_str!("data/cnae_2_3.csv");
+
+/// CNAE sections and the range of divisions each one holds
+const SECTIONS: [(char, u8, u8); 21] = [
+    ('A', 1, 3),
+    ('B', 5, 9),
+    ('C', 10, 33),
+    ('D', 35, 35),
+    ('E', 36, 39),
+    ('F', 41, 43),
+    ('G', 45, 47),
+    ('H', 49, 53),
+    ('I', 55, 56),
+    ('J', 58, 63),
+    ('K', 64, 66),
+    ('L', 68, 68),
+    ('M', 69, 75),
+    ('N', 77, 82),
+    ('O', 84, 84),
+    ('P', 85, 85),
+    ('Q', 86, 88),
+    ('R', 90, 93),
+    ('S', 94, 96),
+    ('T', 97, 97),
+    ('U', 99, 99),
+];
 
 /// CNAE (Classificação Nacional de Atividades Econômicas) data structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,6 +44,361 @@ impl CNAE {
     pub fn new(codigo: i32, descricao: String) -> Self {
         Self { codigo, descricao }
     }
+
+    /// The subclass code, or `None` if `codigo` is not a valid subclass
+    pub fn code(&self) -> Option<CnaeCode> {
+        CnaeCode::subclass(self.codigo)
+    }
+
+    /// Section letter, e.g. `G` for `4751201`
+    pub fn secao(&self) -> Option<char> {
+        section(self.codigo / 100_000)
+    }
+
+    /// Division, the first two digits, e.g. `47` for `4751201`
+    pub fn divisao(&self) -> i32 {
+        self.codigo / 100_000
+    }
+
+    /// Group, the first three digits, e.g. `475` for `4751201`
+    pub fn grupo(&self) -> i32 {
+        self.codigo / 10_000
+    }
+
+    /// Class with its check digit, e.g. `47512` for `4751201`
+    pub fn classe(&self) -> i32 {
+        self.codigo / 100
+    }
+}
+
+/// Section letter of a division, e.g. `C` for `10`
+pub fn section(divisao: i32) -> Option<char> {
+    SECTIONS
+        .iter()
+        .find(|(_, first, last)| (*first as i32..=*last as i32).contains(&divisao))
+        .map(|(letter, _, _)| *letter)
+}
+
+/// Level of the CNAE structure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CnaeLevel {
+    Secao,
+    Divisao,
+    Grupo,
+    Classe,
+    Subclasse,
+}
+
+/// A code at any level of the CNAE structure: a section letter (`G`) or the
+/// digits of a division (`47`), group (`475`), class (`47512`) or subclass
+/// (`4751201`)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CnaeCode(String);
+
+impl CnaeCode {
+    /// Code of a subclass as the Receita Federal files carry it, e.g. `111301`
+    /// for `0111-3/01`
+    pub fn subclass(codigo: i32) -> Option<Self> {
+        if !(0..10_000_000).contains(&codigo) {
+            return None;
+        }
+        format!("{:07}", codigo).parse().ok()
+    }
+
+    pub fn level(&self) -> CnaeLevel {
+        match self.0.len() {
+            1 => CnaeLevel::Secao,
+            2 => CnaeLevel::Divisao,
+            3 => CnaeLevel::Grupo,
+            5 => CnaeLevel::Classe,
+            _ => CnaeLevel::Subclasse,
+        }
+    }
+
+    /// The code one level up, `None` for a section
+    pub fn parent(&self) -> Option<Self> {
+        match self.level() {
+            CnaeLevel::Secao => None,
+            CnaeLevel::Divisao => section(self.0.parse().ok()?).map(|s| Self(s.to_string())),
+            CnaeLevel::Grupo => Some(Self(self.0[..2].to_string())),
+            CnaeLevel::Classe => Some(Self(self.0[..3].to_string())),
+            CnaeLevel::Subclasse => Some(Self(self.0[..5].to_string())),
+        }
+    }
+
+    /// The parent codes from the section down
+    pub fn ancestors(&self) -> Vec<Self> {
+        let mut ancestors: Vec<Self> = std::iter::successors(self.parent(), Self::parent).collect();
+        ancestors.reverse();
+        ancestors
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for CnaeCode {
+    type Err = TransformError;
+
+    /// Accepts bare digits and the IBGE punctuation, e.g. `47.51-2/01`
+    fn from_str(s: &str) -> Result<Self> {
+        let code: String = s.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+        let valid = match code.len() {
+            1 => SECTIONS
+                .iter()
+                .any(|(letter, _, _)| code.starts_with(*letter)),
+            2 | 3 | 5 | 7 => {
+                code.bytes().all(|b| b.is_ascii_digit())
+                    && code[..2].parse().ok().and_then(section).is_some()
+            }
+            _ => false,
+        };
+        if valid {
+            Ok(Self(code))
+        } else {
+            Err(TransformError::InvalidFormat(format!(
+                "not a CNAE code: {}",
+                s
+            )))
+        }
+    }
+}
+
+impl TryFrom<String> for CnaeCode {
+    type Error = TransformError;
+
+    fn try_from(code: String) -> Result<Self> {
+        code.parse()
+    }
+}
+
+impl From<CnaeCode> for String {
+    fn from(code: CnaeCode) -> Self {
+        code.0
+    }
+}
+
+impl fmt::Display for CnaeCode {
+    /// IBGE notation: `G`, `47`, `47.5`, `4751-2`, `4751-2/01`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = &self.0;
+        match self.level() {
+            CnaeLevel::Secao | CnaeLevel::Divisao => write!(f, "{}", c),
+            CnaeLevel::Grupo => write!(f, "{}.{}", &c[..2], &c[2..]),
+            CnaeLevel::Classe => write!(f, "{}-{}", &c[..4], &c[4..]),
+            CnaeLevel::Subclasse => write!(f, "{}-{}/{}", &c[..4], &c[4..5], &c[5..]),
+        }
+    }
+}
+
+/// The CNAE structure: the embedded sections and divisions, the subclasses
+/// of the Receita Federal Cnaes file with their classes and groups, and
+/// anything a `codigo;descricao` structure file adds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CnaeStructure {
+    nodes: BTreeMap<CnaeCode, Option<String>>,
+}
+
+impl CnaeStructure {
+    /// The embedded IBGE CNAE 2.3 sections and divisions
+    pub fn embedded() -> Self {
+        let mut structure = Self {
+            nodes: BTreeMap::new(),
+        };
+        structure.extend(parse(STRUCTURE).expect("the embedded CNAE structure is valid"));
+        structure
+    }
+
+    /// Version of the embedded structure, from its `# version:` line
+    pub fn version() -> &'static str {
+        STRUCTURE
+            .lines()
+            .find_map(|line| line.strip_prefix("# version:"))
+            .map(str::trim)
+            .unwrap_or("unknown")
+    }
+
+    /// Add codes with their description, and their ancestors if missing
+    pub fn extend(&mut self, codes: impl IntoIterator<Item = (CnaeCode, String)>) {
+        for (code, descricao) in codes {
+            for ancestor in code.ancestors() {
+                self.nodes.entry(ancestor).or_insert(None);
+            }
+            self.nodes.insert(code, Some(descricao));
+        }
+    }
+
+    /// Add the subclasses of a Receita Federal Cnaes table
+    ///
+    /// A class with a single subclass numbers it `/00` under the class's own
+    /// description, so an undescribed class takes the description of its
+    /// `/00` subclass.
+    pub fn extend_subclasses(&mut self, cnaes: &HashMap<i32, String>) {
+        self.extend(cnaes.iter().filter_map(|(codigo, descricao)| {
+            Some((CnaeCode::subclass(*codigo)?, descricao.clone()))
+        }));
+        for (codigo, descricao) in cnaes {
+            if codigo % 100 == 0
+                && let Some(class) = CnaeCode::subclass(*codigo).and_then(|c| c.parent())
+                && let Some(slot @ None) = self.nodes.get_mut(&class)
+            {
+                *slot = Some(descricao.clone());
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn contains(&self, code: &CnaeCode) -> bool {
+        self.nodes.contains_key(code)
+    }
+
+    /// Description of a code, if the loaded structure has one
+    pub fn description(&self, code: &CnaeCode) -> Option<&str> {
+        self.nodes.get(code)?.as_deref()
+    }
+
+    /// Known codes one level below `code`, in code order
+    pub fn children(&self, code: &CnaeCode) -> Vec<&CnaeCode> {
+        self.nodes
+            .keys()
+            .filter(|child| child.parent().as_ref() == Some(code))
+            .collect()
+    }
+
+    /// Known codes at one level, in code order
+    pub fn level(&self, level: CnaeLevel) -> Vec<&CnaeCode> {
+        self.nodes
+            .keys()
+            .filter(|code| code.level() == level)
+            .collect()
+    }
+
+    /// Known codes at one level that have no description, in code order
+    pub fn undescribed(&self, level: CnaeLevel) -> Vec<&CnaeCode> {
+        self.nodes
+            .iter()
+            .filter(|(code, descricao)| code.level() == level && descricao.is_none())
+            .map(|(code, _)| code)
+            .collect()
+    }
+
+    /// All codes with their description, in code order
+    pub fn iter(&self) -> impl Iterator<Item = (&CnaeCode, Option<&str>)> {
+        self.nodes
+            .iter()
+            .map(|(code, descricao)| (code, descricao.as_deref()))
+    }
+
+    /// Frame joining `cnae_divisao` to `cnae_secao`, `cnae_secao_descricao`
+    /// and `cnae_divisao_descricao`
+    pub fn divisions(&self) -> PolarsResult<DataFrame> {
+        let divisions = self.level(CnaeLevel::Divisao);
+        let sections: Vec<Option<CnaeCode>> = divisions.iter().map(|d| d.parent()).collect();
+
+        DataFrame::new(vec![
+            Column::new(
+                "cnae_divisao".into(),
+                divisions
+                    .iter()
+                    .map(|d| d.as_str().parse::<i32>().ok())
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "cnae_secao".into(),
+                sections
+                    .iter()
+                    .map(|s| s.as_ref().map(CnaeCode::as_str))
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "cnae_secao_descricao".into(),
+                sections
+                    .iter()
+                    .map(|s| s.as_ref().and_then(|s| self.description(s)))
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "cnae_divisao_descricao".into(),
+                divisions
+                    .iter()
+                    .map(|d| self.description(d))
+                    .collect::<Vec<_>>(),
+            ),
+        ])
+    }
+
+    /// Frame joining `cnae_classe` to `cnae_grupo_descricao` and
+    /// `cnae_classe_descricao`
+    pub fn classes(&self) -> PolarsResult<DataFrame> {
+        let classes = self.level(CnaeLevel::Classe);
+
+        DataFrame::new(vec![
+            Column::new(
+                "cnae_classe".into(),
+                classes
+                    .iter()
+                    .map(|c| c.as_str().parse::<i32>().ok())
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "cnae_grupo_descricao".into(),
+                classes
+                    .iter()
+                    .map(|c| c.parent().and_then(|g| self.description(&g)))
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                "cnae_classe_descricao".into(),
+                classes
+                    .iter()
+                    .map(|c| self.description(c))
+                    .collect::<Vec<_>>(),
+            ),
+        ])
+    }
+}
+
+impl Default for CnaeStructure {
+    fn default() -> Self {
+        Self::embedded()
+    }
+}
+
+/// Parse a `codigo;descricao` structure at any level; a header, blank lines
+/// and lines starting with `#` are skipped
+pub fn parse(text: &str) -> Result<Vec<(CnaeCode, String)>> {
+    let mut codes = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("codigo;") {
+            continue;
+        }
+
+        let (codigo, descricao) = line.split_once(';').unwrap_or((line, ""));
+        let codigo = codigo.trim().trim_matches('"');
+        let descricao = descricao.trim().trim_matches('"');
+        match codigo.parse::<CnaeCode>() {
+            Ok(code) if !descricao.is_empty() => codes.push((code, descricao.to_string())),
+            _ => {
+                return Err(TransformError::InvalidConfig(format!(
+                    "line {} of the CNAE structure is not `codigo;descricao`: {}",
+                    index + 1,
+                    line
+                )));
+            }
+        }
+    }
+    Ok(codes)
 }
 
 #[cfg(test)]
@@ -25,5 +413,88 @@ mod tests {
         );
         assert_eq!(cnae.codigo, 4751201);
         assert!(cnae.descricao.contains("panificação"));
+        assert_eq!(cnae.secao(), Some('G'));
+        assert_eq!(
+            (cnae.divisao(), cnae.grupo(), cnae.classe()),
+            (47, 475, 47512)
+        );
+    }
+
+    #[test]
+    fn test_cnae_codes() {
+        let code = CnaeCode::subclass(111301).unwrap();
+        assert_eq!(code.to_string(), "0111-3/01");
+        assert_eq!(code.level(), CnaeLevel::Subclasse);
+        let ancestors: Vec<String> = code.ancestors().iter().map(|c| c.to_string()).collect();
+        assert_eq!(ancestors, ["A", "01", "01.1", "0111-3"]);
+        assert_eq!(
+            "47.51-2/01".parse::<CnaeCode>().unwrap().as_str(),
+            "4751201"
+        );
+        assert_eq!("C".parse::<CnaeCode>().unwrap().parent(), None);
+
+        assert!("04".parse::<CnaeCode>().is_err());
+        assert!("4751".parse::<CnaeCode>().is_err());
+        assert!("V".parse::<CnaeCode>().is_err());
+        assert!(CnaeCode::subclass(-1).is_none());
+    }
+
+    #[test]
+    fn test_cnae_structure() {
+        let mut structure = CnaeStructure::embedded();
+        assert_eq!(structure.level(CnaeLevel::Secao).len(), 21);
+        assert_eq!(structure.level(CnaeLevel::Divisao).len(), 87);
+        assert_ne!(CnaeStructure::version(), "unknown");
+
+        let section: CnaeCode = "G".parse().unwrap();
+        let children: Vec<&str> = structure
+            .children(&section)
+            .iter()
+            .map(|c| c.as_str())
+            .collect();
+        assert_eq!(children, ["45", "46", "47"]);
+
+        structure.extend_subclasses(&HashMap::from([(4751201, "Panificação".to_string())]));
+        let group: CnaeCode = "475".parse().unwrap();
+        assert!(structure.contains(&group));
+        assert_eq!(structure.description(&group), None);
+        assert_eq!(structure.undescribed(CnaeLevel::Grupo), [&group]);
+        assert!(structure.undescribed(CnaeLevel::Divisao).is_empty());
+        assert_eq!(structure.children(&group).len(), 1);
+        assert_eq!(
+            structure.description(&"4751201".parse().unwrap()),
+            Some("Panificação")
+        );
+
+        structure.extend_subclasses(&HashMap::from([(
+            6204000,
+            "Consultoria em tecnologia da informação".to_string(),
+        )]));
+        let class: CnaeCode = "6204-0".parse().unwrap();
+        assert_eq!(
+            structure.description(&class),
+            Some("Consultoria em tecnologia da informação")
+        );
+        assert_eq!(
+            structure.description(&"4751-2".parse().unwrap()),
+            None,
+            "only a /00 subclass describes its class"
+        );
+
+        let df = structure.divisions().unwrap();
+        assert_eq!(df.height(), 87);
+        let df = structure.classes().unwrap();
+        assert_eq!(df.height(), 2);
+        assert_eq!(
+            df.column("cnae_classe_descricao")
+                .unwrap()
+                .str()
+                .unwrap()
+                .get(1),
+            Some("Consultoria em tecnologia da informação")
+        );
+        assert!(parse("47.5;Comércio varejista não-especializado\n").is_ok());
+        assert!(parse("4751;Comércio\n").is_err());
+        assert!(parse("47\n").is_err());
     }
 }
@@ -0,0 +1,270 @@
+use bytes::BytesMut;
+use diesel::deserialize::{self, FromSql as DieselFromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, Output, ToSql as DieselToSql};
+use diesel::sql_types::Text;
+use postgres::types::{FromSql, IsNull, ToSql, Type, to_sql_checked};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Length of a CNPJ without punctuation
+const CNPJ_LENGTH: usize = 14;
+
+/// Weights of the first check digit; the second uses `6` followed by these
+const WEIGHTS: [u32; 12] = [5, 4, 3, 2, 9, 8, 7, 6, 5, 4, 3, 2];
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CnpjError {
+    #[error("CNPJ must have 14 characters: {0}")]
+    InvalidLength(String),
+
+    #[error("invalid character in CNPJ: {0}")]
+    InvalidCharacter(String),
+
+    #[error("invalid CNPJ check digits: {0}")]
+    InvalidCheckDigits(String),
+}
+
+/// A validated CNPJ, numeric or alphanumeric
+///
+/// The first 12 characters (`basico` and `ordem`) are digits or uppercase
+/// letters and the last two (`dv`) are mod-11 check digits computed over the
+/// ASCII code of each character minus 48, so digits keep their value and
+/// `A` counts as 17. Numeric CNPJs are the special case with no letters.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+pub struct Cnpj(String);
+
+impl Cnpj {
+    /// Build a CNPJ from its `basico` and `ordem`, computing the check digits
+    pub fn from_parts(basico: &str, ordem: &str) -> Result<Self, CnpjError> {
+        let base = format!("{}{}", basico, ordem).to_uppercase();
+        if base.len() != CNPJ_LENGTH - 2 {
+            return Err(CnpjError::InvalidLength(base));
+        }
+        if !base
+            .bytes()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+        {
+            return Err(CnpjError::InvalidCharacter(base));
+        }
+
+        let first = check_digit(base.as_bytes(), &WEIGHTS[..]);
+        let second = check_digit(
+            format!("{}{}", base, first).as_bytes(),
+            &[6].iter().chain(&WEIGHTS).copied().collect::<Vec<_>>(),
+        );
+        Ok(Self(format!("{}{}{}", base, first, second)))
+    }
+
+    /// CNPJ of the head office (`ordem` `0001`) of a `basico`
+    pub fn matriz(basico: &str) -> Result<Self, CnpjError> {
+        Self::from_parts(basico, "0001")
+    }
+
+    /// The 14 characters without punctuation
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Company root shared by the head office and its branches
+    pub fn basico(&self) -> &str {
+        &self.0[..8]
+    }
+
+    /// Establishment number (`0001` for the head office)
+    pub fn ordem(&self) -> &str {
+        &self.0[8..12]
+    }
+
+    /// Check digits
+    pub fn dv(&self) -> &str {
+        &self.0[12..]
+    }
+
+    /// Format with the usual mask, e.g. `33.683.111/0002-80`
+    pub fn formatted(&self) -> String {
+        let s = &self.0;
+        format!(
+            "{}.{}.{}/{}-{}",
+            &s[..2],
+            &s[2..5],
+            &s[5..8],
+            &s[8..12],
+            &s[12..]
+        )
+    }
+}
+
+/// Mod-11 check digit of `chars` with the given weights
+fn check_digit(chars: &[u8], weights: &[u32]) -> u32 {
+    let sum: u32 = chars
+        .iter()
+        .zip(weights)
+        .map(|(&c, &weight)| (c as u32 - 48) * weight)
+        .sum();
+    match sum % 11 {
+        0 | 1 => 0,
+        remainder => 11 - remainder,
+    }
+}
+
+impl FromStr for Cnpj {
+    type Err = CnpjError;
+
+    /// Parse a CNPJ with or without the `.`, `/` and `-` punctuation
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw: String = s
+            .chars()
+            .filter(|c| !matches!(c, '.' | '/' | '-') && !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase();
+
+        if !raw.is_ascii() {
+            return Err(CnpjError::InvalidCharacter(s.to_string()));
+        }
+        if raw.len() != CNPJ_LENGTH {
+            return Err(CnpjError::InvalidLength(s.to_string()));
+        }
+        if !raw[12..].bytes().all(|c| c.is_ascii_digit()) {
+            return Err(CnpjError::InvalidCharacter(s.to_string()));
+        }
+
+        let cnpj = Self::from_parts(&raw[..8], &raw[8..12])
+            .map_err(|_| CnpjError::InvalidCharacter(s.to_string()))?;
+        if cnpj.0 != raw {
+            return Err(CnpjError::InvalidCheckDigits(s.to_string()));
+        }
+        Ok(cnpj)
+    }
+}
+
+/// `00000000000000`, which has valid check digits
+impl Default for Cnpj {
+    fn default() -> Self {
+        Self("0".repeat(CNPJ_LENGTH))
+    }
+}
+
+impl fmt::Display for Cnpj {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for Cnpj {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Serialize for Cnpj {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cnpj {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl ToSql for Cnpj {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        <&str as ToSql>::to_sql(&self.as_str(), ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <&str as ToSql>::accepts(ty)
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for Cnpj {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Ok(<&str as FromSql>::from_sql(ty, raw)?.parse()?)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <&str as FromSql>::accepts(ty)
+    }
+}
+
+impl DieselToSql<Text, Pg> for Cnpj {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as DieselToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl DieselFromSql<Text, Pg> for Cnpj {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let s = <String as DieselFromSql<Text, Pg>>::from_sql(bytes)?;
+        Ok(s.parse()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_numeric() {
+        let cnpj: Cnpj = "33.683.111/0002-80".parse().unwrap();
+        assert_eq!(cnpj.as_str(), "33683111000280");
+        assert_eq!(cnpj.basico(), "33683111");
+        assert_eq!(cnpj.ordem(), "0002");
+        assert_eq!(cnpj.dv(), "80");
+        assert_eq!(cnpj.formatted(), "33.683.111/0002-80");
+        assert_eq!(Cnpj::matriz("33683111").unwrap().as_str(), "33683111000107");
+    }
+
+    #[test]
+    fn test_parse_alphanumeric() {
+        // Example published by the Receita Federal
+        let cnpj: Cnpj = "12.ABC.345/01DE-35".parse().unwrap();
+        assert_eq!(cnpj.as_str(), "12ABC34501DE35");
+        assert_eq!(cnpj.basico(), "12ABC345");
+        assert_eq!(cnpj.ordem(), "01DE");
+        assert_eq!("12abc34501de35".parse::<Cnpj>(), Ok(cnpj));
+    }
+
+    #[test]
+    fn test_reject_invalid() {
+        assert_eq!(
+            "33683111000281".parse::<Cnpj>(),
+            Err(CnpjError::InvalidCheckDigits("33683111000281".to_string()))
+        );
+        assert!(matches!(
+            "3368311100028".parse::<Cnpj>(),
+            Err(CnpjError::InvalidLength(_))
+        ));
+        assert!(matches!(
+            "12ABC34501DEA5".parse::<Cnpj>(),
+            Err(CnpjError::InvalidCharacter(_))
+        ));
+        assert!(matches!(
+            "12ABC3450!DE35".parse::<Cnpj>(),
+            Err(CnpjError::InvalidCharacter(_))
+        ));
+    }
+
+    #[test]
+    fn test_serde() {
+        let cnpj: Cnpj = "33683111000280".parse().unwrap();
+        assert_eq!(serde_json::to_string(&cnpj).unwrap(), "\"33683111000280\"");
+        assert!(serde_json::from_str::<Cnpj>("\"33683111000281\"").is_err());
+    }
+}
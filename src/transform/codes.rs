@@ -0,0 +1,410 @@
+use bytes::BytesMut;
+use postgres::types::{FromSql, IsNull, ToSql, Type, to_sql_checked};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A code outside the values the Receita Federal layout defines for a field
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("unknown {field} code {code} (expected one of {expected})")]
+pub struct UnknownCode {
+    pub field: &'static str,
+    pub code: String,
+    pub expected: String,
+}
+
+/// A field the Receita Federal publishes as a numeric code
+pub trait Coded: Copy + Sized + Send + Sync + 'static {
+    /// Column name of the code, e.g. `situacao_cadastral`
+    const FIELD: &'static str;
+    /// Every value, in code order
+    const ALL: &'static [Self];
+
+    fn code(self) -> i32;
+    fn description(self) -> &'static str;
+
+    /// The value of a code, or an error naming the field and the valid codes
+    fn from_code(code: i32) -> Result<Self, UnknownCode> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|value| value.code() == code)
+            .ok_or_else(|| UnknownCode {
+                field: Self::FIELD,
+                code: code.to_string(),
+                expected: Self::ALL
+                    .iter()
+                    .map(|value| value.code().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+    }
+
+    /// The value of a code (`2`, `02`) or of a description, compared
+    /// without accents or case (`ativa`)
+    fn from_text(text: &str) -> Result<Self, UnknownCode> {
+        if let Ok(code) = text.trim().parse() {
+            return Self::from_code(code);
+        }
+        let normalized = normalize(text);
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|value| normalize(value.description()) == normalized)
+            .ok_or_else(|| UnknownCode {
+                field: Self::FIELD,
+                code: text.trim().to_string(),
+                expected: Self::ALL
+                    .iter()
+                    .map(|value| value.description())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+    }
+}
+
+/// Define a coded enum serialized as its code, displayed as its description
+/// and stored as an `INTEGER` column
+macro_rules! coded {
+    (
+        $(#[$meta:meta])*
+        $name:ident, $field:literal {
+            $($(#[$variant_meta:meta])* $variant:ident = $code:literal => $description:literal,)+
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        #[serde(try_from = "i32", into = "i32")]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)+
+        }
+
+        impl Coded for $name {
+            const FIELD: &'static str = $field;
+            const ALL: &'static [Self] = &[$($name::$variant,)+];
+
+            fn code(self) -> i32 {
+                match self {
+                    $($name::$variant => $code,)+
+                }
+            }
+
+            fn description(self) -> &'static str {
+                match self {
+                    $($name::$variant => $description,)+
+                }
+            }
+        }
+
+        impl TryFrom<i32> for $name {
+            type Error = UnknownCode;
+
+            fn try_from(code: i32) -> Result<Self, UnknownCode> {
+                Self::from_code(code)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = UnknownCode;
+
+            fn from_str(s: &str) -> Result<Self, UnknownCode> {
+                Self::from_text(s)
+            }
+        }
+
+        impl From<$name> for i32 {
+            fn from(value: $name) -> i32 {
+                value.code()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.description())
+            }
+        }
+
+        impl<'a> FromSql<'a> for $name {
+            fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+                Ok(Self::from_code(i32::from_sql(ty, raw)?)?)
+            }
+
+            fn accepts(ty: &Type) -> bool {
+                <i32 as FromSql>::accepts(ty)
+            }
+        }
+
+        impl ToSql for $name {
+            fn to_sql(
+                &self,
+                ty: &Type,
+                out: &mut BytesMut,
+            ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+                self.code().to_sql(ty, out)
+            }
+
+            fn accepts(ty: &Type) -> bool {
+                <i32 as ToSql>::accepts(ty)
+            }
+
+            to_sql_checked!();
+        }
+    };
+}
+
+coded! {
+    /// Whether an establishment is the head office or a branch
+    MatrizFilial, "identificador_matriz_filial" {
+        Matriz = 1 => "MATRIZ",
+        Filial = 2 => "FILIAL",
+    }
+}
+
+coded! {
+    /// Registration status of an establishment
+    SituacaoCadastral, "situacao_cadastral" {
+        Nula = 1 => "NULA",
+        Ativa = 2 => "ATIVA",
+        Suspensa = 3 => "SUSPENSA",
+        Inapta = 4 => "INAPTA",
+        Baixada = 8 => "BAIXADA",
+    }
+}
+
+coded! {
+    /// Size of a company, published as `00`, `01`, `03` or `05`
+    Porte, "codigo_porte" {
+        NaoInformado = 0 => "NÃO INFORMADO",
+        MicroEmpresa = 1 => "MICRO EMPRESA",
+        EmpresaDePequenoPorte = 3 => "EMPRESA DE PEQUENO PORTE",
+        Demais = 5 => "DEMAIS",
+    }
+}
+
+coded! {
+    /// Whether a partner is a company, a person or a foreigner
+    IdentificadorSocio, "identificador_socio" {
+        PessoaJuridica = 1 => "PESSOA JURÍDICA",
+        PessoaFisica = 2 => "PESSOA FÍSICA",
+        Estrangeiro = 3 => "ESTRANGEIRO",
+    }
+}
+
+coded! {
+    /// Age range of a partner; `0` is used for partners that are not people
+    FaixaEtaria, "codigo_faixa_etaria" {
+        NaoSeAplica = 0 => "Não se aplica",
+        Ate12 = 1 => "0 a 12 anos",
+        De13A20 = 2 => "13 a 20 anos",
+        De21A30 = 3 => "21 a 30 anos",
+        De31A40 = 4 => "31 a 40 anos",
+        De41A50 = 5 => "41 a 50 anos",
+        De51A60 = 6 => "51 a 60 anos",
+        De61A70 = 7 => "61 a 70 anos",
+        De71A80 = 8 => "71 a 80 anos",
+        MaisDe80 = 9 => "Maiores de 80 anos",
+    }
+}
+
+/// Special situation of an establishment
+///
+/// The Receita Federal publishes it as free text rather than a code, so the
+/// values are matched without accents or case and written as their
+/// `description`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SituacaoEspecial {
+    RecuperacaoJudicial,
+    Falido,
+    EmLiquidacao,
+    LiquidacaoJudicial,
+    LiquidacaoExtrajudicial,
+    Intervencao,
+    Inventario,
+}
+
+impl SituacaoEspecial {
+    pub const ALL: [SituacaoEspecial; 7] = [
+        SituacaoEspecial::RecuperacaoJudicial,
+        SituacaoEspecial::Falido,
+        SituacaoEspecial::EmLiquidacao,
+        SituacaoEspecial::LiquidacaoJudicial,
+        SituacaoEspecial::LiquidacaoExtrajudicial,
+        SituacaoEspecial::Intervencao,
+        SituacaoEspecial::Inventario,
+    ];
+
+    /// The text as the Receita Federal writes it
+    pub fn description(self) -> &'static str {
+        match self {
+            SituacaoEspecial::RecuperacaoJudicial => "RECUPERACAO JUDICIAL",
+            SituacaoEspecial::Falido => "FALIDO",
+            SituacaoEspecial::EmLiquidacao => "EM LIQUIDACAO",
+            SituacaoEspecial::LiquidacaoJudicial => "LIQUIDACAO JUDICIAL",
+            SituacaoEspecial::LiquidacaoExtrajudicial => "LIQUIDACAO EXTRA-JUDICIAL",
+            SituacaoEspecial::Intervencao => "INTERVENCAO",
+            SituacaoEspecial::Inventario => "INVENTARIO",
+        }
+    }
+
+    /// The special situation of a text, `Ok(None)` when it is blank
+    pub fn parse(text: &str) -> Result<Option<Self>, UnknownCode> {
+        if text.trim().is_empty() {
+            return Ok(None);
+        }
+        text.parse().map(Some)
+    }
+}
+
+/// Upper-case ASCII letters only, so `Liquidação extrajudicial` and
+/// `LIQUIDACAO EXTRA-JUDICIAL` compare equal
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            'á' | 'à' | 'â' | 'ã' | 'Á' | 'À' | 'Â' | 'Ã' => Some('A'),
+            'é' | 'ê' | 'É' | 'Ê' => Some('E'),
+            'í' | 'Í' => Some('I'),
+            'ó' | 'ô' | 'õ' | 'Ó' | 'Ô' | 'Õ' => Some('O'),
+            'ú' | 'Ú' => Some('U'),
+            'ç' | 'Ç' => Some('C'),
+            c if c.is_ascii_alphabetic() => Some(c.to_ascii_uppercase()),
+            _ => None,
+        })
+        .collect()
+}
+
+impl FromStr for SituacaoEspecial {
+    type Err = UnknownCode;
+
+    fn from_str(s: &str) -> Result<Self, UnknownCode> {
+        let text = normalize(s);
+        Self::ALL
+            .into_iter()
+            .find(|value| normalize(value.description()) == text)
+            .ok_or_else(|| UnknownCode {
+                field: "situacao_especial",
+                code: s.trim().to_string(),
+                expected: Self::ALL
+                    .iter()
+                    .map(|value| value.description())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+    }
+}
+
+impl TryFrom<String> for SituacaoEspecial {
+    type Error = UnknownCode;
+
+    fn try_from(text: String) -> Result<Self, UnknownCode> {
+        text.parse()
+    }
+}
+
+impl From<SituacaoEspecial> for String {
+    fn from(value: SituacaoEspecial) -> String {
+        value.description().to_string()
+    }
+}
+
+impl fmt::Display for SituacaoEspecial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl<'a> FromSql<'a> for SituacaoEspecial {
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(<&str>::from_sql(ty, raw)?.parse()?)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <&str as FromSql>::accepts(ty)
+    }
+}
+
+impl ToSql for SituacaoEspecial {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        self.description().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <&str as ToSql>::accepts(ty)
+    }
+
+    to_sql_checked!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coded_values() {
+        assert_eq!(SituacaoCadastral::try_from(2), Ok(SituacaoCadastral::Ativa));
+        assert_eq!(SituacaoCadastral::Baixada.to_string(), "BAIXADA");
+        assert_eq!(
+            Porte::try_from(3).unwrap().to_string(),
+            "EMPRESA DE PEQUENO PORTE"
+        );
+        assert_eq!(FaixaEtaria::try_from(0), Ok(FaixaEtaria::NaoSeAplica));
+        assert_eq!(i32::from(IdentificadorSocio::Estrangeiro), 3);
+
+        let error = SituacaoCadastral::try_from(5).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown situacao_cadastral code 5 (expected one of 1, 2, 3, 4, 8)"
+        );
+        assert!(MatrizFilial::try_from(0).is_err());
+    }
+
+    #[test]
+    fn test_coded_from_text() {
+        assert_eq!("ATIVA".parse(), Ok(SituacaoCadastral::Ativa));
+        assert_eq!("02".parse(), Ok(SituacaoCadastral::Ativa));
+        assert_eq!("micro empresa".parse(), Ok(Porte::MicroEmpresa));
+        assert_eq!(
+            "Pessoa Fisica".parse(),
+            Ok(IdentificadorSocio::PessoaFisica)
+        );
+        let error = "ABERTA".parse::<SituacaoCadastral>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown situacao_cadastral code ABERTA (expected one of NULA, ATIVA, SUSPENSA, INAPTA, BAIXADA)"
+        );
+    }
+
+    #[test]
+    fn test_coded_serde() {
+        assert_eq!(serde_json::to_string(&Porte::Demais).unwrap(), "5");
+        assert_eq!(
+            serde_json::from_str::<MatrizFilial>("2").unwrap(),
+            MatrizFilial::Filial
+        );
+        assert!(serde_json::from_str::<MatrizFilial>("7").is_err());
+    }
+
+    #[test]
+    fn test_situacao_especial() {
+        assert_eq!(
+            "Liquidação extrajudicial".parse(),
+            Ok(SituacaoEspecial::LiquidacaoExtrajudicial)
+        );
+        assert_eq!(
+            SituacaoEspecial::parse(" RECUPERACAO JUDICIAL "),
+            Ok(Some(SituacaoEspecial::RecuperacaoJudicial))
+        );
+        assert_eq!(SituacaoEspecial::parse(""), Ok(None));
+        assert!(SituacaoEspecial::parse("EM OBRAS").is_err());
+        assert_eq!(
+            serde_json::to_string(&SituacaoEspecial::Falido).unwrap(),
+            "\"FALIDO\""
+        );
+    }
+}
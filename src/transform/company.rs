@@ -1,30 +1,33 @@
-use lazy_static::lazy_static;
-use regex::Regex;
+use super::{
+    CNAE, Cnpj, Coded, MatrizFilial, Partner, Porte, SituacaoCadastral, SituacaoEspecial, privacy,
+};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 
-lazy_static! {
-    /// Regex for matching CPF pattern in MEI names: (\D)(\d{3})(\d{5})(\d{3})
-    static ref CPF_REGEX: Regex = Regex::new(r"(\D)(\d{3})(\d{5})(\d{3})$").unwrap();
-}
-
 /// Company data structure matching the Federal Revenue format
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// This is also the document written by `--format jsonl`: fields are serialized
+/// in declaration order, so new fields must only be appended.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Company {
-    pub cnpj: String,
-    pub identificador_matriz_filial: Option<i32>,
+    pub cnpj: Cnpj,
+    pub identificador_matriz_filial: Option<MatrizFilial>,
     pub descricao_identificador_matriz_filial: Option<String>,
     pub nome_fantasia: String,
-    pub situacao_cadastral: Option<i32>,
+    pub situacao_cadastral: Option<SituacaoCadastral>,
     pub descricao_situacao_cadastral: Option<String>,
-    pub data_situacao_cadastral: Option<String>,
+    pub data_situacao_cadastral: Option<NaiveDate>,
     pub motivo_situacao_cadastral: Option<i32>,
     pub descricao_motivo_situacao_cadastral: Option<String>,
     pub nome_cidade_no_exterior: String,
     pub codigo_pais: Option<i32>,
     pub pais: Option<String>,
-    pub data_inicio_atividade: Option<String>,
+    pub data_inicio_atividade: Option<NaiveDate>,
     pub cnae_fiscal: Option<i32>,
     pub cnae_fiscal_descricao: Option<String>,
+    #[serde(default)]
+    pub cnaes_secundarios: Vec<CNAE>,
     pub descricao_tipo_de_logradouro: String,
     pub logradouro: String,
     pub numero: String,
@@ -39,50 +42,71 @@ pub struct Company {
     pub ddd_telefone_2: String,
     pub ddd_fax: String,
     pub email: Option<String>,
-    pub situacao_especial: String,
-    pub data_situacao_especial: Option<String>,
+    pub situacao_especial: Option<SituacaoEspecial>,
+    pub data_situacao_especial: Option<NaiveDate>,
     pub opcao_pelo_simples: Option<bool>,
-    pub data_opcao_pelo_simples: Option<String>,
-    pub data_exclusao_do_simples: Option<String>,
+    pub data_opcao_pelo_simples: Option<NaiveDate>,
+    pub data_exclusao_do_simples: Option<NaiveDate>,
     pub opcao_pelo_mei: Option<bool>,
-    pub data_opcao_pelo_mei: Option<String>,
-    pub data_exclusao_do_mei: Option<String>,
+    pub data_opcao_pelo_mei: Option<NaiveDate>,
+    pub data_exclusao_do_mei: Option<NaiveDate>,
     pub razao_social: String,
     pub codigo_natureza_juridica: Option<i32>,
     pub natureza_juridica: Option<String>,
     pub qualificacao_do_responsavel: Option<i32>,
-    pub capital_social: Option<f32>,
-    pub codigo_porte: Option<i32>,
+    /// Exact amount with two decimal places, serialized as a string
+    pub capital_social: Option<BigDecimal>,
+    pub codigo_porte: Option<Porte>,
     pub porte: Option<String>,
     pub ente_federativo_responsavel: String,
+    #[serde(default)]
+    pub qsa: Vec<Partner>,
+    /// IBGE region of the municipality, e.g. `Centro-Oeste`
+    #[serde(default)]
+    pub regiao: Option<String>,
+    /// CNAE section of `cnae_fiscal`, e.g. `G`
+    #[serde(default)]
+    pub cnae_secao: Option<String>,
+    #[serde(default)]
+    pub cnae_secao_descricao: Option<String>,
+    /// CNAE division of `cnae_fiscal`, e.g. `47`
+    #[serde(default)]
+    pub cnae_divisao: Option<i32>,
+    #[serde(default)]
+    pub cnae_divisao_descricao: Option<String>,
+    /// CNAE group of `cnae_fiscal`, e.g. `475`
+    #[serde(default)]
+    pub cnae_grupo: Option<i32>,
+    /// CNAE class of `cnae_fiscal` with its check digit, e.g. `47512`
+    #[serde(default)]
+    pub cnae_classe: Option<i32>,
+    #[serde(default)]
+    pub cnae_grupo_descricao: Option<String>,
+    #[serde(default)]
+    pub cnae_classe_descricao: Option<String>,
 }
 
 impl Company {
-    /// Parse situacao cadastral and return description
+    /// Description of a situacao cadastral code, see `SituacaoCadastral`
     pub fn parse_situacao_cadastral(code: i32) -> Option<&'static str> {
-        match code {
-            1 => Some("NULA"),
-            2 => Some("ATIVA"),
-            3 => Some("SUSPENSA"),
-            4 => Some("INAPTA"),
-            8 => Some("BAIXADA"),
-            _ => None,
-        }
+        SituacaoCadastral::from_code(code)
+            .ok()
+            .map(Coded::description)
     }
 
-    /// Parse identificador matriz/filial and return description
+    /// Description of an identificador matriz/filial code, see `MatrizFilial`
     pub fn parse_matriz_filial(code: i32) -> Option<&'static str> {
-        match code {
-            1 => Some("MATRIZ"),
-            2 => Some("FILIAL"),
-            _ => None,
-        }
+        MatrizFilial::from_code(code).ok().map(Coded::description)
+    }
+
+    /// Description of a porte code, see `Porte`
+    pub fn parse_porte(code: i32) -> Option<&'static str> {
+        Porte::from_code(code).ok().map(Coded::description)
     }
 
     /// Clean company name for privacy (masks CPF in MEI names)
     pub fn clean_name(name: &str) -> String {
-        // Replace middle digits with ***
-        CPF_REGEX.replace(name, "$1***$3***").trim().to_string()
+        privacy::mask_cpfs(name).trim().to_string()
     }
 }
 
@@ -104,6 +128,18 @@ mod tests {
         assert_eq!(Company::parse_matriz_filial(3), None);
     }
 
+    #[test]
+    fn test_document_schema() {
+        let json = serde_json::to_string(&Company::default()).unwrap();
+        assert!(
+            json.starts_with(r#"{"cnpj":"00000000000000","identificador_matriz_filial":null,"#)
+        );
+        assert!(json.contains(r#""cnae_fiscal_descricao":null,"cnaes_secundarios":[],"#));
+        assert!(json.contains(r#""ente_federativo_responsavel":"","qsa":[],"#));
+        assert!(json.contains(r#""qsa":[],"regiao":null,"cnae_secao":null,"#));
+        assert!(json.ends_with(r#""cnae_grupo":null,"cnae_classe":null,"cnae_grupo_descricao":null,"cnae_classe_descricao":null}"#));
+    }
+
     #[test]
     fn test_clean_name() {
         let name = "JOAO SILVA 12345678901";
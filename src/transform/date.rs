@@ -0,0 +1,55 @@
+use chrono::NaiveDate;
+use thiserror::Error;
+
+/// Date format used by every `data_*` field in the Federal Revenue files
+pub const DATE_FORMAT: &str = "%Y%m%d";
+
+/// A non-empty `data_*` value that is not a calendar date (e.g. `20210231`)
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid date: {0}")]
+pub struct InvalidDate(pub String);
+
+/// Parse a raw `YYYYMMDD` date
+///
+/// Blank values, `0` and `00000000` mean "no date" and give `Ok(None)`. Any
+/// other value that is not a valid date is an `InvalidDate`; the transform
+/// writes those as null too and counts them in the `TransformReport`.
+pub fn parse_date(raw: &str) -> Result<Option<NaiveDate>, InvalidDate> {
+    let raw = raw.trim();
+    if is_empty_date(raw) {
+        return Ok(None);
+    }
+
+    if raw.len() != 8 {
+        return Err(InvalidDate(raw.to_string()));
+    }
+    NaiveDate::parse_from_str(raw, DATE_FORMAT)
+        .map(Some)
+        .map_err(|_| InvalidDate(raw.to_string()))
+}
+
+/// Values the Federal Revenue uses for "no date"
+fn is_empty_date(raw: &str) -> bool {
+    matches!(raw, "" | "0" | "00000000")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(
+            parse_date("20210115"),
+            Ok(NaiveDate::from_ymd_opt(2021, 1, 15))
+        );
+        assert_eq!(parse_date(""), Ok(None));
+        assert_eq!(parse_date("0"), Ok(None));
+        assert_eq!(parse_date("00000000"), Ok(None));
+        assert_eq!(
+            parse_date("20210231"),
+            Err(InvalidDate("20210231".to_string()))
+        );
+        assert!(parse_date("2021011").is_err());
+    }
+}
@@ -0,0 +1,583 @@
+use super::spill::parquet_files;
+use super::{
+    Coded, Compression, OutputFormat, OutputWriter, Result, SituacaoCadastral, TransformConfig,
+    TransformError,
+};
+use chrono::NaiveDate;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// File name of the summary inside the diff output directory
+pub const SUMMARY_FILE: &str = "diff_summary.json";
+
+/// Table of the change events inside the diff output directory
+const CHANGES_TABLE: &str = "changes";
+
+/// Address columns of the companies table
+const ADDRESS_COLUMNS: [&str; 8] = [
+    "descricao_tipo_de_logradouro",
+    "logradouro",
+    "numero",
+    "complemento",
+    "bairro",
+    "cep",
+    "uf",
+    "codigo_municipio",
+];
+
+/// Activity columns of the companies table
+const CNAE_COLUMNS: [&str; 2] = ["cnae_fiscal", "cnaes_secundarios"];
+
+/// Columns identifying a partner of a company
+const PARTNER_KEY: [&str; 4] = [
+    "cnpj_basico",
+    "identificador_socio",
+    "nome_socio",
+    "cnpj_cpf_socio",
+];
+
+/// Columns of the change events, in `ChangeEvent` field order
+const EVENT_COLUMNS: [&str; 6] = ["kind", "cnpj_basico", "cnpj", "field", "before", "after"];
+
+/// Kind of change between two releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// Establishment present only in the later release
+    Opened,
+    /// `situacao_cadastral` changed to BAIXADA
+    Closed,
+    /// `situacao_cadastral` changed to anything but BAIXADA
+    StatusChanged,
+    /// One of the address columns changed
+    AddressChanged,
+    /// The main or a secondary CNAE changed
+    CnaeChanged,
+    /// Partner present only in the later release
+    PartnerAdded,
+    /// Partner present only in the earlier release
+    PartnerRemoved,
+    /// Establishment present only in the earlier release
+    Removed,
+}
+
+impl ChangeKind {
+    pub const ALL: [ChangeKind; 8] = [
+        ChangeKind::Opened,
+        ChangeKind::Closed,
+        ChangeKind::StatusChanged,
+        ChangeKind::AddressChanged,
+        ChangeKind::CnaeChanged,
+        ChangeKind::PartnerAdded,
+        ChangeKind::PartnerRemoved,
+        ChangeKind::Removed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Opened => "opened",
+            ChangeKind::Closed => "closed",
+            ChangeKind::StatusChanged => "status_changed",
+            ChangeKind::AddressChanged => "address_changed",
+            ChangeKind::CnaeChanged => "cnae_changed",
+            ChangeKind::PartnerAdded => "partner_added",
+            ChangeKind::PartnerRemoved => "partner_removed",
+            ChangeKind::Removed => "removed",
+        }
+    }
+}
+
+impl FromStr for ChangeKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        ChangeKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("unknown change kind: {}", s))
+    }
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One change of a company between two releases
+///
+/// Values are written as text: codes as their number, secondary CNAEs
+/// comma-separated and partners by `nome_socio`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    pub kind: ChangeKind,
+    pub cnpj_basico: String,
+    /// Establishment that changed; `None` for partner changes
+    pub cnpj: Option<String>,
+    /// Column that changed; `None` for opened and removed establishments
+    pub field: Option<String>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Counts of the change events of a diff, written as `diff_summary.json`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiffSummary {
+    pub from: String,
+    pub to: String,
+    pub changes: BTreeMap<ChangeKind, u64>,
+}
+
+impl DiffSummary {
+    /// Number of events of every kind
+    pub fn total(&self) -> u64 {
+        self.changes.values().sum()
+    }
+
+    /// Number of events of one kind
+    pub fn count(&self, kind: ChangeKind) -> u64 {
+        self.changes.get(&kind).copied().unwrap_or(0)
+    }
+
+    /// Write the summary as pretty-printed JSON into `dir`
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(SUMMARY_FILE);
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), self)?;
+        Ok(path)
+    }
+}
+
+/// Directory of a release: `<root>/<YYYY-MM>` for a month, or the path itself
+pub fn release_dir(root: &str, release: &str) -> PathBuf {
+    let month = NaiveDate::parse_from_str(&format!("{}-01", release), "%Y-%m-%d").is_ok();
+    match month && release.len() == 7 {
+        true => Path::new(root).join(release),
+        false => PathBuf::from(release),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffConfig {
+    /// Transformed Parquet output of the earlier release
+    pub from: String,
+    /// Transformed Parquet output of the later release
+    pub to: String,
+    pub output_dir: String,
+    pub format: OutputFormat,
+    pub compression: Compression,
+}
+
+impl DiffConfig {
+    /// Validate the configuration
+    pub fn validate(&self) -> Result<()> {
+        self.writer_config().validate()?;
+        for release in [&self.from, &self.to] {
+            if !Path::new(release).join("companies").is_dir() {
+                return Err(TransformError::InvalidConfig(format!(
+                    "{} is not a transform output (no companies directory)",
+                    release
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Settings of the `OutputWriter` of the change events
+    fn writer_config(&self) -> TransformConfig {
+        TransformConfig {
+            output_dir: self.output_dir.clone(),
+            format: self.format,
+            compression: self.compression,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        Self {
+            from: String::new(),
+            to: String::new(),
+            output_dir: "diff".to_string(),
+            format: OutputFormat::default(),
+            compression: Compression::default(),
+        }
+    }
+}
+
+/// Compares the transformed outputs of two releases
+pub struct Differ {
+    config: DiffConfig,
+}
+
+impl Differ {
+    pub fn new(config: DiffConfig) -> Self {
+        Self { config }
+    }
+
+    /// Write the change events between the two releases and their summary
+    ///
+    /// Both companies datasets are scanned once, with the streaming engine,
+    /// and only the establishments that differ are kept in memory.
+    pub async fn diff(&self) -> Result<DiffSummary> {
+        self.config.validate()?;
+
+        // Blocking work, run off the async runtime as in `Transformer::transform`
+        let worker = Self::new(self.config.clone());
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let handle = std::thread::Builder::new()
+            .name("rfb-diff".to_string())
+            .spawn(move || {
+                let _ = tx.send(worker.run());
+            })?;
+
+        match rx.await {
+            Ok(result) => result,
+            Err(_) => std::panic::resume_unwind(handle.join().unwrap_err()),
+        }
+    }
+
+    fn run(&self) -> Result<DiffSummary> {
+        tracing::info!("Comparing {} with {}", self.config.from, self.config.to);
+        std::fs::create_dir_all(&self.config.output_dir)?;
+
+        let from = Path::new(&self.config.from);
+        let to = Path::new(&self.config.to);
+        let columns = tracked_columns();
+        let companies = company_changes(
+            scan(from, "companies", &columns)?,
+            scan(to, "companies", &columns)?,
+            &columns,
+        )?;
+        let partners = partner_changes(
+            scan(from, "partners", &PARTNER_KEY)?,
+            scan(to, "partners", &PARTNER_KEY)?,
+        )?;
+        let events = concat([companies, partners], UnionArgs::default())?
+            .sort(
+                EVENT_COLUMNS,
+                SortMultipleOptions::default()
+                    .with_nulls_last(true)
+                    .with_maintain_order(true),
+            )
+            .collect_with_engine(Engine::Streaming)?;
+
+        let writer = OutputWriter::new(&self.config.writer_config());
+        let mut part = writer.open_part(CHANGES_TABLE, 0, events.schema())?;
+        match self.config.format {
+            OutputFormat::Parquet => part.write_frame(&events)?,
+            OutputFormat::Jsonl => part.write_documents(&change_events(&events)?)?,
+        }
+        part.finish()?;
+
+        let mut summary = DiffSummary {
+            from: self.config.from.clone(),
+            to: self.config.to.clone(),
+            changes: BTreeMap::new(),
+        };
+        for kind in events.column("kind")?.str()?.into_iter().flatten() {
+            let kind = kind.parse().map_err(TransformError::InvalidFormat)?;
+            *summary.changes.entry(kind).or_default() += 1;
+        }
+        summary.write(Path::new(&self.config.output_dir))?;
+
+        tracing::info!("Found {} changes", summary.total());
+        Ok(summary)
+    }
+}
+
+/// Columns of the companies table compared between releases
+fn tracked_columns() -> Vec<&'static str> {
+    let mut columns = vec!["situacao_cadastral"];
+    columns.extend(ADDRESS_COLUMNS);
+    columns.extend(CNAE_COLUMNS);
+    columns
+}
+
+/// Kind of a change of `column`, given its new value
+fn change_kind(column: &str) -> Expr {
+    match column {
+        "situacao_cadastral" => {
+            when(after(column).eq(lit(SituacaoCadastral::Baixada.code().to_string())))
+                .then(lit(ChangeKind::Closed.as_str()))
+                .otherwise(lit(ChangeKind::StatusChanged.as_str()))
+        }
+        column if ADDRESS_COLUMNS.contains(&column) => lit(ChangeKind::AddressChanged.as_str()),
+        _ => lit(ChangeKind::CnaeChanged.as_str()),
+    }
+}
+
+/// A table of a release as text columns, with `cnpj` and `cnpj_basico`
+/// first for companies
+fn scan(release: &Path, table: &str, columns: &[&str]) -> Result<LazyFrame> {
+    let files = parquet_files(&release.join(table))?;
+    if files.is_empty() {
+        return Err(TransformError::InvalidConfig(format!(
+            "{} has no parquet {}; diff compares outputs of `rfb transform --format parquet`",
+            release.display(),
+            table
+        )));
+    }
+
+    let mut args = ScanArgsParquet::default();
+    args.hive_options.enabled = Some(false);
+    let lf = LazyFrame::scan_parquet_files(files.into(), args)?;
+
+    let mut exprs = Vec::with_capacity(columns.len() + 2);
+    if table == "companies" {
+        exprs.extend([col("cnpj"), col("cnpj_basico")]);
+    }
+    exprs.extend(columns.iter().map(|&column| text(column)));
+    Ok(lf.select(exprs))
+}
+
+/// A column cast to text; lists are joined with commas
+fn text(column: &str) -> Expr {
+    match column {
+        "cnaes_secundarios" => col(column)
+            .cast(DataType::List(Box::new(DataType::String)))
+            .list()
+            .join(lit(","), true),
+        _ => col(column).cast(DataType::String),
+    }
+}
+
+fn before(column: &str) -> Expr {
+    col(format!("{}_before", column))
+}
+
+fn after(column: &str) -> Expr {
+    col(format!("{}_after", column))
+}
+
+/// Suffix every column but `cnpj` and mark the rows of the release
+fn side(lf: LazyFrame, suffix: &str) -> LazyFrame {
+    lf.select([
+        col("cnpj"),
+        all()
+            .exclude_cols(["cnpj"])
+            .as_expr()
+            .name()
+            .suffix(&format!("_{}", suffix)),
+        lit(true).alias(format!("_{}", suffix)),
+    ])
+}
+
+/// Events of the establishments that opened, closed or changed one of `columns`
+fn company_changes(from: LazyFrame, to: LazyFrame, columns: &[&str]) -> Result<LazyFrame> {
+    let mut args = JoinArgs::new(JoinType::Full);
+    args.coalesce = JoinCoalesce::CoalesceColumns;
+    let joined = side(from, "before").join(side(to, "after"), [col("cnpj")], [col("cnpj")], args);
+
+    let changed = columns
+        .iter()
+        .map(|column| before(column).neq_missing(after(column)))
+        .fold(
+            col("_before").is_null().or(col("_after").is_null()),
+            Expr::or,
+        );
+    // Only the differing rows are materialized; events are built from them
+    let changed = joined
+        .filter(changed)
+        .collect_with_engine(Engine::Streaming)?
+        .lazy();
+
+    let event = |kind: Expr, cnpj_basico: Expr, field: Expr, old: Expr, new: Expr| {
+        [
+            kind.alias("kind"),
+            cnpj_basico.alias("cnpj_basico"),
+            col("cnpj"),
+            field.alias("field"),
+            old.alias("before"),
+            new.alias("after"),
+        ]
+    };
+    let none = || lit(NULL).cast(DataType::String);
+
+    let mut events = vec![
+        changed
+            .clone()
+            .filter(col("_before").is_null())
+            .select(event(
+                lit(ChangeKind::Opened.as_str()),
+                after("cnpj_basico"),
+                none(),
+                none(),
+                none(),
+            )),
+        changed
+            .clone()
+            .filter(col("_after").is_null())
+            .select(event(
+                lit(ChangeKind::Removed.as_str()),
+                before("cnpj_basico"),
+                none(),
+                none(),
+                none(),
+            )),
+    ];
+    let both = changed.filter(col("_before").and(col("_after")));
+    for column in columns {
+        events.push(
+            both.clone()
+                .filter(before(column).neq_missing(after(column)))
+                .select(event(
+                    change_kind(column),
+                    after("cnpj_basico"),
+                    lit(*column),
+                    before(column),
+                    after(column),
+                )),
+        );
+    }
+
+    Ok(concat(events, UnionArgs::default())?)
+}
+
+/// Events of the partners that joined or left a company
+fn partner_changes(from: LazyFrame, to: LazyFrame) -> Result<LazyFrame> {
+    // Missing documents compare equal, so a partner is matched on every key column
+    let keys = || {
+        PARTNER_KEY
+            .iter()
+            .map(|&column| col(column).fill_null(lit("")))
+            .collect::<Vec<_>>()
+    };
+    let only_in = |partners: LazyFrame, other: LazyFrame, kind: ChangeKind| {
+        let other = other
+            .select(keys())
+            .unique(None, UniqueKeepStrategy::Any)
+            .with_column(lit(true).alias("_other"));
+        let name = col("nome_socio");
+        let (old, new) = match kind {
+            ChangeKind::PartnerAdded => (lit(NULL).cast(DataType::String), name),
+            _ => (name, lit(NULL).cast(DataType::String)),
+        };
+        partners
+            .select(keys())
+            .unique(None, UniqueKeepStrategy::Any)
+            .join(
+                other,
+                PARTNER_KEY.map(col),
+                PARTNER_KEY.map(col),
+                JoinArgs::new(JoinType::Left),
+            )
+            .filter(col("_other").is_null())
+            .select([
+                lit(kind.as_str()).alias("kind"),
+                col("cnpj_basico"),
+                lit(NULL).cast(DataType::String).alias("cnpj"),
+                lit("nome_socio").alias("field"),
+                old.alias("before"),
+                new.alias("after"),
+            ])
+    };
+
+    Ok(concat(
+        [
+            only_in(to.clone(), from.clone(), ChangeKind::PartnerAdded),
+            only_in(from, to, ChangeKind::PartnerRemoved),
+        ],
+        UnionArgs::default(),
+    )?)
+}
+
+/// The rows of an events frame as `ChangeEvent`s
+fn change_events(df: &DataFrame) -> Result<Vec<ChangeEvent>> {
+    let columns = EVENT_COLUMNS
+        .iter()
+        .map(|&name| Ok(df.column(name)?.str()?))
+        .collect::<Result<Vec<_>>>()?;
+    let value = |column: usize, row: usize| columns[column].get(row).map(str::to_string);
+
+    (0..df.height())
+        .map(|row| {
+            Ok(ChangeEvent {
+                kind: value(0, row)
+                    .unwrap_or_default()
+                    .parse()
+                    .map_err(TransformError::InvalidFormat)?,
+                cnpj_basico: value(1, row).unwrap_or_default(),
+                cnpj: value(2, row),
+                field: value(3, row),
+                before: value(4, row),
+                after: value(5, row),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_dir() {
+        assert_eq!(
+            release_dir("output", "2025-09"),
+            Path::new("output/2025-09")
+        );
+        assert_eq!(
+            release_dir("output", "/data/rfb/2025-09"),
+            Path::new("/data/rfb/2025-09")
+        );
+        assert_eq!(release_dir("output", "2025-13"), Path::new("2025-13"));
+    }
+
+    #[test]
+    fn test_change_kind_names() {
+        for kind in ChangeKind::ALL {
+            assert_eq!(kind.as_str().parse(), Ok(kind));
+            assert_eq!(
+                serde_json::to_string(&kind).unwrap(),
+                format!("\"{}\"", kind)
+            );
+        }
+        assert!("moved".parse::<ChangeKind>().is_err());
+    }
+
+    #[test]
+    fn test_company_changes() {
+        let from = df!(
+            "cnpj" => ["1", "2", "3"],
+            "cnpj_basico" => ["a", "b", "c"],
+            "situacao_cadastral" => ["2", "2", "2"],
+            "cep" => [Some("70830010"), None, Some("01001000")],
+        )
+        .unwrap();
+        let to = df!(
+            "cnpj" => ["2", "3", "4"],
+            "cnpj_basico" => ["b", "c", "d"],
+            "situacao_cadastral" => ["8", "2", "2"],
+            "cep" => [None, Some("01001001"), Some("20000000")],
+        )
+        .unwrap();
+        let mut events = company_changes(from.lazy(), to.lazy(), &["situacao_cadastral", "cep"])
+            .unwrap()
+            .collect()
+            .unwrap();
+        events = events
+            .sort(["cnpj", "field"], SortMultipleOptions::default())
+            .unwrap();
+        let events = change_events(&events).unwrap();
+
+        let kinds: Vec<_> = events.iter().map(|e| (e.cnpj.as_deref(), e.kind)).collect();
+        assert_eq!(
+            kinds,
+            [
+                (Some("1"), ChangeKind::Removed),
+                (Some("2"), ChangeKind::Closed),
+                (Some("3"), ChangeKind::AddressChanged),
+                (Some("4"), ChangeKind::Opened),
+            ]
+        );
+        assert_eq!(events[2].before.as_deref(), Some("01001000"));
+        assert_eq!(events[2].after.as_deref(), Some("01001001"));
+    }
+}
@@ -0,0 +1,273 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+/// Size of the chunks read while transcoding a stream
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Character encoding of the Federal Revenue files
+///
+/// The published files are ISO-8859-1 (Latin-1). `Auto` keeps files that
+/// are already valid UTF-8 (e.g. re-encoded mirrors) and decodes the rest as
+/// Latin-1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Auto,
+    Utf8,
+    Latin1,
+}
+
+impl Encoding {
+    /// Guess the encoding of a sample; `None` while it cannot tell, i.e. the
+    /// sample is plain ASCII, possibly ending in the start of a multi-byte
+    /// sequence
+    ///
+    /// A sequence cut at the end of the sample counts as UTF-8 once the sample
+    /// holds a complete one: a single Latin-1 letter such as `Ã` (`0xC3`) is
+    /// also the first byte of a UTF-8 sequence.
+    pub fn detect(sample: &[u8]) -> Option<Encoding> {
+        if sample.is_ascii() {
+            return None;
+        }
+        match std::str::from_utf8(sample) {
+            Ok(_) => Some(Encoding::Utf8),
+            Err(e) if e.error_len().is_none() => {
+                (!sample[..e.valid_up_to()].is_ascii()).then_some(Encoding::Utf8)
+            }
+            Err(_) => Some(Encoding::Latin1),
+        }
+    }
+
+    /// Decode bytes into UTF-8 text
+    pub fn decode(self, bytes: &[u8]) -> Cow<'_, str> {
+        let encoding = match self {
+            // Whole input, so a sequence still cut at its end is not UTF-8
+            Encoding::Auto => Encoding::detect(bytes).unwrap_or(if bytes.is_ascii() {
+                Encoding::Utf8
+            } else {
+                Encoding::Latin1
+            }),
+            other => other,
+        };
+        match encoding {
+            Encoding::Latin1 => Cow::Owned(latin1_to_utf8(bytes)),
+            _ => String::from_utf8_lossy(bytes),
+        }
+    }
+
+    /// Encode text; characters outside Latin-1 are written as `?`
+    pub fn encode(self, text: &str) -> Cow<'_, [u8]> {
+        match self {
+            Encoding::Latin1 => Cow::Owned(
+                text.chars()
+                    .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+                    .collect(),
+            ),
+            _ => Cow::Borrowed(text.as_bytes()),
+        }
+    }
+
+    /// Detect the encoding of a stream, reading only until a chunk decides it
+    ///
+    /// Applies the rules of `transcode` without copying anything: a stream
+    /// that is ASCII throughout is UTF-8, and one that ends inside a
+    /// multi-byte sequence is Latin-1.
+    pub fn sniff<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        let mut pending = Vec::new();
+
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            pending.extend_from_slice(&buffer[..read]);
+            if let Some(encoding) = Encoding::detect(&pending) {
+                return Ok(encoding);
+            }
+            let ascii = pending.iter().take_while(|b| b.is_ascii()).count();
+            pending.drain(..ascii);
+        }
+        Ok(if pending.is_empty() {
+            Encoding::Utf8
+        } else {
+            Encoding::Latin1
+        })
+    }
+
+    /// Copy a stream into `writer` as UTF-8, returning the encoding that was used
+    ///
+    /// With `Auto`, ASCII is passed through until the first chunk with other
+    /// bytes decides the encoding of the whole stream. A multi-byte sequence
+    /// that may be cut at the end of a chunk is held back until the next one.
+    pub fn transcode<R: Read, W: Write>(self, mut reader: R, mut writer: W) -> io::Result<Self> {
+        let mut encoding = match self {
+            Encoding::Auto => None,
+            other => Some(other),
+        };
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        let mut pending = Vec::new();
+
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            let mut chunk = &buffer[..read];
+
+            if encoding.is_none() {
+                pending.extend_from_slice(chunk);
+                encoding = Encoding::detect(&pending);
+                if encoding.is_none() {
+                    let ascii = pending.iter().take_while(|b| b.is_ascii()).count();
+                    writer.write_all(&pending[..ascii])?;
+                    pending.drain(..ascii);
+                    continue;
+                }
+                chunk = &pending;
+            }
+            match encoding {
+                Some(Encoding::Latin1) => writer.write_all(latin1_to_utf8(chunk).as_bytes())?,
+                _ => writer.write_all(chunk)?,
+            }
+            pending.clear();
+        }
+        // A sequence still cut at the end of the stream is not UTF-8
+        if !pending.is_empty() {
+            writer.write_all(latin1_to_utf8(&pending).as_bytes())?;
+            encoding = Some(Encoding::Latin1);
+        }
+
+        writer.flush()?;
+        Ok(encoding.unwrap_or(Encoding::Utf8))
+    }
+}
+
+/// Every Latin-1 byte is the Unicode code point of the same value
+fn latin1_to_utf8(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Encoding::Auto),
+            "utf8" | "utf-8" => Ok(Encoding::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            other => Err(format!("unknown encoding: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Auto => "auto",
+            Encoding::Utf8 => "utf-8",
+            Encoding::Latin1 => "iso-8859-1",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Encoding::detect(b"\"01\";\"BRASIL\""), None);
+        assert_eq!(
+            Encoding::detect("SÃO PAULO".as_bytes()),
+            Some(Encoding::Utf8)
+        );
+        assert_eq!(Encoding::detect(b"S\xC3O PAULO"), Some(Encoding::Latin1));
+        // A UTF-8 sequence cut at the end of a chunk
+        assert_eq!(Encoding::detect(b"S\xC3\x83O P\xC3"), Some(Encoding::Utf8));
+        // ASCII then a byte that may start a sequence cannot tell yet
+        assert_eq!(Encoding::detect(b"S\xC3"), None);
+        assert_eq!(Encoding::detect(b"JOAO\xE9"), None);
+        assert_eq!(Encoding::Auto.decode(b"JOS\xC9"), "JOSÉ");
+    }
+
+    #[test]
+    fn test_transcode_latin1_at_chunk_end() {
+        // A Latin-1 letter ends the first chunk and the next chunk is ASCII
+        let mut input = vec![b'A'; CHUNK_SIZE - 1];
+        input.push(0xC9);
+        input.extend_from_slice(b"\"\n");
+        let mut output = Vec::new();
+        let used = Encoding::Auto.transcode(&input[..], &mut output).unwrap();
+
+        assert_eq!(used, Encoding::Latin1);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.ends_with("AÉ\"\n"));
+        assert_eq!(output.len(), CHUNK_SIZE + 3);
+
+        // And as the very last byte
+        let mut output = Vec::new();
+        let used = Encoding::Auto
+            .transcode(&b"JOS\xC9"[..], &mut output)
+            .unwrap();
+        assert_eq!(used, Encoding::Latin1);
+        assert_eq!(output, "JOSÉ".as_bytes());
+    }
+
+    #[test]
+    fn test_sniff_stops_at_first_decisive_chunk() {
+        // Fails any read past the first chunk
+        struct Once(Option<Vec<u8>>);
+        impl Read for Once {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let chunk = self.0.take().ok_or(io::ErrorKind::Other)?;
+                buf[..chunk.len()].copy_from_slice(&chunk);
+                Ok(chunk.len())
+            }
+        }
+        let sniffed = Encoding::sniff(Once(Some(b"JOS\xC9 DA SILVA".to_vec())));
+        assert_eq!(sniffed.unwrap(), Encoding::Latin1);
+        let sniffed = Encoding::sniff(Once(Some("JOSÉ".as_bytes().to_vec())));
+        assert_eq!(sniffed.unwrap(), Encoding::Utf8);
+
+        // Undecided until the stream ends
+        assert_eq!(Encoding::sniff(&b"BRASIL"[..]).unwrap(), Encoding::Utf8);
+        assert_eq!(Encoding::sniff(&b"JOS\xC9"[..]).unwrap(), Encoding::Latin1);
+    }
+
+    #[test]
+    fn test_encode_latin1() {
+        let bytes = Encoding::Latin1.encode("SÃO PAULO €");
+        assert_eq!(&bytes[..], b"S\xC3O PAULO ?");
+        assert_eq!(Encoding::Latin1.decode(&bytes), "SÃO PAULO ?");
+        assert_eq!(&Encoding::Utf8.encode("SÃO")[..], "SÃO".as_bytes());
+    }
+
+    #[test]
+    fn test_transcode_latin1() {
+        let mut output = Vec::new();
+        let used = Encoding::Auto
+            .transcode(&b"\"4711301\";\"Com\xE9rcio varejista\"\n"[..], &mut output)
+            .unwrap();
+
+        assert_eq!(used, Encoding::Latin1);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\"4711301\";\"Comércio varejista\"\n"
+        );
+    }
+
+    #[test]
+    fn test_transcode_keeps_utf8() {
+        let input = "\"3550308\";\"SÃO PAULO\"\n";
+        let mut output = Vec::new();
+        let used = Encoding::Auto
+            .transcode(input.as_bytes(), &mut output)
+            .unwrap();
+
+        assert_eq!(used, Encoding::Utf8);
+        assert_eq!(output, input.as_bytes());
+    }
+}
@@ -0,0 +1,255 @@
+use super::municipalities;
+use super::{Coded, Porte, Result, SituacaoCadastral, TransformError};
+use polars::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+/// A CNAE subclass (`6204000`, `6204-0/00`), or every subclass starting
+/// with some digits (`62*`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CnaePattern {
+    digits: String,
+    prefix: bool,
+}
+
+impl CnaePattern {
+    /// Whether a raw `cnae_fiscal` column matches the pattern
+    fn matches(&self, column: Expr) -> Expr {
+        match self.prefix {
+            true => column.str().starts_with(lit(self.digits.clone())),
+            false => column.eq(lit(self.digits.clone())),
+        }
+    }
+}
+
+impl FromStr for CnaePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (code, prefix) = match s.trim().strip_suffix('*') {
+            Some(code) => (code, true),
+            None => (s.trim(), false),
+        };
+        let digits: String = code
+            .chars()
+            .filter(|c| !matches!(c, '.' | '-' | '/'))
+            .collect();
+        if digits.is_empty() || digits.len() > 7 || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("invalid CNAE pattern: {}", s));
+        }
+        if !prefix && digits.len() != 7 {
+            return Err(format!(
+                "{} is not a CNAE subclass (use 7 digits, or a prefix such as 62*)",
+                s
+            ));
+        }
+        Ok(Self { digits, prefix })
+    }
+}
+
+impl fmt::Display for CnaePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.prefix {
+            true => write!(f, "{}*", self.digits),
+            false => f.write_str(&self.digits),
+        }
+    }
+}
+
+/// Subset of the companies written by a transform
+///
+/// Each filter keeps the rows matching any of its values, and an empty
+/// filter keeps every row. The establishment filters apply to the raw
+/// Estabelecimentos scan and the porte filter to the raw Empresas scan, so
+/// Polars pushes them down into the CSV readers; the build side of the joins
+/// and the partners are then cut to the selected companies.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filters {
+    /// UFs of the establishments as published, e.g. `SP`, or `EX` abroad
+    pub ufs: Vec<String>,
+    pub situacoes: Vec<SituacaoCadastral>,
+    /// Patterns of the main CNAE of the establishments
+    pub cnaes: Vec<CnaePattern>,
+    pub portes: Vec<Porte>,
+}
+
+impl Filters {
+    pub fn is_empty(&self) -> bool {
+        self.ufs.is_empty()
+            && self.situacoes.is_empty()
+            && self.cnaes.is_empty()
+            && self.portes.is_empty()
+    }
+
+    /// Validate the filters
+    pub fn validate(&self) -> Result<()> {
+        match self.ufs.iter().find(|uf| !municipalities::is_uf(uf)) {
+            Some(uf) => Err(TransformError::InvalidConfig(format!("unknown UF: {}", uf))),
+            None => Ok(()),
+        }
+    }
+
+    /// Keep the establishments of a raw Estabelecimentos frame that match
+    /// the UF, situação and CNAE filters
+    pub fn estabelecimentos(&self, raw: LazyFrame) -> LazyFrame {
+        let mut predicates = Vec::new();
+        if !self.ufs.is_empty() {
+            predicates.push(any(self.ufs.iter().map(|uf| col("uf").eq(lit(uf.clone())))));
+        }
+        if !self.situacoes.is_empty() {
+            predicates.push(any(self.situacoes.iter().map(|situacao| {
+                col("situacao_cadastral")
+                    .cast(DataType::Int32)
+                    .eq(lit(situacao.code()))
+            })));
+        }
+        if !self.cnaes.is_empty() {
+            predicates.push(any(self
+                .cnaes
+                .iter()
+                .map(|pattern| pattern.matches(col("cnae_fiscal")))));
+        }
+        match predicates.into_iter().reduce(Expr::and) {
+            Some(predicate) => raw.filter(predicate),
+            None => raw,
+        }
+    }
+
+    /// Keep the companies of a raw Empresas frame that match the porte filter
+    pub fn empresas(&self, raw: LazyFrame) -> LazyFrame {
+        match self.portes.is_empty() {
+            true => raw,
+            false => raw.filter(self.porte(col("codigo_porte").cast(DataType::Int32))),
+        }
+    }
+
+    /// Keep the rows of a companies frame whose company matches the porte
+    /// filter; their Empresas columns are null otherwise (see `empresas`)
+    pub fn companies(&self, companies: LazyFrame) -> LazyFrame {
+        match self.portes.is_empty() {
+            true => companies,
+            false => companies.filter(self.porte(col("codigo_porte"))),
+        }
+    }
+
+    /// Unique `cnpj_basico` of the companies with a selected establishment,
+    /// from the raw Estabelecimentos and Empresas frames
+    pub fn basicos(&self, estabelecimentos: LazyFrame, empresas: LazyFrame) -> LazyFrame {
+        let mut basicos = self
+            .estabelecimentos(estabelecimentos)
+            .select([col("cnpj_basico")]);
+        if !self.portes.is_empty() {
+            basicos = basicos.join(
+                self.empresas(empresas).select([col("cnpj_basico")]),
+                [col("cnpj_basico")],
+                [col("cnpj_basico")],
+                JoinArgs::new(JoinType::Inner),
+            );
+        }
+        basicos.unique(None, UniqueKeepStrategy::Any)
+    }
+
+    fn porte(&self, code: Expr) -> Expr {
+        any(self
+            .portes
+            .iter()
+            .map(|porte| code.clone().eq(lit(porte.code()))))
+    }
+}
+
+impl fmt::Display for Filters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn join<T: fmt::Display>(values: &[T], code: impl Fn(&T) -> String) -> String {
+            values.iter().map(code).collect::<Vec<_>>().join(",")
+        }
+
+        let filters = [
+            ("uf", join(&self.ufs, |uf| uf.clone())),
+            ("situacao", join(&self.situacoes, |s| s.code().to_string())),
+            ("cnae", join(&self.cnaes, |pattern| pattern.to_string())),
+            (
+                "porte",
+                join(&self.portes, |porte| porte.code().to_string()),
+            ),
+        ];
+        let filters: Vec<_> = filters
+            .iter()
+            .filter(|(_, values)| !values.is_empty())
+            .map(|(name, values)| format!("{}={}", name, values))
+            .collect();
+        match filters.is_empty() {
+            true => f.write_str("none"),
+            false => f.write_str(&filters.join(" ")),
+        }
+    }
+}
+
+/// Rows of a frame whose `cnpj_basico` is in `basicos` (see `Filters::basicos`)
+pub fn selected(lf: LazyFrame, basicos: &DataFrame) -> LazyFrame {
+    lf.join(
+        basicos.clone().lazy(),
+        [col("cnpj_basico")],
+        [col("cnpj_basico")],
+        JoinArgs::new(JoinType::Inner),
+    )
+}
+
+/// Whether any of the predicates holds
+fn any(predicates: impl Iterator<Item = Expr>) -> Expr {
+    predicates.reduce(Expr::or).unwrap_or_else(|| lit(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cnae_pattern() {
+        let pattern: CnaePattern = "62*".parse().unwrap();
+        assert_eq!(pattern.to_string(), "62*");
+        assert_eq!(
+            "6204-0/00".parse::<CnaePattern>().unwrap().to_string(),
+            "6204000"
+        );
+        assert!("62".parse::<CnaePattern>().is_err());
+        assert!("6a*".parse::<CnaePattern>().is_err());
+        assert!("*".parse::<CnaePattern>().is_err());
+    }
+
+    #[test]
+    fn test_filter_estabelecimentos() {
+        let raw = df!(
+            "cnpj_basico" => ["1", "2", "3", "4"],
+            "uf" => ["SP", "RJ", "SP", "MG"],
+            "situacao_cadastral" => ["02", "02", "08", "02"],
+            "cnae_fiscal" => ["6204000", "6201501", "6204000", "6204000"],
+        )
+        .unwrap();
+        let filters = Filters {
+            ufs: vec!["SP".to_string(), "RJ".to_string()],
+            situacoes: vec![SituacaoCadastral::Ativa],
+            cnaes: vec!["62*".parse().unwrap()],
+            ..Default::default()
+        };
+        let kept = filters.estabelecimentos(raw.lazy()).collect().unwrap();
+        let basicos: Vec<_> = kept
+            .column("cnpj_basico")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(basicos, ["1", "2"]);
+        assert_eq!(filters.to_string(), "uf=SP,RJ situacao=2 cnae=62*");
+    }
+
+    #[test]
+    fn test_validate_ufs() {
+        let filters = Filters {
+            ufs: vec!["SP".to_string(), "XX".to_string()],
+            ..Default::default()
+        };
+        assert!(filters.validate().is_err());
+        assert_eq!(Filters::default().to_string(), "none");
+    }
+}
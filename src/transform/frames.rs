@@ -0,0 +1,501 @@
+use super::date::DATE_FORMAT;
+use super::municipalities;
+use super::{
+    Coded, FaixaEtaria, IdentificadorSocio, Lookups, MatrizFilial, Porte, Result,
+    SituacaoCadastral, SituacaoEspecial,
+};
+use polars::prelude::*;
+use std::collections::HashMap;
+
+/// Output columns of the companies table, in `Company` field order
+pub const COMPANY_COLUMNS: [&str; 56] = [
+    "cnpj",
+    "cnpj_basico",
+    "identificador_matriz_filial",
+    "descricao_identificador_matriz_filial",
+    "nome_fantasia",
+    "situacao_cadastral",
+    "descricao_situacao_cadastral",
+    "data_situacao_cadastral",
+    "motivo_situacao_cadastral",
+    "descricao_motivo_situacao_cadastral",
+    "nome_cidade_no_exterior",
+    "codigo_pais",
+    "pais",
+    "data_inicio_atividade",
+    "cnae_fiscal",
+    "cnae_fiscal_descricao",
+    "cnae_secao",
+    "cnae_secao_descricao",
+    "cnae_divisao",
+    "cnae_divisao_descricao",
+    "cnae_grupo",
+    "cnae_grupo_descricao",
+    "cnae_classe",
+    "cnae_classe_descricao",
+    "cnaes_secundarios",
+    "descricao_tipo_de_logradouro",
+    "logradouro",
+    "numero",
+    "complemento",
+    "bairro",
+    "cep",
+    "uf",
+    "codigo_municipio",
+    "codigo_municipio_ibge",
+    "municipio",
+    "regiao",
+    "ddd_telefone_1",
+    "ddd_telefone_2",
+    "ddd_fax",
+    "email",
+    "situacao_especial",
+    "data_situacao_especial",
+    "opcao_pelo_simples",
+    "data_opcao_pelo_simples",
+    "data_exclusao_do_simples",
+    "opcao_pelo_mei",
+    "data_opcao_pelo_mei",
+    "data_exclusao_do_mei",
+    "razao_social",
+    "codigo_natureza_juridica",
+    "natureza_juridica",
+    "qualificacao_do_responsavel",
+    "capital_social",
+    "codigo_porte",
+    "porte",
+    "ente_federativo_responsavel",
+];
+
+/// Output columns of the partners table, in `Partner` field order
+pub const PARTNER_COLUMNS: [&str; 15] = [
+    "cnpj_basico",
+    "identificador_socio",
+    "nome_socio",
+    "cnpj_cpf_socio",
+    "codigo_qualificacao_socio",
+    "qualificacao_socio",
+    "data_entrada_sociedade",
+    "codigo_pais",
+    "pais",
+    "cpf_representante_legal",
+    "nome_representante_legal",
+    "codigo_qualificacao_representante_legal",
+    "qualificacao_representante_legal",
+    "codigo_faixa_etaria",
+    "faixa_etaria",
+];
+
+/// Date columns of the Estabelecimentos files
+pub const ESTABELECIMENTOS_DATES: [&str; 3] = [
+    "data_situacao_cadastral",
+    "data_inicio_atividade",
+    "data_situacao_especial",
+];
+
+/// Date columns of the Simples file
+pub const SIMPLES_DATES: [&str; 4] = [
+    "data_opcao_pelo_simples",
+    "data_exclusao_do_simples",
+    "data_opcao_pelo_mei",
+    "data_exclusao_do_mei",
+];
+
+/// Date columns of the Socios files
+pub const SOCIOS_DATES: [&str; 1] = ["data_entrada_sociedade"];
+
+fn parsed_date(name: &str) -> Expr {
+    col(name).str().to_date(StrptimeOptions {
+        format: Some(DATE_FORMAT.into()),
+        strict: false,
+        exact: true,
+        cache: true,
+    })
+}
+
+/// Parse a `YYYYMMDD` column into a `Date` following `parse_date`: empty
+/// and invalid dates both become null
+fn date(name: &str) -> Expr {
+    parsed_date(name).alias(name)
+}
+
+/// Whether a raw date column holds a value that is neither empty nor a valid date
+fn invalid_date(name: &str) -> Expr {
+    let raw = col(name).str().strip_chars(lit(NULL));
+    let empty = ["", "0", "00000000"]
+        .into_iter()
+        .fold(raw.clone().is_null(), |empty, value| {
+            empty.or(raw.clone().eq(lit(value)))
+        });
+    empty.not().and(parsed_date(name).is_null())
+}
+
+/// Parse a numeric code column into `Int32`
+fn code(name: &str) -> Expr {
+    col(name)
+        .str()
+        .strip_chars(lit(NULL))
+        .cast(DataType::Int32)
+        .alias(name)
+}
+
+/// Parse an `S`/`N` flag column into a `Boolean`
+fn flag(name: &str) -> Expr {
+    when(col(name).eq(lit("S")))
+        .then(lit(true))
+        .when(col(name).eq(lit("N")))
+        .then(lit(false))
+        .otherwise(lit(NULL).cast(DataType::Boolean))
+        .alias(name)
+}
+
+/// Parse an amount in the Brazilian format (see `parse_amount`) into `Decimal(20, 2)`
+fn amount(name: &str) -> Expr {
+    col(name)
+        .str()
+        .replace_all(lit("."), lit(""), true)
+        .str()
+        .replace(lit(","), lit("."), true)
+        .cast(DataType::Decimal(Some(20), Some(2)))
+        .alias(name)
+}
+
+/// Join two raw columns (e.g. DDD and phone number) into one
+fn joined(left: &str, right: &str, alias: &str) -> Expr {
+    concat_str([col(left), col(right)], "", false).alias(alias)
+}
+
+/// Parse the code column of `T`, failing the query on a code `T` does not define
+fn coded<T: Coded>() -> Expr {
+    code(T::FIELD).map(
+        |column| {
+            for code in column.i32()?.into_iter().flatten() {
+                T::from_code(code).map_err(|e| polars_err!(ComputeError: "{}", e))?;
+            }
+            Ok(column)
+        },
+        |_, field| Ok(field.clone()),
+    )
+}
+
+/// Decode the free text `situacao_especial` into the text of its
+/// `SituacaoEspecial`; blank and unknown texts (counted by the quality report)
+/// become null
+fn situacao_especial() -> Expr {
+    col("situacao_especial").map(
+        |column| {
+            let decoded: StringChunked = column
+                .str()?
+                .into_iter()
+                .map(|text| {
+                    text.and_then(|text| SituacaoEspecial::parse(text).ok().flatten())
+                        .map(SituacaoEspecial::description)
+                })
+                .collect();
+            Ok(decoded.with_name(column.name().clone()).into_column())
+        },
+        |_, field| Ok(field.clone()),
+    )
+}
+
+/// Describe the code column of `T` with its descriptions
+fn describe<T: Coded>(alias: &str) -> Expr {
+    T::ALL
+        .iter()
+        .rev()
+        .fold(lit(NULL).cast(DataType::String), |otherwise, value| {
+            when(col(T::FIELD).eq(lit(value.code())))
+                .then(lit(value.description()))
+                .otherwise(otherwise)
+        })
+        .alias(alias)
+}
+
+fn left_join_args() -> JoinArgs {
+    let mut args = JoinArgs::new(JoinType::Left);
+    args.maintain_order = MaintainOrderJoin::Left;
+    args
+}
+
+/// Add the description of a coded column from a lookup table
+fn enrich(
+    lf: LazyFrame,
+    table: &HashMap<i32, String>,
+    key: &str,
+    value: &str,
+) -> Result<LazyFrame> {
+    let lookup = Lookups::frame(table, key, value)?;
+    Ok(lf.join(lookup.lazy(), [col(key)], [col(key)], left_join_args()))
+}
+
+/// Type the columns of a raw Empresas frame
+pub fn empresas(empresas: LazyFrame) -> LazyFrame {
+    empresas
+        .with_columns([
+            code("codigo_natureza_juridica"),
+            code("qualificacao_do_responsavel"),
+            amount("capital_social"),
+            coded::<Porte>(),
+        ])
+        .with_column(describe::<Porte>("porte"))
+}
+
+/// Type the columns of a raw Simples frame
+pub fn simples(simples: LazyFrame) -> LazyFrame {
+    simples.with_columns([
+        flag("opcao_pelo_simples"),
+        date("data_opcao_pelo_simples"),
+        date("data_exclusao_do_simples"),
+        flag("opcao_pelo_mei"),
+        date("data_opcao_pelo_mei"),
+        date("data_exclusao_do_mei"),
+    ])
+}
+
+/// Build the typed companies table from a raw Estabelecimentos frame and the
+/// typed Empresas and Simples frames (see `empresas` and `simples`)
+pub fn companies(
+    estabelecimentos: LazyFrame,
+    empresas: LazyFrame,
+    simples: LazyFrame,
+    lookups: &Lookups,
+) -> Result<LazyFrame> {
+    let estabelecimentos = estabelecimentos
+        .with_columns([
+            concat_str(
+                [col("cnpj_basico"), col("cnpj_ordem"), col("cnpj_dv")],
+                "",
+                false,
+            )
+            .alias("cnpj"),
+            coded::<MatrizFilial>(),
+            coded::<SituacaoCadastral>(),
+            date("data_situacao_cadastral"),
+            code("motivo_situacao_cadastral"),
+            code("codigo_pais"),
+            date("data_inicio_atividade"),
+            code("cnae_fiscal"),
+            col("cnae_fiscal_secundaria")
+                .str()
+                .split(lit(","))
+                .cast(DataType::List(Box::new(DataType::Int32)))
+                .alias("cnaes_secundarios"),
+            code("codigo_municipio"),
+            joined("ddd_1", "telefone_1", "ddd_telefone_1"),
+            joined("ddd_2", "telefone_2", "ddd_telefone_2"),
+            joined("ddd_fax", "fax", "ddd_fax"),
+            situacao_especial(),
+            date("data_situacao_especial"),
+        ])
+        .with_columns([
+            describe::<MatrizFilial>("descricao_identificador_matriz_filial"),
+            describe::<SituacaoCadastral>("descricao_situacao_cadastral"),
+        ]);
+
+    let mut lf = estabelecimentos
+        .join(
+            empresas,
+            [col("cnpj_basico")],
+            [col("cnpj_basico")],
+            left_join_args(),
+        )
+        .join(
+            simples,
+            [col("cnpj_basico")],
+            [col("cnpj_basico")],
+            left_join_args(),
+        );
+
+    lf = enrich(lf, &lookups.countries, "codigo_pais", "pais")?;
+    lf = enrich(lf, &lookups.cities, "codigo_municipio", "municipio")?;
+    lf = lf
+        .join(
+            municipalities::frame(&lookups.cities_ibge)?.lazy(),
+            [col("codigo_municipio")],
+            [col("codigo_municipio")],
+            left_join_args(),
+        )
+        // The UF of the IBGE code stands in for a missing one
+        .with_column(
+            when(col("uf").is_null().or(col("uf").eq(lit(""))))
+                .then(col("uf_ibge"))
+                .otherwise(col("uf"))
+                .alias("uf"),
+        )
+        // The region follows the UF, and the IBGE code only where the UF is unknown
+        .join(
+            municipalities::regions()?.lazy(),
+            [col("uf")],
+            [col("uf")],
+            left_join_args(),
+        )
+        .with_column(coalesce(&[col("regiao_uf"), col("regiao")]).alias("regiao"));
+    lf = enrich(lf, &lookups.cnaes, "cnae_fiscal", "cnae_fiscal_descricao")?;
+    lf = lf
+        // Subclass codes are non-negative, so truncating is the leading digits
+        .with_columns([
+            (col("cnae_fiscal") / lit(100_000))
+                .cast(DataType::Int32)
+                .alias("cnae_divisao"),
+            (col("cnae_fiscal") / lit(10_000))
+                .cast(DataType::Int32)
+                .alias("cnae_grupo"),
+            (col("cnae_fiscal") / lit(100))
+                .cast(DataType::Int32)
+                .alias("cnae_classe"),
+        ])
+        .join(
+            lookups.cnae_structure.divisions()?.lazy(),
+            [col("cnae_divisao")],
+            [col("cnae_divisao")],
+            left_join_args(),
+        )
+        .join(
+            lookups.cnae_structure.classes()?.lazy(),
+            [col("cnae_classe")],
+            [col("cnae_classe")],
+            left_join_args(),
+        );
+    lf = enrich(
+        lf,
+        &lookups.motives,
+        "motivo_situacao_cadastral",
+        "descricao_motivo_situacao_cadastral",
+    )?;
+    lf = enrich(
+        lf,
+        &lookups.legal_natures,
+        "codigo_natureza_juridica",
+        "natureza_juridica",
+    )?;
+
+    Ok(lf.select(COMPANY_COLUMNS.map(col)))
+}
+
+/// Aggregations counting the invalid values of each raw date column
+pub fn invalid_dates(columns: &[&str]) -> Vec<Expr> {
+    columns
+        .iter()
+        .map(|name| invalid_date(name).cast(DataType::UInt64).sum().alias(*name))
+        .collect()
+}
+
+/// Build the typed partners table from a raw Socios frame
+pub fn partners(socios: LazyFrame, lookups: &Lookups) -> Result<LazyFrame> {
+    let mut lf = socios
+        .with_columns([
+            coded::<IdentificadorSocio>(),
+            code("codigo_qualificacao_socio"),
+            date("data_entrada_sociedade"),
+            code("codigo_pais"),
+            code("codigo_qualificacao_representante_legal"),
+            coded::<FaixaEtaria>(),
+        ])
+        .with_column(describe::<FaixaEtaria>("faixa_etaria"));
+
+    lf = enrich(
+        lf,
+        &lookups.qualifications,
+        "codigo_qualificacao_socio",
+        "qualificacao_socio",
+    )?;
+    lf = enrich(lf, &lookups.countries, "codigo_pais", "pais")?;
+    lf = enrich(
+        lf,
+        &lookups.qualifications,
+        "codigo_qualificacao_representante_legal",
+        "qualificacao_representante_legal",
+    )?;
+
+    Ok(lf.select(PARTNER_COLUMNS.map(col)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_columns() {
+        let df = df!(
+            "data" => ["20210115", "00000000", "20210231"],
+            "flag" => ["S", "N", ""],
+            "valor" => ["1234567890,12", "0,00", "1.500.000,50"],
+        )
+        .unwrap()
+        .lazy()
+        .select([date("data"), flag("flag"), amount("valor")])
+        .collect()
+        .unwrap();
+
+        let dates = df.column("data").unwrap();
+        assert_eq!(dates.dtype(), &DataType::Date);
+        assert_eq!(dates.null_count(), 2);
+
+        let flags = df.column("flag").unwrap().bool().unwrap();
+        assert_eq!(flags.get(0), Some(true));
+        assert_eq!(flags.get(1), Some(false));
+        assert_eq!(flags.get(2), None);
+
+        let values = df.column("valor").unwrap();
+        assert_eq!(values.dtype(), &DataType::Decimal(Some(20), Some(2)));
+        assert_eq!(
+            values.get(0).unwrap().to_string(),
+            "1234567890.12".to_string()
+        );
+        assert_eq!(values.get(2).unwrap().to_string(), "1500000.50".to_string());
+    }
+
+    #[test]
+    fn test_invalid_dates() {
+        let df = df!("data" => [Some("20210115"), Some("0"), Some("00000000"), Some("20210231"), Some("2021"), None])
+            .unwrap()
+            .lazy();
+        let counts = df.select(invalid_dates(&["data"])).collect().unwrap();
+        let count = counts.column("data").unwrap().u64().unwrap().get(0);
+        assert_eq!(count, Some(2));
+    }
+
+    #[test]
+    fn test_describe_codes() {
+        let df = df!("situacao_cadastral" => ["02", "8", ""])
+            .unwrap()
+            .lazy()
+            .with_column(coded::<SituacaoCadastral>())
+            .select([describe::<SituacaoCadastral>("descricao")])
+            .collect()
+            .unwrap();
+
+        let descriptions = df.column("descricao").unwrap().str().unwrap();
+        assert_eq!(descriptions.get(0), Some("ATIVA"));
+        assert_eq!(descriptions.get(1), Some("BAIXADA"));
+        assert_eq!(descriptions.get(2), None);
+
+        let error = df!("situacao_cadastral" => ["2", "99"])
+            .unwrap()
+            .lazy()
+            .select([coded::<SituacaoCadastral>()])
+            .collect()
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("unknown situacao_cadastral code 99")
+        );
+    }
+
+    #[test]
+    fn test_decode_situacao_especial() {
+        let df = df!("situacao_especial" => [Some("Recuperação Judicial"), Some("  "), Some("EM OBRAS"), None])
+            .unwrap()
+            .lazy()
+            .select([situacao_especial()])
+            .collect()
+            .unwrap();
+
+        let decoded = df.column("situacao_especial").unwrap().str().unwrap();
+        assert_eq!(decoded.get(0), Some("RECUPERACAO JUDICIAL"));
+        assert_eq!(decoded.get(1), None);
+        assert_eq!(decoded.get(2), None);
+        assert_eq!(decoded.get(3), None);
+    }
+}
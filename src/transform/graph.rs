@@ -0,0 +1,739 @@
+//! Ownership graph of companies and the people and companies among their partners
+
+use super::privacy::masked_cpfs;
+use super::{Cnpj, IdentificadorSocio, PseudonymKey, Result, TransformError};
+use chrono::NaiveDate;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs::{File, create_dir_all};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Directory inside the output directory holding the graph exports
+pub const GRAPH_DIR: &str = "graph";
+
+/// Directory inside the graph directory holding the `neo4j-admin import` files
+const NEO4J_DIR: &str = "neo4j";
+
+/// Type of the relationships in the Neo4j export
+const RELATIONSHIP: &str = "PARTNER_OF";
+
+/// Secret of the person IDs when no pseudonym key is configured
+///
+/// It is public, so anyone with the published files can recompute the IDs;
+/// a pseudonym key makes them comparable only by whoever holds it.
+const DEFAULT_SECRET: &str = "rfb-rs ownership graph";
+
+/// Kind of a node of the ownership graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    /// A company, identified by its `cnpj_basico`
+    Company,
+    /// A person, identified by a token of their CPF and name
+    Person,
+    /// A foreign partner, person or company, identified by a token of their name
+    Foreign,
+}
+
+impl NodeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeKind::Company => "company",
+            NodeKind::Person => "person",
+            NodeKind::Foreign => "foreign",
+        }
+    }
+
+    /// Label of the node in the Neo4j export
+    fn label(&self) -> &'static str {
+        match self {
+            NodeKind::Company => "Company",
+            NodeKind::Person => "Person",
+            NodeKind::Foreign => "Foreign",
+        }
+    }
+}
+
+impl fmt::Display for NodeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for NodeKind {
+    type Err = TransformError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "company" => Ok(NodeKind::Company),
+            "person" => Ok(NodeKind::Person),
+            "foreign" => Ok(NodeKind::Foreign),
+            _ => Err(TransformError::InvalidFormat(format!(
+                "unknown graph node kind {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// A company, person or foreign partner
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Node {
+    pub id: String,
+    pub kind: NodeKind,
+    /// `razao_social` of companies; people are never named
+    pub name: Option<String>,
+}
+
+/// A partner of a company
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Edge {
+    /// The partner
+    pub owner: String,
+    /// `cnpj_basico` of the company
+    pub company: String,
+    pub codigo_qualificacao: Option<i32>,
+    pub qualificacao: Option<String>,
+    pub data_entrada: Option<NaiveDate>,
+}
+
+/// An ultimate controller of a company, see `OwnershipGraph::ultimate_controllers`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Controller {
+    pub node: Node,
+    /// Companies between the controller and the company, 1 for a direct partner
+    pub depth: usize,
+}
+
+/// Key of the person IDs: the pseudonym key when set, the public default otherwise
+pub(super) fn person_key(key: Option<&PseudonymKey>) -> PseudonymKey {
+    match key {
+        Some(key) => key.clone(),
+        None => PseudonymKey::new(DEFAULT_SECRET).expect("the default secret is long enough"),
+    }
+}
+
+/// Edge list of a typed partners frame (see `frames::partners`)
+///
+/// Company partners are identified by the `cnpj_basico` of their CNPJ, other
+/// partners by their token (see `PseudonymKey::token`). Partners without a
+/// usable CPF or CNPJ are left out.
+pub(super) fn edges(partners: LazyFrame, key: &PseudonymKey) -> LazyFrame {
+    let identificador = col("identificador_socio");
+    let company = identificador
+        .clone()
+        .eq(lit(i32::from(IdentificadorSocio::PessoaJuridica)));
+    let basico = col("cnpj_cpf_socio").str().slice(lit(0), lit(8));
+    partners
+        .select([
+            when(company.clone())
+                .then(
+                    when(basico.clone().str().len_chars().eq(lit(8)))
+                        .then(basico)
+                        .otherwise(lit(NULL).cast(DataType::String)),
+                )
+                .otherwise(key.pseudonym("cnpj_cpf_socio", "nome_socio"))
+                .alias("owner"),
+            when(company)
+                .then(lit(NodeKind::Company.as_str()))
+                .when(identificador.eq(lit(i32::from(IdentificadorSocio::PessoaFisica))))
+                .then(lit(NodeKind::Person.as_str()))
+                .otherwise(lit(NodeKind::Foreign.as_str()))
+                .alias("owner_kind"),
+            col("cnpj_basico").alias("company"),
+            col("codigo_qualificacao_socio").alias("codigo_qualificacao"),
+            col("qualificacao_socio").alias("qualificacao"),
+            col("data_entrada_sociedade").alias("data_entrada"),
+        ])
+        .filter(col("owner").is_not_null())
+}
+
+/// Nodes of an edge list, companies named from a raw Empresas frame
+///
+/// In privacy mode, CPFs in company names are masked.
+pub(super) fn nodes(edges: LazyFrame, empresas: LazyFrame, privacy: bool) -> Result<LazyFrame> {
+    let companies = concat(
+        [
+            edges.clone().select([col("company").alias("id")]),
+            edges
+                .clone()
+                .filter(col("owner_kind").eq(lit(NodeKind::Company.as_str())))
+                .select([col("owner").alias("id")]),
+        ],
+        UnionArgs::default(),
+    )?
+    .unique(None, UniqueKeepStrategy::Any);
+    let names = empresas
+        .select([
+            col("cnpj_basico").alias("id"),
+            col("razao_social").alias("name"),
+        ])
+        .unique(Some(cols(["id"])), UniqueKeepStrategy::Any);
+    let name = match privacy {
+        true => masked_cpfs(col("name")),
+        false => col("name"),
+    };
+    let companies = companies
+        .join(
+            names,
+            [col("id")],
+            [col("id")],
+            JoinArgs::new(JoinType::Left),
+        )
+        .select([
+            col("id"),
+            lit(NodeKind::Company.as_str()).alias("kind"),
+            name.alias("name"),
+        ]);
+    let partners = edges
+        .filter(col("owner_kind").neq(lit(NodeKind::Company.as_str())))
+        .select([
+            col("owner").alias("id"),
+            col("owner_kind").alias("kind"),
+            lit(NULL).cast(DataType::String).alias("name"),
+        ])
+        .unique(Some(cols(["id"])), UniqueKeepStrategy::Any);
+
+    Ok(concat([companies, partners], UnionArgs::default())?
+        .sort(["kind", "id"], SortMultipleOptions::default()))
+}
+
+/// Escape a text for an XML attribute or element
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A node or edge attribute of the GraphML export
+fn data(out: &mut impl Write, key: &str, value: Option<&str>) -> std::io::Result<()> {
+    match value {
+        Some(value) => writeln!(
+            out,
+            "      <data key=\"{}\">{}</data>",
+            key,
+            xml_escape(value)
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Write the nodes and edges frames as `graph/ownership.graphml`
+pub(super) fn write_graphml(dir: &Path, nodes: &DataFrame, edges: &DataFrame) -> Result<PathBuf> {
+    create_dir_all(dir)?;
+    let path = dir.join("ownership.graphml");
+    let mut out = BufWriter::new(File::create(&path)?);
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    for (id, target, name, kind) in [
+        ("kind", "node", "kind", "string"),
+        ("name", "node", "name", "string"),
+        ("codigo_qualificacao", "edge", "codigo_qualificacao", "int"),
+        ("qualificacao", "edge", "qualificacao", "string"),
+        ("data_entrada", "edge", "data_entrada", "string"),
+    ] {
+        writeln!(
+            out,
+            r#"  <key id="{}" for="{}" attr.name="{}" attr.type="{}"/>"#,
+            id, target, name, kind
+        )?;
+    }
+    writeln!(out, r#"  <graph id="ownership" edgedefault="directed">"#)?;
+
+    let ids = nodes.column("id")?.str()?;
+    let kinds = nodes.column("kind")?.str()?;
+    let names = nodes.column("name")?.str()?;
+    for ((id, kind), name) in ids.into_iter().zip(kinds).zip(names) {
+        writeln!(
+            out,
+            r#"    <node id="{}">"#,
+            xml_escape(id.unwrap_or_default())
+        )?;
+        data(&mut out, "kind", kind)?;
+        data(&mut out, "name", name)?;
+        writeln!(out, "    </node>")?;
+    }
+
+    let edges = edges_as_text(edges)?;
+    let owners = edges.column("owner")?.str()?;
+    let companies = edges.column("company")?.str()?;
+    let codes = edges.column("codigo_qualificacao")?.str()?;
+    let qualificacoes = edges.column("qualificacao")?.str()?;
+    let dates = edges.column("data_entrada")?.str()?;
+    for i in 0..edges.height() {
+        writeln!(
+            out,
+            r#"    <edge source="{}" target="{}">"#,
+            xml_escape(owners.get(i).unwrap_or_default()),
+            xml_escape(companies.get(i).unwrap_or_default())
+        )?;
+        data(&mut out, "codigo_qualificacao", codes.get(i))?;
+        data(&mut out, "qualificacao", qualificacoes.get(i))?;
+        data(&mut out, "data_entrada", dates.get(i))?;
+        writeln!(out, "    </edge>")?;
+    }
+
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</graphml>")?;
+    out.flush()?;
+
+    tracing::info!(
+        "Wrote {} nodes and {} edges to {:?}",
+        nodes.height(),
+        edges.height(),
+        path
+    );
+    Ok(path)
+}
+
+/// Edges with every attribute as text, dates as `YYYY-MM-DD`
+fn edges_as_text(edges: &DataFrame) -> Result<DataFrame> {
+    Ok(edges
+        .clone()
+        .lazy()
+        .with_columns([
+            col("codigo_qualificacao").cast(DataType::String),
+            col("data_entrada").cast(DataType::String),
+        ])
+        .collect()?)
+}
+
+/// Write a frame as a CSV file with a header
+fn write_csv(path: &Path, df: &mut DataFrame) -> Result<()> {
+    let file = File::create(path)?;
+    CsvWriter::new(BufWriter::new(file))
+        .include_header(true)
+        .finish(df)?;
+    Ok(())
+}
+
+/// Write the nodes and edges frames as `neo4j-admin database import` files
+///
+/// `companies.csv` and `partners.csv` hold the nodes, `partner_of.csv` the
+/// relationships from each partner to its company.
+pub(super) fn write_neo4j(
+    dir: &Path,
+    nodes: &DataFrame,
+    edges: &DataFrame,
+) -> Result<Vec<PathBuf>> {
+    let dir = dir.join(NEO4J_DIR);
+    create_dir_all(&dir)?;
+
+    let label = |kind: Expr| {
+        let is = |node: NodeKind| kind.clone().eq(lit(node.as_str()));
+        when(is(NodeKind::Company))
+            .then(lit(NodeKind::Company.label()))
+            .when(is(NodeKind::Person))
+            .then(lit(NodeKind::Person.label()))
+            .otherwise(lit(NodeKind::Foreign.label()))
+    };
+    let is_company = col("kind").eq(lit(NodeKind::Company.as_str()));
+
+    let mut companies = nodes
+        .clone()
+        .lazy()
+        .filter(is_company.clone())
+        .select([
+            col("id").alias("id:ID"),
+            col("name").alias("razao_social"),
+            label(col("kind")).alias(":LABEL"),
+        ])
+        .collect()?;
+    let mut partners = nodes
+        .clone()
+        .lazy()
+        .filter(is_company.not())
+        .select([col("id").alias("id:ID"), label(col("kind")).alias(":LABEL")])
+        .collect()?;
+    let mut relationships = edges
+        .clone()
+        .lazy()
+        .select([
+            col("owner").alias(":START_ID"),
+            col("company").alias(":END_ID"),
+            col("codigo_qualificacao").alias("codigo_qualificacao:int"),
+            col("qualificacao"),
+            col("data_entrada").alias("data_entrada:date"),
+            lit(RELATIONSHIP).alias(":TYPE"),
+        ])
+        .collect()?;
+
+    let mut paths = Vec::new();
+    for (name, df) in [
+        ("companies.csv", &mut companies),
+        ("partners.csv", &mut partners),
+        ("partner_of.csv", &mut relationships),
+    ] {
+        let path = dir.join(name);
+        write_csv(&path, df)?;
+        paths.push(path);
+    }
+
+    tracing::info!("Wrote the Neo4j import files to {:?}", dir);
+    Ok(paths)
+}
+
+/// Ownership graph written by a transform with `graph` enabled
+///
+/// Nodes are companies, people and foreign partners; each edge goes from a
+/// partner to a company, carrying the partner qualification and entry date.
+#[derive(Debug, Clone, Default)]
+pub struct OwnershipGraph {
+    nodes: BTreeMap<String, Node>,
+    /// Partners of each company, by `cnpj_basico`
+    owners: HashMap<String, Vec<Edge>>,
+}
+
+impl OwnershipGraph {
+    /// Load `graph/nodes.parquet` and `graph/edges.parquet` from an output directory
+    pub fn load(output_dir: &Path) -> Result<Self> {
+        let dir = output_dir.join(GRAPH_DIR);
+        let read = |name: &str| -> Result<DataFrame> {
+            let path = dir.join(name);
+            let file = File::open(&path).map_err(|e| {
+                TransformError::IoError(std::io::Error::new(
+                    e.kind(),
+                    format!("{:?}: {} (was the transform run with --graph?)", path, e),
+                ))
+            })?;
+            Ok(ParquetReader::new(file).finish()?)
+        };
+        Self::from_frames(&read("nodes.parquet")?, &read("edges.parquet")?)
+    }
+
+    /// Build the graph from nodes and edges frames
+    pub fn from_frames(nodes: &DataFrame, edges: &DataFrame) -> Result<Self> {
+        let mut graph = Self::default();
+
+        let ids = nodes.column("id")?.str()?;
+        let kinds = nodes.column("kind")?.str()?;
+        let names = nodes.column("name")?.str()?;
+        for ((id, kind), name) in ids.into_iter().zip(kinds).zip(names) {
+            let (Some(id), Some(kind)) = (id, kind) else {
+                continue;
+            };
+            let node = Node {
+                id: id.to_string(),
+                kind: kind.parse()?,
+                name: name.map(str::to_string),
+            };
+            graph.nodes.insert(node.id.clone(), node);
+        }
+
+        let owners = edges.column("owner")?.str()?;
+        let companies = edges.column("company")?.str()?;
+        let codes = edges.column("codigo_qualificacao")?.i32()?;
+        let qualificacoes = edges.column("qualificacao")?.str()?;
+        let dates: Vec<_> = edges
+            .column("data_entrada")?
+            .date()?
+            .as_date_iter()
+            .collect();
+        for (i, data_entrada) in dates.into_iter().enumerate() {
+            let (Some(owner), Some(company)) = (owners.get(i), companies.get(i)) else {
+                continue;
+            };
+            graph
+                .owners
+                .entry(company.to_string())
+                .or_default()
+                .push(Edge {
+                    owner: owner.to_string(),
+                    company: company.to_string(),
+                    codigo_qualificacao: codes.get(i),
+                    qualificacao: qualificacoes.get(i).map(str::to_string),
+                    data_entrada,
+                });
+        }
+
+        Ok(graph)
+    }
+
+    /// Node of a `cnpj_basico` or person token
+    pub fn node(&self, id: &str) -> Option<&Node> {
+        self.nodes.get(id)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.values()
+    }
+
+    /// Partners of a company, by `cnpj_basico`
+    pub fn owners(&self, basico: &str) -> &[Edge] {
+        self.owners
+            .get(basico)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The people, foreign partners and companies at the top of the
+    /// ownership chains of a company
+    ///
+    /// Company partners are followed up to partners that are not companies,
+    /// or companies without partners of their own. The companies of a
+    /// cross-holding with no partner outside it are all controllers. Each
+    /// controller is reported once, at its shortest distance.
+    pub fn ultimate_controllers(&self, cnpj: &Cnpj) -> Vec<Controller> {
+        let target = cnpj.basico();
+        // Breadth-first, so each node is first reached at its shortest distance
+        let mut depths: HashMap<&str, usize> = HashMap::from([(target, 0)]);
+        let mut above = vec![target];
+        let mut queue = VecDeque::from([target]);
+        while let Some(id) = queue.pop_front() {
+            let depth = depths[id];
+            for edge in self.owners(id) {
+                if !depths.contains_key(edge.owner.as_str()) {
+                    depths.insert(&edge.owner, depth + 1);
+                    above.push(&edge.owner);
+                    queue.push_back(&edge.owner);
+                }
+            }
+        }
+
+        let mut controllers: Vec<Controller> = self
+            .tops(&above)
+            .into_iter()
+            .filter(|id| *id != target)
+            .map(|id| {
+                // The nodes frame given to `from_frames` may miss some partners
+                let node = match self.node(id) {
+                    Some(node) => node.clone(),
+                    None => Node {
+                        id: id.to_string(),
+                        kind: NodeKind::Company,
+                        name: None,
+                    },
+                };
+                Controller {
+                    node,
+                    depth: depths[id],
+                }
+            })
+            .collect();
+        controllers.sort_by(|a, b| a.depth.cmp(&b.depth).then(a.node.id.cmp(&b.node.id)));
+        controllers
+    }
+
+    /// The nodes of `nodes`, which holds the owners of each of them, whose
+    /// strongly connected component has no owner outside it
+    ///
+    /// Components are found with Kosaraju's algorithm, in time linear in the
+    /// nodes and ownership edges.
+    fn tops<'a>(&'a self, nodes: &[&'a str]) -> Vec<&'a str> {
+        // Nodes in the order a depth-first walk up the owners finishes them
+        let mut visited = HashSet::new();
+        let mut finished = Vec::with_capacity(nodes.len());
+        for &start in nodes {
+            if !visited.insert(start) {
+                continue;
+            }
+            let mut stack = vec![(start, 0)];
+            while let Some(&(id, next)) = stack.last() {
+                match self.owners(id).get(next) {
+                    Some(edge) => {
+                        if let Some(last) = stack.last_mut() {
+                            last.1 += 1;
+                        }
+                        if visited.insert(edge.owner.as_str()) {
+                            stack.push((edge.owner.as_str(), 0));
+                        }
+                    }
+                    None => {
+                        finished.push(id);
+                        stack.pop();
+                    }
+                }
+            }
+        }
+
+        // Components, walking down to the owned companies in reverse order
+        let mut owned: HashMap<&str, Vec<&str>> = HashMap::new();
+        for &id in nodes {
+            for edge in self.owners(id) {
+                owned.entry(edge.owner.as_str()).or_default().push(id);
+            }
+        }
+        let mut component: HashMap<&str, usize> = HashMap::new();
+        for (index, &start) in finished.iter().rev().enumerate() {
+            if component.contains_key(start) {
+                continue;
+            }
+            component.insert(start, index);
+            let mut stack = vec![start];
+            while let Some(id) = stack.pop() {
+                for &company in owned.get(id).into_iter().flatten() {
+                    if !component.contains_key(company) {
+                        component.insert(company, index);
+                        stack.push(company);
+                    }
+                }
+            }
+        }
+
+        let owned_from_outside: HashSet<usize> = nodes
+            .iter()
+            .filter(|id| {
+                self.owners(id)
+                    .iter()
+                    .any(|edge| component[edge.owner.as_str()] != component[**id])
+            })
+            .map(|id| component[*id])
+            .collect();
+        nodes
+            .iter()
+            .copied()
+            .filter(|id| !owned_from_outside.contains(&component[*id]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &str)]) -> OwnershipGraph {
+        let mut nodes = BTreeMap::new();
+        for (owner, company) in edges {
+            for id in [owner, company] {
+                let kind = match id.len() {
+                    8 => NodeKind::Company,
+                    _ => NodeKind::Person,
+                };
+                nodes.insert(id.to_string(), kind.as_str());
+            }
+        }
+        let nodes = df!(
+            "id" => nodes.keys().cloned().collect::<Vec<_>>(),
+            "kind" => nodes.values().cloned().collect::<Vec<_>>(),
+            "name" => vec![None::<&str>; nodes.len()],
+        )
+        .unwrap();
+        let edges = df!(
+            "owner" => edges.iter().map(|e| e.0).collect::<Vec<_>>(),
+            "owner_kind" => vec!["company"; edges.len()],
+            "company" => edges.iter().map(|e| e.1).collect::<Vec<_>>(),
+            "codigo_qualificacao" => vec![Some(22); edges.len()],
+            "qualificacao" => vec![Some("Sócio"); edges.len()],
+            "data_entrada" => vec![None::<i32>; edges.len()],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(col("data_entrada").cast(DataType::Date))
+        .collect()
+        .unwrap();
+        OwnershipGraph::from_frames(&nodes, &edges).unwrap()
+    }
+
+    fn controllers(graph: &OwnershipGraph, basico: &str) -> Vec<(String, usize)> {
+        let cnpj = Cnpj::matriz(basico).unwrap();
+        graph
+            .ultimate_controllers(&cnpj)
+            .into_iter()
+            .map(|c| (c.node.id, c.depth))
+            .collect()
+    }
+
+    #[test]
+    fn test_ultimate_controllers() {
+        // ana owns 11111111, which owns 22222222 with bruno; 22222222 and
+        // 33333333 hold each other, and 33333333 has no other partner
+        let graph = graph(&[
+            ("person-ana", "11111111"),
+            ("11111111", "22222222"),
+            ("person-bruno", "22222222"),
+            ("22222222", "33333333"),
+            ("33333333", "22222222"),
+            ("44444444", "33333333"),
+        ]);
+
+        assert_eq!(
+            controllers(&graph, "22222222"),
+            vec![
+                ("person-bruno".to_string(), 1),
+                ("44444444".to_string(), 2),
+                ("person-ana".to_string(), 2)
+            ]
+        );
+        assert_eq!(
+            controllers(&graph, "11111111"),
+            vec![("person-ana".to_string(), 1)]
+        );
+        assert!(controllers(&graph, "44444444").is_empty());
+        assert_eq!(graph.owners("33333333").len(), 2);
+    }
+
+    #[test]
+    fn test_cycle_without_outside_partner() {
+        let graph = graph(&[("11111111", "22222222"), ("22222222", "11111111")]);
+        assert_eq!(
+            controllers(&graph, "22222222"),
+            vec![("11111111".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_cross_holding_above_company() {
+        // 22222222 and 33333333 hold each other and have no other partner
+        let graph = graph(&[
+            ("22222222", "11111111"),
+            ("33333333", "22222222"),
+            ("22222222", "33333333"),
+        ]);
+        assert_eq!(
+            controllers(&graph, "11111111"),
+            vec![("22222222".to_string(), 1), ("33333333".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn test_many_chains() {
+        // Each level has two companies, each owned by both companies of the
+        // level above: 2^60 chains from the bottom to the person at the top
+        let id = |level: usize, side: usize| format!("{:08}", level * 2 + side + 10);
+        let mut edges = Vec::new();
+        for level in 0..60 {
+            for side in 0..2 {
+                for owner in 0..2 {
+                    edges.push((id(level + 1, owner), id(level, side)));
+                }
+            }
+        }
+        edges.push(("person-ana".to_string(), id(60, 0)));
+        edges.push(("person-ana".to_string(), id(60, 1)));
+        let edges: Vec<(&str, &str)> = edges
+            .iter()
+            .map(|(owner, company)| (owner.as_str(), company.as_str()))
+            .collect();
+        let graph = graph(&edges);
+
+        assert_eq!(
+            controllers(&graph, &id(0, 0)),
+            vec![("person-ana".to_string(), 61)]
+        );
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(
+            xml_escape(r#"A & B <"LTDA">"#),
+            "A &amp; B &lt;&quot;LTDA&quot;&gt;"
+        );
+    }
+}
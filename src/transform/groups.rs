@@ -0,0 +1,182 @@
+//! One record per company (`cnpj_basico`) with its head office and branches
+
+use super::MatrizFilial;
+use polars::prelude::*;
+
+/// Output table of the company groups
+pub const GROUPS_TABLE: &str = "company_groups";
+
+/// Columns of the head office carried into its group
+const MATRIZ_COLUMNS: [&str; 17] = [
+    "razao_social",
+    "nome_fantasia",
+    "situacao_cadastral",
+    "descricao_situacao_cadastral",
+    "codigo_natureza_juridica",
+    "natureza_juridica",
+    "capital_social",
+    "codigo_porte",
+    "porte",
+    "cnae_fiscal",
+    "cnae_fiscal_descricao",
+    "uf",
+    "codigo_municipio",
+    "municipio",
+    "regiao",
+    "opcao_pelo_simples",
+    "opcao_pelo_mei",
+];
+
+fn is(kind: MatrizFilial) -> Expr {
+    col("identificador_matriz_filial").eq(lit(i32::from(kind)))
+}
+
+/// Group a companies frame (see `frames::companies`) by `cnpj_basico`
+///
+/// Each group has the CNPJ and `MATRIZ_COLUMNS` of its head office (null
+/// when the head office is not in the frame), its establishment and branch
+/// counts, the earliest `data_inicio_atividade`, the sorted branch CNPJs and
+/// `filiais_por_uf`, the branches counted by UF, region and situação.
+pub(super) fn groups(companies: LazyFrame) -> LazyFrame {
+    let mut matriz = vec![col("cnpj_basico"), col("cnpj").alias("cnpj_matriz")];
+    matriz.extend(MATRIZ_COLUMNS.map(col));
+    let matriz = companies
+        .clone()
+        .filter(is(MatrizFilial::Matriz))
+        .select(matriz)
+        .unique(Some(cols(["cnpj_basico"])), UniqueKeepStrategy::Any);
+
+    let totals = companies.clone().group_by([col("cnpj_basico")]).agg([
+        len().alias("estabelecimentos"),
+        is(MatrizFilial::Filial)
+            .cast(DataType::UInt32)
+            .sum()
+            .alias("filiais"),
+        col("data_inicio_atividade").min(),
+        col("cnpj")
+            .filter(is(MatrizFilial::Filial))
+            .sort(SortOptions::default())
+            .alias("cnpjs_filiais"),
+    ]);
+
+    let count = ["uf", "regiao", "situacao_cadastral"];
+    let branches = companies
+        .filter(is(MatrizFilial::Filial))
+        .group_by([
+            col("cnpj_basico"),
+            col("uf"),
+            col("regiao"),
+            col("situacao_cadastral"),
+        ])
+        .agg([len().alias("filiais")])
+        .group_by([col("cnpj_basico")])
+        .agg([as_struct(vec![
+            col("uf"),
+            col("regiao"),
+            col("situacao_cadastral"),
+            col("filiais"),
+        ])
+        .sort_by(count.map(col), SortMultipleOptions::default())
+        .alias("filiais_por_uf")]);
+
+    let mut columns = vec![col("cnpj_basico"), col("cnpj_matriz")];
+    columns.extend(MATRIZ_COLUMNS.map(col));
+    columns.extend(
+        [
+            "estabelecimentos",
+            "filiais",
+            "data_inicio_atividade",
+            "cnpjs_filiais",
+            "filiais_por_uf",
+        ]
+        .map(col),
+    );
+    let left = || JoinArgs::new(JoinType::Left);
+    totals
+        .join(matriz, [col("cnpj_basico")], [col("cnpj_basico")], left())
+        .join(branches, [col("cnpj_basico")], [col("cnpj_basico")], left())
+        .select(columns)
+        .sort(["cnpj_basico"], SortMultipleOptions::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups() {
+        let mut companies = df!(
+            "cnpj" => ["11222333000181", "11222333000262", "11222333000343", "44555666000200"],
+            "cnpj_basico" => ["11222333", "11222333", "11222333", "44555666"],
+            "identificador_matriz_filial" => [1, 2, 2, 2],
+            "situacao_cadastral" => [2, 2, 8, 2],
+            "uf" => ["SP", "BA", "PE", "RJ"],
+            "regiao" => ["Sudeste", "Nordeste", "Nordeste", "Sudeste"],
+            "data_inicio_atividade" => [20100101, 20050601, 20200101, 20150101],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(
+            col("data_inicio_atividade")
+                .cast(DataType::String)
+                .str()
+                .to_date(StrptimeOptions {
+                    format: Some("%Y%m%d".into()),
+                    ..Default::default()
+                }),
+        )
+        .collect()
+        .unwrap();
+        for column in MATRIZ_COLUMNS {
+            if !companies.get_column_names_str().contains(&column) {
+                companies
+                    .with_column(Column::full_null(column.into(), 4, &DataType::String))
+                    .unwrap();
+            }
+        }
+
+        let groups = groups(companies.lazy()).collect().unwrap();
+        assert_eq!(groups.height(), 2);
+
+        let matriz = groups.column("cnpj_matriz").unwrap().str().unwrap();
+        assert_eq!(matriz.get(0), Some("11222333000181"));
+        assert_eq!(matriz.get(1), None);
+        let counts = |name: &str| -> Vec<_> {
+            let column = groups
+                .column(name)
+                .unwrap()
+                .cast(&DataType::UInt32)
+                .unwrap();
+            column.u32().unwrap().into_no_null_iter().collect()
+        };
+        assert_eq!(counts("estabelecimentos"), [3, 1]);
+        assert_eq!(counts("filiais"), [2, 1]);
+        assert_eq!(
+            groups
+                .column("data_inicio_atividade")
+                .unwrap()
+                .cast(&DataType::String)
+                .unwrap()
+                .str()
+                .unwrap()
+                .get(0),
+            Some("2005-06-01")
+        );
+
+        let branches = groups.column("cnpjs_filiais").unwrap().list().unwrap();
+        let first = branches.get_as_series(0).unwrap();
+        assert_eq!(
+            first.str().unwrap().into_no_null_iter().collect::<Vec<_>>(),
+            ["11222333000262", "11222333000343"]
+        );
+
+        let per_uf = groups.column("filiais_por_uf").unwrap().list().unwrap();
+        let first = per_uf.get_as_series(0).unwrap();
+        let first = first.struct_().unwrap();
+        let ufs = first.field_by_name("uf").unwrap();
+        assert_eq!(
+            ufs.str().unwrap().into_no_null_iter().collect::<Vec<_>>(),
+            ["BA", "PE"]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use super::Result;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// Default number of workers: one per available core
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// A unit of work, labelled for progress reporting
+pub struct Task<'a> {
+    label: String,
+    run: Box<dyn FnOnce() -> Result<()> + Send + 'a>,
+}
+
+impl<'a> Task<'a> {
+    pub fn new(label: impl Into<String>, run: impl FnOnce() -> Result<()> + Send + 'a) -> Self {
+        Self {
+            label: label.into(),
+            run: Box::new(run),
+        }
+    }
+}
+
+/// Fixed-size pool of worker threads
+#[derive(Debug, Clone, Copy)]
+pub struct Jobs {
+    workers: usize,
+}
+
+impl Jobs {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            workers: workers.max(1),
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Run `tasks` on up to `workers` threads, in order of submission
+    ///
+    /// Once a task fails no new task is started. Returns the error of the
+    /// first failed task in submission order, so the result does not depend
+    /// on scheduling.
+    pub fn run(&self, tasks: Vec<Task<'_>>) -> Result<()> {
+        let total = tasks.len();
+        let progress = ProgressBar::new(total as u64);
+        progress.set_style(
+            ProgressStyle::default_bar()
+                .template("{msg} [{bar:40.cyan/blue}] {pos}/{len} ({elapsed})")
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+
+        let queue = Mutex::new(tasks.into_iter().enumerate());
+        let errors = Mutex::new(Vec::new());
+        let failed = AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            for _ in 0..self.workers.min(total) {
+                scope.spawn(|| {
+                    while !failed.load(Ordering::Relaxed) {
+                        let Some((i, task)) = queue.lock().unwrap().next() else {
+                            break;
+                        };
+
+                        progress.set_message(task.label.clone());
+                        let start = Instant::now();
+                        match (task.run)() {
+                            Ok(()) => {
+                                progress.inc(1);
+                                tracing::info!(
+                                    "[{}/{}] {} done in {:.1?}",
+                                    progress.position(),
+                                    total,
+                                    task.label,
+                                    start.elapsed()
+                                );
+                            }
+                            Err(e) => {
+                                failed.store(true, Ordering::Relaxed);
+                                errors.lock().unwrap().push((i, e));
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        progress.finish_and_clear();
+
+        match errors
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .min_by_key(|(i, _)| *i)
+        {
+            Some((_, e)) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::TransformError;
+
+    #[test]
+    fn test_run_reports_first_error() {
+        let done = Mutex::new(Vec::new());
+        let tasks = (0..8)
+            .map(|i| {
+                let done = &done;
+                Task::new(format!("task {}", i), move || {
+                    done.lock().unwrap().push(i);
+                    Ok(())
+                })
+            })
+            .collect();
+        Jobs::new(3).run(tasks).unwrap();
+        let mut done = done.into_inner().unwrap();
+        done.sort();
+        assert_eq!(done, (0..8).collect::<Vec<_>>());
+
+        let tasks = (0..4)
+            .map(|i| {
+                Task::new(format!("task {}", i), move || match i {
+                    1 | 3 => Err(TransformError::InvalidFormat(i.to_string())),
+                    _ => Ok(()),
+                })
+            })
+            .collect();
+        assert!(matches!(
+            Jobs::new(1).run(tasks),
+            Err(TransformError::InvalidFormat(i)) if i == "1"
+        ));
+    }
+}
@@ -0,0 +1,199 @@
+use super::Encoding;
+use polars::prelude::*;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+/// Columns of the Estabelecimentos files, in file order
+pub const ESTABELECIMENTOS_COLUMNS: [&str; 30] = [
+    "cnpj_basico",
+    "cnpj_ordem",
+    "cnpj_dv",
+    "identificador_matriz_filial",
+    "nome_fantasia",
+    "situacao_cadastral",
+    "data_situacao_cadastral",
+    "motivo_situacao_cadastral",
+    "nome_cidade_no_exterior",
+    "codigo_pais",
+    "data_inicio_atividade",
+    "cnae_fiscal",
+    "cnae_fiscal_secundaria",
+    "descricao_tipo_de_logradouro",
+    "logradouro",
+    "numero",
+    "complemento",
+    "bairro",
+    "cep",
+    "uf",
+    "codigo_municipio",
+    "ddd_1",
+    "telefone_1",
+    "ddd_2",
+    "telefone_2",
+    "ddd_fax",
+    "fax",
+    "email",
+    "situacao_especial",
+    "data_situacao_especial",
+];
+
+/// Columns of the Empresas files, in file order
+pub const EMPRESAS_COLUMNS: [&str; 7] = [
+    "cnpj_basico",
+    "razao_social",
+    "codigo_natureza_juridica",
+    "qualificacao_do_responsavel",
+    "capital_social",
+    "codigo_porte",
+    "ente_federativo_responsavel",
+];
+
+/// Columns of the Socios files, in file order
+pub const SOCIOS_COLUMNS: [&str; 11] = [
+    "cnpj_basico",
+    "identificador_socio",
+    "nome_socio",
+    "cnpj_cpf_socio",
+    "codigo_qualificacao_socio",
+    "data_entrada_sociedade",
+    "codigo_pais",
+    "cpf_representante_legal",
+    "nome_representante_legal",
+    "codigo_qualificacao_representante_legal",
+    "codigo_faixa_etaria",
+];
+
+/// Columns of the Simples file, in file order
+pub const SIMPLES_COLUMNS: [&str; 7] = [
+    "cnpj_basico",
+    "opcao_pelo_simples",
+    "data_opcao_pelo_simples",
+    "data_exclusao_do_simples",
+    "opcao_pelo_mei",
+    "data_opcao_pelo_mei",
+    "data_exclusao_do_mei",
+];
+
+/// Columns of the lookup tables (Paises, Municipios, Cnaes...)
+pub const LOOKUP_COLUMNS: [&str; 2] = ["codigo", "descricao"];
+
+/// Build a schema where every column is read as a string.
+///
+/// Codes such as `cnpj_basico` carry leading zeros, so nothing is inferred
+/// at read time; typing happens in the transform expressions.
+pub fn string_schema(columns: &[&str]) -> SchemaRef {
+    Arc::new(
+        columns
+            .iter()
+            .map(|name| Field::new((*name).into(), DataType::String))
+            .collect(),
+    )
+}
+
+/// Parse the bytes of a headerless, `;`-separated Federal Revenue CSV file,
+/// e.g. an entry read from a ZIP archive
+///
+/// The whole file is decoded to UTF-8 in memory first, so this is meant for
+/// small tables such as the lookups; large inputs go through `scan_csv`.
+pub fn parse_csv(bytes: &[u8], columns: &[&str], encoding: Encoding) -> super::Result<DataFrame> {
+    let text = encoding.decode(bytes).into_owned();
+
+    let df = CsvReadOptions::default()
+        .with_has_header(false)
+        .with_schema(Some(string_schema(columns)))
+        .with_parse_options(
+            CsvParseOptions::default()
+                .with_separator(b';')
+                .with_quote_char(Some(b'"')),
+        )
+        .into_reader_with_file_handle(Cursor::new(text.into_bytes()))
+        .finish()?;
+
+    Ok(df)
+}
+
+/// Lazily scan one or more Federal Revenue CSV files as a single frame
+///
+/// An empty `paths` list yields an empty frame with the file's columns, so
+/// optional inputs (e.g. a missing Simples file) still join cleanly.
+pub fn scan_csv(paths: &[PathBuf], columns: &[&str]) -> super::Result<LazyFrame> {
+    if paths.is_empty() {
+        return Ok(DataFrame::empty_with_schema(&string_schema(columns)).lazy());
+    }
+
+    let paths: Arc<[PlPath]> = paths
+        .iter()
+        .map(|path| PlPath::Local(path.as_path().into()))
+        .collect();
+    let lf = LazyCsvReader::new_paths(paths)
+        .with_has_header(false)
+        .with_schema(Some(string_schema(columns)))
+        .with_separator(b';')
+        .with_quote_char(Some(b'"'))
+        .finish()?;
+
+    Ok(lf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_schema() {
+        let schema = string_schema(&SIMPLES_COLUMNS);
+        assert_eq!(schema.len(), 7);
+        assert_eq!(schema.get("cnpj_basico"), Some(&DataType::String));
+    }
+
+    #[test]
+    fn test_parse_csv_keeps_leading_zeros() {
+        let dir = std::env::temp_dir().join("rfb_layout_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("paises.csv");
+        std::fs::write(&path, "\"001\";\"AFEGANISTAO\"\n\"076\";\"BRASIL\"\n").unwrap();
+
+        let df = parse_csv(
+            &std::fs::read(&path).unwrap(),
+            &LOOKUP_COLUMNS,
+            Encoding::Auto,
+        )
+        .unwrap();
+        let codes = df.column("codigo").unwrap().str().unwrap();
+        assert_eq!(codes.get(1), Some("076"));
+
+        std::fs::write(&path, b"\"105\";\"S\xC3O TOM\xC9\"\n").unwrap();
+        let df = parse_csv(
+            &std::fs::read(&path).unwrap(),
+            &LOOKUP_COLUMNS,
+            Encoding::Auto,
+        )
+        .unwrap();
+        let names = df.column("descricao").unwrap().str().unwrap();
+        assert_eq!(names.get(0), Some("SÃO TOMÉ"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_scan_csv_concatenates_files() {
+        let dir = std::env::temp_dir().join("rfb_layout_scan_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("F.K03200$Z.D51011.PAISCSV0");
+        let second = dir.join("F.K03200$Z.D51011.PAISCSV1");
+        std::fs::write(&first, "\"001\";\"AFEGANISTAO\"\n").unwrap();
+        std::fs::write(&second, "\"076\";\"BRASIL\"\n").unwrap();
+
+        let df = scan_csv(&[first, second], &LOOKUP_COLUMNS)
+            .unwrap()
+            .collect()
+            .unwrap();
+        assert_eq!(df.height(), 2);
+
+        let empty = scan_csv(&[], &LOOKUP_COLUMNS).unwrap().collect().unwrap();
+        assert_eq!(empty.width(), 2);
+        assert_eq!(empty.height(), 0);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,10 +1,135 @@
+use super::cnae::{self, CnaeLevel, CnaeStructure};
+use super::layout::{LOOKUP_COLUMNS, parse_csv};
+use super::{Encoding, Result, TransformError, municipalities};
+use ::zip::ZipArchive;
 use polars::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs::read_dir;
-use std::path::Path;
+use std::fs::{File, read_dir};
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+
+/// Version of the lookup cache format, bumped whenever `Lookups` changes shape
+const CACHE_FORMAT: u32 = 1;
+
+/// Lookup tables published by the Receita Federal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LookupKind {
+    Paises,
+    Municipios,
+    Naturezas,
+    Qualificacoes,
+    Cnaes,
+    Motivos,
+}
+
+impl LookupKind {
+    pub const ALL: [LookupKind; 6] = [
+        LookupKind::Paises,
+        LookupKind::Municipios,
+        LookupKind::Naturezas,
+        LookupKind::Qualificacoes,
+        LookupKind::Cnaes,
+        LookupKind::Motivos,
+    ];
+
+    /// Name segment identifying the file, e.g. `F.K03200$Z.D51011.PAISCSV`
+    pub fn token(self) -> &'static str {
+        match self {
+            LookupKind::Paises => "PAISCSV",
+            LookupKind::Municipios => "MUNICCSV",
+            LookupKind::Naturezas => "NATJUCSV",
+            LookupKind::Qualificacoes => "QUALSCSV",
+            LookupKind::Cnaes => "CNAECSV",
+            LookupKind::Motivos => "MOTICSV",
+        }
+    }
+
+    /// ZIP archive the Receita Federal publishes the file in
+    pub fn archive(self) -> &'static str {
+        match self {
+            LookupKind::Paises => "Paises.zip",
+            LookupKind::Municipios => "Municipios.zip",
+            LookupKind::Naturezas => "Naturezas.zip",
+            LookupKind::Qualificacoes => "Qualificacoes.zip",
+            LookupKind::Cnaes => "Cnaes.zip",
+            LookupKind::Motivos => "Motivos.zip",
+        }
+    }
+
+    /// Table name the lookup is exported under, e.g. `paises`
+    pub fn table(self) -> &'static str {
+        match self {
+            LookupKind::Paises => "paises",
+            LookupKind::Municipios => "municipios",
+            LookupKind::Naturezas => "naturezas",
+            LookupKind::Qualificacoes => "qualificacoes",
+            LookupKind::Cnaes => "cnaes",
+            LookupKind::Motivos => "motivos",
+        }
+    }
+
+    /// Columns of the file layout
+    pub fn columns(self) -> &'static [&'static str] {
+        &LOOKUP_COLUMNS
+    }
+
+    /// The lookup table a file name is, from the Receita Federal name
+    /// (`F.K03200$Z.D51011.PAISCSV`) or a short one (`PAISCSV.csv`)
+    pub fn detect(file_name: &str) -> Option<LookupKind> {
+        let upper = file_name.to_uppercase();
+        let segments: Vec<&str> = upper.split('.').collect();
+        if segments.last() == Some(&"ZIP") {
+            return None;
+        }
+
+        Self::ALL.into_iter().find(|kind| {
+            segments.iter().any(|segment| {
+                segment
+                    .strip_prefix(kind.token())
+                    .is_some_and(|rest| rest.bytes().all(|c| c.is_ascii_digit()))
+            })
+        })
+    }
+
+    /// Parse the bytes of a lookup file, checking its layout
+    ///
+    /// Every line must have the file's columns and a numeric code; `source`
+    /// names the file in errors.
+    pub fn parse(
+        self,
+        bytes: &[u8],
+        encoding: Encoding,
+        source: &str,
+    ) -> Result<HashMap<i32, String>> {
+        let invalid = |detail: String| {
+            TransformError::InvalidFormat(format!("{} ({}): {}", source, self.table(), detail))
+        };
+        let df = parse_csv(bytes, self.columns(), encoding).map_err(|e| invalid(e.to_string()))?;
+        let codes = df.column("codigo")?.str()?;
+        let names = df.column("descricao")?.str()?;
+
+        let mut table = HashMap::with_capacity(df.height());
+        for (row, (code, name)) in codes.into_iter().zip(names).enumerate() {
+            let (Some(code), Some(name)) = (code, name) else {
+                return Err(invalid(format!(
+                    "row {} does not have the {} columns {:?}",
+                    row + 1,
+                    self.columns().len(),
+                    self.columns()
+                )));
+            };
+            let code = code.trim().parse().map_err(|_| {
+                invalid(format!("row {} has a non-numeric code {:?}", row + 1, code))
+            })?;
+            table.insert(code, name.to_string());
+        }
+        Ok(table)
+    }
+}
 
 /// Lookup tables for enriching company data
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Lookups {
     pub countries: HashMap<i32, String>,
     pub cities: HashMap<i32, String>,
@@ -12,9 +137,25 @@ pub struct Lookups {
     pub qualifications: HashMap<i32, String>,
     pub cnaes: HashMap<i32, String>,
     pub motives: HashMap<i32, String>,
+    /// IBGE codes by Receita Federal (TOM) municipality code, from the
+    /// embedded crosswalk and any `*IBGE*.csv` file of the data directory
+    pub cities_ibge: HashMap<i32, i32>,
+    /// CNAE hierarchy from the embedded sections and divisions, the `cnaes`
+    /// subclasses and any `*ESTRUTURA*.csv` file of the data directory
+    pub cnae_structure: CnaeStructure,
+}
+
+/// Lookups as written to a cache file
+#[derive(Serialize, Deserialize)]
+struct Cache<L> {
+    format: u32,
+    lookups: L,
 }
 
 impl Lookups {
+    /// File name of the cache the transform writes into the output directory
+    pub const CACHE_FILE: &'static str = "lookups.cache";
+
     pub fn new() -> Self {
         Self {
             countries: HashMap::new(),
@@ -23,175 +164,172 @@ impl Lookups {
             qualifications: HashMap::new(),
             cnaes: HashMap::new(),
             motives: HashMap::new(),
+            cities_ibge: municipalities::crosswalk(),
+            cnae_structure: CnaeStructure::embedded(),
         }
     }
 
-    /// Load lookup tables from CSV files
-    pub fn load_from_directory(&mut self, dir: &str) -> super::Result<()> {
+    /// Load lookup tables from the data directory, detecting their encoding
+    pub fn load_from_directory(&mut self, dir: &str) -> Result<()> {
+        self.load_from_directory_with_encoding(dir, Encoding::Auto)
+    }
+
+    /// Load lookup tables from the data directory, in files written in `encoding`
+    ///
+    /// Tables are found by their Receita Federal file names, both as
+    /// extracted files and inside the ZIP archives, so lookups are available
+    /// before anything is extracted.
+    pub fn load_from_directory_with_encoding(
+        &mut self,
+        dir: &str,
+        encoding: Encoding,
+    ) -> Result<()> {
         tracing::info!("Loading lookup tables from {}", dir);
 
-        // Find and load each lookup file
-        let entries = read_dir(dir)?;
-
-        for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
-
-            if path.extension().and_then(|s| s.to_str()) == Some("csv") {
-                let filename = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
-
-                let filename_upper = filename.to_uppercase();
-
-                if filename_upper.contains("PAIS") {
-                    self.load_countries(&path)?;
-                } else if filename_upper.contains("MUNIC") {
-                    self.load_cities(&path)?;
-                } else if filename_upper.contains("NATUREZA") || filename_upper.contains("NATJU") {
-                    self.load_legal_natures(&path)?;
-                } else if filename_upper.contains("QUALIF") || filename_upper.contains("QUALS") {
-                    self.load_qualifications(&path)?;
-                } else if filename_upper.contains("CNAE") {
-                    self.load_cnaes(&path)?;
-                } else if filename_upper.contains("MOTIV") {
-                    self.load_motives(&path)?;
-                }
+        let mut paths: Vec<PathBuf> = read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<_>>()?;
+        paths.sort();
+
+        for path in paths.iter().filter(|path| path.is_file()) {
+            let filename = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+            let filename_upper = filename.to_uppercase();
+
+            if let Some(kind) = LookupKind::detect(filename) {
+                self.load_table(kind, &std::fs::read(path)?, encoding, filename)?;
+            } else if filename_upper.ends_with(".ZIP") {
+                self.load_archive(path, encoding)?;
+            } else if !filename_upper.ends_with(".CSV") {
+                continue;
+            } else if filename_upper.contains("IBGE") {
+                self.cities_ibge
+                    .extend(municipalities::parse(&std::fs::read_to_string(path)?)?);
+            } else if filename_upper.contains("ESTRUTURA") {
+                self.cnae_structure
+                    .extend(cnae::parse(&std::fs::read_to_string(path)?)?);
             }
         }
 
-        tracing::info!("Loaded {} countries", self.countries.len());
-        tracing::info!("Loaded {} cities", self.cities.len());
-        tracing::info!("Loaded {} legal natures", self.legal_natures.len());
-        tracing::info!("Loaded {} qualifications", self.qualifications.len());
-        tracing::info!("Loaded {} CNAEs", self.cnaes.len());
-        tracing::info!("Loaded {} motives", self.motives.len());
-
-        Ok(())
-    }
-
-    fn load_countries(&mut self, path: &Path) -> super::Result<()> {
-        let df = CsvReadOptions::default()
-            .with_has_header(false)
-            .with_parse_options(CsvParseOptions::default().with_separator(b';'))
-            .try_into_reader_with_file_path(Some(path.into()))?
-            .finish()?;
-
-        if df.width() >= 2 {
-            let codes = df.column("column_1")?.i32()?;
-            let names = df.column("column_2")?.str()?;
-
-            for (code_opt, name_opt) in codes.into_iter().zip(names.into_iter()) {
-                if let (Some(code), Some(name)) = (code_opt, name_opt) {
-                    self.countries.insert(code, name.to_string());
-                }
+        for kind in LookupKind::ALL {
+            tracing::info!("Loaded {} {}", self.table(kind).len(), kind.table());
+        }
+        tracing::info!(
+            "Loaded {} IBGE city codes (embedded crosswalk {})",
+            self.cities_ibge.len(),
+            municipalities::version()
+        );
+        let missing = self.cities_without_ibge();
+        if !missing.is_empty() {
+            tracing::warn!(
+                "{} of {} municipalities have no IBGE code; add a complete TOM_IBGE.csv to {}",
+                missing.len(),
+                self.cities.len(),
+                dir
+            );
+        }
+        tracing::info!(
+            "Loaded {} CNAE structure codes (embedded CNAE {})",
+            self.cnae_structure.len(),
+            CnaeStructure::version()
+        );
+        for level in [CnaeLevel::Grupo, CnaeLevel::Classe] {
+            let undescribed = self.cnae_structure.undescribed(level).len();
+            if undescribed > 0 {
+                tracing::warn!(
+                    "{} of {} CNAE {:?} codes have no description; add a complete CNAE_ESTRUTURA.csv to {}",
+                    undescribed,
+                    self.cnae_structure.level(level).len(),
+                    level,
+                    dir
+                );
             }
         }
 
         Ok(())
     }
 
-    fn load_cities(&mut self, path: &Path) -> super::Result<()> {
-        let df = CsvReadOptions::default()
-            .with_has_header(false)
-            .with_parse_options(CsvParseOptions::default().with_separator(b';'))
-            .try_into_reader_with_file_path(Some(path.into()))?
-            .finish()?;
-
-        if df.width() >= 2 {
-            let codes = df.column("column_1")?.i32()?;
-            let names = df.column("column_2")?.str()?;
-
-            for (code_opt, name_opt) in codes.into_iter().zip(names.into_iter()) {
-                if let (Some(code), Some(name)) = (code_opt, name_opt) {
-                    self.cities.insert(code, name.to_string());
-                }
-            }
+    /// Load the lookup tables inside a ZIP archive; other entries are skipped
+    fn load_archive(&mut self, path: &Path, encoding: Encoding) -> Result<()> {
+        let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            let Some(kind) = LookupKind::detect(entry.name()) else {
+                continue;
+            };
+            let source = format!("{}:{}", path.display(), entry.name());
+            let mut bytes = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut bytes)?;
+            self.load_table(kind, &bytes, encoding, &source)?;
         }
-
         Ok(())
     }
 
-    fn load_legal_natures(&mut self, path: &Path) -> super::Result<()> {
-        let df = CsvReadOptions::default()
-            .with_has_header(false)
-            .with_parse_options(CsvParseOptions::default().with_separator(b';'))
-            .try_into_reader_with_file_path(Some(path.into()))?
-            .finish()?;
-
-        if df.width() >= 2 {
-            let codes = df.column("column_1")?.i32()?;
-            let names = df.column("column_2")?.str()?;
-
-            for (code_opt, name_opt) in codes.into_iter().zip(names.into_iter()) {
-                if let (Some(code), Some(name)) = (code_opt, name_opt) {
-                    self.legal_natures.insert(code, name.to_string());
-                }
-            }
+    fn load_table(
+        &mut self,
+        kind: LookupKind,
+        bytes: &[u8],
+        encoding: Encoding,
+        source: &str,
+    ) -> Result<()> {
+        let table = kind.parse(bytes, encoding, source)?;
+        tracing::debug!("Read {} {} from {}", table.len(), kind.table(), source);
+        if kind == LookupKind::Cnaes {
+            self.cnae_structure.extend_subclasses(&table);
         }
-
+        self.table_mut(kind).extend(table);
         Ok(())
     }
 
-    fn load_qualifications(&mut self, path: &Path) -> super::Result<()> {
-        let df = CsvReadOptions::default()
-            .with_has_header(false)
-            .with_parse_options(CsvParseOptions::default().with_separator(b';'))
-            .try_into_reader_with_file_path(Some(path.into()))?
-            .finish()?;
-
-        if df.width() >= 2 {
-            let codes = df.column("column_1")?.i32()?;
-            let names = df.column("column_2")?.str()?;
-
-            for (code_opt, name_opt) in codes.into_iter().zip(names.into_iter()) {
-                if let (Some(code), Some(name)) = (code_opt, name_opt) {
-                    self.qualifications.insert(code, name.to_string());
-                }
-            }
+    /// Read lookups from a cache file written by `write_cache`
+    pub fn from_cache(path: &Path) -> Result<Self> {
+        let reader = zstd::Decoder::new(File::open(path)?)?;
+        let cache: Cache<Lookups> = serde_json::from_reader(BufReader::new(reader))?;
+        if cache.format != CACHE_FORMAT {
+            return Err(TransformError::InvalidFormat(format!(
+                "{} is a version {} lookup cache, expected version {}",
+                path.display(),
+                cache.format,
+                CACHE_FORMAT
+            )));
         }
+        Ok(cache.lookups)
+    }
 
+    /// Write every table to a compressed cache file
+    pub fn write_cache(&self, path: &Path) -> Result<()> {
+        let mut writer = zstd::Encoder::new(BufWriter::new(File::create(path)?), 0)?;
+        serde_json::to_writer(
+            &mut writer,
+            &Cache {
+                format: CACHE_FORMAT,
+                lookups: self,
+            },
+        )?;
+        writer.finish()?;
         Ok(())
     }
 
-    fn load_cnaes(&mut self, path: &Path) -> super::Result<()> {
-        let df = CsvReadOptions::default()
-            .with_has_header(false)
-            .with_parse_options(CsvParseOptions::default().with_separator(b';'))
-            .try_into_reader_with_file_path(Some(path.into()))?
-            .finish()?;
-
-        if df.width() >= 2 {
-            let codes = df.column("column_1")?.i32()?;
-            let names = df.column("column_2")?.str()?;
-
-            for (code_opt, name_opt) in codes.into_iter().zip(names.into_iter()) {
-                if let (Some(code), Some(name)) = (code_opt, name_opt) {
-                    self.cnaes.insert(code, name.to_string());
-                }
-            }
+    /// The table of one lookup file
+    pub fn table(&self, kind: LookupKind) -> &HashMap<i32, String> {
+        match kind {
+            LookupKind::Paises => &self.countries,
+            LookupKind::Municipios => &self.cities,
+            LookupKind::Naturezas => &self.legal_natures,
+            LookupKind::Qualificacoes => &self.qualifications,
+            LookupKind::Cnaes => &self.cnaes,
+            LookupKind::Motivos => &self.motives,
         }
-
-        Ok(())
     }
 
-    fn load_motives(&mut self, path: &Path) -> super::Result<()> {
-        let df = CsvReadOptions::default()
-            .with_has_header(false)
-            .with_parse_options(CsvParseOptions::default().with_separator(b';'))
-            .try_into_reader_with_file_path(Some(path.into()))?
-            .finish()?;
-
-        if df.width() >= 2 {
-            let codes = df.column("column_1")?.i32()?;
-            let names = df.column("column_2")?.str()?;
-
-            for (code_opt, name_opt) in codes.into_iter().zip(names.into_iter()) {
-                if let (Some(code), Some(name)) = (code_opt, name_opt) {
-                    self.motives.insert(code, name.to_string());
-                }
-            }
+    fn table_mut(&mut self, kind: LookupKind) -> &mut HashMap<i32, String> {
+        match kind {
+            LookupKind::Paises => &mut self.countries,
+            LookupKind::Municipios => &mut self.cities,
+            LookupKind::Naturezas => &mut self.legal_natures,
+            LookupKind::Qualificacoes => &mut self.qualifications,
+            LookupKind::Cnaes => &mut self.cnaes,
+            LookupKind::Motivos => &mut self.motives,
         }
-
-        Ok(())
     }
 
     pub fn get_country(&self, code: i32) -> Option<&String> {
@@ -202,6 +340,24 @@ impl Lookups {
         self.cities.get(&code)
     }
 
+    /// IBGE 7-digit code of a Receita Federal (TOM) municipality code
+    pub fn get_city_ibge(&self, code: i32) -> Option<i32> {
+        self.cities_ibge.get(&code).copied()
+    }
+
+    /// TOM codes of the Municipios table missing from the IBGE crosswalk,
+    /// in code order
+    pub fn cities_without_ibge(&self) -> Vec<i32> {
+        let mut missing: Vec<i32> = self
+            .cities
+            .keys()
+            .filter(|code| !self.cities_ibge.contains_key(code))
+            .copied()
+            .collect();
+        missing.sort_unstable();
+        missing
+    }
+
     pub fn get_legal_nature(&self, code: i32) -> Option<&String> {
         self.legal_natures.get(&code)
     }
@@ -217,6 +373,25 @@ impl Lookups {
     pub fn get_motive(&self, code: i32) -> Option<&String> {
         self.motives.get(&code)
     }
+
+    /// All tables with the name they are exported under
+    pub fn tables(&self) -> [(&'static str, &HashMap<i32, String>); 6] {
+        LookupKind::ALL.map(|kind| (kind.table(), self.table(kind)))
+    }
+
+    /// Build a two-column frame (`key`, `value`) from a table, sorted by code
+    pub fn frame(table: &HashMap<i32, String>, key: &str, value: &str) -> PolarsResult<DataFrame> {
+        let mut rows: Vec<(&i32, &String)> = table.iter().collect();
+        rows.sort_by_key(|(code, _)| **code);
+
+        let codes: Vec<i32> = rows.iter().map(|(code, _)| **code).collect();
+        let names: Vec<&str> = rows.iter().map(|(_, name)| name.as_str()).collect();
+
+        DataFrame::new(vec![
+            Column::new(key.into(), codes),
+            Column::new(value.into(), names),
+        ])
+    }
 }
 
 impl Default for Lookups {
@@ -243,5 +418,94 @@ mod tests {
 
         assert_eq!(lookups.get_country(76), Some(&"Brasil".to_string()));
         assert_eq!(lookups.get_country(999), None);
+        assert_eq!(lookups.get_city_ibge(9701), Some(5300108));
+        assert_eq!(lookups.get_city_ibge(999), None);
+    }
+
+    #[test]
+    fn test_cities_without_ibge() {
+        let mut lookups = Lookups::new();
+        lookups.cities.insert(9701, "BRASILIA".to_string());
+        lookups
+            .cities
+            .insert(1, "ALTA FLORESTA D'OESTE".to_string());
+        lookups.cities.insert(7107, "SAO PAULO".to_string());
+        assert_eq!(lookups.cities_without_ibge(), [1]);
+
+        lookups
+            .cities_ibge
+            .extend(municipalities::parse("tom;ibge\n1;1100015\n").unwrap());
+        assert!(lookups.cities_without_ibge().is_empty());
+    }
+
+    #[test]
+    fn test_lookup_frame_is_sorted() {
+        let mut lookups = Lookups::new();
+        lookups.countries.insert(76, "BRASIL".to_string());
+        lookups.countries.insert(13, "AFEGANISTAO".to_string());
+
+        let df = Lookups::frame(&lookups.countries, "codigo_pais", "pais").unwrap();
+        let codes = df.column("codigo_pais").unwrap().i32().unwrap();
+        assert_eq!(codes.get(0), Some(13));
+        assert_eq!(
+            df.column("pais").unwrap().str().unwrap().get(1),
+            Some("BRASIL")
+        );
+    }
+
+    #[test]
+    fn test_detect_lookup_files() {
+        assert_eq!(
+            LookupKind::detect("F.K03200$Z.D51011.PAISCSV"),
+            Some(LookupKind::Paises)
+        );
+        assert_eq!(
+            LookupKind::detect("F.K03200$Z.D51011.NATJUCSV"),
+            Some(LookupKind::Naturezas)
+        );
+        assert_eq!(LookupKind::detect("MOTICSV.csv"), Some(LookupKind::Motivos));
+        assert_eq!(LookupKind::detect("cnaecsv0.csv"), Some(LookupKind::Cnaes));
+        assert_eq!(LookupKind::detect("Paises.zip"), None);
+        assert_eq!(LookupKind::detect("PAISES.csv"), None);
+        assert_eq!(LookupKind::detect("K3241.K03200Y0.D51011.ESTABELE"), None);
+    }
+
+    #[test]
+    fn test_parse_validates_layout() {
+        let kind = LookupKind::Paises;
+        let table = kind
+            .parse(b"\"076\";\"BRASIL\"\n", Encoding::Utf8, "PAISCSV")
+            .unwrap();
+        assert_eq!(table.get(&76).map(String::as_str), Some("BRASIL"));
+
+        for bytes in [
+            &b"\"076\";\"BRASIL\";\"X\"\n"[..],
+            &b"\"076\"\n"[..],
+            &b"\"BR\";\"BRASIL\"\n"[..],
+        ] {
+            let error = kind.parse(bytes, Encoding::Utf8, "PAISCSV").unwrap_err();
+            assert!(error.to_string().contains("PAISCSV (paises)"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(Lookups::CACHE_FILE);
+        let mut lookups = Lookups::new();
+        lookups.countries.insert(76, "BRASIL".to_string());
+        lookups.cnaes.insert(6204000, "Consultoria".to_string());
+        lookups
+            .cnae_structure
+            .extend_subclasses(&lookups.cnaes.clone());
+        lookups.write_cache(&path).unwrap();
+
+        let cached = Lookups::from_cache(&path).unwrap();
+        assert_eq!(cached.countries, lookups.countries);
+        assert_eq!(cached.cities_ibge, lookups.cities_ibge);
+        assert_eq!(cached.cnae_structure, lookups.cnae_structure);
+
+        std::fs::write(&path, b"not a cache").unwrap();
+        assert!(Lookups::from_cache(&path).is_err());
     }
 }
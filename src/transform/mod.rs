@@ -1,14 +1,59 @@
+mod amount;
+mod budget;
 mod cnae;
+mod cnpj;
+mod codes;
 mod company;
+mod date;
+mod diff;
+mod encoding;
+mod filter;
+mod frames;
+mod graph;
+mod groups;
+mod jobs;
+mod layout;
 mod lookups;
+mod municipalities;
+mod output;
 mod partner;
+mod privacy;
+mod quality;
+mod records;
+mod rejects;
+mod report;
+mod shards;
+mod spill;
+mod state;
+mod suppression;
 mod tax_regime;
 mod transformer;
 
-pub use cnae::CNAE;
+pub use amount::{InvalidAmount, parse_amount};
+pub use budget::MemoryLimit;
+pub use cnae::{CNAE, CnaeCode, CnaeLevel, CnaeStructure};
+pub use cnpj::{Cnpj, CnpjError};
+pub use codes::{
+    Coded, FaixaEtaria, IdentificadorSocio, MatrizFilial, Porte, SituacaoCadastral,
+    SituacaoEspecial, UnknownCode,
+};
 pub use company::Company;
-pub use lookups::Lookups;
+pub use date::{InvalidDate, parse_date};
+pub use diff::{ChangeEvent, ChangeKind, DiffConfig, DiffSummary, Differ, release_dir};
+pub use encoding::Encoding;
+pub use filter::{CnaePattern, Filters};
+pub use graph::{Controller, Edge, GRAPH_DIR, Node, NodeKind, OwnershipGraph};
+pub use groups::GROUPS_TABLE;
+pub use lookups::{LookupKind, Lookups};
+pub use output::{Compression, OutputFormat, OutputWriter};
 pub use partner::Partner;
+pub use privacy::{FieldPolicy, Policy, Privacy, PseudonymKey};
+pub use quality::{ColumnQuality, InputQuality, Metric, QualityReport, Threshold};
+pub use rejects::Reject;
+pub use report::TransformReport;
+pub use shards::RFB_SHARDS;
+pub use state::TransformState;
+pub use suppression::SuppressionList;
 pub use tax_regime::TaxRegime;
 pub use transformer::Transformer;
 
@@ -33,6 +78,27 @@ pub enum TransformError {
 
     #[error("ZIP extraction error: {0}")]
     ZipError(#[from] zip::result::ZipError),
+
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("Missing input shard: {0}")]
+    MissingShard(String),
+
+    #[error("Invalid CNPJ: {0}")]
+    InvalidCnpj(#[from] CnpjError),
+
+    #[error("Invalid code: {0}")]
+    UnknownCode(#[from] UnknownCode),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Data quality check failed: {0}")]
+    QualityCheck(String),
+
+    #[error("Too many malformed lines: {0}")]
+    TooManyRejects(String),
 }
 
 pub type Result<T> = std::result::Result<T, TransformError>;
@@ -42,6 +108,92 @@ pub struct TransformConfig {
     pub data_dir: String,
     pub output_dir: String,
     pub privacy_mode: bool,
+    /// Per-field overrides of the privacy mode policies, e.g. `email=mask`
+    pub privacy_policies: Vec<FieldPolicy>,
+    /// Key of the partner pseudonyms; requires privacy mode
+    pub pseudonym_key: Option<PseudonymKey>,
+    /// People and companies left out of every output
+    pub suppression: SuppressionList,
+    /// Subset of the companies to write; empty filters keep every company
+    pub filters: Filters,
+    pub format: OutputFormat,
+    pub compression: Compression,
+    /// Rows per Parquet row group; `None` uses the Polars default
+    pub row_group_size: Option<usize>,
+    /// Memory budget; joins larger than it are partitioned and spilled to disk
+    pub memory_limit: Option<MemoryLimit>,
+    /// Encoding of the input files; decoded to UTF-8 before parsing
+    pub encoding: Encoding,
+    /// Worker threads for shards and tables; `None` uses one per core
+    pub jobs: Option<usize>,
+    /// Rebuild every output, ignoring the state of previous runs
+    pub force: bool,
+    /// Quality limits that fail the run once `quality_report.json` is written
+    pub fail_on: Vec<Threshold>,
+    /// Malformed lines tolerated before the run stops; `None` tolerates any number
+    pub max_rejects: Option<u64>,
+    /// Also write the ownership graph of companies and partners to `graph/`
+    pub graph: bool,
+    /// Also write one record per `cnpj_basico` with its head office and
+    /// branches to `company_groups/`; Parquet only
+    pub groups: bool,
+    /// Estabelecimentos, Empresas and Socios shards to expect, `RFB_SHARDS`
+    /// for a Receita Federal release; `None` takes any number of shards
+    pub shards: Option<usize>,
+}
+
+impl TransformConfig {
+    /// Validate the configuration
+    pub fn validate(&self) -> Result<()> {
+        self.privacy()?;
+        self.filters.validate()?;
+        if self.pseudonym_key.is_some() && !self.privacy_mode {
+            return Err(TransformError::InvalidConfig(
+                "pseudonyms require privacy mode".to_string(),
+            ));
+        }
+        if self.groups && self.format != OutputFormat::Parquet {
+            return Err(TransformError::InvalidConfig(
+                "company groups are only written for parquet output".to_string(),
+            ));
+        }
+        if self.shards == Some(0) {
+            return Err(TransformError::InvalidConfig(
+                "shards must be at least 1".to_string(),
+            ));
+        }
+        if self.jobs == Some(0) {
+            return Err(TransformError::InvalidConfig(
+                "jobs must be at least 1".to_string(),
+            ));
+        }
+        if self.format == OutputFormat::Jsonl
+            && !matches!(
+                self.compression,
+                Compression::Uncompressed | Compression::Gzip | Compression::Zstd
+            )
+        {
+            return Err(TransformError::InvalidConfig(format!(
+                "{} compression is not supported for jsonl output (use none, gzip or zstd)",
+                self.compression
+            )));
+        }
+        Ok(())
+    }
+
+    /// Privacy policies to apply, or `None` outside privacy mode
+    pub fn privacy(&self) -> Result<Option<Privacy>> {
+        match self.privacy_mode {
+            true => {
+                let privacy = Privacy::new(&self.privacy_policies)?;
+                Ok(Some(match &self.pseudonym_key {
+                    Some(key) => privacy.with_pseudonyms(key.clone()),
+                    None => privacy,
+                }))
+            }
+            false => Ok(None),
+        }
+    }
 }
 
 impl Default for TransformConfig {
@@ -50,6 +202,22 @@ impl Default for TransformConfig {
             data_dir: "data".to_string(),
             output_dir: "output".to_string(),
             privacy_mode: false,
+            privacy_policies: Vec::new(),
+            pseudonym_key: None,
+            suppression: SuppressionList::default(),
+            filters: Filters::default(),
+            format: OutputFormat::default(),
+            compression: Compression::default(),
+            row_group_size: None,
+            memory_limit: None,
+            encoding: Encoding::default(),
+            jobs: None,
+            force: false,
+            fail_on: Vec::new(),
+            max_rejects: None,
+            graph: false,
+            groups: false,
+            shards: None,
         }
     }
 }
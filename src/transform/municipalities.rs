@@ -0,0 +1,223 @@
+use super::{Result, TransformError};
+use polars::prelude::*;
+use std::collections::HashMap;
+
+/// Crosswalk from the Receita Federal (TOM) municipality codes to the IBGE
+/// 7-digit codes, embedded in the binary
+const CROSSWALK: &str = include_str!("data/tom_ibge.csv");
+
+/// IBGE state codes, the first two digits of a municipality code, and their UF
+const STATES: [(i32, &str); 27] = [
+    (11, "RO"),
+    (12, "AC"),
+    (13, "AM"),
+    (14, "RR"),
+    (15, "PA"),
+    (16, "AP"),
+    (17, "TO"),
+    (21, "MA"),
+    (22, "PI"),
+    (23, "CE"),
+    (24, "RN"),
+    (25, "PB"),
+    (26, "PE"),
+    (27, "AL"),
+    (28, "SE"),
+    (29, "BA"),
+    (31, "MG"),
+    (32, "ES"),
+    (33, "RJ"),
+    (35, "SP"),
+    (41, "PR"),
+    (42, "SC"),
+    (43, "RS"),
+    (50, "MS"),
+    (51, "MT"),
+    (52, "GO"),
+    (53, "DF"),
+];
+
+/// IBGE regions by the first digit of a municipality code
+const REGIONS: [&str; 5] = ["Norte", "Nordeste", "Sudeste", "Sul", "Centro-Oeste"];
+
+/// UF of an IBGE municipality code, e.g. `DF` for `5300108`
+pub fn uf(ibge: i32) -> Option<&'static str> {
+    if !(1_000_000..10_000_000).contains(&ibge) {
+        return None;
+    }
+    STATES
+        .iter()
+        .find(|(state, _)| *state == ibge / 100_000)
+        .map(|(_, uf)| *uf)
+}
+
+/// Whether `uf` is a federative unit, or `EX` for establishments abroad
+pub fn is_uf(uf: &str) -> bool {
+    uf == "EX" || STATES.iter().any(|(_, state)| *state == uf)
+}
+
+/// Region of an IBGE municipality code, e.g. `Centro-Oeste` for `5300108`
+pub fn region(ibge: i32) -> Option<&'static str> {
+    uf(ibge)?;
+    REGIONS.get((ibge / 1_000_000 - 1) as usize).copied()
+}
+
+/// Region of a UF, e.g. `Nordeste` for `BA`; `None` for `EX` and unknown UFs
+pub fn region_of_uf(uf: &str) -> Option<&'static str> {
+    let (state, _) = STATES.iter().find(|(_, state)| *state == uf)?;
+    REGIONS.get((state / 10 - 1) as usize).copied()
+}
+
+/// Version of the embedded crosswalk, from its `# version:` line
+pub fn version() -> &'static str {
+    CROSSWALK
+        .lines()
+        .find_map(|line| line.strip_prefix("# version:"))
+        .map(str::trim)
+        .unwrap_or("unknown")
+}
+
+/// The embedded crosswalk, by TOM code
+pub fn crosswalk() -> HashMap<i32, i32> {
+    parse(CROSSWALK).expect("the embedded crosswalk is valid")
+}
+
+/// Parse a `tom;ibge[;municipio]` crosswalk; a header, blank lines and lines
+/// starting with `#` are skipped, and a TOM code listed twice is an error
+pub fn parse(text: &str) -> Result<HashMap<i32, i32>> {
+    let mut crosswalk = HashMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("tom;") {
+            continue;
+        }
+
+        let mut fields = line.split(';').map(|field| field.trim().trim_matches('"'));
+        let (tom, ibge) = (fields.next(), fields.next());
+        let codes = tom
+            .and_then(|tom| tom.parse::<i32>().ok())
+            .zip(ibge.and_then(|ibge| ibge.parse::<i32>().ok()));
+        match codes {
+            Some((tom, ibge)) if uf(ibge).is_some() => {
+                if crosswalk.insert(tom, ibge).is_some() {
+                    return Err(TransformError::InvalidConfig(format!(
+                        "line {} of the TOM to IBGE crosswalk repeats TOM code {}",
+                        index + 1,
+                        tom
+                    )));
+                }
+            }
+            _ => {
+                return Err(TransformError::InvalidConfig(format!(
+                    "line {} of the TOM to IBGE crosswalk is not `tom;ibge`: {}",
+                    index + 1,
+                    line
+                )));
+            }
+        }
+    }
+    Ok(crosswalk)
+}
+
+/// Frame joining `codigo_municipio` to `codigo_municipio_ibge`, `regiao` and
+/// the UF of the IBGE code (`uf_ibge`)
+pub fn frame(crosswalk: &HashMap<i32, i32>) -> PolarsResult<DataFrame> {
+    let mut rows: Vec<(i32, i32)> = crosswalk.iter().map(|(tom, ibge)| (*tom, *ibge)).collect();
+    rows.sort_unstable();
+
+    DataFrame::new(vec![
+        Column::new(
+            "codigo_municipio".into(),
+            rows.iter().map(|(tom, _)| *tom).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "codigo_municipio_ibge".into(),
+            rows.iter().map(|(_, ibge)| *ibge).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "regiao".into(),
+            rows.iter()
+                .map(|(_, ibge)| region(*ibge))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "uf_ibge".into(),
+            rows.iter().map(|(_, ibge)| uf(*ibge)).collect::<Vec<_>>(),
+        ),
+    ])
+}
+
+/// Frame joining `uf` to its region (`regiao_uf`)
+pub fn regions() -> PolarsResult<DataFrame> {
+    DataFrame::new(vec![
+        Column::new(
+            "uf".into(),
+            STATES.iter().map(|(_, uf)| *uf).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "regiao_uf".into(),
+            STATES
+                .iter()
+                .map(|(_, uf)| region_of_uf(uf))
+                .collect::<Vec<_>>(),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uf_and_region() {
+        assert_eq!(uf(5300108), Some("DF"));
+        assert_eq!(region(5300108), Some("Centro-Oeste"));
+        assert_eq!(uf(3550308), Some("SP"));
+        assert_eq!(region(3550308), Some("Sudeste"));
+        assert_eq!(region(1302603), Some("Norte"));
+        assert_eq!(uf(3450308), None);
+        assert_eq!(uf(9701), None);
+        assert_eq!(region(6000000), None);
+
+        assert_eq!(region_of_uf("BA"), Some("Nordeste"));
+        assert_eq!(region_of_uf("DF"), Some("Centro-Oeste"));
+        assert_eq!(region_of_uf("RS"), Some("Sul"));
+        assert_eq!(region_of_uf("EX"), None);
+        assert_eq!(region_of_uf(""), None);
+        assert_eq!(regions().unwrap().height(), 27);
+    }
+
+    #[test]
+    fn test_embedded_crosswalk() {
+        let crosswalk = crosswalk();
+        assert_eq!(crosswalk.get(&9701), Some(&5300108));
+        assert_eq!(crosswalk.get(&7107), Some(&3550308));
+        assert_ne!(version(), "unknown");
+
+        assert_eq!(
+            parse("\"0001\";\"1100015\"\n").unwrap().get(&1),
+            Some(&1100015)
+        );
+        assert!(parse("9701;530010\n").is_err());
+        assert!(parse("BRASILIA\n").is_err());
+        assert!(parse("9701;5300108\n9701;5300108\n").is_err());
+    }
+
+    #[test]
+    fn test_embedded_crosswalk_is_consistent() {
+        // Unique TOM codes and known UFs are checked by `parse`
+        let crosswalk = crosswalk();
+        let lines = CROSSWALK
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with("tom;"))
+            .filter(|line| !line.trim().is_empty())
+            .count();
+        assert_eq!(crosswalk.len(), lines);
+
+        let mut ibge: Vec<i32> = crosswalk.values().copied().collect();
+        ibge.sort_unstable();
+        ibge.dedup();
+        assert_eq!(ibge.len(), crosswalk.len(), "an IBGE code is listed twice");
+        assert!(ibge.iter().all(|code| region(*code).is_some()));
+    }
+}
@@ -0,0 +1,407 @@
+use super::{Result, TransformConfig, TransformError};
+use flate2::write::GzEncoder;
+use polars::prelude::*;
+use serde::Serialize;
+use std::fmt;
+use std::fs::{File, create_dir_all};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Output format of the transform stage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Apache Parquet, one directory per table
+    #[default]
+    Parquet,
+    /// Newline-delimited JSON, one `Company` document per establishment
+    Jsonl,
+}
+
+impl OutputFormat {
+    /// File extension used for the written parts
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "parquet" => Ok(OutputFormat::Parquet),
+            "jsonl" | "ndjson" => Ok(OutputFormat::Jsonl),
+            other => Err(format!("unknown output format: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// Compression codec for the written files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    Uncompressed,
+    Snappy,
+    Gzip,
+    Lz4,
+    #[default]
+    Zstd,
+    Brotli,
+}
+
+impl Compression {
+    fn parquet(&self) -> ParquetCompression {
+        match self {
+            Compression::Uncompressed => ParquetCompression::Uncompressed,
+            Compression::Snappy => ParquetCompression::Snappy,
+            Compression::Gzip => ParquetCompression::Gzip(None),
+            Compression::Lz4 => ParquetCompression::Lz4Raw,
+            Compression::Zstd => ParquetCompression::Zstd(None),
+            Compression::Brotli => ParquetCompression::Brotli(None),
+        }
+    }
+
+    /// Suffix appended to whole-file compressed outputs (e.g. `.jsonl.gz`)
+    fn suffix(&self) -> &'static str {
+        match self {
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+            _ => "",
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" | "uncompressed" => Ok(Compression::Uncompressed),
+            "snappy" => Ok(Compression::Snappy),
+            "gzip" => Ok(Compression::Gzip),
+            "lz4" => Ok(Compression::Lz4),
+            "zstd" => Ok(Compression::Zstd),
+            "brotli" => Ok(Compression::Brotli),
+            other => Err(format!("unknown compression: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::Uncompressed => "uncompressed",
+            Compression::Snappy => "snappy",
+            Compression::Gzip => "gzip",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+            Compression::Brotli => "brotli",
+        })
+    }
+}
+
+/// Writes transformed tables into the output directory
+///
+/// Sharded tables are written as `<table>/part-00000.<ext>` so the directory
+/// can be read as a single dataset by DuckDB or Spark.
+pub struct OutputWriter {
+    output_dir: PathBuf,
+    format: OutputFormat,
+    compression: Compression,
+    row_group_size: Option<usize>,
+}
+
+impl OutputWriter {
+    pub fn new(config: &TransformConfig) -> Self {
+        Self {
+            output_dir: PathBuf::from(&config.output_dir),
+            format: config.format,
+            compression: config.compression,
+            row_group_size: config.row_group_size,
+        }
+    }
+
+    /// Write one Parquet part of a sharded table
+    pub fn write_part(&self, table: &str, part: usize, df: &mut DataFrame) -> Result<PathBuf> {
+        let mut writer = self.open_part(table, part, df.schema())?;
+        writer.write_frame(df)?;
+        writer.finish()
+    }
+
+    /// Write a Parquet file named `name` inside the `table` directory
+    pub fn write(&self, table: &str, name: &str, df: &mut DataFrame) -> Result<PathBuf> {
+        let dir = self.output_dir.join(table);
+        create_dir_all(&dir)?;
+
+        let path = dir.join(format!("{}.parquet", name));
+        let file = File::create(&path)?;
+
+        ParquetWriter::new(file)
+            .with_compression(self.compression.parquet())
+            .with_row_group_size(self.row_group_size)
+            .finish(df)?;
+
+        tracing::info!("Wrote {} rows to {:?}", df.height(), path);
+        Ok(path)
+    }
+
+    /// Stream a lazy frame into one Parquet part of a sharded table
+    ///
+    /// The query runs on the streaming engine, so the part is never fully
+    /// materialized in memory.
+    pub fn sink_part(&self, table: &str, part: usize, lf: LazyFrame) -> Result<PathBuf> {
+        let path = self.part_path(table, part)?;
+
+        lf.sink_parquet(
+            SinkTarget::Path(PlPath::Local(path.as_path().into())),
+            ParquetWriteOptions {
+                compression: self.compression.parquet(),
+                row_group_size: self.row_group_size,
+                ..Default::default()
+            },
+            None,
+            SinkOptions::default(),
+        )?
+        .collect_with_engine(Engine::Streaming)?;
+
+        tracing::info!("Wrote {:?}", path);
+        Ok(path)
+    }
+
+    /// Open one part of a sharded table to be written in batches
+    pub fn open_part(&self, table: &str, part: usize, schema: &Schema) -> Result<PartWriter> {
+        let path = self.part_path(table, part)?;
+        let file = File::create(&path)?;
+
+        let sink = match self.format {
+            OutputFormat::Parquet => PartSink::Parquet(Box::new(
+                ParquetWriter::new(file)
+                    .with_compression(self.compression.parquet())
+                    .with_row_group_size(self.row_group_size)
+                    .batched(schema)?,
+            )),
+            OutputFormat::Jsonl => {
+                let file = BufWriter::new(file);
+                match self.compression {
+                    Compression::Gzip => {
+                        PartSink::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+                    }
+                    Compression::Zstd => PartSink::Zstd(zstd::Encoder::new(file, 0)?),
+                    _ => PartSink::Plain(file),
+                }
+            }
+        };
+
+        Ok(PartWriter {
+            path,
+            rows: 0,
+            sink,
+        })
+    }
+
+    /// Write one part of a document table as NDJSON, one document per line
+    pub fn write_documents<T: Serialize>(
+        &self,
+        table: &str,
+        part: usize,
+        documents: &[T],
+    ) -> Result<PathBuf> {
+        let mut writer = self.open_part(table, part, &Schema::default())?;
+        writer.write_documents(documents)?;
+        writer.finish()
+    }
+
+    /// Path of a part file, creating the table directory
+    fn part_path(&self, table: &str, part: usize) -> Result<PathBuf> {
+        let dir = self.output_dir.join(table);
+        create_dir_all(&dir)?;
+
+        let suffix = match self.format {
+            OutputFormat::Parquet => "",
+            OutputFormat::Jsonl => self.compression.suffix(),
+        };
+        Ok(dir.join(format!(
+            "part-{:05}.{}{}",
+            part,
+            self.format.extension(),
+            suffix
+        )))
+    }
+}
+
+enum PartSink {
+    Parquet(Box<polars::io::parquet::write::BatchedWriter<File>>),
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+/// One part file being written in batches (see `OutputWriter::open_part`)
+pub struct PartWriter {
+    path: PathBuf,
+    rows: usize,
+    sink: PartSink,
+}
+
+impl PartWriter {
+    /// Append a frame as Parquet row groups
+    pub fn write_frame(&mut self, df: &DataFrame) -> Result<()> {
+        match &mut self.sink {
+            PartSink::Parquet(writer) => writer.write_batch(df)?,
+            _ => {
+                return Err(TransformError::InvalidConfig(
+                    "frames can only be written to parquet parts".to_string(),
+                ));
+            }
+        }
+        self.rows += df.height();
+        Ok(())
+    }
+
+    /// Append documents, one JSON object per line
+    pub fn write_documents<T: Serialize>(&mut self, documents: &[T]) -> Result<()> {
+        match &mut self.sink {
+            PartSink::Plain(writer) => write_lines(writer, documents)?,
+            PartSink::Gzip(writer) => write_lines(writer, documents)?,
+            PartSink::Zstd(writer) => write_lines(writer, documents)?,
+            PartSink::Parquet(_) => {
+                return Err(TransformError::InvalidConfig(
+                    "documents can only be written to jsonl parts".to_string(),
+                ));
+            }
+        }
+        self.rows += documents.len();
+        Ok(())
+    }
+
+    /// Rows written so far
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Flush the part and return its path
+    pub fn finish(self) -> Result<PathBuf> {
+        match self.sink {
+            PartSink::Parquet(writer) => {
+                writer.finish()?;
+            }
+            PartSink::Plain(mut writer) => writer.flush()?,
+            PartSink::Gzip(writer) => writer.finish()?.flush()?,
+            PartSink::Zstd(writer) => writer.finish()?.flush()?,
+        }
+
+        tracing::info!("Wrote {} rows to {:?}", self.rows, self.path);
+        Ok(self.path)
+    }
+}
+
+/// Number of rows of a Parquet file, read from its footer
+pub fn parquet_rows(path: &Path) -> Result<usize> {
+    Ok(ParquetReader::new(File::open(path)?).num_rows()?)
+}
+
+fn write_lines<W: Write, T: Serialize>(writer: &mut W, documents: &[T]) -> Result<()> {
+    for document in documents {
+        serde_json::to_writer(&mut *writer, document)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_compression() {
+        assert_eq!("zstd".parse::<Compression>(), Ok(Compression::Zstd));
+        assert_eq!("none".parse::<Compression>(), Ok(Compression::Uncompressed));
+        assert!("rar".parse::<Compression>().is_err());
+    }
+
+    #[test]
+    fn test_write_part() {
+        let dir = std::env::temp_dir().join("rfb_output_test");
+        let config = TransformConfig {
+            output_dir: dir.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let mut df = df!("cnpj" => ["33683111000280"]).unwrap();
+        let path = OutputWriter::new(&config)
+            .write_part("companies", 3, &mut df)
+            .unwrap();
+
+        assert!(path.ends_with("companies/part-00003.parquet"));
+        let read = ParquetReader::new(File::open(&path).unwrap())
+            .finish()
+            .unwrap();
+        assert_eq!(read.height(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_part_writer_appends_batches() {
+        let dir = std::env::temp_dir().join("rfb_output_batches_test");
+        let config = TransformConfig {
+            output_dir: dir.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let first = df!("cnpj" => ["33683111000280"]).unwrap();
+        let second = df!("cnpj" => ["00000000000191", "60746948000112"]).unwrap();
+        let mut writer = OutputWriter::new(&config)
+            .open_part("companies", 0, first.schema())
+            .unwrap();
+        writer.write_frame(&first).unwrap();
+        writer.write_frame(&second).unwrap();
+        let path = writer.finish().unwrap();
+
+        let read = ParquetReader::new(File::open(&path).unwrap())
+            .finish()
+            .unwrap();
+        assert_eq!(read.height(), 3);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_write_documents_gzip() {
+        use std::io::Read;
+
+        let dir = std::env::temp_dir().join("rfb_output_jsonl_test");
+        let config = TransformConfig {
+            output_dir: dir.to_string_lossy().to_string(),
+            format: OutputFormat::Jsonl,
+            compression: Compression::Gzip,
+            ..Default::default()
+        };
+
+        let documents = vec![
+            serde_json::json!({"cnpj": "1"}),
+            serde_json::json!({"cnpj": "2"}),
+        ];
+        let path = OutputWriter::new(&config)
+            .write_documents("companies", 0, &documents)
+            .unwrap();
+        assert!(path.ends_with("companies/part-00000.jsonl.gz"));
+
+        let mut content = String::new();
+        flate2::read::GzDecoder::new(File::open(&path).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "{\"cnpj\":\"1\"}\n{\"cnpj\":\"2\"}\n");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
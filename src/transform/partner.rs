@@ -1,50 +1,40 @@
+use super::{Coded, FaixaEtaria, IdentificadorSocio};
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 
 /// Partner (Sócio) data structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Partner {
-    pub cnpj: String,
-    pub identificador_socio: Option<i32>,
+    /// First eight digits of the CNPJs of the company; the Socios file does
+    /// not tell which establishment is the head office
+    pub cnpj_basico: String,
+    pub identificador_socio: Option<IdentificadorSocio>,
     pub nome_socio: String,
     pub cnpj_cpf_socio: String,
     pub codigo_qualificacao_socio: Option<i32>,
     pub qualificacao_socio: Option<String>,
-    pub data_entrada_sociedade: Option<String>,
+    pub data_entrada_sociedade: Option<NaiveDate>,
     pub codigo_pais: Option<i32>,
     pub pais: Option<String>,
     pub cpf_representante_legal: String,
     pub nome_representante_legal: String,
     pub codigo_qualificacao_representante_legal: Option<i32>,
     pub qualificacao_representante_legal: Option<String>,
-    pub codigo_faixa_etaria: Option<i32>,
+    pub codigo_faixa_etaria: Option<FaixaEtaria>,
     pub faixa_etaria: Option<String>,
 }
 
 impl Partner {
-    /// Parse identifier type
+    /// Description of an identificador de sócio code, see `IdentificadorSocio`
     pub fn parse_identificador_socio(code: i32) -> Option<&'static str> {
-        match code {
-            1 => Some("PESSOA JURÍDICA"),
-            2 => Some("PESSOA FÍSICA"),
-            3 => Some("ESTRANGEIRO"),
-            _ => None,
-        }
+        IdentificadorSocio::from_code(code)
+            .ok()
+            .map(Coded::description)
     }
 
-    /// Parse age range description
+    /// Description of a faixa etária code, see `FaixaEtaria`
     pub fn parse_faixa_etaria(code: i32) -> Option<&'static str> {
-        match code {
-            1 => Some("0 a 12 anos"),
-            2 => Some("13 a 20 anos"),
-            3 => Some("21 a 30 anos"),
-            4 => Some("31 a 40 anos"),
-            5 => Some("41 a 50 anos"),
-            6 => Some("51 a 60 anos"),
-            7 => Some("61 a 70 anos"),
-            8 => Some("71 a 80 anos"),
-            9 => Some("Maiores de 80 anos"),
-            _ => None,
-        }
+        FaixaEtaria::from_code(code).ok().map(Coded::description)
     }
 }
 
@@ -0,0 +1,539 @@
+use super::{Result, TransformError};
+use lazy_static::lazy_static;
+use polars::prelude::*;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// An 11-digit CPF, bare or formatted (`123.456.789-01`), not part of a longer number
+///
+/// A match takes in the characters around the CPF, so of two CPFs a single
+/// character apart only the first matches; the text is replaced twice, and the
+/// second pass masks the CPFs that follow a masked one.
+const CPF_PATTERN: &str = r"(^|\D)(\d{3})\.?(\d{3})\.?(\d{3})-?(\d{2})(\D|$)";
+
+/// Keeps the middle six digits, as the Federal Revenue does for partners (`***456789**`)
+const CPF_MASK: &str = "$1***$3$4**$6";
+
+/// `natureza_juridica` of the Empresário Individual, including MEIs
+const EMPRESARIO_INDIVIDUAL: i32 = 2135;
+
+/// `identificador_socio` of partners who are people
+const PESSOA_FISICA: i32 = 2;
+
+/// Context of the BLAKE3 key derivation; changing it changes every token
+const PSEUDONYM_CONTEXT: &str = "rfb-rs 2025-01 partner pseudonym";
+
+/// Shortest secret accepted for the pseudonym key, in bytes
+const MIN_SECRET_LEN: usize = 16;
+
+lazy_static! {
+    static ref CPF_REGEX: Regex = Regex::new(CPF_PATTERN).unwrap();
+}
+
+/// Mask every CPF found in a text, e.g. the one ending the name of an MEI
+pub fn mask_cpfs(text: &str) -> Cow<'_, str> {
+    match CPF_REGEX.replace_all(text, CPF_MASK) {
+        Cow::Borrowed(text) => Cow::Borrowed(text),
+        Cow::Owned(once) => Cow::Owned(CPF_REGEX.replace_all(&once, CPF_MASK).into_owned()),
+    }
+}
+
+/// Mask every CPF found in a text column
+pub(super) fn masked_cpfs(expr: Expr) -> Expr {
+    expr.str()
+        .replace_all(lit(CPF_PATTERN), lit(CPF_MASK), false)
+        .str()
+        .replace_all(lit(CPF_PATTERN), lit(CPF_MASK), false)
+}
+
+/// The 11 digits of every CPF found in a text
+pub(super) fn cpfs(text: &str) -> impl Iterator<Item = String> + '_ {
+    let digits = |text: &str| -> Vec<String> {
+        CPF_REGEX
+            .captures_iter(text)
+            .map(|caps| (2..=5).map(|group| &caps[group]).collect())
+            .collect()
+    };
+    // The CPFs the first pass misses are still whole in the masked text
+    let mut found = digits(text);
+    found.extend(digits(&CPF_REGEX.replace_all(text, CPF_MASK)));
+    found.into_iter()
+}
+
+/// The six middle digits of a CPF, raw or masked, which are the ones the
+/// Federal Revenue publishes for partners; `None` without a CPF
+pub(super) fn published_digits(cpf: &str) -> Option<String> {
+    let digits: String = mask_cpfs(cpf.trim())
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect();
+    (digits.len() == 6 && digits != "000000").then_some(digits)
+}
+
+/// A name in upper case with single spaces, for comparisons
+pub(super) fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase()
+}
+
+/// What privacy mode does with a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Write the value as is
+    Keep,
+    /// Hide the personal part of the value (see `Field`)
+    Mask,
+    /// Write null
+    Drop,
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "keep" => Ok(Policy::Keep),
+            "mask" => Ok(Policy::Mask),
+            "drop" => Ok(Policy::Drop),
+            other => Err(format!(
+                "unknown privacy policy: {} (expected keep, mask or drop)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Policy::Keep => "keep",
+            Policy::Mask => "mask",
+            Policy::Drop => "drop",
+        })
+    }
+}
+
+/// How a field is masked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mask {
+    /// CPFs in the text are masked (`***456789**`), anything else is kept
+    Cpf,
+    /// The mailbox is hidden, the domain kept (`***@example.com`)
+    Email,
+    /// The area code is kept, the number hidden (`61********`)
+    Phone,
+    /// Any value becomes `***`
+    Text,
+}
+
+/// A field protected by privacy mode
+struct Field {
+    table: &'static str,
+    name: &'static str,
+    mask: Mask,
+    default: Policy,
+    /// Only applied to companies of a single person (Empresário Individual or MEI)
+    individual: bool,
+}
+
+const FIELDS: [Field; 11] = [
+    Field::name("razao_social"),
+    Field::name("nome_fantasia"),
+    Field::company("email", Mask::Email, Policy::Drop),
+    Field::company("ddd_telefone_1", Mask::Phone, Policy::Drop),
+    Field::company("ddd_telefone_2", Mask::Phone, Policy::Drop),
+    Field::company("ddd_fax", Mask::Phone, Policy::Drop),
+    Field::company("logradouro", Mask::Text, Policy::Drop),
+    Field::company("numero", Mask::Text, Policy::Drop),
+    Field::company("complemento", Mask::Text, Policy::Drop),
+    Field::partner("cnpj_cpf_socio"),
+    Field::partner("cpf_representante_legal"),
+];
+
+impl Field {
+    /// A company name, where any company may embed a CPF
+    const fn name(name: &'static str) -> Self {
+        Self {
+            table: "companies",
+            name,
+            mask: Mask::Cpf,
+            default: Policy::Mask,
+            individual: false,
+        }
+    }
+
+    const fn company(name: &'static str, mask: Mask, default: Policy) -> Self {
+        Self {
+            table: "companies",
+            name,
+            mask,
+            default,
+            individual: true,
+        }
+    }
+
+    const fn partner(name: &'static str) -> Self {
+        Self {
+            table: "partners",
+            name,
+            mask: Mask::Cpf,
+            default: Policy::Mask,
+            individual: false,
+        }
+    }
+
+    fn masked(&self) -> Expr {
+        let value = col(self.name).str();
+        match self.mask {
+            Mask::Cpf => masked_cpfs(col(self.name)),
+            Mask::Email => value.replace(lit(r"^[^@]*"), lit("***"), false),
+            Mask::Phone => value.replace(lit(r"^(\d{2}).*$"), lit("$1********"), false),
+            Mask::Text => value.replace(lit(r"^(?s).+$"), lit("***"), false),
+        }
+    }
+
+    fn protected(&self, policy: Policy) -> Expr {
+        match policy {
+            Policy::Keep => col(self.name),
+            Policy::Mask => self.masked(),
+            Policy::Drop => lit(NULL).cast(DataType::String),
+        }
+    }
+}
+
+/// Secret key of the partner pseudonyms
+///
+/// The CLI reads it from the `RFB_PSEUDONYM_KEY` environment variable. The
+/// same secret gives the same tokens in every run, so it must be kept for as
+/// long as the tokens are compared, and never published.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PseudonymKey([u8; 32]);
+
+impl PseudonymKey {
+    /// Environment variable the CLI reads the secret from
+    pub const ENV: &'static str = "RFB_PSEUDONYM_KEY";
+
+    /// Derive a key from a secret of at least 16 bytes
+    pub fn new(secret: &str) -> Result<Self> {
+        if secret.len() < MIN_SECRET_LEN {
+            return Err(TransformError::InvalidConfig(format!(
+                "the pseudonym key must be at least {} bytes long",
+                MIN_SECRET_LEN
+            )));
+        }
+        Ok(Self(blake3::derive_key(
+            PSEUDONYM_CONTEXT,
+            secret.as_bytes(),
+        )))
+    }
+
+    /// Token of a person from a CPF and a name
+    ///
+    /// Only the six middle digits of the CPF are used, as they are the ones
+    /// the Federal Revenue publishes (`***456789**`), so raw and masked CPFs
+    /// give the same token. Names are compared ignoring case and spacing.
+    /// Returns `None` when there is no CPF.
+    pub fn token(&self, cpf: &str, name: &str) -> Option<String> {
+        let digits = published_digits(cpf)?;
+        let mut hasher = blake3::Hasher::new_keyed(&self.0);
+        hasher
+            .update(digits.as_bytes())
+            .update(&[0])
+            .update(normalize_name(name).as_bytes());
+        Some(hasher.finalize().to_hex()[..32].to_string())
+    }
+
+    /// Identifies the key in the transform state without revealing it
+    fn fingerprint(&self) -> String {
+        blake3::hash(&self.0).to_hex()[..16].to_string()
+    }
+
+    /// Replace a CPF column with the token of its CPF and `name`
+    pub(super) fn pseudonym(&self, cpf: &str, name: &str) -> Expr {
+        let key = self.clone();
+        concat_str([col(cpf), col(name).fill_null(lit(""))], "\u{1f}", false).map(
+            move |column| {
+                let tokens: StringChunked = column
+                    .str()?
+                    .into_iter()
+                    .map(|value| {
+                        let (cpf, name) = value?.split_once('\u{1f}')?;
+                        key.token(cpf, name)
+                    })
+                    .collect();
+                Ok(tokens.into_column())
+            },
+            |_, field| {
+                Ok(polars::prelude::Field::new(
+                    field.name().clone(),
+                    DataType::String,
+                ))
+            },
+        )
+    }
+}
+
+impl fmt::Debug for PseudonymKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PseudonymKey({})", self.fingerprint())
+    }
+}
+
+/// Policy for one field, e.g. `email=mask`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldPolicy {
+    pub field: String,
+    pub policy: Policy,
+}
+
+impl FromStr for FieldPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (field, policy) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected <field>=<policy>, got {}", s))?;
+        Ok(Self {
+            field: field.trim().to_string(),
+            policy: policy.trim().parse()?,
+        })
+    }
+}
+
+impl fmt::Display for FieldPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.field, self.policy)
+    }
+}
+
+/// Policies of privacy mode, the defaults overridden per field
+///
+/// CPFs are masked in partners and in the names of every company; the e-mail,
+/// phones and street address of single-person companies are dropped. With a pseudonym
+/// key, partners who are people are also identified by a token instead of
+/// their CPF and name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Privacy {
+    policies: BTreeMap<&'static str, Policy>,
+    key: Option<PseudonymKey>,
+}
+
+impl Privacy {
+    pub fn new(overrides: &[FieldPolicy]) -> Result<Self> {
+        let mut policies: BTreeMap<_, _> = FIELDS.iter().map(|f| (f.name, f.default)).collect();
+        for FieldPolicy { field, policy } in overrides {
+            let Some(current) = policies.get_mut(field.as_str()) else {
+                return Err(TransformError::InvalidConfig(format!(
+                    "privacy mode has no policy for {} (expected one of {})",
+                    field,
+                    FIELDS.map(|f| f.name).join(", ")
+                )));
+            };
+            *current = *policy;
+        }
+        Ok(Self {
+            policies,
+            key: None,
+        })
+    }
+
+    /// Replace the CPF and name of partners who are people, and of legal
+    /// representatives, with a token keyed by `key` (see `PseudonymKey::token`)
+    pub fn with_pseudonyms(mut self, key: PseudonymKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Policy of a field; fields privacy mode does not know are kept
+    pub fn policy(&self, field: &str) -> Policy {
+        self.policies.get(field).copied().unwrap_or(Policy::Keep)
+    }
+
+    fn apply(&self, table: &str, lf: LazyFrame, individual: Expr) -> LazyFrame {
+        let exprs: Vec<Expr> = FIELDS
+            .iter()
+            .filter(|field| field.table == table)
+            .filter_map(|field| {
+                let protected = match self.policy(field.name) {
+                    Policy::Keep => return None,
+                    policy => field.protected(policy),
+                };
+                let protected = match field.individual {
+                    true => when(individual.clone())
+                        .then(protected)
+                        .otherwise(col(field.name)),
+                    false => protected,
+                };
+                Some(protected.alias(field.name))
+            })
+            .collect();
+        lf.with_columns(exprs)
+    }
+
+    /// Protect a companies frame (see `frames::companies`)
+    pub fn companies(&self, companies: LazyFrame) -> LazyFrame {
+        let individual = col("codigo_natureza_juridica")
+            .eq(lit(EMPRESARIO_INDIVIDUAL))
+            .or(col("opcao_pelo_mei").eq(lit(true)))
+            .fill_null(lit(false));
+        self.apply("companies", companies, individual)
+    }
+
+    /// Protect a partners frame (see `frames::partners`)
+    pub fn partners(&self, partners: LazyFrame) -> LazyFrame {
+        let partners = self.apply("partners", partners, lit(true));
+        let Some(key) = &self.key else {
+            return partners;
+        };
+
+        // Masking keeps the digits the tokens are made of
+        let person = col("identificador_socio").eq(lit(PESSOA_FISICA));
+        let representative = key.pseudonym("cpf_representante_legal", "nome_representante_legal");
+        partners.with_columns([
+            when(person.clone())
+                .then(key.pseudonym("cnpj_cpf_socio", "nome_socio"))
+                .otherwise(col("cnpj_cpf_socio"))
+                .alias("cnpj_cpf_socio"),
+            when(person)
+                .then(lit(NULL).cast(DataType::String))
+                .otherwise(col("nome_socio"))
+                .alias("nome_socio"),
+            representative.clone().alias("cpf_representante_legal"),
+            when(representative.is_null())
+                .then(col("nome_representante_legal"))
+                .otherwise(lit(NULL).cast(DataType::String))
+                .alias("nome_representante_legal"),
+        ])
+    }
+}
+
+impl fmt::Display for Privacy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let policies: Vec<String> = self
+            .policies
+            .iter()
+            .map(|(field, policy)| format!("{}={}", field, policy))
+            .collect();
+        f.write_str(&policies.join(","))?;
+        match &self.key {
+            Some(key) => write!(f, ",pseudonyms={}", key.fingerprint()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_cpfs() {
+        assert_eq!(
+            mask_cpfs("JOAO SILVA 12345678901"),
+            "JOAO SILVA ***456789**"
+        );
+        assert_eq!(mask_cpfs("123.456.789-01"), "***456789**");
+        assert_eq!(mask_cpfs("***456789**"), "***456789**");
+        // CNPJs are not CPFs
+        assert_eq!(mask_cpfs("33683111000280"), "33683111000280");
+        // CPFs a single character apart
+        assert_eq!(
+            mask_cpfs("12345678901 98765432100"),
+            "***456789** ***654321**"
+        );
+        assert_eq!(
+            mask_cpfs("123.456.789-01/987.654.321-00/11122233344"),
+            "***456789**/***654321**/***222333**"
+        );
+        assert_eq!(
+            cpfs("12345678901 98765432100").collect::<Vec<_>>(),
+            ["12345678901", "98765432100"]
+        );
+
+        let df = df!("nome" => ["12345678901 98765432100 11122233344"])
+            .unwrap()
+            .lazy()
+            .select([masked_cpfs(col("nome"))])
+            .collect()
+            .unwrap();
+        assert_eq!(
+            df.column("nome").unwrap().str().unwrap().get(0),
+            Some("***456789** ***654321** ***222333**")
+        );
+    }
+
+    #[test]
+    fn test_companies_policies() {
+        let privacy = Privacy::new(&["email=mask".parse().unwrap()]).unwrap();
+        let df = df!(
+            "codigo_natureza_juridica" => [Some(2135), Some(2062), None],
+            "opcao_pelo_mei" => [None, None, Some(true)],
+            "razao_social" => ["JOAO SILVA 12345678901", "ACME 12345678901", "MARIA 98765432100"],
+            "email" => ["joao@example.com", "contato@acme.com", "maria@example.com"],
+            "ddd_telefone_1" => ["6132023000", "1133334444", "2199998888"],
+        )
+        .unwrap();
+
+        let others = [
+            "nome_fantasia",
+            "ddd_telefone_2",
+            "ddd_fax",
+            "logradouro",
+            "numero",
+        ]
+        .map(|name| lit(NULL).cast(DataType::String).alias(name));
+        let df = df
+            .lazy()
+            .with_columns(others)
+            .with_column(lit("APTO 1").alias("complemento"));
+
+        let phones = Privacy::new(&["ddd_telefone_1=mask".parse().unwrap()]).unwrap();
+        let masked = phones.companies(df.clone()).collect().unwrap();
+        let phone = masked
+            .column("ddd_telefone_1")
+            .unwrap()
+            .str()
+            .unwrap()
+            .get(0);
+        assert_eq!(phone, Some("61********"));
+
+        let df = privacy.companies(df).collect().unwrap();
+        let column = |name| df.column(name).unwrap().str().unwrap().clone();
+        let razao_social = column("razao_social");
+        assert_eq!(razao_social.get(0), Some("JOAO SILVA ***456789**"));
+        assert_eq!(razao_social.get(1), Some("ACME ***456789**"));
+        assert_eq!(razao_social.get(2), Some("MARIA ***654321**"));
+        assert_eq!(column("email").get(0), Some("***@example.com"));
+        assert_eq!(column("email").get(1), Some("contato@acme.com"));
+        assert_eq!(column("ddd_telefone_1").get(2), None);
+        assert_eq!(column("complemento").get(1), Some("APTO 1"));
+
+        assert!(Privacy::new(&["cpf=drop".parse().unwrap()]).is_err());
+    }
+
+    #[test]
+    fn test_pseudonym_tokens() {
+        let key = PseudonymKey::new("a secret of at least 16 bytes").unwrap();
+        let token = key.token("12345678901", "Joao  da Silva").unwrap();
+        assert_eq!(token.len(), 32);
+        assert_eq!(
+            key.token("***456789**", "JOAO DA SILVA"),
+            Some(token.clone())
+        );
+        assert_ne!(
+            key.token("***456789**", "MARIA DA SILVA"),
+            Some(token.clone())
+        );
+        assert_eq!(key.token("***000000**", "JOAO DA SILVA"), None);
+
+        let other = PseudonymKey::new("another secret of 16 bytes").unwrap();
+        assert_ne!(other.token("***456789**", "JOAO DA SILVA"), Some(token));
+        assert!(PseudonymKey::new("short").is_err());
+        assert!(!format!("{:?}", key).contains("secret"));
+    }
+}
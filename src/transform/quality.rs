@@ -0,0 +1,523 @@
+use super::shards::InputKind;
+use super::{Cnpj, Lookups, Result, SituacaoEspecial, TransformError, frames};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// File name of the quality report inside the output directory
+pub const QUALITY_REPORT_FILE: &str = "quality_report.json";
+
+/// Rows read from one input file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputQuality {
+    /// Input table the file belongs to, e.g. `estabelecimentos`
+    pub table: String,
+    pub rows_read: u64,
+    /// Lines without the expected number of fields or with an open quote,
+    /// written to `rejects/` instead of the output
+    pub malformed_lines: u64,
+}
+
+/// Empty values of one input column
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ColumnQuality {
+    pub nulls: u64,
+    /// `nulls` over the rows read for the table
+    pub null_rate: f64,
+}
+
+/// How dirty the inputs of a transform run are, written as `quality_report.json`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QualityReport {
+    /// Rows read per input file, by file name
+    pub inputs: BTreeMap<String, InputQuality>,
+    /// Rows written per output file, relative to the output directory
+    pub outputs: BTreeMap<String, u64>,
+    /// Establishments whose CNPJ check digits do not match
+    pub invalid_cnpjs: u64,
+    /// Rows whose code has no match in `Lookups`, per lookup table, and
+    /// `situacao_especial` texts that are not a `SituacaoEspecial`
+    pub unknown_codes: BTreeMap<String, u64>,
+    /// Non-empty dates that are not valid dates, per column
+    pub invalid_dates: BTreeMap<String, u64>,
+    /// Socios and Simples rows whose `cnpj_basico` has no establishment
+    pub orphans: BTreeMap<String, u64>,
+    /// Empty values per input table and column
+    pub columns: BTreeMap<String, BTreeMap<String, ColumnQuality>>,
+    /// Input rows left out by the suppression list per table, and the legal
+    /// representatives it redacted (`representantes`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub suppressed: BTreeMap<String, u64>,
+}
+
+impl QualityReport {
+    /// Report of a step that wrote `rows` rows to `path`
+    pub fn written(output_dir: &Path, path: &Path, rows: usize) -> Self {
+        let name = path.strip_prefix(output_dir).unwrap_or(path);
+        let mut report = Self::default();
+        report
+            .outputs
+            .insert(name.to_string_lossy().to_string(), rows as u64);
+        report
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Add the counts of `other`; null rates are recomputed by `finish`
+    pub fn merge(&mut self, other: QualityReport) {
+        for (file, input) in other.inputs {
+            let merged = self.inputs.entry(file).or_default();
+            if merged.table.is_empty() {
+                merged.table = input.table;
+            }
+            merged.rows_read += input.rows_read;
+            merged.malformed_lines += input.malformed_lines;
+        }
+        self.outputs.extend(other.outputs);
+        self.invalid_cnpjs += other.invalid_cnpjs;
+        add(&mut self.unknown_codes, other.unknown_codes);
+        add(&mut self.invalid_dates, other.invalid_dates);
+        add(&mut self.orphans, other.orphans);
+        add(&mut self.suppressed, other.suppressed);
+        for (table, columns) in other.columns {
+            let merged = self.columns.entry(table).or_default();
+            for (column, quality) in columns {
+                merged.entry(column).or_default().nulls += quality.nulls;
+            }
+        }
+    }
+
+    /// Compute the null rates from the rows read per table
+    pub fn finish(&mut self) {
+        let mut rows: BTreeMap<&str, u64> = BTreeMap::new();
+        for input in self.inputs.values() {
+            *rows.entry(input.table.as_str()).or_default() += input.rows_read;
+        }
+        for (table, columns) in &mut self.columns {
+            let rows = rows.get(table.as_str()).copied().unwrap_or_default();
+            for quality in columns.values_mut() {
+                quality.null_rate = match rows {
+                    0 => 0.0,
+                    rows => quality.nulls as f64 / rows as f64,
+                };
+            }
+        }
+    }
+
+    pub fn malformed_lines(&self) -> u64 {
+        self.inputs
+            .values()
+            .map(|input| input.malformed_lines)
+            .sum()
+    }
+
+    /// Value of a metric, or `None` when the report does not have it
+    pub fn metric(&self, metric: &Metric) -> Option<f64> {
+        let total = |counts: &BTreeMap<String, u64>, key: &Option<String>| match key {
+            Some(key) => counts.get(key).copied(),
+            None => Some(counts.values().sum()),
+        };
+        let value = match metric {
+            Metric::MalformedLines => self.malformed_lines(),
+            Metric::InvalidCnpjs => self.invalid_cnpjs,
+            Metric::UnknownCodes(lookup) => total(&self.unknown_codes, lookup)?,
+            Metric::InvalidDates(column) => total(&self.invalid_dates, column)?,
+            Metric::Orphans(table) => total(&self.orphans, table)?,
+            Metric::NullRate(table, column) => {
+                return self.columns.get(table)?.get(column).map(|c| c.null_rate);
+            }
+        };
+        Some(value as f64)
+    }
+
+    /// Check the report against `thresholds`, naming every metric above its limit
+    ///
+    /// Fails with `InvalidConfig` on a metric the report cannot have.
+    pub fn check(&self, thresholds: &[Threshold]) -> Result<()> {
+        for threshold in thresholds {
+            threshold.metric.validate()?;
+        }
+        let exceeded: Vec<String> = thresholds
+            .iter()
+            .filter_map(|threshold| {
+                let value = self.metric(&threshold.metric).unwrap_or_default();
+                (value > threshold.limit).then(|| {
+                    format!(
+                        "{} is {}, above {}",
+                        threshold.metric, value, threshold.limit
+                    )
+                })
+            })
+            .collect();
+
+        if exceeded.is_empty() {
+            Ok(())
+        } else {
+            Err(TransformError::QualityCheck(exceeded.join("; ")))
+        }
+    }
+
+    /// Write the report as pretty-printed JSON into `dir`
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(QUALITY_REPORT_FILE);
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), self)?;
+        Ok(path)
+    }
+}
+
+fn add(counts: &mut BTreeMap<String, u64>, other: BTreeMap<String, u64>) {
+    for (key, count) in other {
+        *counts.entry(key).or_default() += count;
+    }
+}
+
+/// Input file types, for the keys a metric can have
+const INPUT_KINDS: [InputKind; 4] = [
+    InputKind::Estabelecimentos,
+    InputKind::Empresas,
+    InputKind::Socios,
+    InputKind::Simples,
+];
+
+/// Keys of `unknown_codes`, as counted by `profile`
+const UNKNOWN_CODES: [&str; 5] = ["municipio", "cnae", "pais", "motivo", "situacao_especial"];
+
+/// A quality metric that `--fail-on` can put a limit on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Metric {
+    MalformedLines,
+    InvalidCnpjs,
+    /// All lookups, or one (`unknown_codes.municipio`)
+    UnknownCodes(Option<String>),
+    /// All columns, or one (`invalid_dates.data_inicio_atividade`)
+    InvalidDates(Option<String>),
+    /// Socios and Simples, or one (`orphans.socios`)
+    Orphans(Option<String>),
+    /// Null rate of one input column (`null_rate.empresas.razao_social`)
+    NullRate(String, String),
+}
+
+impl Metric {
+    /// Check that the report can have the key of the metric, so a misspelt
+    /// one does not pass every check by never being counted
+    pub fn validate(&self) -> Result<()> {
+        if self.is_known() {
+            Ok(())
+        } else {
+            Err(TransformError::InvalidConfig(format!(
+                "unknown quality metric {}",
+                self
+            )))
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        match self {
+            Metric::MalformedLines | Metric::InvalidCnpjs => true,
+            Metric::UnknownCodes(key) => key
+                .as_deref()
+                .is_none_or(|key| UNKNOWN_CODES.contains(&key)),
+            Metric::InvalidDates(key) => key
+                .as_deref()
+                .is_none_or(|key| INPUT_KINDS.iter().any(|kind| kind.dates().contains(&key))),
+            Metric::Orphans(key) => key
+                .as_deref()
+                .is_none_or(|key| matches!(key, "socios" | "simples")),
+            Metric::NullRate(table, column) => INPUT_KINDS
+                .iter()
+                .any(|kind| kind.table() == table && kind.columns().contains(&column.as_str())),
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, key) = match s.split_once('.') {
+            Some((name, key)) => (name, Some(key.to_string())),
+            None => (s, None),
+        };
+        let metric = match (name, key) {
+            ("malformed_lines", None) => Metric::MalformedLines,
+            ("invalid_cnpjs", None) => Metric::InvalidCnpjs,
+            ("unknown_codes", key) => Metric::UnknownCodes(key),
+            ("invalid_dates", key) => Metric::InvalidDates(key),
+            ("orphans", key) => Metric::Orphans(key),
+            ("null_rate", Some(key)) => match key.split_once('.') {
+                Some((table, column)) => Metric::NullRate(table.into(), column.into()),
+                None => return Err(format!("expected null_rate.<table>.<column>, got {}", s)),
+            },
+            _ => {
+                return Err(format!(
+                    "unknown metric {} (expected malformed_lines, invalid_cnpjs, unknown_codes, invalid_dates, orphans or null_rate.<table>.<column>)",
+                    s
+                ));
+            }
+        };
+        if !metric.is_known() {
+            return Err(format!("unknown quality metric {}", s));
+        }
+        Ok(metric)
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let with_key = |f: &mut fmt::Formatter<'_>, name: &str, key: &Option<String>| match key {
+            Some(key) => write!(f, "{}.{}", name, key),
+            None => write!(f, "{}", name),
+        };
+        match self {
+            Metric::MalformedLines => write!(f, "malformed_lines"),
+            Metric::InvalidCnpjs => write!(f, "invalid_cnpjs"),
+            Metric::UnknownCodes(key) => with_key(f, "unknown_codes", key),
+            Metric::InvalidDates(key) => with_key(f, "invalid_dates", key),
+            Metric::Orphans(key) => with_key(f, "orphans", key),
+            Metric::NullRate(table, column) => write!(f, "null_rate.{}.{}", table, column),
+        }
+    }
+}
+
+/// Upper limit on a metric, e.g. `invalid_cnpjs=0` or `null_rate.empresas.razao_social=0.01`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    pub metric: Metric,
+    pub limit: f64,
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (metric, limit) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected <metric>=<limit>, got {}", s))?;
+        let limit = limit
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid limit in {}", s))?;
+        Ok(Self {
+            metric: metric.trim().parse()?,
+            limit,
+        })
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.metric, self.limit)
+    }
+}
+
+/// Code columns of an input checked against a lookup table, with the name
+/// the unknown codes are reported under
+fn lookup_columns(
+    kind: InputKind,
+    lookups: &Lookups,
+) -> Vec<(&'static str, &'static str, &HashMap<i32, String>)> {
+    match kind {
+        InputKind::Estabelecimentos => vec![
+            ("codigo_municipio", "municipio", &lookups.cities),
+            ("cnae_fiscal", "cnae", &lookups.cnaes),
+            ("codigo_pais", "pais", &lookups.countries),
+            ("motivo_situacao_cadastral", "motivo", &lookups.motives),
+        ],
+        InputKind::Socios => vec![("codigo_pais", "pais", &lookups.countries)],
+        InputKind::Empresas | InputKind::Simples => Vec::new(),
+    }
+}
+
+/// Whether each value of a string column passes `check`, as a Boolean column
+pub(super) fn check_values(
+    expr: Expr,
+    check: impl Fn(&str) -> bool + Send + Sync + 'static,
+) -> Expr {
+    expr.map(
+        move |column| {
+            let values = column.str()?;
+            let checked: BooleanChunked = values.into_iter().map(|v| v.map(&check)).collect();
+            Ok(checked.with_name(column.name().clone()).into_column())
+        },
+        |_, field| Ok(Field::new(field.name().clone(), DataType::Boolean)),
+    )
+}
+
+fn count(expr: Expr, name: String) -> Expr {
+    expr.cast(DataType::UInt64).sum().alias(name)
+}
+
+/// Single-row frame profiling a raw input: rows, empty values per column,
+/// invalid dates, invalid CNPJs and codes missing from the lookups
+pub fn profile(raw: LazyFrame, kind: InputKind, lookups: &Lookups) -> LazyFrame {
+    let mut exprs = vec![len().cast(DataType::UInt64).alias("rows")];
+    for column in kind.columns() {
+        let empty = col(*column).is_null().or(col(*column).eq(lit("")));
+        exprs.push(count(empty, format!("nulls/{}", column)));
+    }
+    exprs.extend(frames::invalid_dates(kind.dates()));
+
+    if kind == InputKind::Estabelecimentos {
+        let cnpj = concat_str(
+            [col("cnpj_basico"), col("cnpj_ordem"), col("cnpj_dv")],
+            "",
+            false,
+        );
+        let invalid = check_values(cnpj, |cnpj| cnpj.parse::<Cnpj>().is_err()).fill_null(lit(true));
+        exprs.push(count(invalid, "invalid_cnpjs".to_string()));
+
+        // Free text, so an unknown value is counted rather than failing the run
+        let unknown = check_values(col("situacao_especial"), |text| {
+            SituacaoEspecial::parse(text).is_err()
+        });
+        exprs.push(count(
+            unknown,
+            "unknown_codes/situacao_especial".to_string(),
+        ));
+    }
+
+    for (column, lookup, table) in lookup_columns(kind, lookups) {
+        if table.is_empty() {
+            tracing::warn!("No {} lookup loaded, unknown codes are not counted", lookup);
+            continue;
+        }
+        let known: HashSet<i32> = table.keys().copied().collect();
+        let unknown = check_values(col(column), move |value| {
+            let value = value.trim();
+            !value.is_empty() && !value.parse().is_ok_and(|code| known.contains(&code))
+        });
+        exprs.push(count(unknown, format!("unknown_codes/{}", lookup)));
+    }
+
+    raw.select(exprs)
+}
+
+/// Build the report of one input file from its `profile`
+pub fn from_profile(
+    file_name: &str,
+    kind: InputKind,
+    profile: &DataFrame,
+) -> Result<QualityReport> {
+    let mut report = QualityReport::default();
+    let mut columns = BTreeMap::new();
+    let mut rows_read = 0;
+    for column in profile.get_columns() {
+        let value = column.u64()?.get(0).unwrap_or_default();
+        let name = column.name().as_str();
+        match name.split_once('/') {
+            None if name == "rows" => rows_read = value,
+            None if name == "invalid_cnpjs" => report.invalid_cnpjs = value,
+            Some(("nulls", column)) => {
+                let quality = ColumnQuality {
+                    nulls: value,
+                    null_rate: 0.0,
+                };
+                columns.insert(column.to_string(), quality);
+            }
+            Some(("unknown_codes", lookup)) => {
+                report.unknown_codes.insert(lookup.to_string(), value);
+            }
+            _ => {
+                report.invalid_dates.insert(name.to_string(), value);
+            }
+        }
+    }
+
+    report.inputs.insert(
+        file_name.to_string(),
+        InputQuality {
+            table: kind.table().to_string(),
+            rows_read,
+            malformed_lines: 0,
+        },
+    );
+    report.columns.insert(kind.table().to_string(), columns);
+    Ok(report)
+}
+
+/// Single-row frame counting the `rows` whose `cnpj_basico` has no
+/// establishment, in an `orphans` column
+pub fn orphans(estabelecimentos: LazyFrame, rows: LazyFrame) -> LazyFrame {
+    let known = estabelecimentos
+        .select([col("cnpj_basico")])
+        .unique(None, UniqueKeepStrategy::Any)
+        .with_column(lit(true).alias("_establishment"));
+
+    rows.select([col("cnpj_basico")])
+        .join(
+            known,
+            [col("cnpj_basico")],
+            [col("cnpj_basico")],
+            JoinArgs::new(JoinType::Left),
+        )
+        .select([count(
+            col("_establishment").is_null(),
+            "orphans".to_string(),
+        )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_threshold() {
+        let threshold: Threshold = "invalid_cnpjs=0".parse().unwrap();
+        assert_eq!(threshold.metric, Metric::InvalidCnpjs);
+        let threshold: Threshold = "null_rate.empresas.razao_social=0.01".parse().unwrap();
+        assert_eq!(
+            threshold.metric,
+            Metric::NullRate("empresas".into(), "razao_social".into())
+        );
+        assert_eq!(
+            threshold.to_string(),
+            "null_rate.empresas.razao_social=0.01"
+        );
+        assert!("unknown_codes.municipio".parse::<Threshold>().is_err());
+        assert!("typos=1".parse::<Threshold>().is_err());
+        assert!("unknown_codes.municpio=0".parse::<Threshold>().is_err());
+        assert!("null_rate.empresas.razao=0.1".parse::<Threshold>().is_err());
+        assert!(
+            "invalid_dates.data_entrada_sociedade=0"
+                .parse::<Threshold>()
+                .is_ok()
+        );
+        assert!(
+            "unknown_codes.situacao_especial=0"
+                .parse::<Threshold>()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_check_thresholds() {
+        let mut report = QualityReport::default();
+        report.unknown_codes.insert("municipio".into(), 3);
+        report.unknown_codes.insert("cnae".into(), 1);
+
+        let ok: Threshold = "unknown_codes.cnae=1".parse().unwrap();
+        let exceeded: Threshold = "unknown_codes=2".parse().unwrap();
+        assert!(report.check(std::slice::from_ref(&ok)).is_ok());
+        assert!(matches!(
+            report.check(&[ok, exceeded]),
+            Err(TransformError::QualityCheck(message)) if message == "unknown_codes is 4, above 2"
+        ));
+
+        // A misspelt key is not a metric of value 0
+        let typo = Threshold {
+            metric: Metric::Orphans(Some("socio".into())),
+            limit: 0.0,
+        };
+        assert!(matches!(
+            report.check(&[typo]),
+            Err(TransformError::InvalidConfig(message)) if message == "unknown quality metric orphans.socio"
+        ));
+    }
+}
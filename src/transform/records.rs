@@ -0,0 +1,278 @@
+use super::{CNAE, Cnpj, Coded, Company, Lookups, Partner, Result, SituacaoEspecial};
+use bigdecimal::BigDecimal;
+use bigdecimal::num_bigint::BigInt;
+use chrono::NaiveDate;
+use polars::prelude::*;
+use std::collections::HashMap;
+
+/// Partners of each company, keyed by `cnpj_basico`
+pub type Qsa = HashMap<String, Vec<Partner>>;
+
+fn strings<'a>(df: &'a DataFrame, name: &str) -> Result<&'a StringChunked> {
+    Ok(df.column(name)?.str()?)
+}
+
+fn codes<'a>(df: &'a DataFrame, name: &str) -> Result<&'a Int32Chunked> {
+    Ok(df.column(name)?.i32()?)
+}
+
+/// The value of a code of `T`, if any
+fn coded<T: Coded>(values: &Int32Chunked, i: usize) -> Result<Option<T>> {
+    Ok(values.get(i).map(T::from_code).transpose()?)
+}
+
+fn flags<'a>(df: &'a DataFrame, name: &str) -> Result<&'a BooleanChunked> {
+    Ok(df.column(name)?.bool()?)
+}
+
+fn dates(df: &DataFrame, name: &str) -> Result<Vec<Option<NaiveDate>>> {
+    Ok(df.column(name)?.date()?.as_date_iter().collect())
+}
+
+fn amounts(df: &DataFrame, name: &str) -> Result<Vec<Option<BigDecimal>>> {
+    let column = df.column(name)?.decimal()?;
+    let scale = column.scale() as i64;
+    Ok(column
+        .physical()
+        .into_iter()
+        .map(|value| value.map(|value| BigDecimal::new(BigInt::from(value), scale)))
+        .collect())
+}
+
+fn owned(value: Option<&str>) -> Option<String> {
+    value.map(str::to_string)
+}
+
+fn text(value: Option<&str>) -> String {
+    value.unwrap_or_default().to_string()
+}
+
+/// Build `Company` documents from a companies frame (see `frames::companies`)
+///
+/// Secondary CNAEs are described with `lookups` and each document receives the
+/// partners of its `cnpj_basico` from `qsa`.
+pub fn companies(df: &DataFrame, lookups: &Lookups, qsa: &Qsa) -> Result<Vec<Company>> {
+    let cnpj = strings(df, "cnpj")?;
+    let cnpj_basico = strings(df, "cnpj_basico")?;
+    let identificador_matriz_filial = codes(df, "identificador_matriz_filial")?;
+    let descricao_identificador_matriz_filial =
+        strings(df, "descricao_identificador_matriz_filial")?;
+    let nome_fantasia = strings(df, "nome_fantasia")?;
+    let situacao_cadastral = codes(df, "situacao_cadastral")?;
+    let descricao_situacao_cadastral = strings(df, "descricao_situacao_cadastral")?;
+    let data_situacao_cadastral = dates(df, "data_situacao_cadastral")?;
+    let motivo_situacao_cadastral = codes(df, "motivo_situacao_cadastral")?;
+    let descricao_motivo_situacao_cadastral = strings(df, "descricao_motivo_situacao_cadastral")?;
+    let nome_cidade_no_exterior = strings(df, "nome_cidade_no_exterior")?;
+    let codigo_pais = codes(df, "codigo_pais")?;
+    let pais = strings(df, "pais")?;
+    let data_inicio_atividade = dates(df, "data_inicio_atividade")?;
+    let cnae_fiscal = codes(df, "cnae_fiscal")?;
+    let cnae_fiscal_descricao = strings(df, "cnae_fiscal_descricao")?;
+    let cnae_secao = strings(df, "cnae_secao")?;
+    let cnae_secao_descricao = strings(df, "cnae_secao_descricao")?;
+    let cnae_divisao = codes(df, "cnae_divisao")?;
+    let cnae_divisao_descricao = strings(df, "cnae_divisao_descricao")?;
+    let cnae_grupo = codes(df, "cnae_grupo")?;
+    let cnae_classe = codes(df, "cnae_classe")?;
+    let cnae_grupo_descricao = strings(df, "cnae_grupo_descricao")?;
+    let cnae_classe_descricao = strings(df, "cnae_classe_descricao")?;
+    let cnaes_secundarios = df.column("cnaes_secundarios")?.list()?;
+    let descricao_tipo_de_logradouro = strings(df, "descricao_tipo_de_logradouro")?;
+    let logradouro = strings(df, "logradouro")?;
+    let numero = strings(df, "numero")?;
+    let complemento = strings(df, "complemento")?;
+    let bairro = strings(df, "bairro")?;
+    let cep = strings(df, "cep")?;
+    let uf = strings(df, "uf")?;
+    let codigo_municipio = codes(df, "codigo_municipio")?;
+    let codigo_municipio_ibge = codes(df, "codigo_municipio_ibge")?;
+    let municipio = strings(df, "municipio")?;
+    let regiao = strings(df, "regiao")?;
+    let ddd_telefone_1 = strings(df, "ddd_telefone_1")?;
+    let ddd_telefone_2 = strings(df, "ddd_telefone_2")?;
+    let ddd_fax = strings(df, "ddd_fax")?;
+    let email = strings(df, "email")?;
+    let situacao_especial = strings(df, "situacao_especial")?;
+    let data_situacao_especial = dates(df, "data_situacao_especial")?;
+    let opcao_pelo_simples = flags(df, "opcao_pelo_simples")?;
+    let data_opcao_pelo_simples = dates(df, "data_opcao_pelo_simples")?;
+    let data_exclusao_do_simples = dates(df, "data_exclusao_do_simples")?;
+    let opcao_pelo_mei = flags(df, "opcao_pelo_mei")?;
+    let data_opcao_pelo_mei = dates(df, "data_opcao_pelo_mei")?;
+    let data_exclusao_do_mei = dates(df, "data_exclusao_do_mei")?;
+    let razao_social = strings(df, "razao_social")?;
+    let codigo_natureza_juridica = codes(df, "codigo_natureza_juridica")?;
+    let natureza_juridica = strings(df, "natureza_juridica")?;
+    let qualificacao_do_responsavel = codes(df, "qualificacao_do_responsavel")?;
+    let capital_social = amounts(df, "capital_social")?;
+    let codigo_porte = codes(df, "codigo_porte")?;
+    let porte = strings(df, "porte")?;
+    let ente_federativo_responsavel = strings(df, "ente_federativo_responsavel")?;
+
+    let mut documents = Vec::with_capacity(df.height());
+    for i in 0..df.height() {
+        let secundarios = match cnaes_secundarios.get_as_series(i) {
+            Some(series) => series
+                .i32()?
+                .into_iter()
+                .flatten()
+                .map(|codigo| {
+                    let descricao = lookups.get_cnae(codigo).cloned().unwrap_or_default();
+                    CNAE::new(codigo, descricao)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        documents.push(Company {
+            cnpj: text(cnpj.get(i)).parse::<Cnpj>()?,
+            identificador_matriz_filial: coded(identificador_matriz_filial, i)?,
+            descricao_identificador_matriz_filial: owned(
+                descricao_identificador_matriz_filial.get(i),
+            ),
+            nome_fantasia: text(nome_fantasia.get(i)),
+            situacao_cadastral: coded(situacao_cadastral, i)?,
+            descricao_situacao_cadastral: owned(descricao_situacao_cadastral.get(i)),
+            data_situacao_cadastral: data_situacao_cadastral[i],
+            motivo_situacao_cadastral: motivo_situacao_cadastral.get(i),
+            descricao_motivo_situacao_cadastral: owned(descricao_motivo_situacao_cadastral.get(i)),
+            nome_cidade_no_exterior: text(nome_cidade_no_exterior.get(i)),
+            codigo_pais: codigo_pais.get(i),
+            pais: owned(pais.get(i)),
+            data_inicio_atividade: data_inicio_atividade[i],
+            cnae_fiscal: cnae_fiscal.get(i),
+            cnae_fiscal_descricao: owned(cnae_fiscal_descricao.get(i)),
+            cnae_secao: owned(cnae_secao.get(i)),
+            cnae_secao_descricao: owned(cnae_secao_descricao.get(i)),
+            cnae_divisao: cnae_divisao.get(i),
+            cnae_divisao_descricao: owned(cnae_divisao_descricao.get(i)),
+            cnae_grupo: cnae_grupo.get(i),
+            cnae_classe: cnae_classe.get(i),
+            cnae_grupo_descricao: owned(cnae_grupo_descricao.get(i)),
+            cnae_classe_descricao: owned(cnae_classe_descricao.get(i)),
+            cnaes_secundarios: secundarios,
+            descricao_tipo_de_logradouro: text(descricao_tipo_de_logradouro.get(i)),
+            logradouro: text(logradouro.get(i)),
+            numero: text(numero.get(i)),
+            complemento: text(complemento.get(i)),
+            bairro: text(bairro.get(i)),
+            cep: text(cep.get(i)),
+            uf: text(uf.get(i)),
+            codigo_municipio: codigo_municipio.get(i),
+            codigo_municipio_ibge: codigo_municipio_ibge.get(i),
+            municipio: owned(municipio.get(i)),
+            regiao: owned(regiao.get(i)),
+            ddd_telefone_1: text(ddd_telefone_1.get(i)),
+            ddd_telefone_2: text(ddd_telefone_2.get(i)),
+            ddd_fax: text(ddd_fax.get(i)),
+            email: owned(email.get(i)),
+            situacao_especial: situacao_especial
+                .get(i)
+                .and_then(|text| SituacaoEspecial::parse(text).ok().flatten()),
+            data_situacao_especial: data_situacao_especial[i],
+            opcao_pelo_simples: opcao_pelo_simples.get(i),
+            data_opcao_pelo_simples: data_opcao_pelo_simples[i],
+            data_exclusao_do_simples: data_exclusao_do_simples[i],
+            opcao_pelo_mei: opcao_pelo_mei.get(i),
+            data_opcao_pelo_mei: data_opcao_pelo_mei[i],
+            data_exclusao_do_mei: data_exclusao_do_mei[i],
+            razao_social: text(razao_social.get(i)),
+            codigo_natureza_juridica: codigo_natureza_juridica.get(i),
+            natureza_juridica: owned(natureza_juridica.get(i)),
+            qualificacao_do_responsavel: qualificacao_do_responsavel.get(i),
+            capital_social: capital_social[i].clone(),
+            codigo_porte: coded(codigo_porte, i)?,
+            porte: owned(porte.get(i)),
+            ente_federativo_responsavel: text(ente_federativo_responsavel.get(i)),
+            qsa: cnpj_basico
+                .get(i)
+                .and_then(|basico| qsa.get(basico))
+                .cloned()
+                .unwrap_or_default(),
+        });
+    }
+
+    Ok(documents)
+}
+
+/// Build `Partner` records from a partners frame (see `frames::partners`)
+pub fn partners(df: &DataFrame) -> Result<Vec<Partner>> {
+    let cnpj_basico = strings(df, "cnpj_basico")?;
+    let identificador_socio = codes(df, "identificador_socio")?;
+    let nome_socio = strings(df, "nome_socio")?;
+    let cnpj_cpf_socio = strings(df, "cnpj_cpf_socio")?;
+    let codigo_qualificacao_socio = codes(df, "codigo_qualificacao_socio")?;
+    let qualificacao_socio = strings(df, "qualificacao_socio")?;
+    let data_entrada_sociedade = dates(df, "data_entrada_sociedade")?;
+    let codigo_pais = codes(df, "codigo_pais")?;
+    let pais = strings(df, "pais")?;
+    let cpf_representante_legal = strings(df, "cpf_representante_legal")?;
+    let nome_representante_legal = strings(df, "nome_representante_legal")?;
+    let codigo_qualificacao_representante_legal =
+        codes(df, "codigo_qualificacao_representante_legal")?;
+    let qualificacao_representante_legal = strings(df, "qualificacao_representante_legal")?;
+    let codigo_faixa_etaria = codes(df, "codigo_faixa_etaria")?;
+    let faixa_etaria = strings(df, "faixa_etaria")?;
+
+    (0..df.height())
+        .map(|i| {
+            Ok(Partner {
+                cnpj_basico: text(cnpj_basico.get(i)),
+                identificador_socio: coded(identificador_socio, i)?,
+                nome_socio: text(nome_socio.get(i)),
+                cnpj_cpf_socio: text(cnpj_cpf_socio.get(i)),
+                codigo_qualificacao_socio: codigo_qualificacao_socio.get(i),
+                qualificacao_socio: owned(qualificacao_socio.get(i)),
+                data_entrada_sociedade: data_entrada_sociedade[i],
+                codigo_pais: codigo_pais.get(i),
+                pais: owned(pais.get(i)),
+                cpf_representante_legal: text(cpf_representante_legal.get(i)),
+                nome_representante_legal: text(nome_representante_legal.get(i)),
+                codigo_qualificacao_representante_legal: codigo_qualificacao_representante_legal
+                    .get(i),
+                qualificacao_representante_legal: owned(qualificacao_representante_legal.get(i)),
+                codigo_faixa_etaria: coded(codigo_faixa_etaria, i)?,
+                faixa_etaria: owned(faixa_etaria.get(i)),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::frames;
+
+    #[test]
+    fn test_partners_from_frame() {
+        let raw = df!(
+            "cnpj_basico" => ["33683111"],
+            "identificador_socio" => ["2"],
+            "nome_socio" => ["ANTONIO DE SOUSA"],
+            "cnpj_cpf_socio" => ["***123456**"],
+            "codigo_qualificacao_socio" => ["10"],
+            "data_entrada_sociedade" => ["20190101"],
+            "codigo_pais" => [None::<&str>],
+            "cpf_representante_legal" => ["***000000**"],
+            "nome_representante_legal" => [None::<&str>],
+            "codigo_qualificacao_representante_legal" => ["00"],
+            "codigo_faixa_etaria" => ["6"],
+        )
+        .unwrap();
+
+        let df = frames::partners(raw.lazy(), &Lookups::new())
+            .unwrap()
+            .collect()
+            .unwrap();
+        let partners = partners(&df).unwrap();
+
+        assert_eq!(partners.len(), 1);
+        assert_eq!(partners[0].cnpj_basico, "33683111");
+        assert_eq!(
+            partners[0].data_entrada_sociedade,
+            NaiveDate::from_ymd_opt(2019, 1, 1)
+        );
+        assert_eq!(partners[0].faixa_etaria, Some("51 a 60 anos".to_string()));
+    }
+}
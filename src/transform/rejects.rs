@@ -0,0 +1,302 @@
+use super::{Cnpj, Result, SuppressionList, privacy};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, create_dir_all};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// A line of an input file that was left out of the transform
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reject {
+    /// Name of the input file
+    pub file: String,
+    /// Line number where the record starts, from 1
+    pub line: u64,
+    pub reason: String,
+    /// The record as read, without its line break
+    pub content: String,
+}
+
+/// Why a record of a Federal Revenue CSV file cannot be parsed, if it cannot
+fn check(record: &[u8], fields: usize) -> Option<String> {
+    let (mut quoted, mut count) = (false, 1);
+    for &byte in record {
+        match byte {
+            b'"' => quoted = !quoted,
+            b';' if !quoted => count += 1,
+            _ => {}
+        }
+    }
+
+    if quoted {
+        Some("unterminated quote".to_string())
+    } else if count != fields {
+        Some(format!("expected {} fields, found {}", fields, count))
+    } else {
+        None
+    }
+}
+
+/// Why the CNPJ in the first three fields of an Estabelecimentos record is
+/// not valid, if it is not
+fn check_cnpj(record: &[u8]) -> Option<String> {
+    let cnpj: String = record
+        .split(|&b| b == b';')
+        .take(3)
+        .map(|field| String::from_utf8_lossy(field).trim_matches('"').to_string())
+        .collect();
+    cnpj.parse::<Cnpj>().err().map(|e| e.to_string())
+}
+
+/// Longest record read, in lines; a quote left open for longer is a stray quote
+const MAX_RECORD_LINES: usize = 32;
+
+fn odd_quotes(line: &[u8]) -> bool {
+    line.iter().filter(|&&b| b == b'"').count() % 2 == 1
+}
+
+/// Records of a CSV file, numbered by the line they start on
+struct Records<R> {
+    reader: R,
+    line: u64,
+    /// Lines read ahead while looking for the end of a quoted field
+    pending: VecDeque<(u64, Vec<u8>)>,
+}
+
+impl<R: BufRead> Records<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn line(&mut self) -> std::io::Result<Option<(u64, Vec<u8>)>> {
+        if let Some(line) = self.pending.pop_front() {
+            return Ok(Some(line));
+        }
+        let mut buf = Vec::new();
+        if self.reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(None);
+        }
+        self.line += 1;
+        Ok(Some((self.line, buf)))
+    }
+
+    /// Read the next record
+    ///
+    /// A line leaving a quote open continues on the following lines when
+    /// they complete it into a record of `fields` fields, as with a line
+    /// break inside a quoted field. Otherwise the quote is a stray one and
+    /// the record is that line alone, so it does not swallow its neighbours.
+    fn next(&mut self, fields: usize) -> std::io::Result<Option<(u64, Vec<u8>)>> {
+        let Some((start, first)) = self.line()? else {
+            return Ok(None);
+        };
+        if !odd_quotes(&first) {
+            return Ok(Some((start, first)));
+        }
+
+        let mut joined = first.clone();
+        let mut lines = Vec::new();
+        while lines.len() < MAX_RECORD_LINES {
+            let Some(line) = self.line()? else {
+                break;
+            };
+            joined.extend_from_slice(&line.1);
+            let closed = odd_quotes(&line.1);
+            lines.push(line);
+            if closed {
+                break;
+            }
+        }
+
+        if check(trim_line_break(&joined), fields).is_none() {
+            return Ok(Some((start, joined)));
+        }
+        for line in lines.into_iter().rev() {
+            self.pending.push_front(line);
+        }
+        Ok(Some((start, first)))
+    }
+}
+
+fn trim_line_break(record: &[u8]) -> &[u8] {
+    let record = record.strip_suffix(b"\n").unwrap_or(record);
+    record.strip_suffix(b"\r").unwrap_or(record)
+}
+
+/// Visit every non-blank record with the reason it is malformed, if it is,
+/// and return the number of malformed records
+fn scan(
+    path: &Path,
+    fields: usize,
+    cnpjs: bool,
+    mut visit: impl FnMut(u64, &[u8], Option<String>) -> Result<()>,
+) -> Result<u64> {
+    let mut records = Records::new(BufReader::with_capacity(1 << 16, File::open(path)?));
+    let mut malformed = 0;
+
+    while let Some((line, record)) = records.next(fields)? {
+        if trim_line_break(&record).is_empty() {
+            continue;
+        }
+        let reason = check(trim_line_break(&record), fields).or_else(|| {
+            cnpjs
+                .then(|| check_cnpj(trim_line_break(&record)))
+                .flatten()
+        });
+        malformed += u64::from(reason.is_some());
+        visit(line, &record, reason)?;
+    }
+    Ok(malformed)
+}
+
+/// Count the malformed records of a Federal Revenue CSV file
+///
+/// A record is malformed when it does not have `fields` fields or leaves a
+/// quote open, or with `cnpjs`, when its first three fields are not a valid
+/// CNPJ. Separators and line breaks inside quotes are part of a field.
+pub fn count(path: &Path, fields: usize, cnpjs: bool) -> Result<u64> {
+    scan(path, fields, cnpjs, |_, _, _| Ok(()))
+}
+
+/// Split an input file with malformed records into a copy without them at
+/// `clean` and one `Reject` per line at `rejects`, as NDJSON
+///
+/// Malformed records are those counted by `count`. Files without malformed
+/// records are left alone and any `rejects` file of
+/// a previous run is removed. With `privacy`, CPFs in the rejected lines are
+/// masked, and the content of lines mentioning an entry of `suppression` is
+/// left empty. Returns the number of rejected records.
+pub fn split(
+    path: &Path,
+    fields: usize,
+    cnpjs: bool,
+    clean: &Path,
+    rejects: &Path,
+    privacy: bool,
+    suppression: &SuppressionList,
+) -> Result<u64> {
+    if count(path, fields, cnpjs)? == 0 {
+        match std::fs::remove_file(rejects) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => return Ok(0),
+        }
+    }
+
+    for dir in [clean.parent(), rejects.parent()].into_iter().flatten() {
+        create_dir_all(dir)?;
+    }
+    let file = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut clean_writer = BufWriter::new(File::create(clean)?);
+    let mut rejects_writer = BufWriter::new(File::create(rejects)?);
+
+    let rejected = scan(path, fields, cnpjs, |line, record, reason| {
+        let Some(reason) = reason else {
+            return Ok(clean_writer.write_all(record)?);
+        };
+        let content = String::from_utf8_lossy(trim_line_break(record));
+        let content = if suppression.suppresses_line(&content) {
+            String::new()
+        } else if privacy {
+            privacy::mask_cpfs(&content).into_owned()
+        } else {
+            content.into_owned()
+        };
+        let reject = Reject {
+            file: file.to_string(),
+            line,
+            reason,
+            content,
+        };
+        serde_json::to_writer(&mut rejects_writer, &reject)?;
+        Ok(rejects_writer.write_all(b"\n")?)
+    })?;
+
+    clean_writer.flush()?;
+    rejects_writer.flush()?;
+    Ok(rejected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = concat!(
+        "\"1\";\"A;B\";\"x\"\r\n",
+        "\"2\";\"B\"\n",
+        "\"3\";\"C\nD\";\"y\"\n",
+        "\n",
+        "\"4\";\"stray\"\";\"q\"\n",
+        "\"5\";\"ok\";\"r\"\n",
+        "\"6\";\"open;\"z\n",
+    );
+
+    #[test]
+    fn test_count_malformed_records() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("EMPRECSV0.csv");
+        std::fs::write(&path, INPUT).unwrap();
+        assert_eq!(count(&path, 3, false).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_count_invalid_cnpjs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("ESTABELE0.csv");
+        let input = concat!(
+            "\"33683111\";\"0002\";\"80\";\"x\"\n",
+            "\"33683111\";\"0001\";\"00\";\"x\"\n",
+            "\"\";\"0001\";\"00\";\"x\"\n",
+        );
+        std::fs::write(&path, input).unwrap();
+        assert_eq!(count(&path, 4, false).unwrap(), 0);
+        assert_eq!(count(&path, 4, true).unwrap(), 2);
+        assert_eq!(
+            check_cnpj(b"\"33683111\";\"0001\";\"00\";\"x\"").unwrap(),
+            "invalid CNPJ check digits: 33683111000100"
+        );
+    }
+
+    #[test]
+    fn test_split_rejects() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("EMPRECSV0.csv");
+        let clean = dir.path().join("clean/EMPRECSV0.csv");
+        let rejects = dir.path().join("rejects/EMPRECSV0.csv.ndjson");
+        std::fs::write(&path, INPUT).unwrap();
+
+        let none = SuppressionList::default();
+        assert_eq!(
+            split(&path, 3, false, &clean, &rejects, false, &none).unwrap(),
+            3
+        );
+        assert_eq!(
+            std::fs::read_to_string(&clean).unwrap(),
+            "\"1\";\"A;B\";\"x\"\r\n\"3\";\"C\nD\";\"y\"\n\"5\";\"ok\";\"r\"\n"
+        );
+        let rejected: Vec<Reject> = std::fs::read_to_string(&rejects)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(rejected.len(), 3);
+        assert_eq!(rejected[0].line, 2);
+        assert_eq!(rejected[0].reason, "expected 3 fields, found 2");
+        assert_eq!(rejected[0].content, "\"2\";\"B\"");
+        // A stray quote does not swallow the next line
+        assert_eq!(rejected[1].line, 6);
+        assert_eq!(rejected[1].reason, "unterminated quote");
+        assert_eq!(rejected[2].line, 8);
+
+        // Once the file is fixed, the rejects of the previous run are removed
+        std::fs::write(&path, "\"1\";\"A\";\"x\"\n").unwrap();
+        assert_eq!(
+            split(&path, 3, false, &clean, &rejects, false, &none).unwrap(),
+            0
+        );
+        assert!(!rejects.exists());
+    }
+}
@@ -0,0 +1,48 @@
+use super::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// File name of the report inside the output directory
+pub const REPORT_FILE: &str = "transform_report.json";
+
+/// Summary of a transform run, written next to the output tables
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransformReport {
+    /// Non-empty `data_*` values that are not valid dates, per column;
+    /// they are written as null (see `parse_date`)
+    pub invalid_dates: BTreeMap<String, u64>,
+}
+
+impl TransformReport {
+    /// Total number of invalid dates across all columns
+    pub fn invalid_dates_total(&self) -> u64 {
+        self.invalid_dates.values().sum()
+    }
+
+    /// Write the report as pretty-printed JSON into `dir`
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(REPORT_FILE);
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), self)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_dates_total() {
+        let mut report = TransformReport::default();
+        report
+            .invalid_dates
+            .insert("data_inicio_atividade".to_string(), 2);
+        report
+            .invalid_dates
+            .insert("data_entrada_sociedade".to_string(), 1);
+        assert_eq!(report.invalid_dates_total(), 3);
+    }
+}
@@ -0,0 +1,275 @@
+use super::layout::{EMPRESAS_COLUMNS, ESTABELECIMENTOS_COLUMNS, SIMPLES_COLUMNS, SOCIOS_COLUMNS};
+use super::{Result, TransformError, frames};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+
+/// Shards of each sharded file type in a Receita Federal release
+pub const RFB_SHARDS: usize = 10;
+
+/// Input file types read by the transform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    Estabelecimentos,
+    Empresas,
+    Socios,
+    Simples,
+}
+
+impl InputKind {
+    /// Name segment identifying the file type, e.g. `K3241.K03200Y0.D51011.ESTABELE`
+    pub fn token(self) -> &'static str {
+        match self {
+            InputKind::Estabelecimentos => "ESTABELE",
+            InputKind::Empresas => "EMPRECSV",
+            InputKind::Socios => "SOCIOCSV",
+            InputKind::Simples => "SIMPLES",
+        }
+    }
+
+    /// Whether the Federal Revenue publishes the file type in numbered shards
+    pub fn is_sharded(self) -> bool {
+        self != InputKind::Simples
+    }
+
+    /// Table name used in reports, e.g. `estabelecimentos`
+    pub fn table(self) -> &'static str {
+        match self {
+            InputKind::Estabelecimentos => "estabelecimentos",
+            InputKind::Empresas => "empresas",
+            InputKind::Socios => "socios",
+            InputKind::Simples => "simples",
+        }
+    }
+
+    /// Columns of the file layout
+    pub fn columns(self) -> &'static [&'static str] {
+        match self {
+            InputKind::Estabelecimentos => &ESTABELECIMENTOS_COLUMNS,
+            InputKind::Empresas => &EMPRESAS_COLUMNS,
+            InputKind::Socios => &SOCIOS_COLUMNS,
+            InputKind::Simples => &SIMPLES_COLUMNS,
+        }
+    }
+
+    /// Raw `YYYYMMDD` date columns
+    pub fn dates(self) -> &'static [&'static str] {
+        match self {
+            InputKind::Estabelecimentos => &frames::ESTABELECIMENTOS_DATES,
+            InputKind::Empresas => &[],
+            InputKind::Socios => &frames::SOCIOS_DATES,
+            InputKind::Simples => &frames::SIMPLES_DATES,
+        }
+    }
+}
+
+impl fmt::Display for InputKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Match a file name against a file type
+///
+/// Returns `None` when the name is not of that type and `Some(index)` when it
+/// is, where `index` is the shard number if the name carries one. Both the
+/// Federal Revenue names (`K3241.K03200Y3.D51011.ESTABELE`, shard in the `Y3`
+/// segment) and short names (`ESTABELE3.csv`) are recognized.
+pub fn shard_index(file_name: &str, kind: InputKind) -> Option<Option<usize>> {
+    let upper = file_name.to_uppercase();
+    let segments: Vec<&str> = upper.split('.').collect();
+    if segments.last() == Some(&"ZIP") {
+        return None;
+    }
+
+    for segment in &segments {
+        let Some(rest) = segment.strip_prefix(kind.token()) else {
+            continue;
+        };
+        if rest.is_empty() {
+            return Some(segments.iter().find_map(|segment| rfb_index(segment)));
+        }
+        if rest.bytes().all(|c| c.is_ascii_digit()) {
+            return Some(rest.parse().ok());
+        }
+    }
+
+    None
+}
+
+/// Shard number of a Federal Revenue name segment such as `K03200Y3`
+fn rfb_index(segment: &str) -> Option<usize> {
+    let (prefix, index) = segment.rsplit_once('Y')?;
+    if !prefix.starts_with('K') || index.is_empty() {
+        return None;
+    }
+    index.parse().ok()
+}
+
+/// Find the input files of one type in `dir`, ordered by shard
+///
+/// Every file is returned exactly once. Sharded types must be numbered from 0
+/// without gaps and, when `expected` is set, up to `expected - 1`; a missing
+/// shard, a shard past the expected ones, two files for the same shard or a
+/// file whose shard cannot be told are errors.
+pub fn discover(dir: &Path, kind: InputKind, expected: Option<usize>) -> Result<Vec<PathBuf>> {
+    let mut found: BTreeMap<Option<usize>, Vec<PathBuf>> = BTreeMap::new();
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let file_name = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+        if let Some(index) = shard_index(file_name, kind) {
+            found.entry(index).or_default().push(path);
+        }
+    }
+
+    if !kind.is_sharded() {
+        let mut paths: Vec<PathBuf> = found.into_values().flatten().collect();
+        paths.sort();
+        return Ok(paths);
+    }
+
+    let count = found.len();
+    let mut paths = Vec::with_capacity(count);
+    for (next, (index, mut files)) in found.into_iter().enumerate() {
+        let Some(index) = index else {
+            return Err(TransformError::InvalidFormat(format!(
+                "cannot tell the {} shard number of {:?}",
+                kind, files[0]
+            )));
+        };
+        if expected.is_some_and(|expected| index >= expected) {
+            return Err(TransformError::InvalidFormat(format!(
+                "{} shard {} found, expected {} shards: {:?}",
+                kind,
+                index,
+                expected.unwrap_or_default(),
+                files
+            )));
+        }
+        if index != next {
+            return Err(TransformError::MissingShard(format!(
+                "{} shard {} not found in {:?}",
+                kind, next, dir
+            )));
+        }
+        if files.len() > 1 {
+            files.sort();
+            return Err(TransformError::InvalidFormat(format!(
+                "{} shard {} found more than once: {:?}",
+                kind, index, files
+            )));
+        }
+        paths.append(&mut files);
+    }
+    // Gaps are caught above; only the last shards can still be missing
+    if let Some(expected) = expected
+        && (1..expected).contains(&count)
+    {
+        return Err(TransformError::MissingShard(format!(
+            "{} shard {} not found in {:?}",
+            kind, count, dir
+        )));
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shard_index() {
+        let kind = InputKind::Estabelecimentos;
+        assert_eq!(
+            shard_index("K3241.K03200Y3.D51011.ESTABELE", kind),
+            Some(Some(3))
+        );
+        assert_eq!(shard_index("ESTABELE7.csv", kind), Some(Some(7)));
+        assert_eq!(shard_index("estabele0.csv", kind), Some(Some(0)));
+        assert_eq!(shard_index("Estabelecimentos3.zip", kind), None);
+        assert_eq!(shard_index("K3241.K03200Y3.D51011.EMPRECSV", kind), None);
+        assert_eq!(shard_index("ESTABELE.csv", kind), Some(None));
+        assert_eq!(
+            shard_index("F.K03200$W.SIMPLES.CSV.D51011", InputKind::Simples),
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn test_discover() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for i in [2, 0, 1] {
+            let name = format!("K3241.K03200Y{}.D51011.SOCIOCSV", i);
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        std::fs::write(dir.path().join("Socios0.zip"), "").unwrap();
+
+        let paths = discover(dir.path(), InputKind::Socios, None).unwrap();
+        let names: Vec<_> = paths
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "K3241.K03200Y0.D51011.SOCIOCSV",
+                "K3241.K03200Y1.D51011.SOCIOCSV",
+                "K3241.K03200Y2.D51011.SOCIOCSV"
+            ]
+        );
+        assert!(
+            discover(dir.path(), InputKind::Empresas, None)
+                .unwrap()
+                .is_empty()
+        );
+
+        std::fs::remove_file(dir.path().join("K3241.K03200Y1.D51011.SOCIOCSV")).unwrap();
+        assert!(matches!(
+            discover(dir.path(), InputKind::Socios, None),
+            Err(TransformError::MissingShard(_))
+        ));
+
+        std::fs::write(dir.path().join("SOCIOCSV1.csv"), "").unwrap();
+        std::fs::write(dir.path().join("SOCIOCSV0.csv"), "").unwrap();
+        assert!(matches!(
+            discover(dir.path(), InputKind::Socios, None),
+            Err(TransformError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_discover_expected_shards() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let kind = InputKind::Estabelecimentos;
+        // A release without its last shard
+        for i in 0..9 {
+            let name = format!("K3241.K03200Y{}.D51011.ESTABELE", i);
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        assert_eq!(discover(dir.path(), kind, None).unwrap().len(), 9);
+        let error = discover(dir.path(), kind, Some(RFB_SHARDS)).unwrap_err();
+        assert!(matches!(error, TransformError::MissingShard(_)));
+        assert!(error.to_string().contains("shard 9"));
+
+        std::fs::write(dir.path().join("K3241.K03200Y9.D51011.ESTABELE"), "").unwrap();
+        assert_eq!(
+            discover(dir.path(), kind, Some(RFB_SHARDS)).unwrap().len(),
+            RFB_SHARDS
+        );
+        assert!(matches!(
+            discover(dir.path(), kind, Some(2)),
+            Err(TransformError::InvalidFormat(_))
+        ));
+        // No files at all are reported by the caller
+        assert!(
+            discover(dir.path(), InputKind::Socios, Some(RFB_SHARDS))
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use super::Result;
+use polars::prelude::*;
+use std::fs::{create_dir_all, read_dir, remove_dir_all};
+use std::path::{Path, PathBuf};
+
+/// Column holding the partition of each staged row
+const BUCKET_COLUMN: &str = "_bucket";
+
+/// Splits the `cnpj_basico` join of the transform into partitions
+///
+/// With a single partition every frame stays a lazy scan of its inputs. With
+/// more, the build side of the join is spilled to Parquet under `dir`, one
+/// directory per partition, so only one partition is in memory at a time.
+pub struct Spill {
+    dir: PathBuf,
+    partitions: usize,
+}
+
+impl Spill {
+    pub fn new(dir: impl Into<PathBuf>, partitions: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            partitions: partitions.max(1),
+        }
+    }
+
+    pub fn partitions(&self) -> usize {
+        self.partitions
+    }
+
+    /// Partition of each row, derived from its `cnpj_basico`
+    fn bucket(&self) -> Expr {
+        (col("cnpj_basico").hash(0, 0, 0, 0) % lit(self.partitions as u64)).cast(DataType::UInt32)
+    }
+
+    /// Rows of `lf` that belong to `partition`
+    pub fn filter(&self, lf: LazyFrame, partition: usize) -> LazyFrame {
+        if self.partitions == 1 {
+            return lf;
+        }
+        lf.filter(self.bucket().eq(lit(partition as u32)))
+    }
+
+    /// Stage a frame keyed by `cnpj_basico`, writing it to disk when partitioned
+    pub fn stage(&self, name: &str, lf: LazyFrame) -> Result<Staged> {
+        if self.partitions == 1 {
+            return Ok(Staged::Memory(Box::new(lf)));
+        }
+
+        let mut lf = lf;
+        let schema = lf.collect_schema()?;
+        let dir = self.dir.join(name);
+        create_dir_all(&dir)?;
+
+        lf.with_column(self.bucket().alias(BUCKET_COLUMN))
+            .sink_parquet_partitioned(
+                Arc::new(PlPath::Local(dir.as_path().into())),
+                None,
+                PartitionVariant::ByKey {
+                    key_exprs: vec![col(BUCKET_COLUMN)],
+                    include_key: false,
+                },
+                ParquetWriteOptions::default(),
+                None,
+                SinkOptions {
+                    mkdir: true,
+                    ..Default::default()
+                },
+                None,
+                None,
+            )?
+            .collect_with_engine(Engine::Streaming)?;
+
+        tracing::info!("Spilled {} into {} partitions", name, self.partitions);
+        Ok(Staged::Disk { dir, schema })
+    }
+
+    /// Remove everything spilled to disk
+    pub fn cleanup(&self) -> Result<()> {
+        if self.dir.exists() {
+            remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// A frame prepared by `Spill::stage`
+pub enum Staged {
+    Memory(Box<LazyFrame>),
+    Disk { dir: PathBuf, schema: SchemaRef },
+}
+
+impl Staged {
+    /// Rows of the staged frame that belong to `partition`
+    pub fn partition(&self, partition: usize) -> Result<LazyFrame> {
+        match self {
+            Staged::Memory(lf) => Ok(lf.as_ref().clone()),
+            Staged::Disk { dir, schema } => {
+                let files = parquet_files(&dir.join(format!("{}={}", BUCKET_COLUMN, partition)))?;
+                if files.is_empty() {
+                    return Ok(DataFrame::empty_with_schema(schema).lazy());
+                }
+
+                let mut args = ScanArgsParquet::default();
+                args.hive_options.enabled = Some(false);
+                Ok(LazyFrame::scan_parquet_files(files.into(), args)?)
+            }
+        }
+    }
+}
+
+/// Parquet files inside a partition directory, in name order
+pub fn parquet_files(dir: &Path) -> Result<Vec<PlPath>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) == Some("parquet") {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    Ok(files
+        .into_iter()
+        .map(|path| PlPath::Local(path.as_path().into()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_partitions_by_cnpj_basico() {
+        let dir = std::env::temp_dir().join("rfb_spill_test");
+        let spill = Spill::new(&dir, 4);
+
+        let basicos: Vec<String> = (0..100).map(|i| format!("{:08}", i)).collect();
+        let lf = df!("cnpj_basico" => &basicos).unwrap().lazy();
+        let staged = spill.stage("empresas", lf.clone()).unwrap();
+
+        let mut total = 0;
+        for partition in 0..spill.partitions() {
+            let expected = spill.filter(lf.clone(), partition).collect().unwrap();
+            let df = staged.partition(partition).unwrap().collect().unwrap();
+            assert_eq!(df.get_column_names(), ["cnpj_basico"]);
+            assert_eq!(df.height(), expected.height());
+            total += df.height();
+        }
+        assert_eq!(total, 100);
+
+        spill.cleanup().unwrap();
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,294 @@
+use super::{Lookups, QualityReport, Result, TransformConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+/// File name of the state inside the output directory
+pub const STATE_FILE: &str = "transform_state.json";
+
+/// Size and BLAKE3 hash of an input file
+///
+/// The modification time is only used to avoid hashing unchanged files again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub size: u64,
+    pub modified: u128,
+    pub hash: String,
+}
+
+/// A completed step of a transform run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    /// Hash of the step's inputs and of the settings that shape its outputs
+    pub key: String,
+    /// Files written by the step and their sizes
+    pub outputs: BTreeMap<PathBuf, u64>,
+    /// Quality counts of the step, merged into the report of every run
+    #[serde(default, skip_serializing_if = "QualityReport::is_empty")]
+    pub quality: QualityReport,
+}
+
+impl Step {
+    /// Whether every output is still on disk with its recorded size
+    fn is_intact(&self) -> bool {
+        self.outputs.iter().all(|(path, size)| {
+            std::fs::metadata(path)
+                .map(|m| m.len() == *size)
+                .unwrap_or(false)
+        })
+    }
+}
+
+/// Inputs and completed steps of previous transform runs
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransformState {
+    /// Fingerprints of the input files, by path
+    pub inputs: BTreeMap<PathBuf, Fingerprint>,
+    /// Completed steps by name, e.g. `companies/part-00003`
+    pub steps: BTreeMap<String, Step>,
+}
+
+impl TransformState {
+    /// Read the state of `dir`; a missing or unreadable file gives an empty state
+    pub fn load(dir: &Path) -> Self {
+        let path = dir.join(STATE_FILE);
+        let Ok(file) = File::open(&path) else {
+            return Self::default();
+        };
+        serde_json::from_reader(std::io::BufReader::new(file)).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring unreadable state {:?}: {}", path, e);
+            Self::default()
+        })
+    }
+
+    /// Write the state into `dir`, replacing the previous file atomically
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(STATE_FILE);
+        let tmp = path.with_extension("json.tmp");
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&tmp)?), self)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(path)
+    }
+}
+
+/// The state of the current run, saved after every completed step so that an
+/// interrupted run resumes where it stopped
+pub struct StateFile {
+    dir: PathBuf,
+    state: Mutex<TransformState>,
+}
+
+impl StateFile {
+    /// Open the state of `dir`, or start from scratch when `force` is set
+    pub fn open(dir: &Path, force: bool) -> Self {
+        let state = if force {
+            TransformState::default()
+        } else {
+            TransformState::load(dir)
+        };
+        Self {
+            dir: dir.to_path_buf(),
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Fingerprint of an input file, reusing the recorded hash when its size
+    /// and modification time did not change
+    pub fn fingerprint(&self, path: &Path) -> Result<Fingerprint> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+
+        if let Some(known) = self.state.lock().unwrap().inputs.get(path)
+            && known.size == metadata.len()
+            && known.modified == modified
+        {
+            return Ok(known.clone());
+        }
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(File::open(path)?)?;
+        let fingerprint = Fingerprint {
+            size: metadata.len(),
+            modified,
+            hash: hasher.finalize().to_hex().to_string(),
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .inputs
+            .insert(path.to_path_buf(), fingerprint.clone());
+        Ok(fingerprint)
+    }
+
+    /// Key of a step from its name, its input files and other values it depends on
+    pub fn key(&self, name: &str, inputs: &[PathBuf], extra: &[&str]) -> Result<String> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(name.as_bytes());
+        for value in extra {
+            hasher.update(&[0]).update(value.as_bytes());
+        }
+        for path in inputs {
+            hasher
+                .update(&[0])
+                .update(self.fingerprint(path)?.hash.as_bytes());
+        }
+        Ok(hasher.finalize().to_hex().to_string())
+    }
+
+    /// The completed step `name`, if it ran with the same key and its outputs are intact
+    pub fn completed(&self, name: &str, key: &str) -> Option<Step> {
+        let state = self.state.lock().unwrap();
+        let step = state.steps.get(name)?;
+        (step.key == key && step.is_intact()).then(|| step.clone())
+    }
+
+    /// Record a completed step and save the state
+    pub fn complete(&self, name: &str, key: String, outputs: &[PathBuf]) -> Result<()> {
+        self.complete_with(name, key, outputs, QualityReport::default())
+    }
+
+    /// Record a completed step with its quality counts and save the state
+    pub fn complete_with(
+        &self,
+        name: &str,
+        key: String,
+        outputs: &[PathBuf],
+        quality: QualityReport,
+    ) -> Result<()> {
+        let mut sizes = BTreeMap::new();
+        for path in outputs {
+            sizes.insert(path.clone(), std::fs::metadata(path)?.len());
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.steps.insert(
+            name.to_string(),
+            Step {
+                key,
+                outputs: sizes,
+                quality,
+            },
+        );
+        state.write(&self.dir)?;
+        Ok(())
+    }
+
+    /// Forget steps that are not part of the current run and inputs that no
+    /// longer exist, then save
+    pub fn retain(&self, steps: &BTreeSet<String>) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.steps.retain(|name, _| steps.contains(name));
+        state.inputs.retain(|path, _| path.exists());
+        state.write(&self.dir)?;
+        Ok(())
+    }
+
+    /// Quality report of the run: the counts of every step it kept
+    pub fn quality(&self) -> QualityReport {
+        let mut report = QualityReport::default();
+        for step in self.state.lock().unwrap().steps.values() {
+            report.merge(step.quality.clone());
+        }
+        report.finish();
+        report
+    }
+}
+
+/// Hash of the settings that change the content of the outputs
+pub fn config_key(config: &TransformConfig) -> String {
+    let settings = format!(
+        "{}|{}|{}|{:?}|{:?}|{}|{}|{}",
+        env!("CARGO_PKG_VERSION"),
+        config.format,
+        config.compression,
+        config.row_group_size,
+        config
+            .privacy()
+            .ok()
+            .flatten()
+            .map(|privacy| privacy.to_string()),
+        config.encoding,
+        config.suppression.fingerprint(),
+        config.filters
+    );
+    blake3::hash(settings.as_bytes()).to_hex().to_string()
+}
+
+/// Hash of the content of the lookup tables
+pub fn lookups_key(lookups: &Lookups) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for (name, table) in lookups.tables() {
+        let mut rows: Vec<_> = table.iter().collect();
+        rows.sort();
+        hasher.update(name.as_bytes());
+        for (code, description) in rows {
+            hasher
+                .update(&code.to_le_bytes())
+                .update(description.as_bytes())
+                .update(&[0]);
+        }
+    }
+    let mut crosswalk: Vec<_> = lookups.cities_ibge.iter().collect();
+    crosswalk.sort();
+    hasher.update(b"cities_ibge");
+    for (tom, ibge) in crosswalk {
+        hasher
+            .update(&tom.to_le_bytes())
+            .update(&ibge.to_le_bytes());
+    }
+    hasher.update(b"cnae_structure");
+    for (code, description) in lookups.cnae_structure.iter() {
+        hasher
+            .update(code.as_str().as_bytes())
+            .update(&[0])
+            .update(description.unwrap_or_default().as_bytes())
+            .update(&[0]);
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completed_steps() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let input = dir.path().join("ESTABELE0.csv");
+        let output = dir.path().join("part-00000.parquet");
+        std::fs::write(&input, "a").unwrap();
+        std::fs::write(&output, "b").unwrap();
+
+        let state = StateFile::open(dir.path(), false);
+        let key = state
+            .key("companies", std::slice::from_ref(&input), &[])
+            .unwrap();
+        assert_eq!(state.completed("companies", &key), None);
+        state
+            .complete("companies", key.clone(), std::slice::from_ref(&output))
+            .unwrap();
+
+        // A new run reads the saved state
+        let state = StateFile::open(dir.path(), false);
+        assert!(state.completed("companies", &key).is_some());
+        assert!(
+            StateFile::open(dir.path(), true)
+                .completed("companies", &key)
+                .is_none()
+        );
+
+        // Changed inputs give another key, missing outputs invalidate the step
+        std::fs::write(&input, "cd").unwrap();
+        assert_ne!(state.key("companies", &[input], &[]).unwrap(), key);
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(state.completed("companies", &key), None);
+    }
+}
@@ -0,0 +1,355 @@
+use super::privacy::{self, mask_cpfs, normalize_name, published_digits};
+use super::quality::check_values;
+use super::shards::InputKind;
+use super::{Cnpj, Result, TransformError};
+use polars::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+/// People and companies whose data must not be published, e.g. after an
+/// LGPD erasure request
+///
+/// The list is a text file with one entry per line; blank lines and text
+/// after `#` are ignored. An entry is one of:
+///
+/// - a CNPJ (`12.345.678/0001-95`), suppressing that establishment
+/// - a CNPJ base (`12345678`), suppressing the company with all its
+///   establishments and partners
+/// - a CPF (`123.456.789-01`), suppressing the partners and legal
+///   representatives with that CPF and the Empresário Individual or MEI
+///   named after it
+/// - `name:` and a name, suppressing the partners and legal
+///   representatives with that name
+///
+/// The Federal Revenue only publishes the six middle digits of partner CPFs
+/// (`***456789**`), so a CPF entry also matches the partners who share them.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SuppressionList {
+    cnpjs: BTreeSet<String>,
+    basicos: BTreeSet<String>,
+    cpfs: BTreeSet<String>,
+    names: BTreeSet<String>,
+}
+
+impl SuppressionList {
+    /// Read a suppression list file
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+            .map_err(|e| TransformError::InvalidConfig(format!("{:?}: {}", path, e)))
+    }
+
+    /// Parse the content of a suppression list file
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut list = Self::default();
+        for (index, line) in text.lines().enumerate() {
+            let entry = line.split('#').next().unwrap_or_default().trim();
+            if entry.is_empty() {
+                continue;
+            }
+            list.add(entry)
+                .map_err(|e| TransformError::InvalidConfig(format!("line {}: {}", index + 1, e)))?;
+        }
+        Ok(list)
+    }
+
+    fn add(&mut self, entry: &str) -> std::result::Result<(), String> {
+        if let Some(name) = entry.strip_prefix("name:") {
+            let name = normalize_name(name);
+            if name.is_empty() {
+                return Err("empty name".to_string());
+            }
+            self.names.insert(name);
+            return Ok(());
+        }
+
+        let document: String = entry
+            .chars()
+            .filter(|c| !matches!(c, '.' | '-' | '/' | ' '))
+            .collect::<String>()
+            .to_uppercase();
+        match document.len() {
+            14 => {
+                let cnpj: Cnpj = document.parse().map_err(|e| format!("{}", e))?;
+                self.cnpjs.insert(cnpj.as_str().to_string());
+            }
+            8 if document.chars().all(|c| c.is_ascii_alphanumeric()) => {
+                self.basicos.insert(document);
+            }
+            11 if document.chars().all(|c| c.is_ascii_digit()) => {
+                self.cpfs.insert(document);
+            }
+            _ => {
+                return Err(format!(
+                    "expected a CNPJ, a CNPJ base, a CPF or name:<name>, found {:?}",
+                    entry
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.cnpjs.len() + self.basicos.len() + self.cpfs.len() + self.names.len()
+    }
+
+    /// Hash of the entries, which changes whenever the list does
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = blake3::Hasher::new();
+        for set in [&self.cnpjs, &self.basicos, &self.cpfs, &self.names] {
+            for entry in set {
+                hasher.update(entry.as_bytes()).update(&[0]);
+            }
+            hasher.update(&[1]);
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+
+    /// Listed CNPJs, 14 characters without punctuation
+    pub fn cnpjs(&self) -> impl Iterator<Item = &str> {
+        self.cnpjs.iter().map(String::as_str)
+    }
+
+    /// Listed CNPJ bases
+    pub fn basicos(&self) -> impl Iterator<Item = &str> {
+        self.basicos.iter().map(String::as_str)
+    }
+
+    /// Listed CPFs, 11 digits without punctuation
+    pub fn cpfs(&self) -> impl Iterator<Item = &str> {
+        self.cpfs.iter().map(String::as_str)
+    }
+
+    /// Listed names, in upper case with single spaces
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    /// Whether an establishment is suppressed by its CNPJ or CNPJ base
+    pub fn suppresses_cnpj(&self, cnpj: &Cnpj) -> bool {
+        self.cnpjs.contains(cnpj.as_str()) || self.basicos.contains(cnpj.basico())
+    }
+
+    /// Whether an establishment is suppressed, also by a CPF in its company name
+    pub fn suppresses_company(&self, cnpj: &Cnpj, razao_social: &str) -> bool {
+        self.suppresses_cnpj(cnpj)
+            || privacy::cpfs(razao_social).any(|cpf| self.cpfs.contains(&cpf))
+    }
+
+    /// Whether a raw input line mentions any entry, in any of its fields
+    ///
+    /// Malformed lines cannot be told apart by establishment, so the base of
+    /// a listed CNPJ is enough.
+    pub fn suppresses_line(&self, line: &str) -> bool {
+        let document = |field: &str| {
+            self.cnpjs.contains(field)
+                || self.basicos.contains(field)
+                || self.cnpjs.iter().any(|cnpj| cnpj[..8] == *field)
+        };
+        if line
+            .split(';')
+            .map(|field| field.trim().trim_matches('"'))
+            .any(document)
+        {
+            return true;
+        }
+
+        let masked = mask_cpfs(line);
+        let name = normalize_name(line);
+        self.cpfs
+            .iter()
+            .any(|cpf| masked.contains(&format!("***{}**", &cpf[3..9])))
+            || self
+                .names
+                .iter()
+                .any(|listed| name.contains(listed.as_str()))
+    }
+
+    /// Whether a CNPJ, a CNPJ base or a partner's CNPJ is listed
+    fn document(&self, expr: Expr) -> Expr {
+        let (cnpjs, basicos) = (self.cnpjs.clone(), self.basicos.clone());
+        check_values(expr, move |value| {
+            cnpjs.contains(value)
+                || (matches!(value.len(), 8 | 14)
+                    && value
+                        .get(..8)
+                        .is_some_and(|basico| basicos.contains(basico)))
+        })
+        .fill_null(lit(false))
+    }
+
+    /// Whether a text, such as the name of an MEI, holds a listed CPF
+    fn holds_cpf(&self, expr: Expr) -> Expr {
+        let cpfs = self.cpfs.clone();
+        check_values(expr, move |value| {
+            privacy::cpfs(value).any(|cpf| cpfs.contains(&cpf))
+        })
+        .fill_null(lit(false))
+    }
+
+    /// Whether a partner CPF, raw or as published, matches a listed CPF
+    fn published_cpf(&self, expr: Expr) -> Expr {
+        let digits: BTreeSet<String> = self.cpfs.iter().map(|cpf| cpf[3..9].to_string()).collect();
+        check_values(expr, move |value| {
+            published_digits(value).is_some_and(|value| digits.contains(&value))
+        })
+        .fill_null(lit(false))
+    }
+
+    fn named(&self, expr: Expr) -> Expr {
+        let names = self.names.clone();
+        check_values(expr, move |value| names.contains(&normalize_name(value)))
+            .fill_null(lit(false))
+    }
+
+    fn company(&self, cnpj: Expr, razao_social: Expr) -> Expr {
+        self.document(cnpj).or(self.holds_cpf(razao_social))
+    }
+
+    fn partner(&self) -> Expr {
+        self.document(col("cnpj_basico"))
+            .or(self.document(col("cnpj_cpf_socio")))
+            .or(self.published_cpf(col("cnpj_cpf_socio")))
+            .or(self.named(col("nome_socio")))
+    }
+
+    fn representative(&self) -> Expr {
+        self.published_cpf(col("cpf_representante_legal"))
+            .or(self.named(col("nome_representante_legal")))
+    }
+
+    /// Leave the suppressed establishments out of a companies frame (see
+    /// `frames::companies`)
+    pub fn companies(&self, companies: LazyFrame) -> LazyFrame {
+        if self.is_empty() {
+            return companies;
+        }
+        companies.filter(self.company(col("cnpj"), col("razao_social")).not())
+    }
+
+    /// Leave the suppressed partners out of a partners frame, raw or typed,
+    /// and write null for the suppressed legal representatives
+    pub fn partners(&self, partners: LazyFrame) -> LazyFrame {
+        if self.is_empty() {
+            return partners;
+        }
+        let null = lit(NULL).cast(DataType::String);
+        partners.filter(self.partner().not()).with_columns([
+            when(self.representative())
+                .then(null.clone())
+                .otherwise(col("cpf_representante_legal"))
+                .alias("cpf_representante_legal"),
+            when(self.representative())
+                .then(null)
+                .otherwise(col("nome_representante_legal"))
+                .alias("nome_representante_legal"),
+        ])
+    }
+
+    /// Rows of a raw input that the list suppresses, by input table, plus
+    /// the redacted legal representatives (`representantes`)
+    pub fn count(&self, raw: LazyFrame, kind: InputKind) -> Result<BTreeMap<String, u64>> {
+        let sum = |expr: Expr, name: &str| expr.cast(DataType::UInt64).sum().alias(name);
+        let exprs = match kind {
+            InputKind::Estabelecimentos => {
+                let cnpj = concat_str(
+                    [col("cnpj_basico"), col("cnpj_ordem"), col("cnpj_dv")],
+                    "",
+                    false,
+                );
+                vec![sum(self.document(cnpj), kind.table())]
+            }
+            InputKind::Empresas => vec![sum(
+                self.company(col("cnpj_basico"), col("razao_social")),
+                kind.table(),
+            )],
+            InputKind::Socios => vec![
+                sum(self.partner(), kind.table()),
+                sum(
+                    self.representative().and(self.partner().not()),
+                    "representantes",
+                ),
+            ],
+            InputKind::Simples => return Ok(BTreeMap::new()),
+        };
+
+        let counts = raw.select(exprs).collect_with_engine(Engine::Streaming)?;
+        let mut suppressed = BTreeMap::new();
+        for column in counts.get_columns() {
+            let count = column.u64()?.get(0).unwrap_or_default();
+            suppressed.insert(column.name().to_string(), count);
+        }
+        Ok(suppressed)
+    }
+}
+
+impl fmt::Debug for SuppressionList {
+    // The entries are personal data, so they are kept out of logs
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SuppressionList({} entries)", self.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST: &str = "\
+# Erasure requests
+12345678              # whole company
+33.683.111/0002-80
+123.456.789-01
+name: Maria  Souza
+";
+
+    #[test]
+    fn test_parse_suppression_list() {
+        let list = SuppressionList::parse(LIST).unwrap();
+        assert_eq!(list.len(), 4);
+        assert_eq!(list.cnpjs().collect::<Vec<_>>(), ["33683111000280"]);
+        assert_eq!(list.basicos().collect::<Vec<_>>(), ["12345678"]);
+        assert_eq!(list.cpfs().collect::<Vec<_>>(), ["12345678901"]);
+        assert_eq!(list.names().collect::<Vec<_>>(), ["MARIA SOUZA"]);
+        assert_eq!(format!("{:?}", list), "SuppressionList(4 entries)");
+
+        assert!(SuppressionList::parse("33.683.111/0002-81").is_err());
+        assert!(SuppressionList::parse("JOAO").is_err());
+        assert!(SuppressionList::parse("\n# none\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_suppress_partners() {
+        let list = SuppressionList::parse(LIST).unwrap();
+        let partners = df!(
+            "cnpj_basico" => ["12345678", "11222333", "11222333", "11222333", "11222333"],
+            "nome_socio" => ["A", "B", "C", "maria souza", "D"],
+            "cnpj_cpf_socio" => ["***111111**", "***456789**", "33683111000280", "***222222**", "***333333**"],
+            "cpf_representante_legal" => [None, None, None, None, Some("***456789**")],
+            "nome_representante_legal" => [None, None, None, None, Some("E")],
+        )
+        .unwrap();
+
+        let kept = list.partners(partners.lazy()).collect().unwrap();
+        // Only D is kept, without its legal representative
+        assert_eq!(kept.height(), 1);
+        assert_eq!(
+            kept.column("nome_socio").unwrap().str().unwrap().get(0),
+            Some("D")
+        );
+        assert_eq!(
+            kept.column("nome_representante_legal")
+                .unwrap()
+                .null_count(),
+            1
+        );
+
+        assert!(list.suppresses_line("\"11222333\";\"2\";\"X\";\"***456789**\""));
+        assert!(list.suppresses_line("\"33683111\";\"0002\";\"80\";\"1\""));
+        assert!(!list.suppresses_line("\"11222333\";\"2\";\"X\";\"***999999**\""));
+    }
+}
@@ -1,19 +1,22 @@
+use super::Cnpj;
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 
 /// Tax regime information (Simples Nacional and MEI)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaxRegime {
-    pub cnpj: String,
+    /// CNPJ of the head office; the Simples file is keyed by `cnpj_basico`
+    pub cnpj: Cnpj,
     pub opcao_simples: Option<bool>,
-    pub data_opcao_simples: Option<String>,
-    pub data_exclusao_simples: Option<String>,
+    pub data_opcao_simples: Option<NaiveDate>,
+    pub data_exclusao_simples: Option<NaiveDate>,
     pub opcao_mei: Option<bool>,
-    pub data_opcao_mei: Option<String>,
-    pub data_exclusao_mei: Option<String>,
+    pub data_opcao_mei: Option<NaiveDate>,
+    pub data_exclusao_mei: Option<NaiveDate>,
 }
 
 impl TaxRegime {
-    pub fn new(cnpj: String) -> Self {
+    pub fn new(cnpj: Cnpj) -> Self {
         Self {
             cnpj,
             opcao_simples: None,
@@ -32,8 +35,8 @@ mod tests {
 
     #[test]
     fn test_tax_regime_creation() {
-        let regime = TaxRegime::new("12345678000195".to_string());
-        assert_eq!(regime.cnpj, "12345678000195");
+        let regime = TaxRegime::new("12345678000195".parse().unwrap());
+        assert_eq!(regime.cnpj.as_str(), "12345678000195");
         assert_eq!(regime.opcao_simples, None);
         assert_eq!(regime.opcao_mei, None);
     }
@@ -1,8 +1,70 @@
-use super::{Lookups, Result, TransformConfig};
+use super::filter;
+use super::graph::{self, GRAPH_DIR};
+use super::groups::{self, GROUPS_TABLE};
+use super::jobs::{self, Jobs, Task};
+use super::layout::{
+    EMPRESAS_COLUMNS, ESTABELECIMENTOS_COLUMNS, SIMPLES_COLUMNS, SOCIOS_COLUMNS, scan_csv,
+};
+use super::output::parquet_rows;
+use super::records::{self, Qsa};
+use super::rejects;
+use super::shards::{InputKind, discover};
+use super::spill::{Spill, Staged, parquet_files};
+use super::state::{self, StateFile};
+use super::{Encoding, InputQuality, OutputFormat, QualityReport, TransformError, frames, quality};
+use super::{Lookups, OutputWriter, Result, TransformConfig, TransformReport};
 use ::zip::ZipArchive;
 use polars::prelude::*;
+use std::collections::BTreeSet;
 use std::fs::{File, read_dir};
+use std::io::BufWriter;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Directory inside the output directory holding spilled join partitions
+const STAGING_DIR: &str = ".staging";
+
+/// Directory inside the staging directory holding inputs decoded to UTF-8
+const DECODED_DIR: &str = "utf8";
+
+/// Directory inside the staging directory holding inputs without their malformed lines
+const CLEAN_DIR: &str = "clean";
+
+/// Directory inside the output directory holding the malformed lines of each input
+const REJECTS_DIR: &str = "rejects";
+
+/// Input files of one transform run
+struct Shards {
+    estabelecimentos: Vec<PathBuf>,
+    empresas: Vec<PathBuf>,
+    simples: Vec<PathBuf>,
+    socios: Vec<PathBuf>,
+}
+
+/// A per-shard step whose outputs are not up to date
+struct Pending<'a> {
+    index: usize,
+    path: &'a Path,
+    name: String,
+    key: String,
+}
+
+/// Build side of the company join, staged for the spill partitions
+struct BuildSide {
+    empresas: Staged,
+    simples: Staged,
+    /// Partners nested into jsonl documents
+    socios: Option<Staged>,
+}
+
+/// Total size in bytes of a set of input files
+fn total_size(paths: &[PathBuf]) -> Result<u64> {
+    let mut size = 0;
+    for path in paths {
+        size += std::fs::metadata(path)?.len();
+    }
+    Ok(size)
+}
 
 /// Main transformer that orchestrates the transformation process
 pub struct Transformer {
@@ -20,183 +82,845 @@ impl Transformer {
 
     /// Load lookup tables
     pub fn load_lookups(&mut self) -> Result<()> {
-        self.lookups.load_from_directory(&self.config.data_dir)?;
+        self.lookups
+            .load_from_directory_with_encoding(&self.config.data_dir, self.config.encoding)?;
         Ok(())
     }
 
     /// Transform all data files
-    pub async fn transform(&self) -> Result<()> {
+    ///
+    /// Inputs are scanned lazily and written with the streaming engine, with
+    /// shards and tables processed in parallel by `jobs` workers. When a
+    /// memory limit is set and the `cnpj_basico` joins would exceed it, the
+    /// Empresas, Simples (and, for jsonl, Socios) frames are spilled to disk in
+    /// partitions and the establishment shards are joined one partition at a time.
+    pub async fn transform(&self) -> Result<TransformReport> {
+        self.config.validate()?;
+
+        // The work is blocking and the streaming engine drives its own async
+        // runtime, so it runs on a dedicated thread and the caller's executor
+        // only awaits the result
+        let worker = Self {
+            config: self.config.clone(),
+            lookups: self.lookups.clone(),
+        };
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let handle = std::thread::Builder::new()
+            .name("rfb-transform".to_string())
+            .spawn(move || {
+                let _ = tx.send(worker.run());
+            })?;
+
+        match rx.await {
+            Ok(result) => result,
+            Err(_) => std::panic::resume_unwind(handle.join().unwrap_err()),
+        }
+    }
+
+    fn run(&self) -> Result<TransformReport> {
+        let jobs = Jobs::new(self.config.jobs.unwrap_or_else(jobs::default_jobs));
+
         tracing::info!("Starting transformation process");
         tracing::info!("Data directory: {}", self.config.data_dir);
         tracing::info!("Output directory: {}", self.config.output_dir);
         tracing::info!("Privacy mode: {}", self.config.privacy_mode);
+        if !self.config.suppression.is_empty() {
+            tracing::info!(
+                "Suppression list: {} entries",
+                self.config.suppression.len()
+            );
+        }
+        tracing::info!("Output format: {}", self.config.format);
+        tracing::info!("Workers: {}", jobs.workers());
 
         // Create output directory
         std::fs::create_dir_all(&self.config.output_dir)?;
+        let state = StateFile::open(Path::new(&self.config.output_dir), self.config.force);
+        let mut steps = BTreeSet::new();
 
         // Extract all ZIP files first
-        self.extract_all_zips()?;
+        let extracted = self.extract_all_zips(&jobs, &state, &mut steps)?;
+
+        // Entries extracted above are already UTF-8; other inputs are decoded here
+        let mut shards = Shards {
+            estabelecimentos: self.find(InputKind::Estabelecimentos, &extracted)?,
+            empresas: self.find(InputKind::Empresas, &extracted)?,
+            simples: self.find(InputKind::Simples, &extracted)?,
+            socios: self.find(InputKind::Socios, &extracted)?,
+        };
+        self.reject(&jobs, &state, &mut steps, &mut shards)?;
 
         // Process different file types
-        self.process_estabelecimentos()?;
-        self.process_empresas()?;
-        self.process_socios()?;
-        self.process_simples()?;
+        let writer = OutputWriter::new(&self.config);
+        let spill = self.plan_spill(&shards, &jobs)?;
+        let result = self.process(&jobs, &writer, &spill, &shards, &state, &mut steps);
+        spill.cleanup()?;
+        result?;
+        state.retain(&steps)?;
+
+        let output_dir = Path::new(&self.config.output_dir);
+        let quality = state.quality();
+        let report = TransformReport {
+            invalid_dates: quality.invalid_dates.clone(),
+        };
+        let path = report.write(output_dir)?;
+        if report.invalid_dates_total() > 0 {
+            tracing::warn!(
+                "{} invalid dates written as null, see {:?}",
+                report.invalid_dates_total(),
+                path
+            );
+        }
+        let path = quality.write(output_dir)?;
+        tracing::info!("Wrote quality report to {:?}", path);
+        quality.check(&self.config.fail_on)?;
 
         tracing::info!("Transformation complete!");
-        Ok(())
+        Ok(report)
     }
 
-    fn extract_all_zips(&self) -> Result<()> {
-        tracing::info!("Extracting ZIP files...");
+    /// Run the steps whose inputs changed since the last run
+    ///
+    /// Each company or partner part is a step keyed by the hashes of the files
+    /// it reads, the lookups and the output settings; steps with a matching key
+    /// in the state and intact outputs are skipped.
+    fn process(
+        &self,
+        jobs: &Jobs,
+        writer: &OutputWriter,
+        spill: &Spill,
+        shards: &Shards,
+        state: &StateFile,
+        steps: &mut BTreeSet<String>,
+    ) -> Result<()> {
+        // Hash every input up front, in parallel; the step keys reuse the hashes
+        let inputs = [
+            &shards.estabelecimentos,
+            &shards.empresas,
+            &shards.simples,
+            &shards.socios,
+        ];
+        let tasks = inputs
+            .into_iter()
+            .flatten()
+            .map(|path| {
+                Task::new(format!("hash {:?}", path), move || {
+                    state.fingerprint(path)?;
+                    Ok(())
+                })
+            })
+            .collect();
+        jobs.run(tasks)?;
+
+        let config_key = state::config_key(&self.config);
+        let lookups_key = state::lookups_key(&self.lookups);
+        let keys = [config_key.as_str(), lookups_key.as_str()];
+
+        // Every company part reads the whole build side of the join
+        let mut joined = [shards.empresas.as_slice(), &shards.simples].concat();
+        if self.config.format == OutputFormat::Jsonl {
+            joined.extend_from_slice(&shards.socios);
+        }
+        let companies = self.pending(
+            state,
+            steps,
+            "companies",
+            &shards.estabelecimentos,
+            &joined,
+            &keys,
+        )?;
+        // With filters, the partners kept depend on the selected companies
+        let selecting = match self.config.filters.is_empty() {
+            true => Vec::new(),
+            false => [shards.estabelecimentos.as_slice(), &shards.empresas].concat(),
+        };
+        let partners = match self.config.format {
+            OutputFormat::Parquet => {
+                self.pending(state, steps, "partners", &shards.socios, &selecting, &keys)?
+            }
+            OutputFormat::Jsonl => Vec::new(),
+        };
+
+        let graph = match self.config.graph {
+            true => {
+                let inputs = [shards.socios.as_slice(), &shards.empresas, &selecting].concat();
+                let key = state.key("graph", &inputs, &keys)?;
+                steps.insert("graph".to_string());
+                state.completed("graph", &key).is_none().then_some(key)
+            }
+            false => None,
+        };
+
+        let basicos = if self.config.filters.is_empty()
+            || (companies.is_empty() && partners.is_empty() && graph.is_none())
+        {
+            None
+        } else {
+            Some(self.select(shards)?)
+        };
+        let basicos = basicos.as_ref();
+        let build = if companies.is_empty() {
+            None
+        } else {
+            Some(self.stage(spill, shards, basicos)?)
+        };
+
+        // Without partitions, Parquet company parts are streamed straight to disk
+        // alongside the other tables
+        let streamed = spill.partitions() == 1 && self.config.format == OutputFormat::Parquet;
+        let mut tasks = Vec::new();
+        if streamed && let Some(build) = &build {
+            for step in &companies {
+                tasks.push(Task::new(format!("companies {:?}", step.path), move || {
+                    let companies = self.companies(spill, step.path, build, 0)?;
+                    let path = writer.sink_part("companies", step.index, companies)?;
+                    let written = self.written(&path, parquet_rows(&path)?);
+                    state.complete_with(&step.name, step.key.clone(), &[path], written)
+                }));
+            }
+        }
+        for step in &partners {
+            tasks.push(Task::new(format!("partners {:?}", step.path), move || {
+                let socios = scan_csv(&[step.path.to_path_buf()], &SOCIOS_COLUMNS)?;
+                let partners = self.partners(socios, basicos)?;
+                let path = writer.sink_part("partners", step.index, partners)?;
+                let written = self.written(&path, parquet_rows(&path)?);
+                state.complete_with(&step.name, step.key.clone(), &[path], written)
+            }));
+        }
+        if let Some(key) = graph {
+            tasks.push(Task::new("graph", move || {
+                let (paths, written) = self.write_graph(writer, shards, basicos)?;
+                state.complete_with("graph", key, &paths, written)
+            }));
+        }
+        let key = state.key("lookups/cache", &[], &keys)?;
+        steps.insert("lookups/cache".to_string());
+        if state.completed("lookups/cache", &key).is_none() {
+            tasks.push(Task::new("lookups/cache", move || {
+                let path = Path::new(&self.config.output_dir).join(Lookups::CACHE_FILE);
+                self.lookups.write_cache(&path)?;
+                tracing::info!("Wrote the lookup cache to {:?}", path);
+                state.complete("lookups/cache", key, &[path])
+            }));
+        }
+        if self.config.format == OutputFormat::Parquet {
+            let key = state.key("lookups", &[], &keys)?;
+            steps.insert("lookups".to_string());
+            if state.completed("lookups", &key).is_none() {
+                tasks.push(Task::new("lookups", move || {
+                    let paths = self.write_lookups(writer)?;
+                    let mut written = QualityReport::default();
+                    for path in &paths {
+                        written.merge(self.written(path, parquet_rows(path)?));
+                    }
+                    state.complete_with("lookups", key, &paths, written)
+                }));
+            }
+        }
+        tasks.extend(self.check_quality(shards, state, steps, &keys)?);
+        jobs.run(tasks)?;
 
-        let entries = read_dir(&self.config.data_dir)?;
-        for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
+        if !streamed && let Some(build) = &build {
+            self.join_partitions(jobs, writer, spill, &companies, build, state)?;
+        }
 
-            if path.extension().and_then(|s| s.to_str()) == Some("zip") {
-                tracing::info!("Extracting: {:?}", path.file_name());
-                self.extract_zip(&path.to_string_lossy(), &self.config.data_dir)?;
+        // Groups span every company part, so they are built once all are written
+        if self.config.groups {
+            let inputs = [shards.estabelecimentos.as_slice(), &joined].concat();
+            let key = state.key(GROUPS_TABLE, &inputs, &keys)?;
+            steps.insert(GROUPS_TABLE.to_string());
+            if state.completed(GROUPS_TABLE, &key).is_none() {
+                let path = self.write_groups(writer)?;
+                let written = self.written(&path, parquet_rows(&path)?);
+                state.complete_with(GROUPS_TABLE, key, &[path], written)?;
             }
         }
 
         Ok(())
     }
 
-    fn process_estabelecimentos(&self) -> Result<()> {
-        tracing::info!("Processing Estabelecimentos files...");
+    /// Write one record per `cnpj_basico` from the company parts
+    fn write_groups(&self, writer: &OutputWriter) -> Result<PathBuf> {
+        let dir = Path::new(&self.config.output_dir).join("companies");
+        let mut args = ScanArgsParquet::default();
+        args.hive_options.enabled = Some(false);
+        let companies = LazyFrame::scan_parquet_files(parquet_files(&dir)?.into(), args)?;
+        writer.sink_part(GROUPS_TABLE, 0, groups::groups(companies))
+    }
+
+    /// Quality report of a step that wrote `rows` rows to `path`
+    fn written(&self, path: &Path, rows: usize) -> QualityReport {
+        QualityReport::written(Path::new(&self.config.output_dir), path, rows)
+    }
 
-        for i in 0..10 {
-            let pattern = format!("{}/*ESTABELE{}.csv", self.config.data_dir, i);
-            if let Some(csv_path) = self.find_csv_by_pattern(&pattern)? {
-                tracing::info!("Processing: {:?}", csv_path);
-                self.process_estabelecimentos_file(&csv_path)?;
+    /// Steps writing one part per shard of `table` that are not up to date
+    ///
+    /// Each part depends on its shard, on the `shared` inputs and on `keys`.
+    fn pending<'a>(
+        &self,
+        state: &StateFile,
+        steps: &mut BTreeSet<String>,
+        table: &str,
+        shards: &'a [PathBuf],
+        shared: &[PathBuf],
+        keys: &[&str],
+    ) -> Result<Vec<Pending<'a>>> {
+        let mut pending = Vec::new();
+        for (index, path) in shards.iter().enumerate() {
+            let name = format!("{}/part-{:05}", table, index);
+            let inputs = [std::slice::from_ref(path), shared].concat();
+            let key = state.key(&name, &inputs, keys)?;
+            steps.insert(name.clone());
+
+            if state.completed(&name, &key).is_none() {
+                pending.push(Pending {
+                    index,
+                    path,
+                    name,
+                    key,
+                });
             }
         }
 
-        Ok(())
+        tracing::info!(
+            "{}: {} of {} parts up to date",
+            table,
+            shards.len() - pending.len(),
+            shards.len()
+        );
+        Ok(pending)
     }
 
-    fn process_estabelecimentos_file(&self, csv_path: &Path) -> Result<()> {
-        // Read CSV with Polars
-        let df = CsvReadOptions::default()
-            .with_has_header(false)
-            .with_parse_options(CsvParseOptions::default().with_separator(b';'))
-            .try_into_reader_with_file_path(Some(csv_path.into()))?
-            .finish()?;
-
-        tracing::info!("Loaded {} rows from estabelecimentos", df.height());
-
-        // Process and transform data
-        // The actual processing would involve:
-        // 1. Parse each row into Company structure
-        // 2. Enrich with lookup data
-        // 3. Apply privacy mode if enabled
-        // 4. Write to output
+    /// `cnpj_basico` of the companies selected by the filters
+    fn select(&self, shards: &Shards) -> Result<DataFrame> {
+        let basicos = self
+            .config
+            .filters
+            .basicos(
+                scan_csv(&shards.estabelecimentos, &ESTABELECIMENTOS_COLUMNS)?,
+                scan_csv(&shards.empresas, &EMPRESAS_COLUMNS)?,
+            )
+            .collect_with_engine(Engine::Streaming)?;
+        tracing::info!(
+            "Filters {} select {} companies",
+            self.config.filters,
+            basicos.height()
+        );
+        Ok(basicos)
+    }
 
-        Ok(())
+    /// Stage the build side of the company join, cut to the companies
+    /// selected by the filters
+    fn stage(
+        &self,
+        spill: &Spill,
+        shards: &Shards,
+        basicos: Option<&DataFrame>,
+    ) -> Result<BuildSide> {
+        let only_selected = |lf: LazyFrame| match basicos {
+            Some(basicos) => filter::selected(lf, basicos),
+            None => lf,
+        };
+        let empresas = self
+            .config
+            .filters
+            .empresas(scan_csv(&shards.empresas, &EMPRESAS_COLUMNS)?);
+        let empresas = spill.stage("empresas", frames::empresas(only_selected(empresas)))?;
+        let simples = only_selected(scan_csv(&shards.simples, &SIMPLES_COLUMNS)?);
+        let simples = spill.stage("simples", frames::simples(simples))?;
+        // For jsonl, partners are nested into each document, so they are staged too
+        let socios = match self.config.format {
+            OutputFormat::Parquet => None,
+            OutputFormat::Jsonl => Some(spill.stage(
+                "socios",
+                self.partners(scan_csv(&shards.socios, &SOCIOS_COLUMNS)?, basicos)?,
+            )?),
+        };
+
+        Ok(BuildSide {
+            empresas,
+            simples,
+            socios,
+        })
     }
 
-    fn process_empresas(&self) -> Result<()> {
-        tracing::info!("Processing Empresas files...");
+    /// Tasks profiling each input file and counting orphan rows for the
+    /// quality report; inputs unchanged since the last run are not read again
+    fn check_quality<'a>(
+        &'a self,
+        shards: &'a Shards,
+        state: &'a StateFile,
+        steps: &mut BTreeSet<String>,
+        keys: &[&str],
+    ) -> Result<Vec<Task<'a>>> {
+        let inputs = [
+            (InputKind::Estabelecimentos, &shards.estabelecimentos),
+            (InputKind::Empresas, &shards.empresas),
+            (InputKind::Simples, &shards.simples),
+            (InputKind::Socios, &shards.socios),
+        ];
+
+        let mut tasks = Vec::new();
+        for (kind, paths) in inputs {
+            for path in paths {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                let name = format!("quality/{}", file_name);
+                let key = state.key(&name, std::slice::from_ref(path), keys)?;
+                steps.insert(name.clone());
+                if state.completed(&name, &key).is_some() {
+                    continue;
+                }
 
-        for i in 0..10 {
-            let pattern = format!("{}/*EMPRE{}.csv", self.config.data_dir, i);
-            if let Some(csv_path) = self.find_csv_by_pattern(&pattern)? {
-                tracing::info!("Processing: {:?}", csv_path);
-                self.process_empresas_file(&csv_path)?;
+                tasks.push(Task::new(name.clone(), move || {
+                    let raw = scan_csv(std::slice::from_ref(path), kind.columns())?;
+                    let profile = quality::profile(raw, kind, &self.lookups)
+                        .collect_with_engine(Engine::Streaming)?;
+                    let mut found = quality::from_profile(&file_name, kind, &profile)?;
+                    if !self.config.suppression.is_empty() {
+                        let raw = scan_csv(std::slice::from_ref(path), kind.columns())?;
+                        found.suppressed = self.config.suppression.count(raw, kind)?;
+                    }
+                    state.complete_with(&name, key, &[], found)
+                }));
             }
         }
 
-        Ok(())
+        let name = "quality/orphans";
+        let inputs = [
+            shards.estabelecimentos.as_slice(),
+            &shards.socios,
+            &shards.simples,
+        ]
+        .concat();
+        let key = state.key(name, &inputs, keys)?;
+        steps.insert(name.to_string());
+        if state.completed(name, &key).is_none() {
+            tasks.push(Task::new(name, move || {
+                let estabelecimentos =
+                    scan_csv(&shards.estabelecimentos, &ESTABELECIMENTOS_COLUMNS)?;
+                let mut found = QualityReport::default();
+                for (table, paths, columns) in [
+                    ("socios", &shards.socios, SOCIOS_COLUMNS.as_slice()),
+                    ("simples", &shards.simples, SIMPLES_COLUMNS.as_slice()),
+                ] {
+                    let orphans =
+                        quality::orphans(estabelecimentos.clone(), scan_csv(paths, columns)?)
+                            .collect_with_engine(Engine::Streaming)?;
+                    let count = orphans.column("orphans")?.u64()?.get(0).unwrap_or_default();
+                    found.orphans.insert(table.to_string(), count);
+                }
+                state.complete_with(name, key, &[], found)
+            }));
+        }
+
+        Ok(tasks)
     }
 
-    fn process_empresas_file(&self, csv_path: &Path) -> Result<()> {
-        let df = CsvReadOptions::default()
-            .with_has_header(false)
-            .with_parse_options(CsvParseOptions::default().with_separator(b';'))
-            .try_into_reader_with_file_path(Some(csv_path.into()))?
-            .finish()?;
+    /// Move the malformed lines of every input to `rejects/<file>.ndjson`
+    ///
+    /// Inputs with malformed lines are replaced by a copy without them in the
+    /// staging directory. With `--format jsonl`, establishments with an invalid
+    /// CNPJ are rejected too. Fails once more lines than `max_rejects` are malformed.
+    fn reject(
+        &self,
+        jobs: &Jobs,
+        state: &StateFile,
+        steps: &mut BTreeSet<String>,
+        shards: &mut Shards,
+    ) -> Result<()> {
+        let output_dir = Path::new(&self.config.output_dir);
+        let (rejects_dir, clean_dir) = (
+            output_dir.join(REJECTS_DIR),
+            output_dir.join(STAGING_DIR).join(CLEAN_DIR),
+        );
+        let config_key = state::config_key(&self.config);
+        let rejected = Mutex::new(0);
+
+        let inputs = [
+            (InputKind::Estabelecimentos, &mut shards.estabelecimentos),
+            (InputKind::Empresas, &mut shards.empresas),
+            (InputKind::Simples, &mut shards.simples),
+            (InputKind::Socios, &mut shards.socios),
+        ];
+        let mut tasks = Vec::new();
+        for (kind, paths) in inputs {
+            for path in paths.iter_mut() {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                let file_name = file_name.to_string();
+                let name = format!("rejects/{}", file_name);
+                steps.insert(name.clone());
+
+                let (rejects_dir, clean_dir) = (&rejects_dir, &clean_dir);
+                let (config_key, rejected) = (&config_key, &rejected);
+                tasks.push(Task::new(format!("check {}", file_name), move || {
+                    // Inputs that had malformed lines are split again, as the
+                    // staging directory does not outlive a run
+                    let key = state.key(&name, std::slice::from_ref(path), &[config_key])?;
+                    if state
+                        .completed(&name, &key)
+                        .is_some_and(|step| step.outputs.is_empty())
+                    {
+                        return Ok(());
+                    }
 
-        tracing::info!("Loaded {} rows from empresas", df.height());
+                    let rejects = rejects_dir.join(format!("{}.ndjson", file_name));
+                    let clean = clean_dir.join(&file_name);
+                    let fields = kind.columns().len();
+                    // JSON documents carry a parsed CNPJ, which an invalid one cannot be
+                    let cnpjs = kind == InputKind::Estabelecimentos
+                        && self.config.format == OutputFormat::Jsonl;
+                    let (privacy, suppression) =
+                        (self.config.privacy_mode, &self.config.suppression);
+                    let count = rejects::split(
+                        path,
+                        fields,
+                        cnpjs,
+                        &clean,
+                        &rejects,
+                        privacy,
+                        suppression,
+                    )?;
+                    let mut found = QualityReport::default();
+                    let input = InputQuality {
+                        table: kind.table().to_string(),
+                        rows_read: 0,
+                        malformed_lines: count,
+                    };
+                    found.inputs.insert(file_name, input);
+
+                    if count == 0 {
+                        return state.complete_with(&name, key, &[], found);
+                    }
+                    tracing::warn!(
+                        "{} malformed lines of {:?} written to {:?}",
+                        count,
+                        path,
+                        rejects
+                    );
+                    state.complete_with(&name, key, &[rejects], found)?;
+                    *rejected.lock().unwrap() += count;
+                    *path = clean;
+                    Ok(())
+                }));
+            }
+        }
+        jobs.run(tasks)?;
+
+        let rejected = rejected.into_inner().unwrap();
+        if let Some(max) = self.config.max_rejects
+            && rejected > max
+        {
+            return Err(TransformError::TooManyRejects(format!(
+                "{} lines rejected, more than the maximum of {}",
+                rejected, max
+            )));
+        }
         Ok(())
     }
 
-    fn process_socios(&self) -> Result<()> {
-        tracing::info!("Processing Socios files...");
+    /// Choose the number of join partitions from the input sizes and the memory limit
+    fn plan_spill(&self, shards: &Shards, jobs: &Jobs) -> Result<Spill> {
+        let staging = Path::new(&self.config.output_dir).join(STAGING_DIR);
+        let Some(limit) = self.config.memory_limit else {
+            return Ok(Spill::new(staging, 1));
+        };
+
+        // The build side of the join is held while up to `jobs` establishment
+        // shards are joined against it
+        let mut input_bytes = total_size(&shards.empresas)? + total_size(&shards.simples)?;
+        if self.config.format == OutputFormat::Jsonl {
+            input_bytes += total_size(&shards.socios)?;
+        }
+        let mut shard_sizes = Vec::with_capacity(shards.estabelecimentos.len());
+        for path in &shards.estabelecimentos {
+            shard_sizes.push(total_size(std::slice::from_ref(path))?);
+        }
+        shard_sizes.sort_unstable_by(|a, b| b.cmp(a));
+        input_bytes += shard_sizes.iter().take(jobs.workers()).sum::<u64>();
+
+        let spill = Spill::new(staging, limit.partitions(input_bytes));
+        tracing::info!(
+            "Memory limit: {} ({} join partitions)",
+            limit,
+            spill.partitions()
+        );
+        Ok(spill)
+    }
+
+    /// Extract the ZIP files of the data directory, skipping those already
+    /// extracted from the same archive
+    fn extract_all_zips(
+        &self,
+        jobs: &Jobs,
+        state: &StateFile,
+        steps: &mut BTreeSet<String>,
+    ) -> Result<Vec<PathBuf>> {
+        tracing::info!("Extracting ZIP files...");
 
-        for i in 0..10 {
-            let pattern = format!("{}/*SOCIO{}.csv", self.config.data_dir, i);
-            if let Some(csv_path) = self.find_csv_by_pattern(&pattern)? {
-                tracing::info!("Processing: {:?}", csv_path);
-                self.process_socios_file(&csv_path)?;
+        let mut zips = Vec::new();
+        let entries = read_dir(&self.config.data_dir)?;
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+
+            if path.extension().and_then(|s| s.to_str()) == Some("zip") {
+                zips.push(path);
             }
         }
+        zips.sort();
+
+        let encoding = self.config.encoding.to_string();
+        let extracted = Mutex::new(Vec::new());
+        let tasks = zips
+            .iter()
+            .map(|path| {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                let name = format!("extract/{}", file_name);
+                steps.insert(name.clone());
+
+                let (encoding, extracted) = (&encoding, &extracted);
+                Task::new(format!("extract {}", file_name), move || {
+                    let key = state.key(&name, std::slice::from_ref(path), &[encoding])?;
+                    let paths = match state.completed(&name, &key) {
+                        Some(step) => {
+                            tracing::info!("Already extracted: {:?}", path);
+                            step.outputs.into_keys().collect()
+                        }
+                        None => {
+                            let paths =
+                                self.extract_zip(&path.to_string_lossy(), &self.config.data_dir)?;
+                            state.complete(&name, key, &paths)?;
+                            paths
+                        }
+                    };
+                    extracted.lock().unwrap().extend(paths);
+                    Ok(())
+                })
+            })
+            .collect();
+        jobs.run(tasks)?;
+
+        Ok(extracted.into_inner().unwrap())
+    }
 
-        Ok(())
+    /// Find the input files of one type, decoded to UTF-8
+    fn find(&self, kind: InputKind, extracted: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let paths = discover(Path::new(&self.config.data_dir), kind, self.config.shards)?;
+        if paths.is_empty() {
+            tracing::warn!("No {} files found in {}", kind, self.config.data_dir);
+        } else {
+            tracing::info!("Found {} {} file(s)", paths.len(), kind);
+        }
+        self.decode(paths, extracted)
     }
 
-    fn process_socios_file(&self, csv_path: &Path) -> Result<()> {
-        let df = CsvReadOptions::default()
-            .with_has_header(false)
-            .with_parse_options(CsvParseOptions::default().with_separator(b';'))
-            .try_into_reader_with_file_path(Some(csv_path.into()))?
-            .finish()?;
+    /// Decode input files that are not UTF-8 into the staging directory
+    ///
+    /// Returns the path to read for each input: the original file when it is
+    /// already UTF-8 (or was transcoded on extraction), the decoded copy otherwise.
+    fn decode(&self, paths: Vec<PathBuf>, extracted: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let dir = Path::new(&self.config.output_dir)
+            .join(STAGING_DIR)
+            .join(DECODED_DIR);
+
+        let mut decoded = Vec::with_capacity(paths.len());
+        for path in paths {
+            if self.config.encoding == Encoding::Utf8
+                || extracted.contains(&path)
+                || (self.config.encoding == Encoding::Auto && sniff(&path)? != Encoding::Latin1)
+            {
+                decoded.push(path);
+                continue;
+            }
 
-        tracing::info!("Loaded {} rows from socios", df.height());
-        Ok(())
-    }
+            std::fs::create_dir_all(&dir)?;
+            let target = dir.join(path.file_name().unwrap_or_default());
+            tracing::info!("Decoding {:?} from Latin-1", path);
+            Encoding::Latin1
+                .transcode(File::open(&path)?, BufWriter::new(File::create(&target)?))?;
+            decoded.push(target);
+        }
 
-    fn process_simples(&self) -> Result<()> {
-        tracing::info!("Processing Simples file...");
+        Ok(decoded)
+    }
 
-        let pattern = format!("{}/*SIMPLES.csv", self.config.data_dir);
-        if let Some(csv_path) = self.find_csv_by_pattern(&pattern)? {
-            tracing::info!("Processing: {:?}", csv_path);
+    /// Join the pending establishment shards one spill partition at a time
+    ///
+    /// Each shard appends to its own part, so the output does not depend on
+    /// which worker handles which shard.
+    fn join_partitions(
+        &self,
+        jobs: &Jobs,
+        writer: &OutputWriter,
+        spill: &Spill,
+        companies: &[Pending],
+        build: &BuildSide,
+        state: &StateFile,
+    ) -> Result<()> {
+        tracing::info!("Processing Estabelecimentos files...");
 
-            let df = CsvReadOptions::default()
-                .with_has_header(false)
-                .with_parse_options(CsvParseOptions::default().with_separator(b';'))
-                .try_into_reader_with_file_path(Some(csv_path.as_path().into()))?
-                .finish()?;
+        let mut parts = Vec::with_capacity(companies.len());
+        for step in companies {
+            let schema = self
+                .companies(spill, step.path, build, 0)?
+                .collect_schema()?;
+            parts.push(writer.open_part("companies", step.index, &schema)?);
+        }
 
-            tracing::info!("Loaded {} rows from simples", df.height());
+        for partition in 0..spill.partitions() {
+            tracing::info!("Joining partition {}/{}", partition + 1, spill.partitions());
+            let qsa = match &build.socios {
+                Some(socios) => self.load_qsa(socios.partition(partition)?)?,
+                None => Qsa::new(),
+            };
+
+            let qsa = &qsa;
+            let tasks = companies
+                .iter()
+                .zip(parts.iter_mut())
+                .map(|(step, part)| {
+                    let label = format!("companies {:?} ({})", step.path, partition + 1);
+                    Task::new(label, move || {
+                        let companies = self
+                            .companies(spill, step.path, build, partition)?
+                            .collect_with_engine(Engine::Streaming)?;
+
+                        match self.config.format {
+                            OutputFormat::Parquet => part.write_frame(&companies),
+                            OutputFormat::Jsonl => {
+                                let documents = records::companies(&companies, &self.lookups, qsa)?;
+                                part.write_documents(&documents)
+                            }
+                        }
+                    })
+                })
+                .collect();
+            jobs.run(tasks)?;
         }
 
+        for (step, part) in companies.iter().zip(parts) {
+            let rows = part.rows();
+            let path = part.finish()?;
+            let written = self.written(&path, rows);
+            state.complete_with(&step.name, step.key.clone(), &[path], written)?;
+        }
         Ok(())
     }
 
-    fn find_csv_by_pattern(&self, pattern: &str) -> Result<Option<PathBuf>> {
-        let entries = read_dir(&self.config.data_dir)?;
+    /// Companies of one establishment shard within one join partition,
+    /// selected by the filters, without the suppressed ones and protected in
+    /// privacy mode
+    fn companies(
+        &self,
+        spill: &Spill,
+        csv_path: &Path,
+        build: &BuildSide,
+        partition: usize,
+    ) -> Result<LazyFrame> {
+        let estabelecimentos = scan_csv(&[csv_path.to_path_buf()], &ESTABELECIMENTOS_COLUMNS)?;
+        let estabelecimentos = self.config.filters.estabelecimentos(estabelecimentos);
+
+        let companies = frames::companies(
+            spill.filter(estabelecimentos, partition),
+            build.empresas.partition(partition)?,
+            build.simples.partition(partition)?,
+            &self.lookups,
+        )?;
+        let companies = self.config.filters.companies(companies);
+        let companies = self.config.suppression.companies(companies);
+        Ok(match self.config.privacy()? {
+            Some(privacy) => privacy.companies(companies),
+            None => companies,
+        })
+    }
 
-        for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
+    /// Typed partners of a raw Socios frame, of the companies in `basicos`
+    /// when filtering, without the suppressed ones and protected in privacy mode
+    fn partners(&self, socios: LazyFrame, basicos: Option<&DataFrame>) -> Result<LazyFrame> {
+        let socios = match basicos {
+            Some(basicos) => filter::selected(socios, basicos),
+            None => socios,
+        };
+        let partners = frames::partners(socios, &self.lookups)?;
+        let partners = self.config.suppression.partners(partners);
+        Ok(match self.config.privacy()? {
+            Some(privacy) => privacy.partners(partners),
+            None => partners,
+        })
+    }
 
-            if path.extension().and_then(|s| s.to_str()) == Some("csv") {
-                let filename = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+    /// Write the ownership graph of the partners of the companies in `basicos`
+    /// (all when not filtering) as Parquet, GraphML and Neo4j import files
+    fn write_graph(
+        &self,
+        writer: &OutputWriter,
+        shards: &Shards,
+        basicos: Option<&DataFrame>,
+    ) -> Result<(Vec<PathBuf>, QualityReport)> {
+        let socios = scan_csv(&shards.socios, &SOCIOS_COLUMNS)?;
+        let socios = match basicos {
+            Some(basicos) => filter::selected(socios, basicos),
+            None => socios,
+        };
+        let partners = frames::partners(socios, &self.lookups)?;
+        let partners = self.config.suppression.partners(partners);
+        let key = graph::person_key(self.config.pseudonym_key.as_ref());
+        let mut edges = graph::edges(partners, &key).collect_with_engine(Engine::Streaming)?;
+
+        let empresas = scan_csv(&shards.empresas, &EMPRESAS_COLUMNS)?;
+        let mut nodes = graph::nodes(edges.clone().lazy(), empresas, self.config.privacy_mode)?
+            .collect_with_engine(Engine::Streaming)?;
+
+        let mut paths = vec![
+            writer.write(GRAPH_DIR, "edges", &mut edges)?,
+            writer.write(GRAPH_DIR, "nodes", &mut nodes)?,
+        ];
+        let mut written = QualityReport::default();
+        for path in &paths {
+            written.merge(self.written(path, parquet_rows(path)?));
+        }
 
-                // Simple pattern matching - check if filename contains key parts
-                let pattern_upper = pattern.to_uppercase();
-                let filename_upper = filename.to_uppercase();
-                for token in ["ESTABELE", "EMPRE", "SOCIO", "SIMPLES"] {
-                    if pattern_upper.contains(token) && filename_upper.contains(token) {
-                        return Ok(Some(path));
-                    }
-                }
-            }
+        let dir = Path::new(&self.config.output_dir).join(GRAPH_DIR);
+        paths.push(graph::write_graphml(&dir, &nodes, &edges)?);
+        paths.extend(graph::write_neo4j(&dir, &nodes, &edges)?);
+        Ok((paths, written))
+    }
+
+    /// Group the partners of one join partition by `cnpj_basico`
+    fn load_qsa(&self, partners: LazyFrame) -> Result<Qsa> {
+        let partners = partners.collect_with_engine(Engine::Streaming)?;
+
+        let mut qsa = Qsa::new();
+        for partner in records::partners(&partners)? {
+            qsa.entry(partner.cnpj_basico.clone())
+                .or_default()
+                .push(partner);
         }
 
-        Ok(None)
+        tracing::info!("Loaded partners for {} companies", qsa.len());
+        Ok(qsa)
     }
 
-    /// Extract a single ZIP file
-    pub fn extract_zip(&self, zip_path: &str, output_dir: &str) -> Result<()> {
-        use std::io::copy;
+    fn write_lookups(&self, writer: &OutputWriter) -> Result<Vec<PathBuf>> {
+        tracing::info!("Writing lookup tables...");
+
+        let mut paths = Vec::new();
+        for (name, table) in self.lookups.tables() {
+            let mut df = Lookups::frame(table, "codigo", "descricao")?;
+            paths.push(writer.write("lookups", name, &mut df)?);
+        }
+
+        Ok(paths)
+    }
 
+    /// Extract a single ZIP file, transcoding its entries to UTF-8
+    ///
+    /// Returns the paths of the extracted files.
+    pub fn extract_zip(&self, zip_path: &str, output_dir: &str) -> Result<Vec<PathBuf>> {
         let file = File::open(zip_path)?;
         let mut archive = ZipArchive::new(file)?;
+        let mut extracted = Vec::new();
 
         for i in 0..archive.len() {
             let mut file = archive.by_index(i)?;
@@ -210,15 +934,22 @@ impl Transformer {
                 {
                     std::fs::create_dir_all(p)?;
                 }
-                let mut outfile = File::create(&outpath)?;
-                copy(&mut file, &mut outfile)?;
+                let outfile = BufWriter::new(File::create(&outpath)?);
+                let encoding = self.config.encoding.transcode(&mut file, outfile)?;
+                tracing::debug!("Extracted {} ({})", file.name(), encoding);
+                extracted.push(PathBuf::from(outpath));
             }
         }
 
-        Ok(())
+        Ok(extracted)
     }
 }
 
+/// Detect the encoding of a file from its first non-ASCII chunk
+fn sniff(path: &Path) -> Result<Encoding> {
+    Ok(Encoding::sniff(File::open(path)?)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
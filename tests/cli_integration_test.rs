@@ -54,6 +54,33 @@ fn test_transform_command_help() {
         .stdout(predicate::str::contains("--privacy"));
 }
 
+#[test]
+fn test_transform_invalid_filter() {
+    let mut cmd = cli_command();
+    cmd.arg("transform").arg("--situacao").arg("ABERTA");
+
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "unknown situacao_cadastral code ABERTA",
+    ));
+}
+
+#[test]
+fn test_diff_missing_release() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut cmd = cli_command();
+    cmd.arg("diff")
+        .arg("--from")
+        .arg("2025-09")
+        .arg("--to")
+        .arg("2025-10")
+        .arg("--root")
+        .arg(temp_dir.path());
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a transform output"));
+}
+
 #[test]
 fn test_db_command_help() {
     let mut cmd = cli_command();
@@ -111,6 +138,63 @@ fn test_check_empty_directory() {
         .stdout(predicate::str::contains("Checked 0 files"));
 }
 
+#[test]
+fn test_mock_server_missing_directory() {
+    let mut cmd = cli_command();
+    cmd.arg("mock-server")
+        .arg("--directory")
+        .arg("/nonexistent/path")
+        .arg("--port")
+        .arg("0");
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a directory"));
+}
+
+#[test]
+fn test_download_invalid_base_url() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut cmd = cli_command();
+    cmd.arg("download")
+        .arg("--directory")
+        .arg(temp_dir.path())
+        .arg("--base-url")
+        .arg("ftp://example.com/");
+
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid URL: ftp://example.com/"));
+}
+
+#[test]
+fn test_fixtures_generate() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let mut cmd = cli_command();
+    cmd.arg("fixtures")
+        .arg("generate")
+        .arg("--directory")
+        .arg(temp_dir.path())
+        .arg("--companies")
+        .arg("10")
+        .arg("--shards")
+        .arg("2");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "K3241.K03200Y1.D51011.EMPRECSV: 5 rows",
+        ))
+        .stdout(predicate::str::contains("malformed lines: 0"));
+
+    // Every archive opens, as a downloaded release would
+    let mut cmd = cli_command();
+    cmd.arg("check").arg("--directory").arg(temp_dir.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Checked 13 files, 0 errors"));
+}
+
 #[test]
 fn test_db_create_without_url() {
     let mut cmd = cli_command();
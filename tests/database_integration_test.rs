@@ -63,3 +63,62 @@ fn test_postgres_create_and_drop_cycle() {
     let create_again = db.create();
     assert!(create_again.is_ok());
 }
+
+#[test]
+#[ignore]
+fn test_postgres_suppress() {
+    let db = PostgresDatabase::from_env().unwrap();
+    db.create().unwrap();
+
+    let list =
+        rfb_rs::transform::SuppressionList::parse("12345678\n123.456.789-01\nname:MARIA").unwrap();
+    let suppressed = db.suppress(&list).unwrap();
+    assert!(suppressed.contains_key("companies"));
+    assert!(suppressed.contains_key("partners"));
+}
+
+#[test]
+#[ignore]
+fn test_postgres_aggregate_company_groups() {
+    let db = PostgresDatabase::from_env().unwrap();
+    db.drop().unwrap();
+    db.create().unwrap();
+
+    let url = std::env::var("DATABASE_URL").unwrap();
+    let mut client = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+    client
+        .batch_execute(
+            "INSERT INTO companies (cnpj, identificador_matriz_filial, situacao_cadastral, uf, regiao, data_inicio_atividade, razao_social)
+            VALUES
+                ('11222333000181', 1, 2, 'SP', 'Sudeste', '2010-01-01', 'EXEMPLO LTDA'),
+                ('11222333000262', 2, 2, 'BA', 'Nordeste', '2005-06-01', 'EXEMPLO LTDA'),
+                ('11222333000343', 2, 8, 'PE', 'Nordeste', '2020-01-01', 'EXEMPLO LTDA')",
+        )
+        .unwrap();
+
+    let rows = db.aggregate().unwrap();
+    assert_eq!(rows["company_groups"], 1);
+    assert_eq!(rows["company_group_branches"], 2);
+
+    let group = client
+        .query_one(
+            "SELECT cnpj_matriz, filiais, cnpjs_filiais FROM company_groups WHERE cnpj_basico = '11222333'",
+            &[],
+        )
+        .unwrap();
+    assert_eq!(group.get::<_, String>(0), "11222333000181");
+    assert_eq!(group.get::<_, i32>(1), 2);
+    assert_eq!(
+        group.get::<_, Vec<String>>(2),
+        ["11222333000262", "11222333000343"]
+    );
+
+    let northeast = client
+        .query_one(
+            "SELECT sum(filiais)::INTEGER FROM company_group_branches
+            WHERE cnpj_basico = '11222333' AND regiao = 'Nordeste'",
+            &[],
+        )
+        .unwrap();
+    assert_eq!(northeast.get::<_, i32>(0), 2);
+}
@@ -1,5 +1,10 @@
 /// Integration tests for download module
-use rfb_rs::download::{DownloadConfig, Downloader, FederalRevenue, check_zip_integrity};
+use rfb_rs::download::{
+    DownloadConfig, Downloader, Faults, FederalRevenue, MockServer, MockServerConfig,
+    check_zip_integrity,
+};
+use rfb_rs::fixtures::{FixtureConfig, Generator};
+use std::path::Path;
 use tempfile::TempDir;
 
 #[tokio::test]
@@ -13,6 +18,7 @@ async fn test_downloader_initialization() {
         chunk_size: 1_048_576,
         skip_existing: false,
         restart: false,
+        base_url: None,
     };
 
     let downloader = Downloader::new(config);
@@ -82,3 +88,206 @@ fn test_zip_integrity_invalid_file() {
     let result = check_zip_integrity(&invalid_zip);
     assert!(result.is_err());
 }
+
+fn mock_server(root: &Path, faults: Faults) -> MockServer {
+    MockServer::start(MockServerConfig {
+        root: root.to_str().unwrap().to_string(),
+        faults,
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+/// Download config of every file of `server` but `file`, left out by
+/// `skip_existing`, with small chunks
+fn download_one(data_dir: &Path, server: &MockServer, file: &str) -> DownloadConfig {
+    for url in FederalRevenue::file_urls() {
+        let name = FederalRevenue::filename_from_url(&url).unwrap();
+        if name != file {
+            std::fs::write(data_dir.join(name), b"").unwrap();
+        }
+    }
+    DownloadConfig {
+        data_dir: data_dir.to_str().unwrap().to_string(),
+        chunk_size: 1024,
+        skip_existing: true,
+        base_url: Some(server.url().to_string()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_mock_server_listing_and_ranges() {
+    use reqwest::{StatusCode, header};
+
+    let root = TempDir::new().unwrap();
+    std::fs::create_dir(root.path().join("2025-10")).unwrap();
+    std::fs::write(root.path().join("2025-10/Cnaes.zip"), b"0123456789").unwrap();
+    let server = mock_server(root.path(), Faults::default());
+    let client = reqwest::Client::new();
+
+    let listing = client.get(server.url()).send().await.unwrap();
+    assert!(listing.text().await.unwrap().contains("href=\"2025-10/\""));
+    // Directories are redirected to their trailing slash
+    let listing = client
+        .get(format!("{}2025-10", server.url()))
+        .send()
+        .await
+        .unwrap();
+    assert!(listing.text().await.unwrap().contains("Cnaes.zip"));
+
+    let url = format!("{}2025-10/Cnaes.zip", server.url());
+    let head = client.head(&url).send().await.unwrap();
+    assert_eq!(head.headers()[header::CONTENT_LENGTH], "10");
+    assert_eq!(head.headers()[header::ACCEPT_RANGES], "bytes");
+    let etag = head.headers()[header::ETAG].clone();
+
+    let range = client
+        .get(&url)
+        .header(header::RANGE, "bytes=2-5")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(range.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(range.headers()[header::CONTENT_RANGE], "bytes 2-5/10");
+    assert_eq!(range.bytes().await.unwrap().as_ref(), b"2345");
+
+    let cached = client
+        .get(&url)
+        .header(header::IF_NONE_MATCH, etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(cached.status(), StatusCode::NOT_MODIFIED);
+
+    // A range of another version of the file gets the whole file
+    let changed = client
+        .get(&url)
+        .header(header::RANGE, "bytes=2-5")
+        .header(header::IF_RANGE, "\"other\"")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(changed.status(), StatusCode::OK);
+    assert_eq!(changed.bytes().await.unwrap().len(), 10);
+
+    let outside = client
+        .get(&url)
+        .header(header::RANGE, "bytes=10-")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(outside.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+    let escape = client
+        .get(format!("{}..%2fCnaes.zip", server.url()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(escape.status(), StatusCode::NOT_FOUND);
+    server.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_download_from_mock_server_resumes_truncated_bodies() {
+    let root = TempDir::new().unwrap();
+    let data_dir = TempDir::new().unwrap();
+    let fixtures = FixtureConfig {
+        output_dir: root.path().to_str().unwrap().to_string(),
+        companies: 30,
+        ..Default::default()
+    };
+    Generator::new(fixtures).generate().unwrap();
+    let faults = Faults {
+        truncate_rate: 1.0,
+        max_per_file: Some(2),
+        ..Default::default()
+    };
+    let server = mock_server(root.path(), faults);
+
+    let config = DownloadConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        chunk_size: 4096,
+        base_url: Some(server.url().to_string()),
+        ..Default::default()
+    };
+    Downloader::new(config).download().await.unwrap();
+
+    assert_eq!(server.stats().truncations, 2 * 37);
+    for url in FederalRevenue::file_urls() {
+        let name = FederalRevenue::filename_from_url(&url).unwrap();
+        let downloaded = std::fs::read(data_dir.path().join(&name)).unwrap();
+        assert_eq!(downloaded, std::fs::read(root.path().join(&name)).unwrap());
+        check_zip_integrity(&data_dir.path().join(&name)).unwrap();
+    }
+    server.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_download_retries_mock_server_faults() {
+    let root = TempDir::new().unwrap();
+    let payload: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(root.path().join("Cnaes.zip"), &payload).unwrap();
+
+    for faults in [
+        Faults {
+            error_rate: 1.0,
+            max_per_file: Some(1),
+            ..Default::default()
+        },
+        Faults {
+            drop_rate: 1.0,
+            max_per_file: Some(1),
+            ..Default::default()
+        },
+    ] {
+        let data_dir = TempDir::new().unwrap();
+        let server = mock_server(root.path(), faults);
+        let config = download_one(data_dir.path(), &server, "Cnaes.zip");
+        Downloader::new(config).download().await.unwrap();
+
+        let stats = server.stats();
+        assert_eq!(stats.errors + stats.drops, 1);
+        assert_eq!(
+            std::fs::read(data_dir.path().join("Cnaes.zip")).unwrap(),
+            payload
+        );
+        server.stop().await.unwrap();
+    }
+
+    // Without a limit on the faults, the retries run out
+    let data_dir = TempDir::new().unwrap();
+    let faults = Faults {
+        error_rate: 1.0,
+        ..Default::default()
+    };
+    let server = mock_server(root.path(), faults);
+    let config = DownloadConfig {
+        max_retries: 1,
+        ..download_one(data_dir.path(), &server, "Cnaes.zip")
+    };
+    assert!(Downloader::new(config).download().await.is_err());
+    server.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_server_limits_bandwidth() {
+    let root = TempDir::new().unwrap();
+    std::fs::write(root.path().join("Motivos.zip"), vec![0u8; 2000]).unwrap();
+    let faults = Faults {
+        bandwidth: Some(4000),
+        ..Default::default()
+    };
+    let server = mock_server(root.path(), faults);
+
+    let started = std::time::Instant::now();
+    let body = reqwest::get(format!("{}Motivos.zip", server.url()))
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    assert_eq!(body.len(), 2000);
+    assert!(started.elapsed() >= std::time::Duration::from_millis(450));
+    server.stop().await.unwrap();
+}
@@ -0,0 +1,51 @@
+/// Integration tests for the synthetic dataset generator
+use rfb_rs::fixtures::{DirtyRates, FixtureConfig, Generator};
+use rfb_rs::transform::{QualityReport, TransformConfig, Transformer};
+use std::fs;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_transform_reports_the_generated_dirty_data() {
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    let config = FixtureConfig {
+        output_dir: data_dir.path().to_str().unwrap().to_string(),
+        companies: 300,
+        shards: 3,
+        dirty: DirtyRates::uniform(0.05),
+        ..Default::default()
+    };
+    let fixture = Generator::new(config).generate().unwrap();
+    assert!(fixture.malformed_lines > 0);
+    assert!(fixture.invalid_cnpjs > 0);
+    assert!(fixture.orphans["socios"] > 0);
+
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        ..Default::default()
+    };
+    let mut transformer = Transformer::new(config);
+    transformer.load_lookups().unwrap();
+    transformer.transform().await.unwrap();
+
+    let written = fs::read_to_string(output_dir.path().join("quality_report.json")).unwrap();
+    let quality: QualityReport = serde_json::from_str(&written).unwrap();
+    for (file, input) in &quality.inputs {
+        assert_eq!(input.rows_read, fixture.rows[file], "{}", file);
+    }
+    assert_eq!(quality.inputs.len(), 3 * 3 + 1);
+    assert_eq!(quality.malformed_lines(), fixture.malformed_lines);
+    assert_eq!(quality.invalid_cnpjs, fixture.invalid_cnpjs);
+    for (column, count) in &fixture.invalid_dates {
+        assert_eq!(quality.invalid_dates[column], *count, "{}", column);
+    }
+    assert_eq!(
+        quality.unknown_codes["municipio"],
+        fixture.unknown_codes["municipio"]
+    );
+    for lookup in ["cnae", "pais", "motivo", "situacao_especial"] {
+        assert_eq!(quality.unknown_codes[lookup], 0, "{}", lookup);
+    }
+    assert_eq!(quality.orphans, fixture.orphans);
+}
@@ -1,5 +1,7 @@
 /// Integration tests for transform module
-use rfb_rs::transform::{CNAE, Company, Lookups, Partner, TaxRegime, TransformConfig, Transformer};
+use rfb_rs::transform::{
+    CNAE, Cnpj, Company, Lookups, Partner, TaxRegime, TransformConfig, TransformError, Transformer,
+};
 use std::fs;
 use tempfile::TempDir;
 
@@ -9,6 +11,7 @@ fn test_transformer_initialization() {
         data_dir: "test_data".to_string(),
         output_dir: "test_output".to_string(),
         privacy_mode: false,
+        ..Default::default()
     };
 
     let transformer = Transformer::new(config);
@@ -22,6 +25,7 @@ fn test_transformer_with_privacy_mode() {
         data_dir: "data".to_string(),
         output_dir: "output".to_string(),
         privacy_mode: true,
+        ..Default::default()
     };
 
     let transformer = Transformer::new(config);
@@ -126,9 +130,9 @@ fn test_cnae_creation() {
 
 #[test]
 fn test_tax_regime_creation() {
-    let regime = TaxRegime::new("12345678000195".to_string());
+    let regime = TaxRegime::new("12345678000195".parse().unwrap());
 
-    assert_eq!(regime.cnpj, "12345678000195");
+    assert_eq!(regime.cnpj.as_str(), "12345678000195");
     assert_eq!(regime.opcao_simples, None);
     assert_eq!(regime.opcao_mei, None);
 }
@@ -140,6 +144,7 @@ async fn test_zip_extraction() {
         data_dir: temp_dir.path().to_str().unwrap().to_string(),
         output_dir: temp_dir.path().to_str().unwrap().to_string(),
         privacy_mode: false,
+        ..Default::default()
     };
 
     let transformer = Transformer::new(config);
@@ -163,3 +168,1597 @@ async fn test_zip_extraction() {
     assert!(result.is_ok());
     assert!(temp_dir.path().join("test.txt").exists());
 }
+
+/// Write a small Federal Revenue-like dataset into `dir`
+fn write_sample_data(dir: &std::path::Path) {
+    fs::write(
+        dir.join("ESTABELE0.csv"),
+        "\"33683111\";\"0002\";\"80\";\"2\";\"REGIONAL BRASILIA-DF\";\"02\";\"20040522\";\"00\";\"\";\"\";\"19670630\";\"6204000\";\"6209100,7490104\";\"QUADRA\";\"SGAN 601\";\"S/N\";\"\";\"ASA NORTE\";\"70830010\";\"DF\";\"9701\";\"61\";\"32023000\";\"\";\"\";\"\";\"\";\"\";\"\";\"\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("EMPRECSV0.csv"),
+        "\"33683111\";\"SERVICO FEDERAL DE PROCESSAMENTO DE DADOS (SERPRO)\";\"2011\";\"16\";\"1061004800,00\";\"05\";\"\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("SOCIOCSV0.csv"),
+        "\"33683111\";\"2\";\"ANTONIO DE SOUSA\";\"***123456**\";\"10\";\"20190101\";\"\";\"***000000**\";\"\";\"00\";\"6\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("SIMPLES.csv"),
+        "\"33683111\";\"N\";\"00000000\";\"00000000\";\"N\";\"00000000\";\"00000000\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("MUNICCSV.csv"), "\"9701\";\"BRASILIA\"\n").unwrap();
+    fs::write(dir.join("QUALSCSV.csv"), "\"10\";\"Diretor\"\n").unwrap();
+}
+
+#[tokio::test]
+async fn test_transform_writes_typed_parquet() {
+    use polars::prelude::*;
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        ..Default::default()
+    };
+    let mut transformer = Transformer::new(config);
+    transformer.load_lookups().unwrap();
+    transformer.transform().await.unwrap();
+
+    let companies_path = output_dir.path().join("companies/part-00000.parquet");
+    let companies = ParquetReader::new(fs::File::open(companies_path).unwrap())
+        .finish()
+        .unwrap();
+
+    assert_eq!(companies.height(), 1);
+    let cnpj = companies.column("cnpj").unwrap().str().unwrap();
+    assert_eq!(cnpj.get(0), Some("33683111000280"));
+    assert_eq!(
+        companies.column("data_inicio_atividade").unwrap().dtype(),
+        &DataType::Date
+    );
+    assert_eq!(
+        companies.column("capital_social").unwrap().dtype(),
+        &DataType::Decimal(Some(20), Some(2))
+    );
+    assert_eq!(
+        companies.column("opcao_pelo_simples").unwrap().dtype(),
+        &DataType::Boolean
+    );
+    let municipio = companies.column("municipio").unwrap().str().unwrap();
+    assert_eq!(municipio.get(0), Some("BRASILIA"));
+
+    let partners_path = output_dir.path().join("partners/part-00000.parquet");
+    let partners = ParquetReader::new(fs::File::open(partners_path).unwrap())
+        .finish()
+        .unwrap();
+    let qualificacao = partners
+        .column("qualificacao_socio")
+        .unwrap()
+        .str()
+        .unwrap();
+    assert_eq!(qualificacao.get(0), Some("Diretor"));
+
+    assert!(
+        output_dir
+            .path()
+            .join("lookups/municipios.parquet")
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn test_transform_writes_jsonl_documents() {
+    use rfb_rs::transform::{Compression, OutputFormat};
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        format: OutputFormat::Jsonl,
+        compression: Compression::Uncompressed,
+        ..Default::default()
+    };
+    let mut transformer = Transformer::new(config);
+    transformer.load_lookups().unwrap();
+    transformer.transform().await.unwrap();
+
+    let content = fs::read_to_string(output_dir.path().join("companies/part-00000.jsonl")).unwrap();
+    let documents: Vec<Company> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(documents.len(), 1);
+    let company = &documents[0];
+    assert_eq!(company.cnpj.as_str(), "33683111000280");
+    assert_eq!(
+        company.data_inicio_atividade,
+        chrono::NaiveDate::from_ymd_opt(1967, 6, 30)
+    );
+    assert!(content.contains(r#""data_inicio_atividade":"1967-06-30""#));
+    assert!(content.contains(r#""capital_social":"1061004800.00""#));
+    assert_eq!(company.cnaes_secundarios.len(), 2);
+    assert_eq!(company.cnaes_secundarios[0].codigo, 6209100);
+    assert_eq!(company.qsa.len(), 1);
+    assert_eq!(company.qsa[0].nome_socio, "ANTONIO DE SOUSA");
+}
+
+#[tokio::test]
+async fn test_transform_jsonl_rejects_invalid_cnpjs() {
+    use rfb_rs::transform::{Compression, OutputFormat, Reject};
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    let path = data_dir.path().join("ESTABELE0.csv");
+    let content = fs::read_to_string(&path).unwrap()
+        + "\"33683111\";\"0001\";\"00\";\"1\";\"\";\"02\";\"20040522\";\"00\";\"\";\"\";\"19670630\";\"6204000\";\"\";\"QUADRA\";\"SGAN 601\";\"S/N\";\"\";\"ASA NORTE\";\"70830010\";\"DF\";\"9701\";\"\";\"\";\"\";\"\";\"\";\"\";\"\";\"\";\"\"\n";
+    fs::write(&path, content).unwrap();
+
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        format: OutputFormat::Jsonl,
+        compression: Compression::Uncompressed,
+        ..Default::default()
+    };
+    let mut transformer = Transformer::new(config);
+    transformer.load_lookups().unwrap();
+    transformer.transform().await.unwrap();
+
+    let content = fs::read_to_string(output_dir.path().join("companies/part-00000.jsonl")).unwrap();
+    let documents: Vec<Company> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(documents.len(), 1);
+    assert_eq!(documents[0].cnpj.as_str(), "33683111000280");
+
+    let rejects =
+        fs::read_to_string(output_dir.path().join("rejects/ESTABELE0.csv.ndjson")).unwrap();
+    let rejects: Vec<Reject> = rejects
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rejects.len(), 1);
+    assert_eq!(rejects[0].line, 2);
+    assert_eq!(
+        rejects[0].reason,
+        "invalid CNPJ check digits: 33683111000100"
+    );
+}
+
+#[test]
+fn test_jsonl_rejects_parquet_only_compression() {
+    use rfb_rs::transform::{Compression, OutputFormat};
+
+    let config = TransformConfig {
+        format: OutputFormat::Jsonl,
+        compression: Compression::Snappy,
+        ..Default::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+/// Write `count` companies, each with one establishment and one partner
+fn write_many_companies(dir: &std::path::Path, count: usize) {
+    let mut estabelecimentos = String::new();
+    let mut empresas = String::new();
+    let mut socios = String::new();
+    for i in 0..count {
+        let basico = format!("{:08}", i);
+        let dv = Cnpj::matriz(&basico).unwrap().dv().to_string();
+        estabelecimentos.push_str(&format!(
+            "\"{basico}\";\"0001\";\"{dv}\";\"1\";\"\";\"02\";\"20200101\";\"00\";\"\";\"\";\"20200101\";\"6204000\";\"\";\"RUA\";\"A\";\"1\";\"\";\"CENTRO\";\"70000000\";\"DF\";\"9701\";\"\";\"\";\"\";\"\";\"\";\"\";\"\";\"\";\"\"\n"
+        ));
+        empresas.push_str(&format!(
+            "\"{basico}\";\"EMPRESA {i}\";\"2062\";\"49\";\"{i},00\";\"01\";\"\"\n"
+        ));
+        socios.push_str(&format!(
+            "\"{basico}\";\"2\";\"SOCIO {i}\";\"***123456**\";\"49\";\"20200101\";\"\";\"***000000**\";\"\";\"00\";\"4\"\n"
+        ));
+    }
+    fs::write(dir.join("ESTABELE0.csv"), estabelecimentos).unwrap();
+    fs::write(dir.join("EMPRECSV0.csv"), empresas).unwrap();
+    fs::write(dir.join("SOCIOCSV0.csv"), socios).unwrap();
+}
+
+#[tokio::test]
+async fn test_memory_limit_spills_join_partitions() {
+    use rfb_rs::transform::{Compression, MemoryLimit, OutputFormat};
+
+    let data_dir = TempDir::new().unwrap();
+    write_many_companies(data_dir.path(), 200);
+
+    for format in [OutputFormat::Parquet, OutputFormat::Jsonl] {
+        let output_dir = TempDir::new().unwrap();
+        let config = TransformConfig {
+            data_dir: data_dir.path().to_str().unwrap().to_string(),
+            output_dir: output_dir.path().to_str().unwrap().to_string(),
+            format,
+            compression: Compression::Uncompressed,
+            memory_limit: Some(MemoryLimit::new(64 << 10)),
+            ..Default::default()
+        };
+        let mut transformer = Transformer::new(config);
+        transformer.load_lookups().unwrap();
+        transformer.transform().await.unwrap();
+
+        let part = output_dir
+            .path()
+            .join(format!("companies/part-00000.{}", format.extension()));
+        let mut companies: Vec<(String, Option<String>, usize)> = match format {
+            OutputFormat::Parquet => {
+                use polars::prelude::*;
+
+                let df = ParquetReader::new(fs::File::open(part).unwrap())
+                    .finish()
+                    .unwrap();
+                let cnpj = df.column("cnpj").unwrap().str().unwrap();
+                let razao_social = df.column("razao_social").unwrap().str().unwrap();
+                (0..df.height())
+                    .map(|i| {
+                        let razao_social = razao_social.get(i).map(str::to_string);
+                        (cnpj.get(i).unwrap().to_string(), razao_social, 1)
+                    })
+                    .collect()
+            }
+            OutputFormat::Jsonl => fs::read_to_string(part)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<Company>(line).unwrap())
+                .map(|company| {
+                    let cnpj = company.cnpj.to_string();
+                    (cnpj, Some(company.razao_social), company.qsa.len())
+                })
+                .collect(),
+        };
+        companies.sort();
+
+        assert_eq!(companies.len(), 200);
+        assert_eq!(
+            companies[42],
+            (
+                Cnpj::matriz("00000042").unwrap().to_string(),
+                Some("EMPRESA 42".to_string()),
+                1
+            )
+        );
+        assert!(
+            companies
+                .iter()
+                .all(|(_, razao_social, qsa)| { razao_social.is_some() && *qsa == 1 })
+        );
+        assert!(!output_dir.path().join(".staging").exists());
+    }
+}
+
+/// Latin-1 encoded sample files, as published by the Federal Revenue
+const LATIN1_FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/latin1");
+
+#[test]
+fn test_lookups_decode_latin1() {
+    let raw = fs::read(format!("{}/CNAECSV.csv", LATIN1_FIXTURES)).unwrap();
+    assert!(std::str::from_utf8(&raw).is_err());
+
+    let mut lookups = Lookups::new();
+    lookups.load_from_directory(LATIN1_FIXTURES).unwrap();
+
+    assert_eq!(
+        lookups.get_cnae(6204000),
+        Some(&"Consultoria em tecnologia da informação".to_string())
+    );
+    assert!(lookups.get_cnae(4711301).unwrap().starts_with("Comércio"));
+}
+
+#[tokio::test]
+async fn test_transform_decodes_latin1_inputs() {
+    use polars::prelude::*;
+
+    let fixture = fs::read(format!("{}/ESTABELE0.csv", LATIN1_FIXTURES)).unwrap();
+
+    for zipped in [false, true] {
+        let data_dir = TempDir::new().unwrap();
+        let output_dir = TempDir::new().unwrap();
+        fs::copy(
+            format!("{}/CNAECSV.csv", LATIN1_FIXTURES),
+            data_dir.path().join("CNAECSV.csv"),
+        )
+        .unwrap();
+
+        if zipped {
+            let file = fs::File::create(data_dir.path().join("Estabelecimentos0.zip")).unwrap();
+            let mut archive = ::zip::ZipWriter::new(file);
+            archive
+                .start_file::<&str, ()>("ESTABELE0.csv", ::zip::write::FileOptions::default())
+                .unwrap();
+            std::io::Write::write_all(&mut archive, &fixture).unwrap();
+            archive.finish().unwrap();
+        } else {
+            fs::write(data_dir.path().join("ESTABELE0.csv"), &fixture).unwrap();
+        }
+
+        let config = TransformConfig {
+            data_dir: data_dir.path().to_str().unwrap().to_string(),
+            output_dir: output_dir.path().to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let mut transformer = Transformer::new(config);
+        transformer.load_lookups().unwrap();
+        transformer.transform().await.unwrap();
+
+        let path = output_dir.path().join("companies/part-00000.parquet");
+        let companies = ParquetReader::new(fs::File::open(path).unwrap())
+            .finish()
+            .unwrap();
+        let column = |name: &str| {
+            let values = companies.column(name).unwrap().str().unwrap();
+            values.get(0).unwrap().to_string()
+        };
+
+        assert_eq!(column("nome_fantasia"), "PADARIA SÃO JOÃO");
+        assert_eq!(column("logradouro"), "CONCEIÇÃO");
+        assert!(column("cnae_fiscal_descricao").starts_with("Comércio varejista"));
+    }
+}
+
+#[tokio::test]
+async fn test_transform_reports_invalid_dates() {
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    fs::write(
+        data_dir.path().join("SIMPLES.csv"),
+        "\"33683111\";\"S\";\"20210231\";\"00000000\";\"N\";\"0\";\"\"\n",
+    )
+    .unwrap();
+
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        ..Default::default()
+    };
+    let mut transformer = Transformer::new(config);
+    transformer.load_lookups().unwrap();
+    let report = transformer.transform().await.unwrap();
+
+    assert_eq!(report.invalid_dates_total(), 1);
+    assert_eq!(report.invalid_dates["data_opcao_pelo_simples"], 1);
+    assert_eq!(report.invalid_dates["data_inicio_atividade"], 0);
+
+    let written = fs::read_to_string(output_dir.path().join("transform_report.json")).unwrap();
+    let written: rfb_rs::transform::TransformReport = serde_json::from_str(&written).unwrap();
+    assert_eq!(written, report);
+}
+
+#[tokio::test]
+async fn test_transform_processes_every_rfb_shard() {
+    use polars::prelude::*;
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_many_companies(data_dir.path(), 4);
+
+    // Split the establishments into two shards named as in the Federal Revenue ZIPs
+    let estabelecimentos = fs::read_to_string(data_dir.path().join("ESTABELE0.csv")).unwrap();
+    fs::remove_file(data_dir.path().join("ESTABELE0.csv")).unwrap();
+    let lines: Vec<&str> = estabelecimentos.lines().collect();
+    for (i, shard) in lines.chunks(2).enumerate() {
+        let name = format!("K3241.K03200Y{}.D51011.ESTABELE", i);
+        fs::write(data_dir.path().join(name), shard.join("\n")).unwrap();
+    }
+
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        ..Default::default()
+    };
+    let mut transformer = Transformer::new(config.clone());
+    transformer.load_lookups().unwrap();
+    transformer.transform().await.unwrap();
+
+    let mut cnpjs = Vec::new();
+    for part in 0..2 {
+        let path = output_dir
+            .path()
+            .join(format!("companies/part-{:05}.parquet", part));
+        let df = ParquetReader::new(fs::File::open(path).unwrap())
+            .finish()
+            .unwrap();
+        assert_eq!(df.height(), 2);
+        let column = df.column("cnpj").unwrap().str().unwrap();
+        cnpjs.extend(column.into_iter().map(|c| c.unwrap().to_string()));
+    }
+    cnpjs.sort();
+    let expected: Vec<String> = (0..4)
+        .map(|i| Cnpj::matriz(&format!("{:08}", i)).unwrap().to_string())
+        .collect();
+    assert_eq!(cnpjs, expected);
+
+    // A gap in the shard numbers is an error rather than a silently smaller output
+    fs::rename(
+        data_dir.path().join("K3241.K03200Y1.D51011.ESTABELE"),
+        data_dir.path().join("K3241.K03200Y2.D51011.ESTABELE"),
+    )
+    .unwrap();
+    let transformer = Transformer::new(config);
+    assert!(matches!(
+        transformer.transform().await,
+        Err(TransformError::MissingShard(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_transform_output_does_not_depend_on_jobs() {
+    use polars::prelude::*;
+    use rfb_rs::transform::{Compression, OutputFormat};
+
+    let data_dir = TempDir::new().unwrap();
+    write_many_companies(data_dir.path(), 30);
+    let estabelecimentos = fs::read_to_string(data_dir.path().join("ESTABELE0.csv")).unwrap();
+    fs::remove_file(data_dir.path().join("ESTABELE0.csv")).unwrap();
+    let lines: Vec<&str> = estabelecimentos.lines().collect();
+    for (i, shard) in lines.chunks(10).enumerate() {
+        fs::write(
+            data_dir.path().join(format!("ESTABELE{}.csv", i)),
+            shard.join("\n"),
+        )
+        .unwrap();
+    }
+
+    for format in [OutputFormat::Parquet, OutputFormat::Jsonl] {
+        let mut outputs = Vec::new();
+        for jobs in [1, 4] {
+            let output_dir = TempDir::new().unwrap();
+            let config = TransformConfig {
+                data_dir: data_dir.path().to_str().unwrap().to_string(),
+                output_dir: output_dir.path().to_str().unwrap().to_string(),
+                format,
+                compression: Compression::Uncompressed,
+                jobs: Some(jobs),
+                ..Default::default()
+            };
+            let mut transformer = Transformer::new(config);
+            transformer.load_lookups().unwrap();
+            transformer.transform().await.unwrap();
+
+            let mut parts = Vec::new();
+            for part in 0..3 {
+                let path = output_dir.path().join(format!(
+                    "companies/part-{:05}.{}",
+                    part,
+                    format.extension()
+                ));
+                let content = match format {
+                    OutputFormat::Parquet => ParquetReader::new(fs::File::open(path).unwrap())
+                        .finish()
+                        .unwrap(),
+                    OutputFormat::Jsonl => {
+                        let lines: Vec<String> = fs::read_to_string(path)
+                            .unwrap()
+                            .lines()
+                            .map(str::to_string)
+                            .collect();
+                        df!("line" => lines).unwrap()
+                    }
+                };
+                parts.push(content);
+            }
+            outputs.push(parts);
+        }
+        for (sequential, parallel) in outputs[0].iter().zip(&outputs[1]) {
+            assert_eq!(sequential.height(), 10);
+            assert!(sequential.equals_missing(parallel));
+        }
+    }
+
+    let config = TransformConfig {
+        jobs: Some(0),
+        ..Default::default()
+    };
+    assert!(config.validate().is_err());
+
+    let config = TransformConfig {
+        shards: Some(0),
+        ..Default::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[tokio::test]
+async fn test_transform_skips_unchanged_inputs() {
+    use rfb_rs::transform::TransformState;
+    use std::time::SystemTime;
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+
+    let run = |force: bool| {
+        let config = TransformConfig {
+            data_dir: data_dir.path().to_str().unwrap().to_string(),
+            output_dir: output_dir.path().to_str().unwrap().to_string(),
+            force,
+            ..Default::default()
+        };
+        async move {
+            let mut transformer = Transformer::new(config);
+            transformer.load_lookups().unwrap();
+            transformer.transform().await.unwrap()
+        }
+    };
+    let modified = |table: &str| -> SystemTime {
+        let path = output_dir.path().join(table);
+        fs::metadata(path).unwrap().modified().unwrap()
+    };
+    let companies = "companies/part-00000.parquet";
+    let partners = "partners/part-00000.parquet";
+
+    let report = run(false).await;
+    let state = fs::read_to_string(output_dir.path().join("transform_state.json")).unwrap();
+    let state: TransformState = serde_json::from_str(&state).unwrap();
+    assert!(state.steps.contains_key("companies/part-00000"));
+    assert!(state.steps.contains_key("partners/part-00000"));
+    let (first_companies, first_partners) = (modified(companies), modified(partners));
+
+    // Nothing changed: every step is skipped and the report is the same
+    assert_eq!(run(false).await, report);
+    assert_eq!(modified(companies), first_companies);
+    assert_eq!(modified(partners), first_partners);
+
+    // A new Simples file only rebuilds the companies it is joined into
+    fs::write(
+        data_dir.path().join("SIMPLES.csv"),
+        "\"33683111\";\"S\";\"20070701\";\"00000000\";\"N\";\"00000000\";\"00000000\"\n",
+    )
+    .unwrap();
+    run(false).await;
+    assert_ne!(modified(companies), first_companies);
+    assert_eq!(modified(partners), first_partners);
+
+    // A missing output is rebuilt, and --force rebuilds everything
+    let second_companies = modified(companies);
+    fs::remove_file(output_dir.path().join(companies)).unwrap();
+    run(false).await;
+    assert!(output_dir.path().join(companies).exists());
+    assert_eq!(modified(partners), first_partners);
+
+    run(true).await;
+    assert_ne!(modified(companies), second_companies);
+    assert_ne!(modified(partners), first_partners);
+}
+
+#[tokio::test]
+async fn test_transform_writes_quality_report() {
+    use rfb_rs::transform::{QualityReport, Threshold};
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    let append = |name: &str, line: &str| {
+        let path = data_dir.path().join(name);
+        let content = fs::read_to_string(&path).unwrap() + line;
+        fs::write(path, content).unwrap();
+    };
+    // Wrong check digits, an unknown municipality and situacao_especial
+    append(
+        "ESTABELE0.csv",
+        "\"33683111\";\"0001\";\"00\";\"1\";\"\";\"02\";\"20040522\";\"00\";\"\";\"\";\"19670630\";\"6204000\";\"\";\"QUADRA\";\"SGAN 601\";\"S/N\";\"\";\"ASA NORTE\";\"70830010\";\"DF\";\"1234\";\"\";\"\";\"\";\"\";\"\";\"\";\"\";\"EM OBRAS\";\"\"\n",
+    );
+    // A partner without establishment and a line missing fields
+    append(
+        "SOCIOCSV0.csv",
+        "\"99999999\";\"2\";\"MARIA\";\"***654321**\";\"10\";\"20190101\";\"\";\"***000000**\";\"\";\"00\";\"6\"\n",
+    );
+    append("EMPRECSV0.csv", "\"11111111\";\"SEM CAMPOS\"\n");
+
+    let run = |fail_on: Vec<Threshold>| {
+        let config = TransformConfig {
+            data_dir: data_dir.path().to_str().unwrap().to_string(),
+            output_dir: output_dir.path().to_str().unwrap().to_string(),
+            fail_on,
+            ..Default::default()
+        };
+        async move {
+            let mut transformer = Transformer::new(config);
+            transformer.load_lookups().unwrap();
+            transformer.transform().await
+        }
+    };
+    run(Vec::new()).await.unwrap();
+
+    let read = || {
+        let written = fs::read_to_string(output_dir.path().join("quality_report.json")).unwrap();
+        serde_json::from_str::<QualityReport>(&written).unwrap()
+    };
+    let quality = read();
+    assert_eq!(quality.inputs["ESTABELE0.csv"].rows_read, 2);
+    assert_eq!(quality.inputs["ESTABELE0.csv"].malformed_lines, 0);
+    assert_eq!(quality.inputs["EMPRECSV0.csv"].malformed_lines, 1);
+    assert_eq!(quality.outputs["companies/part-00000.parquet"], 2);
+    assert_eq!(quality.outputs["partners/part-00000.parquet"], 2);
+    assert_eq!(quality.invalid_cnpjs, 1);
+    assert_eq!(quality.unknown_codes["municipio"], 1);
+    assert_eq!(quality.unknown_codes["situacao_especial"], 1);
+    assert_eq!(quality.orphans["socios"], 1);
+    assert_eq!(quality.orphans["simples"], 0);
+    assert_eq!(
+        quality.columns["estabelecimentos"]["nome_fantasia"].null_rate,
+        0.5
+    );
+
+    // Thresholds are checked against the report, also when every step is skipped
+    let limits = ["invalid_cnpjs=0", "orphans.socios=5"].map(|t| t.parse().unwrap());
+    assert!(matches!(
+        run(limits.to_vec()).await,
+        Err(TransformError::QualityCheck(message)) if message == "invalid_cnpjs is 1, above 0"
+    ));
+    assert_eq!(read(), quality);
+}
+
+#[tokio::test]
+async fn test_transform_rejects_malformed_lines() {
+    use polars::prelude::*;
+    use rfb_rs::transform::Reject;
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    let estabelecimentos = data_dir.path().join("ESTABELE0.csv");
+    let content = fs::read_to_string(&estabelecimentos).unwrap();
+    fs::write(
+        &estabelecimentos,
+        format!("\"11111111\";\"0001\";\"\"BROKEN\";\"1\"\n{}", content),
+    )
+    .unwrap();
+
+    let run = |max_rejects| {
+        let config = TransformConfig {
+            data_dir: data_dir.path().to_str().unwrap().to_string(),
+            output_dir: output_dir.path().to_str().unwrap().to_string(),
+            max_rejects,
+            ..Default::default()
+        };
+        async move {
+            let mut transformer = Transformer::new(config);
+            transformer.load_lookups().unwrap();
+            transformer.transform().await
+        }
+    };
+    run(Some(1)).await.unwrap();
+
+    let rejects =
+        fs::read_to_string(output_dir.path().join("rejects/ESTABELE0.csv.ndjson")).unwrap();
+    let rejects: Vec<Reject> = rejects
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rejects.len(), 1);
+    assert_eq!(rejects[0].file, "ESTABELE0.csv");
+    assert_eq!(rejects[0].line, 1);
+    assert_eq!(rejects[0].reason, "unterminated quote");
+
+    let companies = output_dir.path().join("companies/part-00000.parquet");
+    let companies = ParquetReader::new(fs::File::open(companies).unwrap())
+        .finish()
+        .unwrap();
+    assert_eq!(companies.height(), 1);
+
+    assert!(matches!(
+        run(Some(0)).await,
+        Err(TransformError::TooManyRejects(_))
+    ));
+}
+
+/// Add an MEI whose name, partner and legal representative carry raw CPFs,
+/// and a malformed line with a CPF
+fn write_individual_company(dir: &std::path::Path) {
+    let append = |name: &str, line: &str| {
+        let path = dir.join(name);
+        let content = fs::read_to_string(&path).unwrap() + line;
+        fs::write(path, content).unwrap();
+    };
+    let dv = Cnpj::matriz("12345678").unwrap().dv().to_string();
+    append(
+        "ESTABELE0.csv",
+        &format!(
+            "\"12345678\";\"0001\";\"{dv}\";\"1\";\"JOAO 123.456.789-01\";\"02\";\"20200101\";\"00\";\"\";\"\";\"20200101\";\"4751201\";\"\";\"RUA\";\"DAS FLORES\";\"10\";\"CASA 2\";\"CENTRO\";\"70000000\";\"DF\";\"9701\";\"61\";\"32023000\";\"\";\"\";\"\";\"\";\"joao@example.com\";\"\";\"\"\n"
+        ),
+    );
+    append(
+        "EMPRECSV0.csv",
+        "\"12345678\";\"JOAO DA SILVA 12345678901\";\"2135\";\"50\";\"1000,00\";\"01\";\"\"\n",
+    );
+    append(
+        "SOCIOCSV0.csv",
+        "\"12345678\";\"2\";\"JOAO DA SILVA\";\"12345678901\";\"50\";\"20200101\";\"\";\"98765432100\";\"MARIA\";\"00\";\"4\"\n",
+    );
+    append(
+        "SIMPLES.csv",
+        "\"12345678\";\"S\";\"20200101\";\"\";\"S\";\"20200101\";\"\"\n",
+    );
+    append("SOCIOCSV0.csv", "\"12345678\";\"2\";\"12345678901\"\n");
+}
+
+#[tokio::test]
+async fn test_privacy_mode_writes_no_raw_cpf() {
+    use polars::prelude::*;
+    use rfb_rs::transform::{Compression, OutputFormat};
+
+    let cpf = regex::Regex::new(r"(^|\D)\d{3}\.?\d{3}\.?\d{3}-?\d{2}(\D|$)").unwrap();
+    let data_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    write_individual_company(data_dir.path());
+
+    for format in [OutputFormat::Parquet, OutputFormat::Jsonl] {
+        let output_dir = TempDir::new().unwrap();
+        let config = TransformConfig {
+            data_dir: data_dir.path().to_str().unwrap().to_string(),
+            output_dir: output_dir.path().to_str().unwrap().to_string(),
+            privacy_mode: true,
+            format,
+            compression: Compression::Uncompressed,
+            ..Default::default()
+        };
+        let mut transformer = Transformer::new(config);
+        transformer.load_lookups().unwrap();
+        transformer.transform().await.unwrap();
+
+        let mut files = vec![output_dir.path().to_path_buf()];
+        let mut checked = 0;
+        while let Some(path) = files.pop() {
+            if path.is_dir() {
+                files.extend(fs::read_dir(&path).unwrap().map(|e| e.unwrap().path()));
+                continue;
+            }
+            // The state only holds hashes, whose hex digits may look like a CPF
+            if path.ends_with("transform_state.json") {
+                continue;
+            }
+            let values: Vec<String> = if path.extension().is_some_and(|e| e == "parquet") {
+                let df = ParquetReader::new(fs::File::open(&path).unwrap())
+                    .finish()
+                    .unwrap();
+                df.get_columns()
+                    .iter()
+                    .filter_map(|column| column.str().ok())
+                    .flat_map(|values| values.into_iter().flatten().map(str::to_string))
+                    .collect()
+            } else if path.ends_with(Lookups::CACHE_FILE) {
+                let cache = zstd::decode_all(fs::File::open(&path).unwrap()).unwrap();
+                vec![String::from_utf8(cache).unwrap()]
+            } else {
+                vec![fs::read_to_string(&path).unwrap()]
+            };
+            for value in values {
+                assert!(!cpf.is_match(&value), "raw CPF in {:?}: {}", path, value);
+            }
+            checked += 1;
+        }
+        assert!(checked > 3);
+
+        if format == OutputFormat::Jsonl {
+            let content =
+                fs::read_to_string(output_dir.path().join("companies/part-00000.jsonl")).unwrap();
+            let company: Company = content
+                .lines()
+                .map(|line| serde_json::from_str::<Company>(line).unwrap())
+                .find(|company| company.cnpj.basico() == "12345678")
+                .unwrap();
+            assert_eq!(company.razao_social, "JOAO DA SILVA ***456789**");
+            assert_eq!(company.email, None);
+            assert_eq!(company.logradouro, "");
+            assert_eq!(company.qsa[0].cnpj_cpf_socio, "***456789**");
+            assert_eq!(company.qsa[0].cpf_representante_legal, "***654321**");
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_pseudonyms_link_partners_across_companies() {
+    use rfb_rs::transform::{Compression, OutputFormat, PseudonymKey};
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    write_individual_company(data_dir.path());
+    // The partner of the single-person company, also a partner of SERPRO
+    // with the CPF as published
+    let path = data_dir.path().join("SOCIOCSV0.csv");
+    let content = fs::read_to_string(&path).unwrap()
+        + "\"33683111\";\"2\";\"Joao da  Silva\";\"***456789**\";\"10\";\"20190101\";\"\";\"\";\"\";\"00\";\"6\"\n";
+    fs::write(&path, content).unwrap();
+
+    let key = PseudonymKey::new("a secret of at least 16 bytes").unwrap();
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        privacy_mode: true,
+        pseudonym_key: Some(key.clone()),
+        format: OutputFormat::Jsonl,
+        compression: Compression::Uncompressed,
+        ..Default::default()
+    };
+    let mut transformer = Transformer::new(config);
+    transformer.load_lookups().unwrap();
+    transformer.transform().await.unwrap();
+
+    let content = fs::read_to_string(output_dir.path().join("companies/part-00000.jsonl")).unwrap();
+    let companies: Vec<Company> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let partners: Vec<_> = companies.iter().flat_map(|c| &c.qsa).collect();
+    let token = key.token("12345678901", "JOAO DA SILVA").unwrap();
+
+    let joao: Vec<_> = partners
+        .iter()
+        .filter(|p| p.cnpj_cpf_socio == token)
+        .collect();
+    assert_eq!(joao.len(), 2);
+    assert!(joao.iter().all(|p| p.nome_socio.is_empty()));
+    assert!(
+        partners
+            .iter()
+            .all(|p| !p.cnpj_cpf_socio.contains('*') && !p.nome_socio.contains("JOAO"))
+    );
+
+    // Legal representatives are pseudonymized, and missing CPFs left alone
+    let individual = partners
+        .iter()
+        .find(|p| p.cnpj_basico == "12345678")
+        .unwrap();
+    assert_eq!(
+        individual.cpf_representante_legal,
+        key.token("98765432100", "MARIA").unwrap()
+    );
+    assert!(individual.nome_representante_legal.is_empty());
+    let antonio = partners
+        .iter()
+        .find(|p| p.cnpj_cpf_socio == key.token("***123456**", "ANTONIO DE SOUSA").unwrap())
+        .unwrap();
+    assert_eq!(antonio.cpf_representante_legal, "");
+
+    // Pseudonyms without privacy mode are a configuration error
+    let config = TransformConfig {
+        pseudonym_key: Some(key),
+        ..Default::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[tokio::test]
+async fn test_suppressed_entities_never_appear_in_outputs() {
+    use polars::prelude::*;
+    use rfb_rs::transform::{Compression, OutputFormat, QualityReport, SuppressionList};
+
+    let data_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    write_individual_company(data_dir.path());
+    let path = data_dir.path().join("SOCIOCSV0.csv");
+    let content = fs::read_to_string(&path).unwrap()
+        + "\"33683111\";\"2\";\"MARIA  SOUZA\";\"***222222**\";\"10\";\"20190101\";\"\";\"\";\"\";\"00\";\"6\"\n"
+        + "\"33683111\";\"2\";\"PEDRO ALVES\";\"***444444**\";\"10\";\"20190101\";\"\";\"***333333**\";\"CARLA LIMA\";\"05\";\"2\"\n";
+    fs::write(&path, content).unwrap();
+
+    // The owner of the single-person company, by CPF, and two people by name
+    let list = SuppressionList::parse(
+        "123.456.789-01\nname:Maria Souza\nname: carla lima # representative\n",
+    )
+    .unwrap();
+    let forbidden = [
+        "12345678901",
+        "456789",
+        "JOAO",
+        "MARIA SOUZA",
+        "MARIA  SOUZA",
+        "222222",
+        "CARLA",
+        "333333",
+    ];
+
+    for format in [OutputFormat::Parquet, OutputFormat::Jsonl] {
+        let output_dir = TempDir::new().unwrap();
+        let config = TransformConfig {
+            data_dir: data_dir.path().to_str().unwrap().to_string(),
+            output_dir: output_dir.path().to_str().unwrap().to_string(),
+            suppression: list.clone(),
+            format,
+            compression: Compression::Uncompressed,
+            ..Default::default()
+        };
+        let mut transformer = Transformer::new(config);
+        transformer.load_lookups().unwrap();
+        transformer.transform().await.unwrap();
+
+        let mut files = vec![output_dir.path().to_path_buf()];
+        let mut names = Vec::new();
+        while let Some(path) = files.pop() {
+            if path.is_dir() {
+                files.extend(fs::read_dir(&path).unwrap().map(|e| e.unwrap().path()));
+                continue;
+            }
+            // The state only holds hashes, whose hex digits may look like a CPF
+            if path.ends_with("transform_state.json") {
+                continue;
+            }
+            let values: Vec<String> = if path.extension().is_some_and(|e| e == "parquet") {
+                let df = ParquetReader::new(fs::File::open(&path).unwrap())
+                    .finish()
+                    .unwrap();
+                df.get_columns()
+                    .iter()
+                    .filter_map(|column| column.str().ok())
+                    .flat_map(|values| values.into_iter().flatten().map(str::to_string))
+                    .collect()
+            } else if path.ends_with(Lookups::CACHE_FILE) {
+                let cache = zstd::decode_all(fs::File::open(&path).unwrap()).unwrap();
+                vec![String::from_utf8(cache).unwrap()]
+            } else {
+                vec![fs::read_to_string(&path).unwrap()]
+            };
+            for value in values {
+                for text in forbidden {
+                    assert!(!value.contains(text), "{} in {:?}: {}", text, path, value);
+                }
+                names.push(value);
+            }
+        }
+        // Other people and companies are still written
+        assert!(names.iter().any(|value| value.contains("PEDRO ALVES")));
+        assert!(names.iter().any(|value| value.contains("ANTONIO DE SOUSA")));
+        assert!(names.iter().any(|value| value.contains("SERPRO")));
+
+        // The rejected line of the single-person company is kept without its content
+        let rejects =
+            fs::read_to_string(output_dir.path().join("rejects/SOCIOCSV0.csv.ndjson")).unwrap();
+        assert!(rejects.contains("\"content\":\"\""));
+
+        let report: QualityReport = serde_json::from_str(
+            &fs::read_to_string(output_dir.path().join("quality_report.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(report.suppressed["empresas"], 1);
+        assert_eq!(report.suppressed["estabelecimentos"], 0);
+        assert_eq!(report.suppressed["socios"], 2);
+        assert_eq!(report.suppressed["representantes"], 1);
+    }
+}
+
+#[tokio::test]
+async fn test_transform_fills_ibge_municipality_codes() {
+    use rfb_rs::transform::{Compression, OutputFormat};
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    // A branch in a municipality the embedded crosswalk does not have, with
+    // the UF left empty, mapped by a crosswalk file of the data directory
+    let path = data_dir.path().join("ESTABELE0.csv");
+    let dv = &Cnpj::from_parts("33683111", "0003").unwrap().to_string()[12..];
+    let line = fs::read_to_string(&path).unwrap();
+    let branch = line
+        .replace("\"0002\";\"80\"", &format!("\"0003\";\"{}\"", dv))
+        .replace("\"DF\";\"9701\"", "\"\";\"1\"");
+    fs::write(&path, line + &branch).unwrap();
+    fs::write(
+        data_dir.path().join("MUNICCSV.csv"),
+        "\"9701\";\"BRASILIA\"\n\"1\";\"ALTA FLORESTA D'OESTE\"\n",
+    )
+    .unwrap();
+    let mut lookups = Lookups::new();
+    lookups
+        .load_from_directory(data_dir.path().to_str().unwrap())
+        .unwrap();
+    assert_eq!(lookups.cities_without_ibge(), [1]);
+    fs::write(
+        data_dir.path().join("TOM_IBGE.csv"),
+        "tom;ibge\n1;1100015\n",
+    )
+    .unwrap();
+    lookups
+        .load_from_directory(data_dir.path().to_str().unwrap())
+        .unwrap();
+    assert!(lookups.cities_without_ibge().is_empty());
+
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        format: OutputFormat::Jsonl,
+        compression: Compression::Uncompressed,
+        ..Default::default()
+    };
+    let mut transformer = Transformer::new(config);
+    transformer.load_lookups().unwrap();
+    transformer.transform().await.unwrap();
+
+    let content = fs::read_to_string(output_dir.path().join("companies/part-00000.jsonl")).unwrap();
+    let companies: Vec<Company> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(companies.len(), 2);
+    let brasilia = companies.iter().find(|c| c.cnpj.ordem() == "0002").unwrap();
+    assert_eq!(brasilia.codigo_municipio_ibge, Some(5300108));
+    assert_eq!(brasilia.regiao.as_deref(), Some("Centro-Oeste"));
+    assert_eq!(brasilia.uf, "DF");
+    let other = companies.iter().find(|c| c.cnpj.ordem() == "0003").unwrap();
+    assert_eq!(other.codigo_municipio_ibge, Some(1100015));
+    assert_eq!(other.regiao.as_deref(), Some("Norte"));
+    assert_eq!(other.uf, "RO");
+}
+
+#[tokio::test]
+async fn test_transform_adds_cnae_hierarchy() {
+    use rfb_rs::transform::{CnaeCode, Compression, OutputFormat};
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    fs::write(
+        data_dir.path().join("CNAECSV.csv"),
+        "\"6204000\";\"Consultoria em tecnologia da informação\"\n",
+    )
+    .unwrap();
+    fs::write(
+        data_dir.path().join("CNAE_ESTRUTURA.csv"),
+        "codigo;descricao\n62.0;Atividades dos serviços de tecnologia da informação\n",
+    )
+    .unwrap();
+
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        format: OutputFormat::Jsonl,
+        compression: Compression::Uncompressed,
+        ..Default::default()
+    };
+    let mut lookups = Lookups::new();
+    lookups
+        .load_from_directory(data_dir.path().to_str().unwrap())
+        .unwrap();
+    let structure = &lookups.cnae_structure;
+    let group: CnaeCode = "62.0".parse().unwrap();
+    assert!(
+        structure
+            .description(&group)
+            .unwrap()
+            .contains("tecnologia")
+    );
+    let classes = structure.children(&group);
+    assert_eq!(classes.len(), 1);
+    assert_eq!(classes[0].to_string(), "6204-0");
+    assert_eq!(structure.children(classes[0])[0].as_str(), "6204000");
+
+    let mut transformer = Transformer::new(config);
+    transformer.load_lookups().unwrap();
+    transformer.transform().await.unwrap();
+
+    let content = fs::read_to_string(output_dir.path().join("companies/part-00000.jsonl")).unwrap();
+    let company: Company = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+    assert_eq!(company.cnae_secao.as_deref(), Some("J"));
+    assert_eq!(
+        company.cnae_secao_descricao.as_deref(),
+        Some("Informação e comunicação")
+    );
+    assert_eq!(company.cnae_divisao, Some(62));
+    assert!(
+        company
+            .cnae_divisao_descricao
+            .unwrap()
+            .contains("tecnologia da informação")
+    );
+    assert_eq!(company.cnae_grupo, Some(620));
+    assert_eq!(
+        company.cnae_grupo_descricao.as_deref(),
+        Some("Atividades dos serviços de tecnologia da informação")
+    );
+    assert_eq!(company.cnae_classe, Some(62040));
+    // Described by its only subclass, 6204-0/00
+    assert_eq!(
+        company.cnae_classe_descricao.as_deref(),
+        Some("Consultoria em tecnologia da informação")
+    );
+}
+
+#[tokio::test]
+async fn test_lookups_load_from_archives_and_cache() {
+    use rfb_rs::transform::LookupKind;
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    for (kind, content) in [
+        (LookupKind::Paises, &b"\"105\";\"S\xC3O TOM\xC9\"\n"[..]),
+        (
+            LookupKind::Naturezas,
+            &b"\"2011\";\"Empresa P\xFAblica\"\n"[..],
+        ),
+    ] {
+        let file = fs::File::create(data_dir.path().join(kind.archive())).unwrap();
+        let mut archive = ::zip::ZipWriter::new(file);
+        archive
+            .start_file::<String, ()>(
+                format!("F.K03200$Z.D51011.{}", kind.token()),
+                ::zip::write::FileOptions::default(),
+            )
+            .unwrap();
+        std::io::Write::write_all(&mut archive, content).unwrap();
+        archive.finish().unwrap();
+    }
+
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        ..Default::default()
+    };
+    let mut transformer = Transformer::new(config);
+    transformer.load_lookups().unwrap();
+    transformer.transform().await.unwrap();
+
+    let cache = Lookups::from_cache(&output_dir.path().join(Lookups::CACHE_FILE)).unwrap();
+    assert_eq!(cache.get_country(105).map(String::as_str), Some("SÃO TOMÉ"));
+    assert_eq!(
+        cache.get_legal_nature(2011).map(String::as_str),
+        Some("Empresa Pública")
+    );
+    assert_eq!(cache.get_city(9701).map(String::as_str), Some("BRASILIA"));
+
+    // A lookup file with the wrong layout is an error naming the file
+    fs::write(
+        data_dir.path().join("F.K03200$Z.D51011.MOTICSV"),
+        "\"01\";\"EXTINCAO\";\"X\"\n",
+    )
+    .unwrap();
+    let error = Lookups::new()
+        .load_from_directory(data_dir.path().to_str().unwrap())
+        .unwrap_err();
+    assert!(error.to_string().contains("MOTICSV (motivos)"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_transform_decodes_coded_fields() {
+    use rfb_rs::transform::{
+        Compression, FaixaEtaria, IdentificadorSocio, MatrizFilial, OutputFormat, Porte,
+        SituacaoCadastral,
+    };
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        format: OutputFormat::Jsonl,
+        compression: Compression::Uncompressed,
+        ..Default::default()
+    };
+    let mut transformer = Transformer::new(config.clone());
+    transformer.load_lookups().unwrap();
+    transformer.transform().await.unwrap();
+
+    let content = fs::read_to_string(output_dir.path().join("companies/part-00000.jsonl")).unwrap();
+    let company: Company = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+    assert_eq!(
+        company.identificador_matriz_filial,
+        Some(MatrizFilial::Filial)
+    );
+    assert_eq!(company.situacao_cadastral, Some(SituacaoCadastral::Ativa));
+    assert_eq!(company.codigo_porte, Some(Porte::Demais));
+    assert_eq!(company.porte.as_deref(), Some("DEMAIS"));
+    let partner = &company.qsa[0];
+    assert_eq!(
+        partner.identificador_socio,
+        Some(IdentificadorSocio::PessoaFisica)
+    );
+    assert_eq!(partner.codigo_faixa_etaria, Some(FaixaEtaria::De51A60));
+
+    // An undocumented situacao_cadastral fails the run instead of being dropped
+    let estabele = fs::read_to_string(data_dir.path().join("ESTABELE0.csv")).unwrap();
+    fs::write(
+        data_dir.path().join("ESTABELE0.csv"),
+        estabele.replacen("BRASILIA-DF\";\"02\"", "BRASILIA-DF\";\"05\"", 1),
+    )
+    .unwrap();
+    let output_dir = TempDir::new().unwrap();
+    let config = TransformConfig {
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        ..config
+    };
+    let mut transformer = Transformer::new(config);
+    transformer.load_lookups().unwrap();
+    let error = transformer.transform().await.unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("unknown situacao_cadastral code 5"),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn test_diff_reports_changes_between_releases() {
+    use polars::prelude::*;
+    use rfb_rs::transform::{
+        ChangeEvent, ChangeKind, Compression, DiffConfig, DiffSummary, Differ, OutputFormat,
+        release_dir,
+    };
+
+    let data_dir = TempDir::new().unwrap();
+    let root = TempDir::new().unwrap();
+    let root_path = root.path().to_str().unwrap();
+    write_sample_data(data_dir.path());
+
+    let transform = |release: &str| {
+        let config = TransformConfig {
+            data_dir: data_dir.path().to_str().unwrap().to_string(),
+            output_dir: release_dir(root_path, release)
+                .to_string_lossy()
+                .to_string(),
+            ..Default::default()
+        };
+        async move {
+            let mut transformer = Transformer::new(config);
+            transformer.load_lookups().unwrap();
+            transformer.transform().await.unwrap();
+        }
+    };
+    transform("2025-09").await;
+
+    // The next month, the establishment closes and moves, a branch opens and
+    // a partner joins
+    let estabele = fs::read_to_string(data_dir.path().join("ESTABELE0.csv")).unwrap();
+    let closed = estabele
+        .replacen("BRASILIA-DF\";\"02\"", "BRASILIA-DF\";\"08\"", 1)
+        .replacen("\"70830010\"", "\"70830020\"", 1);
+    let opened = estabele.replacen("\"0002\";\"80\"", "\"0003\";\"60\"", 1);
+    fs::write(data_dir.path().join("ESTABELE0.csv"), closed + &opened).unwrap();
+    let socios = fs::read_to_string(data_dir.path().join("SOCIOCSV0.csv")).unwrap();
+    let joined = socios.replacen("ANTONIO DE SOUSA", "MARIA DE SOUSA", 1);
+    fs::write(data_dir.path().join("SOCIOCSV0.csv"), socios + &joined).unwrap();
+    transform("2025-10").await;
+
+    let output_dir = TempDir::new().unwrap();
+    let config = DiffConfig {
+        from: release_dir(root_path, "2025-09")
+            .to_string_lossy()
+            .to_string(),
+        to: release_dir(root_path, "2025-10")
+            .to_string_lossy()
+            .to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        format: OutputFormat::Jsonl,
+        compression: Compression::Uncompressed,
+    };
+    let summary = Differ::new(config.clone()).diff().await.unwrap();
+    assert_eq!(summary.count(ChangeKind::Opened), 1);
+    assert_eq!(summary.count(ChangeKind::Closed), 1);
+    assert_eq!(summary.count(ChangeKind::AddressChanged), 1);
+    assert_eq!(summary.count(ChangeKind::PartnerAdded), 1);
+    assert_eq!(summary.count(ChangeKind::PartnerRemoved), 0);
+    assert_eq!(summary.total(), 4);
+
+    let written = fs::read_to_string(output_dir.path().join("diff_summary.json")).unwrap();
+    assert_eq!(
+        serde_json::from_str::<DiffSummary>(&written).unwrap(),
+        summary
+    );
+
+    let content = fs::read_to_string(output_dir.path().join("changes/part-00000.jsonl")).unwrap();
+    let events: Vec<ChangeEvent> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let address = events
+        .iter()
+        .find(|event| event.kind == ChangeKind::AddressChanged)
+        .unwrap();
+    assert_eq!(address.cnpj.as_deref(), Some("33683111000280"));
+    assert_eq!(address.field.as_deref(), Some("cep"));
+    assert_eq!(address.before.as_deref(), Some("70830010"));
+    assert_eq!(address.after.as_deref(), Some("70830020"));
+    let closed = events
+        .iter()
+        .find(|event| event.kind == ChangeKind::Closed)
+        .unwrap();
+    assert_eq!(closed.before.as_deref(), Some("2"));
+    assert_eq!(closed.after.as_deref(), Some("8"));
+    let partner = events
+        .iter()
+        .find(|event| event.kind == ChangeKind::PartnerAdded)
+        .unwrap();
+    assert_eq!(partner.cnpj_basico, "33683111");
+    assert_eq!(partner.after.as_deref(), Some("MARIA DE SOUSA"));
+
+    // The same events as a Parquet dataset
+    let parquet_dir = TempDir::new().unwrap();
+    let config = DiffConfig {
+        output_dir: parquet_dir.path().to_str().unwrap().to_string(),
+        format: OutputFormat::Parquet,
+        compression: Compression::Zstd,
+        ..config
+    };
+    Differ::new(config).diff().await.unwrap();
+    let changes = ParquetReader::new(
+        fs::File::open(parquet_dir.path().join("changes/part-00000.parquet")).unwrap(),
+    )
+    .finish()
+    .unwrap();
+    assert_eq!(changes.height(), events.len());
+}
+
+#[tokio::test]
+async fn test_transform_writes_filtered_subset() {
+    use polars::prelude::*;
+    use rfb_rs::transform::{Filters, Porte, SituacaoCadastral};
+
+    let data_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    let append = |name: &str, line: &str| {
+        let path = data_dir.path().join(name);
+        let content = fs::read_to_string(&path).unwrap() + line;
+        fs::write(path, content).unwrap();
+    };
+    // An active micro company in São Paulo, besides the one in Brasília
+    append(
+        "ESTABELE0.csv",
+        "\"11222333\";\"0001\";\"81\";\"1\";\"PADARIA\";\"02\";\"20200101\";\"00\";\"\";\"\";\"20200101\";\"1091102\";\"\";\"RUA\";\"AUGUSTA\";\"1\";\"\";\"CONSOLACAO\";\"01305000\";\"SP\";\"7107\";\"\";\"\";\"\";\"\";\"\";\"\";\"\";\"\";\"\"\n",
+    );
+    append(
+        "EMPRECSV0.csv",
+        "\"11222333\";\"PADARIA LTDA\";\"2062\";\"49\";\"1000,00\";\"01\";\"\"\n",
+    );
+    append(
+        "SOCIOCSV0.csv",
+        "\"11222333\";\"2\";\"JOANA SILVA\";\"***654321**\";\"49\";\"20200101\";\"\";\"***000000**\";\"\";\"00\";\"4\"\n",
+    );
+
+    let transform = |filters: Filters| {
+        let output_dir = TempDir::new().unwrap();
+        let config = TransformConfig {
+            data_dir: data_dir.path().to_str().unwrap().to_string(),
+            output_dir: output_dir.path().to_str().unwrap().to_string(),
+            filters,
+            ..Default::default()
+        };
+        async move {
+            let mut transformer = Transformer::new(config);
+            transformer.load_lookups().unwrap();
+            transformer.transform().await.unwrap();
+            let read = |table: &str| {
+                let path = output_dir.path().join(table).join("part-00000.parquet");
+                let df = ParquetReader::new(fs::File::open(path).unwrap())
+                    .finish()
+                    .unwrap();
+                df.column("cnpj_basico")
+                    .unwrap()
+                    .str()
+                    .unwrap()
+                    .into_no_null_iter()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            };
+            (read("companies"), read("partners"))
+        }
+    };
+
+    let (companies, partners) = transform(Filters {
+        ufs: vec!["SP".to_string()],
+        situacoes: vec![SituacaoCadastral::Ativa],
+        cnaes: vec!["10*".parse().unwrap()],
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(companies, ["11222333"]);
+    assert_eq!(partners, ["11222333"]);
+
+    let (companies, partners) = transform(Filters {
+        portes: vec![Porte::Demais],
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(companies, ["33683111"]);
+    assert_eq!(partners, ["33683111"]);
+
+    let (companies, _) = transform(Filters {
+        cnaes: vec!["62*".parse().unwrap()],
+        ufs: vec!["SP".to_string()],
+        ..Default::default()
+    })
+    .await;
+    assert!(companies.is_empty());
+}
+
+#[tokio::test]
+async fn test_transform_writes_ownership_graph() {
+    use rfb_rs::transform::{GRAPH_DIR, NodeKind, OwnershipGraph, PseudonymKey};
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    let append = |file: &str, line: &str| {
+        let path = data_dir.path().join(file);
+        fs::write(&path, fs::read_to_string(&path).unwrap() + line).unwrap();
+    };
+    // A holding company, partner of SERPRO, owned by a person
+    append(
+        "EMPRECSV0.csv",
+        "\"11222333\";\"HOLDING & CIA LTDA\";\"2062\";\"49\";\"1000,00\";\"03\";\"\"\n",
+    );
+    append(
+        "SOCIOCSV0.csv",
+        "\"33683111\";\"1\";\"HOLDING & CIA LTDA\";\"11222333000181\";\"22\";\"20200101\";\"\";\"***000000**\";\"\";\"00\";\"0\"\n",
+    );
+    append(
+        "SOCIOCSV0.csv",
+        "\"11222333\";\"2\";\"JOANA SILVA\";\"***654321**\";\"49\";\"20180301\";\"\";\"***000000**\";\"\";\"00\";\"4\"\n",
+    );
+    append(
+        "QUALSCSV.csv",
+        "\"22\";\"Sócio\"\n\"49\";\"Sócio-Administrador\"\n",
+    );
+
+    let key = PseudonymKey::new("a secret of at least 16 bytes").unwrap();
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        privacy_mode: true,
+        pseudonym_key: Some(key.clone()),
+        graph: true,
+        ..Default::default()
+    };
+    let mut transformer = Transformer::new(config);
+    transformer.load_lookups().unwrap();
+    transformer.transform().await.unwrap();
+
+    let graph = OwnershipGraph::load(output_dir.path()).unwrap();
+    let antonio = key.token("***123456**", "ANTONIO DE SOUSA").unwrap();
+    let joana = key.token("***654321**", "JOANA SILVA").unwrap();
+    assert_eq!(graph.nodes().count(), 4);
+    assert_eq!(graph.node(&joana).unwrap().kind, NodeKind::Person);
+    assert_eq!(graph.node(&joana).unwrap().name, None);
+    assert_eq!(
+        graph.node("11222333").unwrap().name.as_deref(),
+        Some("HOLDING & CIA LTDA")
+    );
+
+    let holding = graph
+        .owners("33683111")
+        .iter()
+        .find(|edge| edge.owner == "11222333")
+        .unwrap();
+    assert_eq!(holding.codigo_qualificacao, Some(22));
+    assert_eq!(holding.qualificacao.as_deref(), Some("Sócio"));
+    assert_eq!(holding.data_entrada.unwrap().to_string(), "2020-01-01");
+
+    let serpro: Cnpj = "33683111000280".parse().unwrap();
+    let controllers: Vec<_> = graph
+        .ultimate_controllers(&serpro)
+        .into_iter()
+        .map(|c| (c.node.id, c.depth))
+        .collect();
+    assert_eq!(controllers, [(antonio, 1), (joana.clone(), 2)]);
+
+    let dir = output_dir.path().join(GRAPH_DIR);
+    let graphml = fs::read_to_string(dir.join("ownership.graphml")).unwrap();
+    assert!(graphml.contains(r#"<edge source="11222333" target="33683111">"#));
+    assert!(graphml.contains("HOLDING &amp; CIA LTDA"));
+    assert!(!graphml.contains("JOANA"));
+
+    let relationships = fs::read_to_string(dir.join("neo4j/partner_of.csv")).unwrap();
+    let mut lines = relationships.lines();
+    assert_eq!(
+        lines.next(),
+        Some(":START_ID,:END_ID,codigo_qualificacao:int,qualificacao,data_entrada:date,:TYPE")
+    );
+    assert_eq!(lines.count(), 3);
+    let people = fs::read_to_string(dir.join("neo4j/partners.csv")).unwrap();
+    assert!(people.contains(&format!("{},Person", joana)));
+}
+
+#[tokio::test]
+async fn test_transform_writes_company_groups() {
+    use polars::prelude::*;
+    use rfb_rs::transform::{GROUPS_TABLE, OutputFormat};
+
+    let data_dir = TempDir::new().unwrap();
+    let output_dir = TempDir::new().unwrap();
+    write_sample_data(data_dir.path());
+    // The head office of SERPRO, in another shard than its branch, and a
+    // second branch in a municipality the IBGE crosswalk does not have
+    let head_office = "\"33683111\";\"0001\";\"99\";\"1\";\"SERPRO SEDE\";\"02\";\"20040522\";\"00\";\"\";\"\";\"19680101\";\"6204000\";\"\";\"QUADRA\";\"SGAN 601\";\"S/N\";\"\";\"ASA NORTE\";\"70830010\";\"DF\";\"9701\";\"61\";\"32023000\";\"\";\"\";\"\";\"\";\"\";\"\";\"\"\n";
+    let dv = &Cnpj::from_parts("33683111", "0003").unwrap().to_string()[12..];
+    let salvador = head_office
+        .replace(
+            "\"0001\";\"99\";\"1\"",
+            &format!("\"0003\";\"{}\";\"2\"", dv),
+        )
+        .replace("\"DF\";\"9701\"", "\"BA\";\"3849\"");
+    fs::write(
+        data_dir.path().join("ESTABELE1.csv"),
+        head_office.to_string() + &salvador,
+    )
+    .unwrap();
+
+    let config = TransformConfig {
+        data_dir: data_dir.path().to_str().unwrap().to_string(),
+        output_dir: output_dir.path().to_str().unwrap().to_string(),
+        groups: true,
+        ..Default::default()
+    };
+    let mut transformer = Transformer::new(config);
+    transformer.load_lookups().unwrap();
+    transformer.transform().await.unwrap();
+
+    let path = output_dir
+        .path()
+        .join(GROUPS_TABLE)
+        .join("part-00000.parquet");
+    let groups = ParquetReader::new(fs::File::open(path).unwrap())
+        .finish()
+        .unwrap();
+    assert_eq!(groups.height(), 1);
+    let text = |name: &str| {
+        groups
+            .column(name)
+            .unwrap()
+            .cast(&DataType::String)
+            .unwrap()
+            .str()
+            .unwrap()
+            .get(0)
+            .map(str::to_string)
+    };
+    assert_eq!(text("cnpj_basico").as_deref(), Some("33683111"));
+    assert_eq!(text("cnpj_matriz").as_deref(), Some("33683111000199"));
+    assert_eq!(text("nome_fantasia").as_deref(), Some("SERPRO SEDE"));
+    assert_eq!(text("estabelecimentos").as_deref(), Some("3"));
+    assert_eq!(text("filiais").as_deref(), Some("2"));
+    assert_eq!(text("data_inicio_atividade").as_deref(), Some("1967-06-30"));
+    let branches = groups.column("cnpjs_filiais").unwrap().list().unwrap();
+    let branches = branches.get_as_series(0).unwrap();
+    assert_eq!(
+        branches
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .collect::<Vec<_>>(),
+        ["33683111000280", &format!("336831110003{}", dv)]
+    );
+    // Regions come from the UF, also where the crosswalk has no IBGE code
+    let per_uf = groups.column("filiais_por_uf").unwrap().list().unwrap();
+    let per_uf = per_uf.get_as_series(0).unwrap();
+    let per_uf = per_uf.struct_().unwrap();
+    let field = |name: &str| -> Vec<String> {
+        per_uf
+            .field_by_name(name)
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .map(str::to_string)
+            .collect()
+    };
+    assert_eq!(field("uf"), ["BA", "DF"]);
+    assert_eq!(field("regiao"), ["Nordeste", "Centro-Oeste"]);
+
+    // Groups are Parquet only
+    let config = TransformConfig {
+        format: OutputFormat::Jsonl,
+        groups: true,
+        ..Default::default()
+    };
+    assert!(config.validate().is_err());
+}